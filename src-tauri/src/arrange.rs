@@ -0,0 +1,595 @@
+//! Automatic layer layouts. The layout functions are pure: they take sizes
+//! and sort keys and return placements, so results depend only on input.
+
+use crate::database::{Board, Layer};
+use crate::render;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArrangeStrategy {
+    /// Equal-width columns, each item dropped into the shortest column.
+    Masonry,
+    /// Justified rows of equal height per row.
+    Shelf,
+    /// Uniform cells, each item fitted inside its cell.
+    #[default]
+    Grid,
+    /// Items at their current size, packed tightly into a block of the
+    /// target aspect ratio.
+    Pack,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArrangeOrder {
+    /// Top-to-bottom, left-to-right by current position.
+    #[default]
+    Position,
+    Name,
+    /// By hue of the average color, with greys last from dark to light.
+    Color,
+    /// By creation time, oldest first.
+    Date,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ArrangeOptions {
+    pub strategy: ArrangeStrategy,
+    pub order: ArrangeOrder,
+    pub gap: f64,
+    /// Column count for masonry and grid.
+    pub columns: Option<usize>,
+    /// Column width for masonry, row height for shelf, cell size for grid.
+    /// Defaults to the median size of the arranged items.
+    pub size: Option<f64>,
+    /// Width / height of the block that shelf and pack aim for.
+    pub aspect_ratio: f64,
+    /// Top-left corner of the arrangement. Defaults to the top-left of the
+    /// items' current bounds.
+    pub origin: Option<(f64, f64)>,
+    pub persist: bool,
+}
+
+impl Default for ArrangeOptions {
+    fn default() -> Self {
+        Self {
+            strategy: ArrangeStrategy::Grid,
+            order: ArrangeOrder::Position,
+            gap: 20.0,
+            columns: None,
+            size: None,
+            aspect_ratio: 16.0 / 9.0,
+            origin: None,
+            persist: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LayerPlacement {
+    pub id: f64,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// What the layout needs to know about a layer.
+#[derive(Debug, Clone)]
+pub struct ArrangeItem {
+    pub id: f64,
+    pub name: String,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub created: f64,
+    pub color: Option<[u8; 3]>,
+}
+
+impl ArrangeItem {
+    fn from_layer(layer: &Layer) -> Self {
+        Self {
+            id: layer.id,
+            name: layer.name.clone(),
+            x: layer.x,
+            y: layer.y,
+            width: layer.width.max(1.0),
+            height: layer.height.max(1.0),
+            created: created_at(layer),
+            color: None,
+        }
+    }
+}
+
+/// Arranges the given layers of a board (all layers when `layer_ids` is
+/// empty) and, when `persist` is set, writes the placements back into it.
+pub fn arrange_board(
+    board: &mut Board,
+    layer_ids: &[f64],
+    options: &ArrangeOptions,
+    images_dir: &Path,
+) -> Result<Vec<LayerPlacement>, String> {
+    let selected: Vec<&Layer> = board
+        .layers
+        .iter()
+        .filter(|l| layer_ids.is_empty() || layer_ids.contains(&l.id))
+        .collect();
+    if selected.is_empty() {
+        return Err("No layers to arrange".to_string());
+    }
+
+    let items: Vec<ArrangeItem> = selected
+        .iter()
+        .map(|layer| {
+            let mut item = ArrangeItem::from_layer(layer);
+            if options.order == ArrangeOrder::Color {
+                item.color = average_color(layer, images_dir);
+            }
+            item
+        })
+        .collect();
+    let placements = arrange(&items, options);
+
+    if options.persist {
+        for placement in &placements {
+            if let Some(layer) = board.layers.iter_mut().find(|l| l.id == placement.id) {
+                layer.x = placement.x;
+                layer.y = placement.y;
+                layer.width = placement.width;
+                layer.height = placement.height;
+            }
+        }
+    }
+    Ok(placements)
+}
+
+/// Lays out items with the chosen strategy and order.
+pub fn arrange(items: &[ArrangeItem], options: &ArrangeOptions) -> Vec<LayerPlacement> {
+    if items.is_empty() {
+        return Vec::new();
+    }
+    let mut ordered: Vec<&ArrangeItem> = items.iter().collect();
+    sort_items(&mut ordered, options.order);
+
+    let origin = options.origin.unwrap_or_else(|| {
+        let x = items.iter().map(|i| i.x).fold(f64::INFINITY, f64::min);
+        let y = items.iter().map(|i| i.y).fold(f64::INFINITY, f64::min);
+        (x, y)
+    });
+    let size = options
+        .size
+        .filter(|s| *s > 0.0)
+        .unwrap_or_else(|| median_size(items));
+    let gap = options.gap.max(0.0);
+    let aspect = if options.aspect_ratio > 0.0 { options.aspect_ratio } else { 1.0 };
+
+    let sizes: Vec<(f64, f64)> = ordered.iter().map(|i| (i.width, i.height)).collect();
+    let boxes = match options.strategy {
+        ArrangeStrategy::Grid => grid(&sizes, options.columns, size, gap),
+        ArrangeStrategy::Masonry => masonry(&sizes, options.columns, size, gap),
+        ArrangeStrategy::Shelf => shelf(&sizes, size, gap, aspect),
+        ArrangeStrategy::Pack => pack(&sizes, gap, aspect),
+    };
+
+    ordered
+        .iter()
+        .zip(boxes)
+        .map(|(item, (x, y, width, height))| LayerPlacement {
+            id: item.id,
+            x: origin.0 + x,
+            y: origin.1 + y,
+            width,
+            height,
+        })
+        .collect()
+}
+
+fn sort_items(items: &mut [&ArrangeItem], order: ArrangeOrder) {
+    // Stable sorts, so ties keep their input order
+    match order {
+        ArrangeOrder::Position => items.sort_by(|a, b| a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x))),
+        ArrangeOrder::Name => items.sort_by_key(|item| item.name.to_lowercase()),
+        ArrangeOrder::Date => items.sort_by(|a, b| a.created.total_cmp(&b.created)),
+        ArrangeOrder::Color => items.sort_by(|a, b| color_order(a.color, b.color)),
+    }
+}
+
+fn color_order(a: Option<[u8; 3]>, b: Option<[u8; 3]>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            let (ka, kb) = (color_key(a), color_key(b));
+            ka.0.cmp(&kb.0)
+                .then(ka.1.total_cmp(&kb.1))
+                .then(ka.2.total_cmp(&kb.2))
+        }
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// (is grey, hue, lightness) of an RGB color.
+fn color_key([r, g, b]: [u8; 3]) -> (bool, f64, f64) {
+    let (r, g, b) = (r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.0;
+    let chroma = max - min;
+    if chroma < 0.08 {
+        return (true, 0.0, lightness);
+    }
+    let hue = if max == r {
+        ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    } * 60.0;
+    (false, hue, lightness)
+}
+
+fn median_size(items: &[ArrangeItem]) -> f64 {
+    let mut sizes: Vec<f64> = items.iter().map(|i| (i.width * i.height).sqrt()).collect();
+    sizes.sort_by(f64::total_cmp);
+    sizes[sizes.len() / 2].round().max(1.0)
+}
+
+fn default_columns(count: usize) -> usize {
+    ((count as f64).sqrt().ceil() as usize).max(1)
+}
+
+/// Boxes as (x, y, width, height) relative to the arrangement origin.
+type Boxes = Vec<(f64, f64, f64, f64)>;
+
+fn grid(sizes: &[(f64, f64)], columns: Option<usize>, cell: f64, gap: f64) -> Boxes {
+    let columns = columns.filter(|c| *c > 0).unwrap_or_else(|| default_columns(sizes.len()));
+    sizes
+        .iter()
+        .enumerate()
+        .map(|(i, (w, h))| {
+            let scale = (cell / w).min(cell / h);
+            let (w, h) = (w * scale, h * scale);
+            let (col, row) = ((i % columns) as f64, (i / columns) as f64);
+            (
+                col * (cell + gap) + (cell - w) / 2.0,
+                row * (cell + gap) + (cell - h) / 2.0,
+                w,
+                h,
+            )
+        })
+        .collect()
+}
+
+fn masonry(sizes: &[(f64, f64)], columns: Option<usize>, column_width: f64, gap: f64) -> Boxes {
+    let columns = columns.filter(|c| *c > 0).unwrap_or_else(|| default_columns(sizes.len()));
+    let mut heights = vec![0.0_f64; columns];
+    sizes
+        .iter()
+        .map(|(w, h)| {
+            // Shortest column, leftmost on ties
+            let col = (0..columns)
+                .min_by(|a, b| heights[*a].total_cmp(&heights[*b]).then(a.cmp(b)))
+                .unwrap_or(0);
+            let height = h * column_width / w;
+            let placed = (col as f64 * (column_width + gap), heights[col], column_width, height);
+            heights[col] += height + gap;
+            placed
+        })
+        .collect()
+}
+
+/// Justified rows: items are scaled to `row_height`, broken into rows no
+/// wider than the width that gives the block the target aspect ratio, and
+/// every row but the last is scaled to fill that width exactly.
+fn shelf(sizes: &[(f64, f64)], row_height: f64, gap: f64, aspect: f64) -> Boxes {
+    let widths: Vec<f64> = sizes.iter().map(|(w, h)| w * row_height / h).collect();
+    let total: f64 = widths.iter().map(|w| w + gap).sum();
+    let max_width = (total * (row_height + gap) * aspect).sqrt().max(row_height);
+
+    let mut rows: Vec<Vec<usize>> = vec![Vec::new()];
+    let mut row_width = 0.0;
+    for (i, w) in widths.iter().enumerate() {
+        let current = rows.last_mut().expect("rows is never empty");
+        if !current.is_empty() && row_width + gap + w > max_width {
+            rows.push(vec![i]);
+            row_width = *w;
+        } else {
+            row_width += if current.is_empty() { *w } else { gap + w };
+            current.push(i);
+        }
+    }
+
+    let mut boxes = vec![(0.0, 0.0, 0.0, 0.0); sizes.len()];
+    let mut y = 0.0;
+    let last = rows.len() - 1;
+    for (r, row) in rows.iter().enumerate() {
+        let content: f64 = row.iter().map(|i| widths[*i]).sum();
+        let gaps = gap * (row.len() - 1) as f64;
+        let scale = if r < last { (max_width - gaps) / content } else { 1.0 };
+        let height = row_height * scale;
+        let mut x = 0.0;
+        for i in row {
+            let w = widths[*i] * scale;
+            boxes[*i] = (x, y, w, height);
+            x += w + gap;
+        }
+        y += height + gap;
+    }
+    boxes
+}
+
+/// Skyline bottom-left packing at the items' own sizes. Items go in tallest
+/// first; several strip widths around the target are tried and the one
+/// whose bounds, padded out to the target aspect ratio, are smallest wins.
+fn pack(sizes: &[(f64, f64)], gap: f64, aspect: f64) -> Boxes {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by(|a, b| sizes[*b].1.total_cmp(&sizes[*a].1).then(a.cmp(b)));
+
+    let area: f64 = sizes.iter().map(|(w, h)| (w + gap) * (h + gap)).sum();
+    let widest = sizes.iter().map(|(w, _)| w + gap).fold(0.0, f64::max);
+    let ideal = (area * aspect).sqrt();
+
+    let mut best: Option<(f64, Boxes)> = None;
+    for step in 0..=12 {
+        let width = (ideal * (0.7 + step as f64 * 0.05)).max(widest);
+        let boxes = skyline(sizes, &order, width, gap);
+        let (w, h) = boxes
+            .iter()
+            .fold((0.0_f64, 0.0_f64), |(w, h), b| (w.max(b.0 + b.2), h.max(b.1 + b.3)));
+        let score = (w.max(h * aspect)) * (h.max(w / aspect));
+        if best.as_ref().is_none_or(|(s, _)| score < *s - 1e-9) {
+            best = Some((score, boxes));
+        }
+    }
+    best.map(|(_, boxes)| boxes).unwrap_or_default()
+}
+
+fn skyline(sizes: &[(f64, f64)], order: &[usize], strip_width: f64, gap: f64) -> Boxes {
+    // Segments of (x, top, width) covering [0, strip_width)
+    let mut segments: Vec<(f64, f64, f64)> = vec![(0.0, 0.0, strip_width)];
+    let mut boxes = vec![(0.0, 0.0, 0.0, 0.0); sizes.len()];
+
+    for &i in order {
+        let (w, h) = sizes[i];
+        let span = w + gap;
+        let mut best: Option<(f64, f64)> = None;
+        for start in 0..segments.len() {
+            let x = segments[start].0;
+            if x + span > strip_width + 1e-9 && x > 0.0 {
+                break;
+            }
+            let top = segments
+                .iter()
+                .filter(|(sx, _, sw)| *sx < x + span - 1e-9 && sx + sw > x + 1e-9)
+                .map(|(_, top, _)| *top)
+                .fold(0.0, f64::max);
+            if best.is_none_or(|(bx, by)| top < by - 1e-9 || ((top - by).abs() <= 1e-9 && x < bx)) {
+                best = Some((x, top));
+            }
+        }
+        let (x, y) = best.unwrap_or((0.0, 0.0));
+        boxes[i] = (x, y, w, h);
+        raise(&mut segments, x, span, y + h + gap);
+    }
+    boxes
+}
+
+/// Sets the skyline over [x, x + width) to `top`.
+fn raise(segments: &mut Vec<(f64, f64, f64)>, x: f64, width: f64, top: f64) {
+    let end = x + width;
+    let mut next = Vec::with_capacity(segments.len() + 2);
+    for &(sx, st, sw) in segments.iter() {
+        let se = sx + sw;
+        if se <= x + 1e-9 || sx >= end - 1e-9 {
+            next.push((sx, st, sw));
+            continue;
+        }
+        if sx < x {
+            next.push((sx, st, x - sx));
+        }
+        if se > end {
+            next.push((end, st, se - end));
+        }
+    }
+    next.push((x, top, width));
+    next.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    // Merge neighbours at the same height
+    segments.clear();
+    for segment in next {
+        match segments.last_mut() {
+            Some(last) if (last.1 - segment.1).abs() < 1e-9 => last.2 += segment.2,
+            _ => segments.push(segment),
+        }
+    }
+}
+
+/// When a layer was added: the editor stamps ids with `Date.now()`, and
+/// media files carry the save time as a filename prefix.
+fn created_at(layer: &Layer) -> f64 {
+    if layer.id > 1e12 {
+        return layer.id;
+    }
+    layer
+        .src
+        .split_once('_')
+        .and_then(|(prefix, _)| prefix.parse::<f64>().ok())
+        .unwrap_or(layer.id)
+}
+
+fn average_color(layer: &Layer, images_dir: &Path) -> Option<[u8; 3]> {
+    let bytes = render::layer_source_bytes(layer, images_dir)?;
+    let image = image::load_from_memory(&bytes).ok()?.thumbnail(32, 32).to_rgba8();
+    let (mut sum, mut weight) = ([0.0_f64; 3], 0.0);
+    for pixel in image.pixels() {
+        let alpha = pixel[3] as f64 / 255.0;
+        for c in 0..3 {
+            sum[c] += pixel[c] as f64 * alpha;
+        }
+        weight += alpha;
+    }
+    (weight > 0.0).then(|| sum.map(|s| (s / weight).round() as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: f64, width: f64, height: f64) -> ArrangeItem {
+        ArrangeItem {
+            id,
+            name: format!("item {}", id),
+            x: 0.0,
+            y: 0.0,
+            width,
+            height,
+            created: id,
+            color: None,
+        }
+    }
+
+    fn options(strategy: ArrangeStrategy) -> ArrangeOptions {
+        ArrangeOptions {
+            strategy,
+            origin: Some((0.0, 0.0)),
+            ..Default::default()
+        }
+    }
+
+    fn overlaps(a: &LayerPlacement, b: &LayerPlacement) -> bool {
+        a.x < b.x + b.width - 1e-6 && b.x < a.x + a.width - 1e-6 && a.y < b.y + b.height - 1e-6 && b.y < a.y + a.height - 1e-6
+    }
+
+    fn assert_no_overlaps(placements: &[LayerPlacement]) {
+        for (i, a) in placements.iter().enumerate() {
+            for b in &placements[i + 1..] {
+                assert!(!overlaps(a, b), "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    fn mixed_items() -> Vec<ArrangeItem> {
+        [(400.0, 300.0), (200.0, 600.0), (800.0, 450.0), (300.0, 300.0), (150.0, 500.0), (640.0, 480.0), (1000.0, 200.0)]
+            .iter()
+            .enumerate()
+            .map(|(i, (w, h))| item(i as f64 + 1.0, *w, *h))
+            .collect()
+    }
+
+    #[test]
+    fn grid_fits_items_in_cells() {
+        let items = vec![item(1.0, 200.0, 100.0), item(2.0, 100.0, 200.0), item(3.0, 50.0, 50.0)];
+        let opts = ArrangeOptions { size: Some(100.0), gap: 10.0, ..options(ArrangeStrategy::Grid) };
+        let placed = arrange(&items, &opts);
+
+        assert_eq!(placed[0], LayerPlacement { id: 1.0, x: 0.0, y: 25.0, width: 100.0, height: 50.0 });
+        assert_eq!(placed[1], LayerPlacement { id: 2.0, x: 135.0, y: 0.0, width: 50.0, height: 100.0 });
+        assert_eq!(placed[2], LayerPlacement { id: 3.0, x: 0.0, y: 110.0, width: 100.0, height: 100.0 });
+    }
+
+    #[test]
+    fn masonry_fills_shortest_column() {
+        let items = vec![item(1.0, 100.0, 300.0), item(2.0, 100.0, 100.0), item(3.0, 100.0, 100.0)];
+        let opts = ArrangeOptions { size: Some(100.0), gap: 0.0, columns: Some(2), ..options(ArrangeStrategy::Masonry) };
+        let placed = arrange(&items, &opts);
+
+        assert_eq!((placed[0].x, placed[0].y), (0.0, 0.0));
+        assert_eq!((placed[1].x, placed[1].y), (100.0, 0.0));
+        assert_eq!((placed[2].x, placed[2].y), (100.0, 100.0));
+        assert!(placed.iter().all(|p| p.width == 100.0));
+    }
+
+    #[test]
+    fn shelf_keeps_aspect_ratios_and_justifies_rows() {
+        let items = mixed_items();
+        let placed = arrange(&items, &ArrangeOptions { size: Some(200.0), ..options(ArrangeStrategy::Shelf) });
+        for (p, i) in placed.iter().zip(&items) {
+            assert!((p.width / p.height - i.width / i.height).abs() < 1e-9);
+        }
+        assert_no_overlaps(&placed);
+
+        // Rows other than the last end at the same right edge
+        let last_row_y = placed.iter().map(|p| p.y).fold(0.0, f64::max);
+        let mut row_ends: Vec<(i64, f64)> = Vec::new();
+        for p in placed.iter().filter(|p| p.y < last_row_y) {
+            let key = (p.y * 1000.0).round() as i64;
+            let end = p.x + p.width;
+            match row_ends.iter_mut().find(|(k, _)| *k == key) {
+                Some(entry) => entry.1 = entry.1.max(end),
+                None => row_ends.push((key, end)),
+            }
+        }
+        assert!(row_ends.windows(2).all(|w| (w[0].1 - w[1].1).abs() < 1e-6));
+    }
+
+    #[test]
+    fn pack_keeps_sizes_without_overlap_near_target_aspect() {
+        let items = mixed_items();
+        for aspect in [1.0, 16.0 / 9.0, 0.5] {
+            let opts = ArrangeOptions { aspect_ratio: aspect, gap: 10.0, ..options(ArrangeStrategy::Pack) };
+            let placed = arrange(&items, &opts);
+            assert_no_overlaps(&placed);
+            for p in &placed {
+                let original = items.iter().find(|i| i.id == p.id).unwrap();
+                assert_eq!((p.width, p.height), (original.width, original.height));
+            }
+            let width = placed.iter().map(|p| p.x + p.width).fold(0.0, f64::max);
+            let height = placed.iter().map(|p| p.y + p.height).fold(0.0, f64::max);
+            let ratio = width / height;
+            assert!(ratio > aspect / 2.0 && ratio < aspect * 2.0, "aspect {} gave {}", aspect, ratio);
+        }
+    }
+
+    #[test]
+    fn layouts_are_deterministic() {
+        let items = mixed_items();
+        for strategy in [ArrangeStrategy::Grid, ArrangeStrategy::Masonry, ArrangeStrategy::Shelf, ArrangeStrategy::Pack] {
+            let opts = options(strategy);
+            assert_eq!(arrange(&items, &opts), arrange(&items, &opts));
+        }
+    }
+
+    #[test]
+    fn origin_defaults_to_current_bounds() {
+        let mut items = vec![item(1.0, 100.0, 100.0), item(2.0, 100.0, 100.0)];
+        items[0].x = 50.0;
+        items[0].y = 80.0;
+        items[1].x = 300.0;
+        items[1].y = 20.0;
+        let opts = ArrangeOptions { size: Some(100.0), ..Default::default() };
+        let placed = arrange(&items, &opts);
+        // Position order puts the higher item first
+        assert_eq!(placed[0].id, 2.0);
+        assert_eq!((placed[0].x, placed[0].y), (50.0, 20.0));
+    }
+
+    #[test]
+    fn sorts_by_color_then_greys() {
+        let mut items = vec![item(1.0, 10.0, 10.0), item(2.0, 10.0, 10.0), item(3.0, 10.0, 10.0), item(4.0, 10.0, 10.0), item(5.0, 10.0, 10.0)];
+        items[0].color = Some([200, 200, 200]);
+        items[1].color = Some([0, 0, 255]);
+        items[2].color = Some([255, 0, 0]);
+        items[3].color = Some([20, 20, 20]);
+        items[4].color = Some([0, 200, 0]);
+        let opts = ArrangeOptions { order: ArrangeOrder::Color, ..options(ArrangeStrategy::Grid) };
+        let ids: Vec<f64> = arrange(&items, &opts).iter().map(|p| p.id).collect();
+        assert_eq!(ids, vec![3.0, 5.0, 2.0, 4.0, 1.0]);
+    }
+
+    #[test]
+    fn sorts_by_date() {
+        let mut items = vec![item(3.0, 10.0, 10.0), item(1.0, 10.0, 10.0), item(2.0, 10.0, 10.0)];
+        items[0].created = 300.0;
+        items[1].created = 100.0;
+        items[2].created = 200.0;
+        let opts = ArrangeOptions { order: ArrangeOrder::Date, ..options(ArrangeStrategy::Grid) };
+        let ids: Vec<f64> = arrange(&items, &opts).iter().map(|p| p.id).collect();
+        assert_eq!(ids, vec![1.0, 2.0, 3.0]);
+    }
+}
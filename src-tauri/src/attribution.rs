@@ -0,0 +1,236 @@
+//! Source attribution for media imported from the web: where it came from,
+//! who made it and under what terms, read from the page it was found on.
+
+use crate::database::{self, Asset, DataDir, SourceInfo};
+use crate::fetch;
+use scraper::{ElementRef, Html, Selector};
+use serde_json::Value;
+use std::sync::LazyLock;
+use url::Url;
+
+static META: LazyLock<Selector> = LazyLock::new(|| Selector::parse("meta").unwrap());
+static TITLE: LazyLock<Selector> = LazyLock::new(|| Selector::parse("title").unwrap());
+static LINKS: LazyLock<Selector> = LazyLock::new(|| Selector::parse("link[rel][href], a[rel][href]").unwrap());
+static JSON_LD: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"script[type="application/ld+json"]"#).unwrap());
+
+/// Attribution for `source_url`, read from `page_url` when given. The page
+/// is fetched best-effort; what can't be read is left empty.
+pub fn describe(app: &impl DataDir, source_url: &str, page_url: Option<&str>) -> SourceInfo {
+    let fetched_at = database::now_millis();
+    let page = page_url.and_then(|p| Url::parse(p).ok());
+    if let Some(page) = &page {
+        match fetch::fetch_text(app, page.as_str()) {
+            Ok(html) => return from_page(&html, page, source_url, fetched_at),
+            Err(e) => log::warn!("Failed to read attribution from {}: {}", page, e),
+        }
+    }
+
+    let site_name = page.clone().or_else(|| Url::parse(source_url).ok()).as_ref().and_then(site_from_host);
+    SourceInfo {
+        source_url: source_url.to_string(),
+        page_url: page.map(|p| p.to_string()),
+        site_name,
+        fetched_at,
+        ..Default::default()
+    }
+}
+
+/// Attribution from a page's meta tags, links and JSON-LD.
+pub fn from_page(html: &str, page: &Url, source_url: &str, fetched_at: u64) -> SourceInfo {
+    let document = Html::parse_document(html);
+
+    let mut meta: Vec<(String, String)> = Vec::new();
+    for element in document.select(&META) {
+        let element = element.value();
+        let key = element
+            .attr("property")
+            .or_else(|| element.attr("name"))
+            .or_else(|| element.attr("itemprop"))
+            .unwrap_or_default()
+            .to_lowercase();
+        if let Some(content) = element.attr("content").map(clean).filter(|c| !c.is_empty()) {
+            meta.push((key, content));
+        }
+    }
+    let meta_value = |keys: &[&str]| {
+        keys.iter()
+            .find_map(|key| meta.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone()))
+    };
+
+    let link = |rel: &str| {
+        document
+            .select(&LINKS)
+            .find(|l| {
+                l.value()
+                    .attr("rel")
+                    .is_some_and(|r| r.split_whitespace().any(|r| r.eq_ignore_ascii_case(rel)))
+            })
+            .and_then(|l| l.value().attr("href"))
+            .and_then(|href| page.join(href.trim()).ok())
+            .map(|u| u.to_string())
+    };
+
+    let json_ld: Vec<Value> = document
+        .select(&JSON_LD)
+        .filter_map(|s| serde_json::from_str::<Value>(s.text().collect::<String>().trim()).ok())
+        .flat_map(|v| match v {
+            Value::Array(items) => items,
+            Value::Object(ref o) => match o.get("@graph") {
+                Some(Value::Array(items)) => items.clone(),
+                _ => vec![v],
+            },
+            other => vec![other],
+        })
+        .collect();
+    let ld_value = |keys: &[&str]| {
+        json_ld
+            .iter()
+            .find_map(|item| keys.iter().find_map(|key| item.get(*key).and_then(ld_name)))
+    };
+
+    let page_url = link("canonical")
+        .or_else(|| meta_value(&["og:url"]).and_then(|u| page.join(&u).ok()).map(|u| u.to_string()))
+        .unwrap_or_else(|| page.to_string());
+
+    let page_title = meta_value(&["og:title", "twitter:title"])
+        .or_else(|| document.select(&TITLE).next().map(text).filter(|t| !t.is_empty()));
+
+    let site_name = meta_value(&["og:site_name", "application-name"])
+        .or_else(|| json_ld.iter().find_map(|item| item.get("publisher").and_then(ld_name)))
+        .or_else(|| site_from_host(page));
+
+    let author = meta_value(&["author", "article:author", "dc.creator", "dcterms.creator", "twitter:creator"])
+        .or_else(|| ld_value(&["author", "creator"]))
+        .or_else(|| {
+            document
+                .select(&LINKS)
+                .find(|l| l.value().name() == "a" && l.value().attr("rel") == Some("author"))
+                .map(text)
+                .filter(|t| !t.is_empty())
+        });
+
+    let license = link("license")
+        .or_else(|| ld_value(&["license", "acquireLicensePage"]))
+        .or_else(|| meta_value(&["license", "dc.rights.license", "dcterms.license"]));
+
+    let copyright = meta_value(&["copyright", "dc.rights", "dcterms.rights", "rights"])
+        .or_else(|| ld_value(&["copyrightNotice", "creditText", "copyrightHolder"]));
+
+    SourceInfo {
+        source_url: source_url.to_string(),
+        page_url: Some(page_url),
+        page_title,
+        site_name,
+        author,
+        license,
+        copyright,
+        fetched_at,
+    }
+}
+
+/// A readable name from a JSON-LD value: the string itself, or the `name`
+/// (or `url`) of an object, or the first of a list.
+fn ld_name(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(clean(s)).filter(|s| !s.is_empty()),
+        Value::Array(items) => items.iter().find_map(ld_name),
+        Value::Object(o) => o.get("name").or_else(|| o.get("url")).or_else(|| o.get("@id")).and_then(ld_name),
+        _ => None,
+    }
+}
+
+fn site_from_host(url: &Url) -> Option<String> {
+    url.host_str().map(|h| h.trim_start_matches("www.").to_string())
+}
+
+fn text(element: ElementRef) -> String {
+    clean(&element.text().collect::<String>())
+}
+
+/// Collapses runs of whitespace.
+fn clean(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn asset_source(asset: &Asset) -> Option<SourceInfo> {
+    let source = asset.metadata.as_ref()?.get("source")?;
+    serde_json::from_value(source.clone()).ok()
+}
+
+/// The attribution for a layer or asset `id`: looked up on the board when
+/// given, falling back to the board's and then the library's asset with
+/// the same file.
+pub fn find(app: &impl DataDir, board_id: Option<u64>, id: f64) -> Result<Option<SourceInfo>, String> {
+    let library = database::load_all_assets(app)?;
+    let Some(board_id) = board_id else {
+        return Ok(library.iter().find(|a| a.id == id).and_then(asset_source));
+    };
+
+    let board = database::load_board(app, board_id)?;
+    let src = match board.layers.iter().find(|l| l.id == id) {
+        Some(layer) if layer.source.is_some() => return Ok(layer.source.clone()),
+        Some(layer) => layer.src.clone(),
+        None => match board.assets.iter().chain(&library).find(|a| a.id == id) {
+            Some(asset) => match asset_source(asset) {
+                Some(source) => return Ok(Some(source)),
+                None => asset.src.clone(),
+            },
+            None => return Err(format!("No layer or asset with id {}", id)),
+        },
+    };
+    Ok(board
+        .assets
+        .iter()
+        .chain(&library)
+        .filter(|a| a.src == src)
+        .find_map(asset_source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> String {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/pages").join(name);
+        std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+    }
+
+    #[test]
+    fn reads_attribution_from_meta_and_json_ld() {
+        let page = Url::parse("https://example.com/news/lighthouse?utm_source=feed").unwrap();
+        let source = from_page(&fixture("credits.html"), &page, "https://cdn.example.com/a.jpg", 42);
+
+        assert_eq!(source.source_url, "https://cdn.example.com/a.jpg");
+        assert_eq!(source.page_url.as_deref(), Some("https://example.com/news/lighthouse"));
+        assert_eq!(source.page_title.as_deref(), Some("Lighthouse keepers of the north coast"));
+        assert_eq!(source.site_name.as_deref(), Some("Example News"));
+        assert_eq!(source.author.as_deref(), Some("Mara Quinn"));
+        assert_eq!(source.license.as_deref(), Some("https://creativecommons.org/licenses/by/4.0/"));
+        assert_eq!(source.copyright.as_deref(), Some("© 2024 Example News"));
+        assert_eq!(source.fetched_at, 42);
+    }
+
+    #[test]
+    fn falls_back_to_title_and_host() {
+        let html = "<html><head><title>\n  Harbor   sketches </title></head><body></body></html>";
+        let page = Url::parse("https://www.sketches.test/harbor").unwrap();
+        let source = from_page(html, &page, "https://www.sketches.test/h.png", 1);
+
+        assert_eq!(source.page_url.as_deref(), Some("https://www.sketches.test/harbor"));
+        assert_eq!(source.page_title.as_deref(), Some("Harbor sketches"));
+        assert_eq!(source.site_name.as_deref(), Some("sketches.test"));
+        assert_eq!(source.author, None);
+        assert_eq!(source.license, None);
+    }
+
+    #[test]
+    fn json_ld_author_objects_and_lists() {
+        let html = r#"<script type="application/ld+json">{"@graph":[{"@type":"WebSite","name":"Gallery"},
+            {"@type":"VisualArtwork","creator":[{"@type":"Person","name":"Ines Ortega"}],"license":"https://example.org/terms"}]}</script>"#;
+        let page = Url::parse("https://gallery.test/work/7").unwrap();
+        let source = from_page(html, &page, "https://gallery.test/7.jpg", 1);
+
+        assert_eq!(source.author.as_deref(), Some("Ines Ortega"));
+        assert_eq!(source.license.as_deref(), Some("https://example.org/terms"));
+    }
+}
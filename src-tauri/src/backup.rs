@@ -0,0 +1,671 @@
+//! Whole-library backups: one zip holding every board (encrypted ones stay
+//! sealed), the media store, the asset library and tag presets, plus a
+//! `manifest.json` with the size and SHA-256 of each file. Caches aren't
+//! included; they rebuild themselves.
+//!
+//! Scheduled backups are named `eyedea-auto-<time>.zip` and only those are
+//! pruned, so backups made by hand are never deleted.
+
+use crate::crdt;
+use crate::database::{self, Asset, Board, BoardMetadata, DataDir};
+use crate::media_protocol;
+use crate::vault;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+const FORMAT_VERSION: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+
+/// Directories and files of the data directory that make up the library.
+const DIRS: &[&str] = &["boards", "images", "locked_media"];
+const FILES: &[&str] = &["all_assets.json", "tag_presets.json"];
+
+/// Snapshots retried when the library changes while being archived.
+const ATTEMPTS: usize = 3;
+
+/// How often the scheduler checks whether a backup is due.
+const SCHEDULE_CHECK: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestFile {
+    /// Relative to the data directory, with `/` separators.
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupManifest {
+    pub version: u32,
+    pub app_version: String,
+    pub created_at: u64,
+    /// Without thumbnails.
+    pub boards: Vec<BoardMetadata>,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupInfo {
+    pub path: String,
+    pub created_at: u64,
+    pub boards: usize,
+    pub files: usize,
+    /// Uncompressed size of the files.
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RestoreMode {
+    /// The library becomes exactly what's in the backup.
+    Replace,
+    /// Boards and media missing here are added; a board is only replaced
+    /// when the backup's copy is newer.
+    Merge,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreSummary {
+    pub mode: RestoreMode,
+    pub restored: Vec<u64>,
+    /// Boards kept because the copy here is as new or newer.
+    pub skipped: Vec<u64>,
+    pub media_added: usize,
+}
+
+/// A library file as it was when listed.
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    path: String,
+    size: u64,
+    modified: u128,
+}
+
+/// Hashes and counts what goes through to the archive.
+struct Tee<W> {
+    inner: W,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl<W: Write> Write for Tee<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Where scheduled backups go.
+pub fn backup_dir(app: &impl DataDir) -> PathBuf {
+    match database::load_backup_settings(app).ok().and_then(|s| s.directory) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => app.data_dir().join("backups"),
+    }
+}
+
+/// `YYYYMMDD-HHMMSS` in UTC.
+fn timestamp(millis: u64) -> String {
+    let secs = millis / 1000;
+    let (days, rem) = ((secs / 86400) as i64, secs % 86400);
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}{:02}{:02}-{:02}{:02}{:02}", year, month, day, rem / 3600, rem % 3600 / 60, rem % 60)
+}
+
+pub fn backup_file_name(scheduled: bool, millis: u64) -> String {
+    let kind = if scheduled { "auto" } else { "backup" };
+    format!("eyedea-{}-{}.zip", kind, timestamp(millis))
+}
+
+fn walk(root: &Path, rel: &str, entries: &mut Vec<Entry>) -> Result<(), String> {
+    let Ok(read) = fs::read_dir(root) else {
+        return Ok(());
+    };
+    for item in read.flatten() {
+        let name = item.file_name().to_string_lossy().to_string();
+        let rel = format!("{}/{}", rel, name);
+        let file_type = item.file_type().map_err(|e| e.to_string())?;
+        if file_type.is_dir() {
+            walk(&item.path(), &rel, entries)?;
+        } else if file_type.is_file() && !name.ends_with(".part") {
+            let metadata = item.metadata().map_err(|e| e.to_string())?;
+            let modified = metadata.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map_or(0, |d| d.as_nanos());
+            entries.push(Entry { path: rel, size: metadata.len(), modified });
+        }
+    }
+    Ok(())
+}
+
+/// Every library file, sorted by path.
+fn list_library(app: &impl DataDir) -> Result<Vec<Entry>, String> {
+    let data_dir = app.data_dir();
+    let mut entries = Vec::new();
+    for dir in DIRS {
+        walk(&data_dir.join(dir), dir, &mut entries)?;
+    }
+    for file in FILES {
+        if let Ok(metadata) = fs::metadata(data_dir.join(file)) {
+            let modified = metadata.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map_or(0, |d| d.as_nanos());
+            entries.push(Entry { path: file.to_string(), size: metadata.len(), modified });
+        }
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+/// List entry for a board file, plain or encrypted.
+fn board_metadata(path: &str, content: &[u8]) -> Option<BoardMetadata> {
+    let content = std::str::from_utf8(content).ok()?;
+    if path.ends_with(".locked") {
+        return vault::read_metadata(content);
+    }
+    let board: Board = serde_json::from_str(content).ok()?;
+    Some(BoardMetadata {
+        id: board.id,
+        name: board.name,
+        bg_color: board.bg_color,
+        created_at: board.created_at,
+        updated_at: board.updated_at,
+        thumbnail: None,
+        locked: false,
+        encrypted: false,
+    })
+}
+
+fn is_board_file(path: &str) -> bool {
+    path.strip_prefix("boards/")
+        .is_some_and(|name| !name.contains('/') && (name.ends_with(".json") || name.ends_with(".locked")))
+}
+
+fn write_archive(app: &impl DataDir, target: &Path, entries: &[Entry], created_at: u64) -> Result<BackupManifest, String> {
+    let data_dir = app.data_dir();
+    let mut zip = ZipWriter::new(fs::File::create(target).map_err(|e| e.to_string())?);
+    let mut manifest = BackupManifest {
+        version: FORMAT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at,
+        boards: Vec::new(),
+        files: Vec::new(),
+    };
+
+    for entry in entries {
+        // Media is already compressed
+        let method = if entry.path.ends_with(".json") || entry.path.ends_with(".locked") {
+            CompressionMethod::Deflated
+        } else {
+            CompressionMethod::Stored
+        };
+        zip.start_file(entry.path.as_str(), SimpleFileOptions::default().compression_method(method).large_file(entry.size > u32::MAX as u64))
+            .map_err(|e| e.to_string())?;
+        let mut tee = Tee { inner: &mut zip, hasher: Sha256::new(), size: 0 };
+        let mut file = fs::File::open(data_dir.join(&entry.path)).map_err(|e| format!("{}: {}", entry.path, e))?;
+        if is_board_file(&entry.path) {
+            let mut content = Vec::new();
+            file.read_to_end(&mut content).map_err(|e| e.to_string())?;
+            tee.write_all(&content).map_err(|e| e.to_string())?;
+            manifest.boards.extend(board_metadata(&entry.path, &content));
+        } else {
+            io::copy(&mut file, &mut tee).map_err(|e| e.to_string())?;
+        }
+        let (size, sha256) = (tee.size, hex(&tee.hasher.finalize()));
+        manifest.files.push(ManifestFile { path: entry.path.clone(), size, sha256 });
+    }
+
+    zip.start_file(MANIFEST, SimpleFileOptions::default()).map_err(|e| e.to_string())?;
+    let json = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+    zip.write_all(&json).map_err(|e| e.to_string())?;
+    zip.finish().map_err(|e| e.to_string())?.sync_all().map_err(|e| e.to_string())?;
+    Ok(manifest)
+}
+
+/// Writes a backup to `target`. The library is listed before and after;
+/// if anything changed meanwhile the backup is made again, so the archive
+/// is always one consistent state.
+pub fn create_backup(app: &impl DataDir, target: &Path) -> Result<BackupInfo, String> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let partial = target.with_extension("zip.part");
+    for _ in 0..ATTEMPTS {
+        let created_at = database::now_millis();
+        let before = list_library(app)?;
+        let result = write_archive(app, &partial, &before, created_at);
+        let manifest = match result {
+            Ok(manifest) if list_library(app)? == before => manifest,
+            Ok(_) => continue,
+            Err(e) => {
+                let _ = fs::remove_file(&partial);
+                return Err(e);
+            }
+        };
+        fs::rename(&partial, target).map_err(|e| e.to_string())?;
+        return Ok(BackupInfo {
+            path: target.to_string_lossy().to_string(),
+            created_at,
+            boards: manifest.boards.len(),
+            files: manifest.files.len(),
+            bytes: manifest.files.iter().map(|f| f.size).sum(),
+        });
+    }
+    let _ = fs::remove_file(&partial);
+    Err("The library kept changing during the backup; try again".to_string())
+}
+
+/// Whether a manifest path is a library file, never anything outside it.
+fn is_library_path(path: &str) -> bool {
+    let parts: Vec<Component> = Path::new(path).components().collect();
+    if path.contains('\\') || !parts.iter().all(|c| matches!(c, Component::Normal(_))) {
+        return false;
+    }
+    FILES.contains(&path) || DIRS.iter().any(|dir| path.strip_prefix(dir).is_some_and(|rest| rest.len() > 1 && rest.starts_with('/')))
+}
+
+/// Opens a backup and checks every file against the manifest.
+pub fn verify_backup(path: &Path) -> Result<(ZipArchive<fs::File>, BackupManifest), String> {
+    let file = fs::File::open(path).map_err(|e| format!("Failed to open backup: {}", e))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| format!("Not a backup archive: {}", e))?;
+    let manifest: BackupManifest = {
+        let mut entry = archive.by_name(MANIFEST).map_err(|_| "The backup has no manifest")?;
+        let mut json = Vec::new();
+        entry.read_to_end(&mut json).map_err(|e| e.to_string())?;
+        serde_json::from_slice(&json).map_err(|e| format!("Invalid backup manifest: {}", e))?
+    };
+    if manifest.version > FORMAT_VERSION {
+        return Err(format!("The backup was made by a newer version ({})", manifest.app_version));
+    }
+
+    let listed: HashSet<&str> = manifest.files.iter().map(|f| f.path.as_str()).collect();
+    if let Some(extra) = archive.file_names().find(|name| *name != MANIFEST && !listed.contains(name)) {
+        return Err(format!("The backup contains {} which its manifest doesn't list", extra));
+    }
+    for expected in &manifest.files {
+        if !is_library_path(&expected.path) {
+            return Err(format!("The backup contains an invalid path: {}", expected.path));
+        }
+        let mut entry = archive.by_name(&expected.path).map_err(|_| format!("The backup is missing {}", expected.path))?;
+        let mut tee = Tee { inner: io::sink(), hasher: Sha256::new(), size: 0 };
+        io::copy(&mut entry, &mut tee).map_err(|e| format!("{}: {}", expected.path, e))?;
+        if tee.size != expected.size || hex(&tee.hasher.finalize()) != expected.sha256 {
+            return Err(format!("The backup is damaged: {} doesn't match its checksum", expected.path));
+        }
+    }
+    Ok((archive, manifest))
+}
+
+fn extract(archive: &mut ZipArchive<fs::File>, path: &str, target: &Path) -> Result<(), String> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let mut entry = archive.by_name(path).map_err(|e| e.to_string())?;
+    let mut file = fs::File::create(target).map_err(|e| e.to_string())?;
+    io::copy(&mut entry, &mut file).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn read_entry(archive: &mut ZipArchive<fs::File>, path: &str) -> Result<Vec<u8>, String> {
+    let mut entry = archive.by_name(path).map_err(|e| e.to_string())?;
+    let mut content = Vec::new();
+    entry.read_to_end(&mut content).map_err(|e| e.to_string())?;
+    Ok(content)
+}
+
+/// Verifies a backup, then restores it.
+pub fn restore_backup(app: &impl DataDir, path: &Path, mode: RestoreMode) -> Result<RestoreSummary, String> {
+    let (mut archive, manifest) = verify_backup(path)?;
+    // Unlocked boards may be about to change underneath their sessions
+    vault::lock_all(app);
+    let summary = match mode {
+        RestoreMode::Replace => replace(app, &mut archive, &manifest)?,
+        RestoreMode::Merge => merge(app, &mut archive, &manifest)?,
+    };
+    // Variants and the hash index are keyed by filename, which may now
+    // name different content
+    let data_dir = app.data_dir();
+    let _ = fs::remove_dir_all(data_dir.join("media_variants"));
+    media_protocol::clear_hash_index(app);
+    // Change histories aren't backed up; a stale one would bring back what
+    // the restore replaced when merged with another copy
+    match mode {
+        RestoreMode::Replace => {
+            let _ = fs::remove_dir_all(data_dir.join("crdt"));
+        }
+        RestoreMode::Merge => summary.restored.iter().for_each(|id| crdt::forget(app, *id)),
+    }
+    database::init_storage(app)?;
+    Ok(summary)
+}
+
+fn replace(app: &impl DataDir, archive: &mut ZipArchive<fs::File>, manifest: &BackupManifest) -> Result<RestoreSummary, String> {
+    let data_dir = app.data_dir();
+    let stamp = database::now_millis();
+    let staging = data_dir.join(format!("restore-{}.part", stamp));
+    let previous = data_dir.join(format!("restore-{}.previous", stamp));
+
+    let staged = (|| {
+        for file in &manifest.files {
+            extract(archive, &file.path, &staging.join(&file.path))?;
+        }
+        Ok::<_, String>(())
+    })();
+    if let Err(e) = staged {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+
+    // Move the current library aside, then the staged one in; put the old
+    // one back if any move fails
+    fs::create_dir_all(&previous).map_err(|e| e.to_string())?;
+    let mut moved = Vec::new();
+    let swapped = DIRS.iter().chain(FILES).try_for_each(|name| {
+        if data_dir.join(name).exists() {
+            fs::rename(data_dir.join(name), previous.join(name)).map_err(|e| e.to_string())?;
+            moved.push(*name);
+        }
+        if staging.join(name).exists() {
+            fs::rename(staging.join(name), data_dir.join(name)).map_err(|e| e.to_string())?;
+        }
+        Ok::<_, String>(())
+    });
+    if let Err(e) = swapped {
+        for name in DIRS.iter().chain(FILES) {
+            let _ = fs::remove_dir_all(data_dir.join(name));
+            let _ = fs::remove_file(data_dir.join(name));
+        }
+        for name in moved {
+            let _ = fs::rename(previous.join(name), data_dir.join(name));
+        }
+        let _ = fs::remove_dir_all(&staging);
+        return Err(format!("Failed to replace the library: {}", e));
+    }
+    let _ = fs::remove_dir_all(&staging);
+    let _ = fs::remove_dir_all(&previous);
+
+    Ok(RestoreSummary {
+        mode: RestoreMode::Replace,
+        restored: manifest.boards.iter().map(|b| b.id).collect(),
+        skipped: Vec::new(),
+        media_added: manifest.files.iter().filter(|f| f.path.starts_with("images/")).count(),
+    })
+}
+
+fn merge(app: &impl DataDir, archive: &mut ZipArchive<fs::File>, manifest: &BackupManifest) -> Result<RestoreSummary, String> {
+    let data_dir = app.data_dir();
+    let local: HashMap<u64, u64> = database::load_all_boards(app)?.into_iter().map(|b| (b.id, b.updated_at)).collect();
+    let mut summary = RestoreSummary { mode: RestoreMode::Merge, restored: Vec::new(), skipped: Vec::new(), media_added: 0 };
+
+    for file in manifest.files.iter().filter(|f| is_board_file(&f.path)) {
+        let content = read_entry(archive, &file.path)?;
+        let Some(board) = board_metadata(&file.path, &content) else {
+            continue;
+        };
+        if local.get(&board.id).is_some_and(|updated_at| *updated_at >= board.updated_at) {
+            summary.skipped.push(board.id);
+            continue;
+        }
+        if local.contains_key(&board.id) {
+            database::delete_board(app, board.id)?;
+        }
+        if file.path.ends_with(".locked") {
+            let sealed_media = format!("locked_media/{}/", board.id);
+            for media in manifest.files.iter().filter(|f| f.path.starts_with(&sealed_media)) {
+                extract(archive, &media.path, &data_dir.join(&media.path))?;
+            }
+        }
+        fs::write(data_dir.join(&file.path), &content).map_err(|e| e.to_string())?;
+        summary.restored.push(board.id);
+    }
+
+    for file in manifest.files.iter().filter(|f| f.path.starts_with("images/")) {
+        let target = data_dir.join(&file.path);
+        if !target.exists() {
+            extract(archive, &file.path, &target)?;
+            summary.media_added += 1;
+        }
+    }
+
+    if manifest.files.iter().any(|f| f.path == "all_assets.json") {
+        let backed_up: Vec<Asset> = serde_json::from_slice(&read_entry(archive, "all_assets.json")?).map_err(|e| e.to_string())?;
+        let mut assets = database::load_all_assets(app)?;
+        let known: HashSet<u64> = assets.iter().map(|a| a.id.to_bits()).collect();
+        let missing: Vec<Asset> = backed_up.into_iter().filter(|a| !known.contains(&a.id.to_bits())).collect();
+        if !missing.is_empty() {
+            assets.extend(missing);
+            let content = serde_json::to_string_pretty(&assets).map_err(|e| e.to_string())?;
+            fs::write(data_dir.join("all_assets.json"), content).map_err(|e| e.to_string())?;
+        }
+    }
+
+    if manifest.files.iter().any(|f| f.path == "tag_presets.json") {
+        let backed_up: Vec<String> = serde_json::from_slice(&read_entry(archive, "tag_presets.json")?).map_err(|e| e.to_string())?;
+        let mut presets = database::load_tag_presets(app)?;
+        let missing: Vec<String> = backed_up.into_iter().filter(|p| !presets.contains(p)).collect();
+        if !missing.is_empty() {
+            presets.extend(missing);
+            database::save_tag_presets(app, presets)?;
+        }
+    }
+    Ok(summary)
+}
+
+/// Scheduled backups in `dir`, newest first.
+fn scheduled_backups(dir: &Path) -> Vec<PathBuf> {
+    let mut backups: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.path())
+                .filter(|p| p.file_name().is_some_and(|n| {
+                    let n = n.to_string_lossy();
+                    n.starts_with("eyedea-auto-") && n.ends_with(".zip")
+                }))
+                .collect()
+        })
+        .unwrap_or_default();
+    // The timestamp in the name sorts chronologically
+    backups.sort();
+    backups.reverse();
+    backups
+}
+
+/// Makes a scheduled backup if one is due and prunes old ones. Returns the
+/// backup made, if any.
+pub fn run_scheduled(app: &impl DataDir, now: u64) -> Result<Option<BackupInfo>, String> {
+    let settings = database::load_backup_settings(app)?;
+    if !settings.enabled {
+        return Ok(None);
+    }
+    let dir = backup_dir(app);
+    let interval = u64::from(settings.interval_hours.max(1)) * 3600 * 1000;
+    let last = scheduled_backups(&dir)
+        .first()
+        .and_then(|p| fs::metadata(p).ok())
+        .and_then(|m| m.modified().ok())
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64);
+    if last.is_some_and(|last| now.saturating_sub(last) < interval) {
+        return Ok(None);
+    }
+
+    let info = create_backup(app, &dir.join(backup_file_name(true, now)))?;
+    for old in scheduled_backups(&dir).into_iter().skip(settings.keep.max(1)) {
+        let _ = fs::remove_file(old);
+    }
+    Ok(Some(info))
+}
+
+/// Checks for due backups in the background for as long as the app runs,
+/// emitting `backup-completed` or `backup-failed`.
+pub fn start_scheduler(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        match run_scheduled(&app, database::now_millis()) {
+            Ok(Some(info)) => {
+                let _ = app.emit("backup-completed", info);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                log::error!("Scheduled backup failed: {}", e);
+                let _ = app.emit("backup-failed", e);
+            }
+        }
+        std::thread::sleep(SCHEDULE_CHECK);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data_dir(label: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("eyedea-backup-{}-{}", label, database::random_token().unwrap()));
+        database::init_storage(&dir).unwrap();
+        dir
+    }
+
+    fn board(dir: &PathBuf, id: u64, name: &str, updated_at: u64) -> Board {
+        let mut board = database::new_board(name.to_string(), "#ffffff".to_string());
+        board.id = id;
+        board.updated_at = updated_at;
+        database::save_board(dir, &board).unwrap();
+        board
+    }
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(timestamp(0), "19700101-000000");
+        assert_eq!(timestamp(1_709_251_199_000), "20240229-235959");
+        assert_eq!(backup_file_name(true, 1_000), "eyedea-auto-19700101-000001.zip");
+    }
+
+    #[test]
+    fn backs_up_and_restores() {
+        let source = data_dir("source");
+        board(&source, 1, "Moodboard", 100);
+        let src = database::write_media_file(&database::get_images_dir(&source), b"pixels", "ref", "png").unwrap();
+        database::save_tag_presets(&source, vec!["wood".to_string()]).unwrap();
+        let archive = source.join("out").join("backup.zip");
+
+        let info = create_backup(&source, &archive).unwrap();
+        assert_eq!(info.boards, 1);
+        let (_, manifest) = verify_backup(&archive).unwrap();
+        assert!(manifest.files.iter().any(|f| f.path == format!("images/{}", src)));
+
+        // Merging keeps the newer local board and adds what's missing
+        let target = data_dir("target");
+        board(&target, 1, "Moodboard", 200);
+        board(&target, 2, "Local only", 50);
+        let summary = restore_backup(&target, &archive, RestoreMode::Merge).unwrap();
+        assert_eq!(summary.skipped, vec![1]);
+        assert_eq!(summary.media_added, 1);
+        assert_eq!(database::load_board(&target, 1).unwrap().updated_at, 200);
+        assert_eq!(database::load_tag_presets(&target).unwrap(), vec!["wood".to_string()]);
+
+        board(&source, 1, "Moodboard", 300);
+        create_backup(&source, &archive).unwrap();
+        let history = |id: u64| target.join("crdt").join(format!("{}.automerge", id));
+        for id in [1, 2] {
+            crdt::record_board(&target, &database::load_board(&target, id).unwrap()).unwrap();
+        }
+        let summary = restore_backup(&target, &archive, RestoreMode::Merge).unwrap();
+        assert_eq!(summary.restored, vec![1]);
+        assert!(!history(1).exists() && history(2).exists());
+        assert_eq!(database::load_board(&target, 1).unwrap().updated_at, 300);
+        assert_eq!(database::load_all_boards(&target).unwrap().len(), 2);
+
+        // Replacing drops what the backup doesn't have
+        restore_backup(&target, &archive, RestoreMode::Replace).unwrap();
+        assert!(!history(2).exists());
+        let boards = database::load_all_boards(&target).unwrap();
+        assert_eq!(boards.iter().map(|b| b.id).collect::<Vec<_>>(), vec![1]);
+        assert!(database::get_images_dir(&target).join(&src).is_file());
+
+        fs::remove_dir_all(&source).unwrap();
+        fs::remove_dir_all(&target).unwrap();
+    }
+
+    #[test]
+    fn rejects_damaged_backups() {
+        let source = data_dir("damaged");
+        board(&source, 1, "Moodboard", 100);
+        let archive = source.join("backup.zip");
+        create_backup(&source, &archive).unwrap();
+
+        let (mut zip, mut manifest) = verify_backup(&archive).unwrap();
+        let path = manifest.files[0].path.clone();
+        let content = read_entry(&mut zip, &path).unwrap();
+        manifest.files[0].sha256 = hex(&Sha256::digest(b"something else"));
+
+        let tampered = source.join("tampered.zip");
+        let mut writer = ZipWriter::new(fs::File::create(&tampered).unwrap());
+        writer.start_file(path.as_str(), SimpleFileOptions::default()).unwrap();
+        writer.write_all(&content).unwrap();
+        writer.start_file(MANIFEST, SimpleFileOptions::default()).unwrap();
+        writer.write_all(&serde_json::to_vec(&manifest).unwrap()).unwrap();
+        writer.finish().unwrap();
+
+        let error = restore_backup(&source, &tampered, RestoreMode::Replace).unwrap_err();
+        assert!(error.contains("checksum"), "{}", error);
+        assert_eq!(database::load_all_boards(&source).unwrap().len(), 1);
+        fs::remove_dir_all(&source).unwrap();
+    }
+
+    #[test]
+    fn prunes_old_scheduled_backups() {
+        let dir = data_dir("schedule");
+        let backups = dir.join("backups");
+        database::save_backup_settings(&dir, &database::BackupSettings {
+            enabled: true,
+            interval_hours: 1,
+            keep: 2,
+            directory: Some(backups.to_string_lossy().to_string()),
+        })
+        .unwrap();
+        fs::create_dir_all(&backups).unwrap();
+        for name in ["eyedea-auto-20200101-000000.zip", "eyedea-auto-20200102-000000.zip", "eyedea-backup-20200101-000000.zip"] {
+            fs::write(backups.join(name), b"old").unwrap();
+            let file = fs::File::options().write(true).open(backups.join(name)).unwrap();
+            file.set_modified(UNIX_EPOCH + Duration::from_secs(1_577_836_800)).unwrap();
+        }
+
+        let made = run_scheduled(&dir, database::now_millis()).unwrap().unwrap();
+        let left: Vec<String> = scheduled_backups(&backups).iter().map(|p| p.file_name().unwrap().to_string_lossy().to_string()).collect();
+        assert_eq!(left.len(), 2);
+        assert!(made.path.ends_with(&left[0]));
+        assert_eq!(left[1], "eyedea-auto-20200102-000000.zip");
+        assert!(backups.join("eyedea-backup-20200101-000000.zip").exists());
+        // Not due again yet
+        assert!(run_scheduled(&dir, database::now_millis()).unwrap().is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,411 @@
+//! Opt-in HTTP endpoint on 127.0.0.1 that lets browser extensions and
+//! scripts push media into the library or a board.
+//!
+//! Every route except `GET /ping` needs the token from the capture settings
+//! in an `X-EyeDea-Token` header or as `Authorization: Bearer <token>`.
+//! Requests from web pages are refused; only browser extensions, and
+//! clients that send no `Origin`, get through.
+//!
+//! `POST /capture` takes either a JSON [`CaptureRequest`] or raw media bytes
+//! with the same fields as query parameters (`tags` comma-separated).
+
+use crate::attribution;
+use crate::database::{self, Asset, CaptureSettings, DataDir};
+use crate::events;
+use crate::fetch;
+use crate::import;
+use crate::pool::Pool;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Manager};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+const MAX_BODY: u64 = 200 * 1024 * 1024;
+
+/// Threads handling requests that got past the token check.
+const HANDLER_THREADS: usize = 4;
+
+const EXTENSION_ORIGINS: &[&str] = &["chrome-extension://", "moz-extension://", "safari-web-extension://"];
+
+/// The running server, held in Tauri state.
+#[derive(Default)]
+pub struct CaptureState {
+    server: Mutex<Option<Arc<Server>>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CaptureRequest {
+    /// Media URL to download.
+    pub url: Option<String>,
+    /// Base64 media, bare or as a data URL.
+    pub data: Option<String>,
+    pub name: Option<String>,
+    /// Board to add the media to; the library when absent.
+    pub board_id: Option<u64>,
+    pub tags: Vec<String>,
+    /// Page the media was captured from.
+    pub source_url: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Captured {
+    pub board_id: Option<u64>,
+    pub asset: Asset,
+}
+
+/// Starts or stops the server to match the saved settings.
+pub fn apply_settings(app: &AppHandle) -> Result<(), String> {
+    stop(app);
+    let settings = database::load_capture_settings(app)?;
+    if settings.enabled {
+        start(app, &settings)?;
+    }
+    Ok(())
+}
+
+fn start(app: &AppHandle, settings: &CaptureSettings) -> Result<(), String> {
+    let server = Server::http(("127.0.0.1", settings.port))
+        .map_err(|e| format!("Failed to start capture server on port {}: {}", settings.port, e))?;
+    let server = Arc::new(server);
+
+    let state = app.state::<CaptureState>();
+    *state.server.lock().unwrap() = Some(server.clone());
+
+    let app = app.clone();
+    let pool = Pool::new("capture", HANDLER_THREADS);
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            // Refused before the body is read or a handler is taken
+            if let Err(refused) = screen(&app, &request) {
+                respond(request, Err(refused));
+                continue;
+            }
+            let app = app.clone();
+            pool.run(move || handle(&app, request));
+        }
+    });
+    Ok(())
+}
+
+pub fn stop(app: &AppHandle) {
+    let state = app.state::<CaptureState>();
+    let server = state.server.lock().unwrap().take();
+    if let Some(server) = server {
+        server.unblock();
+    }
+}
+
+fn request_url(request: &Request) -> Option<reqwest::Url> {
+    reqwest::Url::parse(&format!("http://127.0.0.1{}", request.url())).ok()
+}
+
+/// Checks the origin and token from the headers alone.
+fn screen(app: &impl DataDir, request: &Request) -> Result<(), (u16, String)> {
+    if header(request, "Origin").is_some_and(|origin| !is_extension_origin(origin)) {
+        return Err((403, "Origin not allowed".to_string()));
+    }
+    let path = request_url(request).map(|u| u.path().to_string()).unwrap_or_default();
+    match (request.method(), path.as_str()) {
+        (Method::Options, _) | (Method::Get, "/ping") => Ok(()),
+        _ if authorized(app, request) => Ok(()),
+        _ => Err((401, "Missing or invalid token".to_string())),
+    }
+}
+
+fn is_extension_origin(origin: &str) -> bool {
+    EXTENSION_ORIGINS.iter().any(|scheme| origin.starts_with(scheme))
+}
+
+fn handle(app: &AppHandle, mut request: Request) {
+    let url = request_url(&request);
+    let path = url.as_ref().map(|u| u.path().to_string()).unwrap_or_default();
+
+    let result = match (request.method(), path.as_str()) {
+        (Method::Options, _) => Ok(serde_json::Value::Null),
+        (Method::Get, "/ping") => Ok(serde_json::json!({
+            "app": "EyeDea",
+            "version": app.package_info().version.to_string(),
+        })),
+        (Method::Get, "/boards") => database::load_all_boards(app)
+            .map(|boards| {
+                boards
+                    .iter()
+                    .map(|b| serde_json::json!({ "id": b.id, "name": b.name }))
+                    .collect()
+            })
+            .map_err(|e| (500, e)),
+        (Method::Post, "/capture") => read_capture(&mut request, url.as_ref())
+            .and_then(|(capture, body)| {
+                capture_media(app, capture, body).map_err(|e| (422, e))
+            })
+            .and_then(|captured| serde_json::to_value(captured).map_err(|e| (500, e.to_string()))),
+        _ => Err((404, "Not found".to_string())),
+    };
+    respond(request, result);
+}
+
+fn respond(request: Request, result: Result<serde_json::Value, (u16, String)>) {
+    let (status, body) = match result {
+        Ok(value) => (200, value),
+        Err((status, error)) => (status, serde_json::json!({ "error": error })),
+    };
+    let mut response = Response::from_string(body.to_string()).with_status_code(status);
+    // Only extensions are told they may read the response
+    let origin = header(&request, "Origin").filter(|o| is_extension_origin(o)).unwrap_or_default().to_string();
+    for (name, value) in [
+        ("Content-Type", "application/json"),
+        ("Access-Control-Allow-Origin", origin.as_str()),
+        ("Vary", "Origin"),
+        ("Access-Control-Allow-Methods", "GET, POST, OPTIONS"),
+        ("Access-Control-Allow-Headers", "Content-Type, Authorization, X-EyeDea-Token"),
+    ] {
+        if value.is_empty() {
+            continue;
+        }
+        if let Ok(header) = Header::from_bytes(name.as_bytes(), value.as_bytes()) {
+            response.add_header(header);
+        }
+    }
+    let _ = request.respond(response);
+}
+
+fn header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv(name))
+        .map(|h| h.value.as_str())
+}
+
+fn authorized(app: &impl DataDir, request: &Request) -> bool {
+    let Ok(settings) = database::load_capture_settings(app) else {
+        return false;
+    };
+    let given = header(request, "X-EyeDea-Token")
+        .or_else(|| header(request, "Authorization").and_then(|v| v.strip_prefix("Bearer ")))
+        .unwrap_or_default()
+        .trim();
+    // Compare without bailing at the first differing byte
+    given.len() == settings.token.len()
+        && given
+            .bytes()
+            .zip(settings.token.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Raw media bytes and their content type, when the body is the media.
+type Body = Option<(Vec<u8>, Option<String>)>;
+
+/// Parses a capture from a JSON body or from raw bytes plus query fields.
+fn read_capture(request: &mut Request, url: Option<&reqwest::Url>) -> Result<(CaptureRequest, Body), (u16, String)> {
+    if request.body_length().is_some_and(|len| len as u64 > MAX_BODY) {
+        return Err((413, "Body too large".to_string()));
+    }
+    let content_type = header(request, "Content-Type").map(|v| v.to_string());
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(MAX_BODY + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| (400, e.to_string()))?;
+    if body.len() as u64 > MAX_BODY {
+        return Err((413, "Body too large".to_string()));
+    }
+
+    if content_type.as_deref().is_some_and(|t| t.starts_with("application/json")) {
+        let capture = serde_json::from_slice(&body).map_err(|e| (400, format!("Invalid JSON: {}", e)))?;
+        return Ok((capture, None));
+    }
+
+    let mut capture = CaptureRequest::default();
+    for (key, value) in url.into_iter().flat_map(|u| u.query_pairs()) {
+        match key.as_ref() {
+            "name" => capture.name = Some(value.to_string()),
+            "boardId" => capture.board_id = value.parse().ok(),
+            "tags" => {
+                capture.tags = value
+                    .split(',')
+                    .map(|t| t.trim().to_string())
+                    .filter(|t| !t.is_empty())
+                    .collect()
+            }
+            "sourceUrl" => capture.source_url = Some(value.to_string()),
+            "url" => capture.url = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    let raw = (!body.is_empty()).then_some((body, content_type));
+    Ok((capture, raw))
+}
+
+/// Saves captured media and adds it to the target board or the library,
+/// notifying open windows.
+pub fn capture_media(app: &AppHandle, capture: CaptureRequest, body: Body) -> Result<Captured, String> {
+    let (bytes, content_type) = match (body, &capture.data, &capture.url) {
+        (Some(body), _, _) => body,
+        (None, Some(data), _) => decode_data(data)?,
+        (None, None, Some(url)) => {
+            let fetched = fetch::fetch(app, url, fetch::Expect::Media, Some(url))?;
+            (fetched.bytes, fetched.content_type)
+        }
+        (None, None, None) => return Err("Nothing to capture: send media bytes, data or url".to_string()),
+    };
+
+    let name = capture
+        .name
+        .clone()
+        .or_else(|| capture.url.as_deref().and_then(import::url_file_name))
+        .unwrap_or_else(|| "capture".to_string());
+    let ext = import::media_extension(content_type.as_deref(), &name, &bytes)
+        .or_else(|| capture.url.as_deref().and_then(|u| import::media_extension(None, u, &bytes)))
+        .ok_or("Unsupported media type")?;
+
+    let images_dir = database::get_images_dir(app);
+    let mut layer = import::import_bytes(&images_dir, &bytes, &name, ext)?;
+    let media_url = capture.url.as_deref().filter(|u| u.starts_with("http")).or(capture.source_url.as_deref());
+    layer.source = media_url.map(|url| attribution::describe(app, url, capture.source_url.as_deref()));
+    let metadata = import::asset_for(&layer, Vec::new()).metadata;
+
+    match capture.board_id {
+        Some(board_id) => {
+            let board = database::load_board(app, board_id)?;
+            let mut board = import::add_layers_at_view(board, vec![layer]);
+            let Some(added) = board.layers.last().cloned() else {
+                return Err("Failed to add layer".to_string());
+            };
+            let Some(asset) = board.assets.last_mut() else {
+                return Err("Failed to add asset".to_string());
+            };
+            asset.tags = capture.tags;
+            asset.metadata = metadata;
+            let asset = asset.clone();
+            board.updated_at = database::now_millis();
+            database::save_board(app, &board)?;
+
+            events::board_updated(app, &board, vec![added], None);
+            Ok(Captured { board_id: Some(board_id), asset })
+        }
+        None => {
+            let asset = Asset {
+                id: database::new_ids(1),
+                name: layer.name,
+                src: layer.src,
+                tags: capture.tags,
+                metadata,
+            };
+            database::add_assets_to_library(app, vec![asset.clone()])?;
+            events::asset_library_changed(app, vec![asset.clone()], Vec::new(), None);
+            Ok(Captured { board_id: None, asset })
+        }
+    }
+}
+
+fn decode_data(data: &str) -> Result<(Vec<u8>, Option<String>), String> {
+    let (content_type, encoded) = match data.strip_prefix("data:").and_then(|rest| rest.split_once(',')) {
+        Some((meta, encoded)) => (meta.split(';').next().map(|t| t.to_string()), encoded),
+        None => (None, data),
+    };
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .map_err(|e| format!("Base64 decode error: {}", e))?;
+    Ok((bytes, content_type))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+    use std::net::{Shutdown, TcpStream};
+    use std::path::PathBuf;
+
+    fn data_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("eyedea-capture-{}", database::random_token().unwrap()));
+        database::init_storage(&dir).unwrap();
+        dir
+    }
+
+    /// Sends `head` (request line and headers) and `body` to a local server
+    /// and returns the request as the server sees it, with the client's
+    /// connection to read the response from. The client stops writing, so
+    /// a body left unread ends early instead of blocking the request's drop.
+    fn send(head: &str, body: &[u8]) -> (Request, TcpStream) {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(server.server_addr().to_ip().unwrap()).unwrap();
+        client.write_all(format!("{}\r\nHost: 127.0.0.1\r\n\r\n", head).as_bytes()).unwrap();
+        client.write_all(body).unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+        (server.recv().unwrap(), client)
+    }
+
+    #[test]
+    fn refuses_bad_tokens_before_reading_the_body() {
+        let dir = data_dir();
+        let token = database::load_capture_settings(&dir).unwrap().token;
+        // The body announced is never sent, so only the headers can be checked
+        let pending = "POST /capture HTTP/1.1\r\nContent-Type: image/png\r\nContent-Length: 1000000";
+
+        let (missing, mut client) = send(pending, b"");
+        assert_eq!(screen(&dir, &missing).unwrap_err().0, 401);
+        respond(missing, Err((401, "Missing or invalid token".to_string())));
+        let mut reply = [0u8; 12];
+        client.read_exact(&mut reply).unwrap();
+        assert_eq!(&reply, b"HTTP/1.1 401");
+
+        let (wrong, _client) = send(&format!("{}\r\nX-EyeDea-Token: {}0", pending, &token[1..]), b"");
+        assert_eq!(screen(&dir, &wrong).unwrap_err().0, 401);
+        let (web_page, _client) = send(&format!("{}\r\nX-EyeDea-Token: {}\r\nOrigin: https://example.com", pending, token), b"");
+        assert_eq!(screen(&dir, &web_page).unwrap_err().0, 403);
+
+        let (bearer, _client) = send(&format!("{}\r\nAuthorization: Bearer {}", pending, token), b"");
+        assert!(screen(&dir, &bearer).is_ok());
+        let (ping, _client) = send("GET /ping HTTP/1.1", b"");
+        assert!(screen(&dir, &ping).is_ok());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn reads_json_and_raw_captures() {
+        let json = r#"{"data":"aGk=","boardId":7,"tags":["a"]}"#;
+        let (mut request, _client) =
+            send(&format!("POST /capture HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}", json.len()), json.as_bytes());
+        let (capture, body) = read_capture(&mut request, None).unwrap();
+        assert_eq!((capture.data.as_deref(), capture.board_id, capture.tags), (Some("aGk="), Some(7), vec!["a".to_string()]));
+        assert!(body.is_none());
+
+        let (mut request, _client) = send("POST /capture HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: 5", b"{nope");
+        assert_eq!(read_capture(&mut request, None).unwrap_err().0, 400);
+
+        let (mut request, _client) = send("POST /capture?name=shot&tags=a,%20b,,&boardId=3 HTTP/1.1\r\nContent-Type: image/png\r\nContent-Length: 4", b"\x89PNG");
+        let url = request_url(&request);
+        let (capture, body) = read_capture(&mut request, url.as_ref()).unwrap();
+        assert_eq!((capture.name.as_deref(), capture.board_id), (Some("shot"), Some(3)));
+        assert_eq!(capture.tags, vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(body, Some((b"\x89PNG".to_vec(), Some("image/png".to_string()))));
+
+        let (mut request, _client) = send(&format!("POST /capture HTTP/1.1\r\nContent-Length: {}", MAX_BODY + 1), b"");
+        assert_eq!(read_capture(&mut request, None).unwrap_err().0, 413);
+    }
+
+    #[test]
+    fn decodes_bare_and_data_url_payloads() {
+        assert_eq!(decode_data("aGk=").unwrap(), (b"hi".to_vec(), None));
+        assert_eq!(decode_data("data:image/png;base64, aGk=\n").unwrap(), (b"hi".to_vec(), Some("image/png".to_string())));
+        assert!(decode_data("data:image/png;base64,not base64!").is_err());
+        assert!(decode_data("%%%").is_err());
+    }
+
+    #[test]
+    fn only_extensions_are_allowed_origins() {
+        assert!(is_extension_origin("chrome-extension://abcdefghijklmnop"));
+        assert!(is_extension_origin("moz-extension://0b5a7c1e-2f6d-4a8e-9c3b-1d2e3f4a5b6c"));
+        assert!(!is_extension_origin("https://example.com"));
+        assert!(!is_extension_origin("http://127.0.0.1:5173"));
+        assert!(!is_extension_origin("null"));
+    }
+}
@@ -0,0 +1,441 @@
+//! Headless command-line mode. Works directly on the app data directory,
+//! so it can script the same boards the app shows without opening a window.
+
+use crate::database::{self, Asset, Board};
+use crate::import::{self, ImportLayout};
+use crate::pdf::{self, PdfOptions};
+use crate::render::{self, RenderOptions};
+use crate::url_import;
+use base64::Engine;
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Bundle identifier from tauri.conf.json; Tauri keeps app data under it.
+const IDENTIFIER: &str = "net.eyeda.eye";
+
+const SUBCOMMANDS: &[&str] = &["boards", "layers", "create", "import", "export", "search", "help"];
+
+#[derive(Parser)]
+#[command(name = "eyedea", version, about = "Work with EyeDea boards from the command line")]
+struct Cli {
+    /// App data directory. Defaults to the one the app uses.
+    #[arg(long, global = true, env = "EYEDEA_DATA_DIR")]
+    data_dir: Option<PathBuf>,
+    /// Print results as JSON.
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List boards.
+    Boards,
+    /// Show a board's layers.
+    Layers { board: String },
+    /// Create an empty board.
+    Create {
+        name: String,
+        #[arg(long, default_value = "#ffffff")]
+        bg_color: String,
+    },
+    /// Import files, folders or URLs into a board.
+    Import {
+        board: String,
+        #[arg(required = true)]
+        sources: Vec<String>,
+        #[arg(long, value_enum, default_value = "grid")]
+        layout: Layout,
+        /// Cell size for the grid, row height for the packed layout.
+        #[arg(long, default_value_t = 400.0)]
+        size: f64,
+        /// Don't descend into subfolders.
+        #[arg(long)]
+        no_recursive: bool,
+        /// Also add the media to the asset library, tagged with subfolder names.
+        #[arg(long)]
+        library: bool,
+    },
+    /// Export a board as a JSON bundle (.eyed/.json), PNG, JPEG or PDF.
+    Export {
+        board: String,
+        output: PathBuf,
+        /// Defaults to the output file's extension.
+        #[arg(long, value_enum)]
+        format: Option<ExportFormat>,
+        /// Output pixels per board unit for images, print scale for PDF.
+        #[arg(long)]
+        scale: Option<f64>,
+        #[arg(long)]
+        transparent: bool,
+    },
+    /// Search the asset library by name and tags.
+    Search {
+        query: Option<String>,
+        /// Only assets with this tag; repeat to require several.
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Layout {
+    Grid,
+    Packed,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum ExportFormat {
+    Json,
+    Png,
+    Jpg,
+    Pdf,
+}
+
+/// Runs a command when the first argument names one. Returns the exit code,
+/// or `None` when the app should start normally.
+pub fn run_if_requested() -> Option<i32> {
+    let first = std::env::args().nth(1)?;
+    let wants_cli = SUBCOMMANDS.contains(&first.as_str())
+        || matches!(first.as_str(), "--help" | "-h" | "--version" | "-V" | "--data-dir" | "--json");
+    if !wants_cli {
+        return None;
+    }
+    attach_console();
+
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            return Some(e.exit_code());
+        }
+    };
+    match run(cli) {
+        Ok(()) => Some(0),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            Some(1)
+        }
+    }
+}
+
+/// Release builds use the Windows GUI subsystem, which has no console of
+/// its own; borrow the one the command was run from.
+fn attach_console() {
+    #[cfg(windows)]
+    {
+        extern "system" {
+            fn AttachConsole(process_id: u32) -> i32;
+        }
+        const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+        unsafe {
+            AttachConsole(ATTACH_PARENT_PROCESS);
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), String> {
+    let data_dir = match cli.data_dir {
+        Some(dir) => dir,
+        None => dirs::data_dir()
+            .ok_or("Could not find the user data directory; pass --data-dir")?
+            .join(IDENTIFIER),
+    };
+    database::init_storage(&data_dir)?;
+    let json = cli.json;
+
+    match cli.command {
+        Command::Boards => {
+            let mut boards = database::load_all_boards(&data_dir)?;
+            boards.sort_by_key(|b| std::cmp::Reverse(b.updated_at));
+            if json {
+                return print_json(&boards);
+            }
+            for board in boards {
+                println!("{}\t{}", board.id, board.name);
+            }
+        }
+        Command::Layers { board } => {
+            let board = find_board(&data_dir, &board)?;
+            if json {
+                return print_json(&board.layers);
+            }
+            let mut layers: Vec<_> = board.layers.iter().collect();
+            layers.sort_by(|a, b| a.z_index.total_cmp(&b.z_index));
+            for layer in layers {
+                println!(
+                    "{}\t{}\t{}x{} at ({}, {})\t{}",
+                    layer.id,
+                    layer.name,
+                    layer.width.round(),
+                    layer.height.round(),
+                    layer.x.round(),
+                    layer.y.round(),
+                    layer.media_type.as_deref().unwrap_or("image"),
+                );
+            }
+        }
+        Command::Create { name, bg_color } => {
+            let board = database::new_board(name, bg_color);
+            database::save_board(&data_dir, &board)?;
+            if json {
+                return print_json(&board);
+            }
+            println!("{}\t{}", board.id, board.name);
+        }
+        Command::Import { board, sources, layout, size, no_recursive, library } => {
+            let board = find_board(&data_dir, &board)?;
+            let layout = match layout {
+                Layout::Grid => ImportLayout::Grid,
+                Layout::Packed => ImportLayout::Packed,
+            };
+            let summary = import_sources(&data_dir, board, &sources, layout, size, !no_recursive, library)?;
+            if json {
+                return print_json(&summary);
+            }
+            println!("Imported {} item(s) into {}", summary.imported, summary.board);
+            for failure in &summary.failed {
+                eprintln!("failed: {}", failure);
+            }
+        }
+        Command::Export { board, output, format, scale, transparent } => {
+            let board = find_board(&data_dir, &board)?;
+            let images_dir = database::get_images_dir(&data_dir);
+            let format = match format {
+                Some(format) => format,
+                None => format_from_path(&output)?,
+            };
+            match format {
+                ExportFormat::Json => {
+                    let bundle = board_bundle(&board, &images_dir);
+                    let content = serde_json::to_string_pretty(&bundle).map_err(|e| e.to_string())?;
+                    std::fs::write(&output, content).map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+                }
+                ExportFormat::Png | ExportFormat::Jpg => {
+                    let options = RenderOptions { scale, transparent, ..Default::default() };
+                    render::export_board_image(&board, &images_dir, &options, &output, None)?;
+                }
+                ExportFormat::Pdf => {
+                    let options = PdfOptions { scale, transparent, ..Default::default() };
+                    pdf::export_board_pdf(&board, &images_dir, &options, &output)?;
+                }
+            }
+            if !json {
+                println!("Exported {} to {}", board.name, output.display());
+            }
+        }
+        Command::Search { query, tags } => {
+            let assets = search_assets(database::load_all_assets(&data_dir)?, query.as_deref(), &tags);
+            if json {
+                return print_json(&assets);
+            }
+            for asset in assets {
+                println!("{}\t{}\t{}", asset.id, asset.name, asset.tags.join(", "));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_json(value: &impl Serialize) -> Result<(), String> {
+    let text = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    println!("{}", text);
+    Ok(())
+}
+
+/// A board by id, or by name when unambiguous.
+fn find_board(data_dir: &PathBuf, key: &str) -> Result<Board, String> {
+    if let Ok(id) = key.parse::<u64>() {
+        if let Ok(board) = database::load_board(data_dir, id) {
+            return Ok(board);
+        }
+    }
+    let boards = database::load_all_boards(data_dir)?;
+    let matches: Vec<_> = boards.iter().filter(|b| b.name.eq_ignore_ascii_case(key)).collect();
+    match matches.as_slice() {
+        [board] => database::load_board(data_dir, board.id),
+        [] => Err(format!("No board named or numbered {:?}", key)),
+        _ => Err(format!("Several boards are named {:?}; use the id", key)),
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ImportSummary {
+    board: String,
+    board_id: u64,
+    imported: usize,
+    failed: Vec<String>,
+}
+
+fn import_sources(
+    data_dir: &PathBuf,
+    board: Board,
+    sources: &[String],
+    layout: ImportLayout,
+    size: f64,
+    recursive: bool,
+    library: bool,
+) -> Result<ImportSummary, String> {
+    let images_dir = database::get_images_dir(data_dir);
+    let mut layers = Vec::new();
+    let mut tags = Vec::new();
+    let mut failed = Vec::new();
+
+    for source in sources {
+        if source.starts_with("http://") || source.starts_with("https://") {
+            let imported = url_import::download(data_dir, &images_dir, source, |_| {}).map(|(layer, _)| layer);
+            match imported {
+                Ok(layer) => {
+                    layers.push(layer);
+                    tags.push(Vec::new());
+                }
+                Err(e) => failed.push(format!("{}: {}", source, e)),
+            }
+            continue;
+        }
+
+        let path = Path::new(source);
+        let files = if path.is_dir() {
+            import::collect_media(path, recursive)?
+        } else {
+            vec![path.to_path_buf()]
+        };
+        for file in files {
+            match import::import_file(&images_dir, &file) {
+                Ok(layer) => {
+                    layers.push(layer);
+                    tags.push(if path.is_dir() { import::folder_tags(path, &file) } else { Vec::new() });
+                }
+                Err(e) => failed.push(format!("{}: {}", file.display(), e)),
+            }
+        }
+    }
+
+    let imported = layers.len();
+    let mut board = import::add_layers(board, layers, layout, size, 20.0, None);
+    board.updated_at = database::now_millis();
+    database::save_board(data_dir, &board)?;
+
+    if library && imported > 0 {
+        let added = &board.layers[board.layers.len() - imported..];
+        let assets = added
+            .iter()
+            .zip(tags)
+            .map(|(layer, tags)| import::asset_for(layer, tags))
+            .collect();
+        database::add_assets_to_library(data_dir, assets)?;
+    }
+
+    Ok(ImportSummary { board: board.name, board_id: board.id, imported, failed })
+}
+
+fn format_from_path(path: &Path) -> Result<ExportFormat, String> {
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "json" | "eyed" => Ok(ExportFormat::Json),
+        "png" => Ok(ExportFormat::Png),
+        "jpg" | "jpeg" => Ok(ExportFormat::Jpg),
+        "pdf" => Ok(ExportFormat::Pdf),
+        _ => Err(format!("Can't tell the export format from {}; pass --format", path.display())),
+    }
+}
+
+/// The editor's `.eyed` export format, with media embedded as data URLs so
+/// the file stands alone.
+fn board_bundle(board: &Board, images_dir: &Path) -> serde_json::Value {
+    let mut embedded: HashMap<String, String> = HashMap::new();
+    let mut embed = |src: &str| -> String {
+        if src.starts_with("data:") || src.contains("://") {
+            return src.to_string();
+        }
+        embedded
+            .entry(src.to_string())
+            .or_insert_with(|| match database::media_path(images_dir, src).and_then(|path| std::fs::read(path).map_err(|e| e.to_string())) {
+                Ok(bytes) => {
+                    let mime = mime_for(src);
+                    let data = base64::engine::general_purpose::STANDARD.encode(bytes);
+                    format!("data:{};base64,{}", mime, data)
+                }
+                Err(_) => src.to_string(),
+            })
+            .clone()
+    };
+
+    let layers: Vec<serde_json::Value> = board
+        .layers
+        .iter()
+        .map(|layer| {
+            let mut value = serde_json::to_value(layer).unwrap_or_default();
+            value["src"] = serde_json::Value::String(embed(&layer.src));
+            value
+        })
+        .collect();
+    let assets: Vec<serde_json::Value> = board
+        .assets
+        .iter()
+        .map(|asset| {
+            let mut value = serde_json::to_value(asset).unwrap_or_default();
+            value["src"] = serde_json::Value::String(embed(&asset.src));
+            value
+        })
+        .collect();
+
+    serde_json::json!({
+        "version": 1,
+        "name": board.name,
+        "bgColor": board.bg_color,
+        "layers": layers,
+        "groups": board.groups.clone().unwrap_or_else(|| serde_json::json!([])),
+        "assets": assets,
+        "strokes": board.strokes.clone().unwrap_or_else(|| serde_json::json!([])),
+        "objects": board.objects.clone().unwrap_or_else(|| serde_json::json!([])),
+        "exportedAt": database::now_millis(),
+    })
+}
+
+fn mime_for(src: &str) -> &'static str {
+    let ext = Path::new(src)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "bmp" => "image/bmp",
+        "mp4" => "video/mp4",
+        "mov" => "video/quicktime",
+        "webm" => "video/webm",
+        _ => "image/png",
+    }
+}
+
+/// Assets whose name or tags contain every word of the query and that
+/// carry all of the given tags, case-insensitively.
+fn search_assets(assets: Vec<Asset>, query: Option<&str>, tags: &[String]) -> Vec<Asset> {
+    let words: Vec<String> = query
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(|w| w.to_lowercase())
+        .collect();
+    let tags: Vec<String> = tags.iter().map(|t| t.to_lowercase()).collect();
+    assets
+        .into_iter()
+        .filter(|asset| {
+            let asset_tags: Vec<String> = asset.tags.iter().map(|t| t.to_lowercase()).collect();
+            let name = asset.name.to_lowercase();
+            tags.iter().all(|t| asset_tags.contains(t))
+                && words
+                    .iter()
+                    .all(|w| name.contains(w.as_str()) || asset_tags.iter().any(|t| t.contains(w.as_str())))
+        })
+        .collect()
+}
@@ -0,0 +1,1260 @@
+//! Real-time collaboration on one board over the local network.
+//!
+//! One instance hosts a session: a WebSocket server that others join with
+//! its address and a 12-letter code such as `7KQ2-M9XD-04TB`. Edits
+//! travel as [`Op`]s on layers, strokes, objects and groups (and the
+//! board's name and color), which the host relays to everybody else. Every
+//! op carries a Lamport [`Stamp`] and each element and field keeps the
+//! value with the highest stamp, ties broken by peer id, so every replica
+//! converges whatever order ops arrive in. Media a peer lacks is fetched
+//! from the host, which in turn fetches it from whoever added it.
+//!
+//! Sessions also share presence and cursors, which aren't stored.
+
+use crate::crdt;
+use crate::database::{self, Board, DataDir, Layer};
+use crate::events;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{ErrorKind, Read};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use tungstenite::protocol::WebSocketConfig;
+use tungstenite::{Message as Frame, WebSocket};
+
+/// How long sockets wait for data before checking for outgoing messages.
+const POLL: Duration = Duration::from_millis(20);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often edits are written to the board file.
+const SAVE_EVERY: Duration = Duration::from_millis(500);
+/// Larger files aren't streamed to peers.
+const MAX_MEDIA: u64 = 128 * 1024 * 1024;
+/// Board fields ops may set, besides the element collections.
+const BOARD_FIELDS: [&str; 2] = ["name", "bgColor"];
+/// How far ahead of a replica's clock an op's may be. Honest peers stay
+/// close, so further means a bad peer trying to win every edit or run the
+/// clock out.
+const MAX_CLOCK_AHEAD: u64 = 1 << 32;
+/// Wrong codes a session takes before its code is replaced.
+const MAX_WRONG_CODES: usize = 5;
+/// Connections the host serves at once, joined or still handshaking.
+const MAX_CONNECTIONS: usize = 32;
+/// Join code letters: Crockford's base32, without ones easily misread.
+const CODE_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const PEER_COLORS: [&str; 8] = ["#e5484d", "#3e63dd", "#30a46c", "#f76b15", "#8e4ec6", "#12a594", "#d6409f", "#978365"];
+
+/// Where session events go: emitted to the windows in the app.
+pub type Emit = Arc<dyn Fn(&str, Value) + Send + Sync>;
+
+/// The session this instance hosts or joined, held in Tauri state.
+#[derive(Default)]
+pub struct CollabState {
+    session: Mutex<Option<Session<AppHandle>>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Collection {
+    /// The board itself; only patches of [`BOARD_FIELDS`] apply.
+    Board,
+    Layers,
+    Strokes,
+    Objects,
+    Groups,
+}
+
+impl Collection {
+    pub(crate) const ELEMENTS: [Collection; 4] = [Collection::Layers, Collection::Strokes, Collection::Objects, Collection::Groups];
+
+    pub(crate) fn field(self) -> &'static str {
+        match self {
+            Collection::Board => "",
+            Collection::Layers => "layers",
+            Collection::Strokes => "strokes",
+            Collection::Objects => "objects",
+            Collection::Groups => "groups",
+        }
+    }
+}
+
+/// Orders ops: by Lamport clock, then by the peer that made them.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Stamp {
+    pub clock: u64,
+    pub peer: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Change {
+    /// Adds or replaces a whole element.
+    Put { value: Value },
+    /// Sets some fields of an element, or of the board.
+    Patch { fields: Map<String, Value> },
+    Remove,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Op {
+    /// Set when the op is sent; anything the frontend passes is replaced.
+    #[serde(default)]
+    pub stamp: Stamp,
+    pub collection: Collection,
+    /// The element's `id`; unused for the board.
+    #[serde(default)]
+    pub id: Value,
+    pub change: Change,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Peer {
+    pub id: String,
+    pub name: String,
+    pub color: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    pub board_id: u64,
+    pub host: bool,
+    /// `host:port` to join at; only known to the host.
+    pub address: Option<String>,
+    pub code: Option<String>,
+    pub me: Peer,
+    pub peers: Vec<Peer>,
+    /// False once the host ended the session or the connection dropped.
+    pub active: bool,
+}
+
+/// Payload of `collab-op`, sent when an op from another peer changed the
+/// board.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteOp {
+    pub board_id: u64,
+    pub op: Op,
+    /// The element after merging, `null` when it's gone; for the board, the
+    /// fields set in the session.
+    pub value: Value,
+}
+
+/// Payload of `collab-cursor`, in board coordinates.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Cursor {
+    pub peer_id: String,
+    pub x: f64,
+    pub y: f64,
+}
+
+/// What peers send each other.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Message {
+    Hello { code: String, name: String },
+    /// The host's replica, stamps and all, so the joiner merges later ops
+    /// exactly as the host does.
+    Welcome { me: Peer, snapshot: Box<Snapshot>, peers: Vec<Peer> },
+    Rejected { reason: String },
+    Op { op: Op },
+    Cursor { cursor: Cursor },
+    PeerJoined { peer: Peer },
+    PeerLeft { peer_id: String },
+    NeedMedia { src: String },
+    Media { src: String, data: String },
+    Ended,
+}
+
+/// One element's state: a base value from the latest put or remove, and
+/// fields patched since.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Element {
+    /// Where the element sorts: its index on the starting board, or the
+    /// stamp of its first put.
+    order: Option<(Stamp, usize)>,
+    base: Stamp,
+    /// `None` when removed, or patched before it was put.
+    value: Option<Value>,
+    fields: HashMap<String, (Stamp, Value)>,
+}
+
+impl Element {
+    fn apply(&mut self, stamp: &Stamp, id: &Value, change: &Change) -> Result<bool, String> {
+        match change {
+            Change::Put { value } => {
+                let Value::Object(map) = value else {
+                    return Err("An element must be an object".to_string());
+                };
+                let order = (stamp.clone(), 0);
+                if self.order.as_ref().is_none_or(|o| order < *o) {
+                    self.order = Some(order);
+                }
+                if *stamp <= self.base {
+                    return Ok(false);
+                }
+                let mut map = map.clone();
+                map.insert("id".to_string(), id.clone());
+                self.base = stamp.clone();
+                self.value = Some(Value::Object(map));
+                Ok(true)
+            }
+            Change::Patch { fields } => Ok(patch(&mut self.fields, stamp, fields.iter().filter(|(name, _)| *name != "id"))),
+            Change::Remove => {
+                if *stamp <= self.base {
+                    return Ok(false);
+                }
+                self.base = stamp.clone();
+                self.value = None;
+                Ok(true)
+            }
+        }
+    }
+
+    fn value(&self) -> Option<Value> {
+        let mut value = self.value.clone()?;
+        if let Value::Object(map) = &mut value {
+            for (name, (stamp, field)) in &self.fields {
+                if *stamp > self.base {
+                    map.insert(name.clone(), field.clone());
+                }
+            }
+        }
+        Some(value)
+    }
+}
+
+/// Sets each field whose stamp is older than `stamp`.
+fn patch<'a>(
+    target: &mut HashMap<String, (Stamp, Value)>,
+    stamp: &Stamp,
+    fields: impl Iterator<Item = (&'a String, &'a Value)>,
+) -> bool {
+    let mut changed = false;
+    for (name, value) in fields {
+        if target.get(name).is_none_or(|(current, _)| stamp > current) {
+            target.insert(name.clone(), (stamp.clone(), value.clone()));
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Element ids as keys, so `1` from the frontend matches `1.0` in Rust.
+pub(crate) fn key(id: &Value) -> String {
+    match id {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.as_f64().map(|f| f.to_string()).unwrap_or_else(|| n.to_string()),
+        other => other.to_string(),
+    }
+}
+
+/// A board as a set of last-writer-wins elements.
+pub struct Replica {
+    initial: Board,
+    fields: HashMap<String, (Stamp, Value)>,
+    elements: HashMap<(Collection, String), Element>,
+    clock: u64,
+}
+
+/// A [`Replica`] as sent to joining peers; its size follows the board's,
+/// not how long the session has run.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Snapshot {
+    board: Board,
+    fields: HashMap<String, (Stamp, Value)>,
+    elements: Vec<(Collection, String, Element)>,
+    clock: u64,
+}
+
+impl Replica {
+    pub fn new(board: Board) -> Result<Self, String> {
+        let json = serde_json::to_value(&board).map_err(|e| e.to_string())?;
+        let mut elements = HashMap::new();
+        for collection in Collection::ELEMENTS {
+            let Some(Value::Array(items)) = json.get(collection.field()) else {
+                continue;
+            };
+            for (index, item) in items.iter().enumerate() {
+                let Some(id) = item.get("id") else {
+                    continue;
+                };
+                let element = Element {
+                    order: Some((Stamp::default(), index)),
+                    value: Some(item.clone()),
+                    ..Element::default()
+                };
+                elements.insert((collection, key(id)), element);
+            }
+        }
+        Ok(Replica { initial: board, fields: HashMap::new(), elements, clock: 0 })
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            board: self.initial.clone(),
+            fields: self.fields.clone(),
+            elements: self.elements.iter().map(|((collection, key), element)| (*collection, key.clone(), element.clone())).collect(),
+            clock: self.clock,
+        }
+    }
+
+    fn from_snapshot(snapshot: Snapshot) -> Self {
+        Replica {
+            initial: snapshot.board,
+            fields: snapshot.fields,
+            elements: snapshot.elements.into_iter().map(|(collection, key, element)| ((collection, key), element)).collect(),
+            clock: snapshot.clock,
+        }
+    }
+
+    fn stamp(&mut self, peer: &str) -> Stamp {
+        self.clock = self.clock.saturating_add(1);
+        Stamp { clock: self.clock, peer: peer.to_string() }
+    }
+
+    /// Applies an op; false when newer edits already cover it.
+    pub fn apply(&mut self, op: &Op) -> Result<bool, String> {
+        if op.stamp.clock > self.clock.saturating_add(MAX_CLOCK_AHEAD) {
+            return Err("The op's clock is too far ahead".to_string());
+        }
+        let changed = match op.collection {
+            Collection::Board => {
+                let Change::Patch { fields } = &op.change else {
+                    return Err("Only patches apply to the board".to_string());
+                };
+                if let Some((name, _)) = fields.iter().find(|(name, value)| !BOARD_FIELDS.contains(&name.as_str()) || !value.is_string()) {
+                    return Err(format!("Can't set board field {}", name));
+                }
+                patch(&mut self.fields, &op.stamp, fields.iter())
+            }
+            collection => {
+                let key = (collection, key(&op.id));
+                let mut element = self.elements.get(&key).cloned().unwrap_or_default();
+                let changed = element.apply(&op.stamp, &op.id, &op.change)?;
+                if let (Collection::Layers, Some(value)) = (collection, element.value()) {
+                    serde_json::from_value::<Layer>(value).map_err(|e| format!("Invalid layer: {}", e))?;
+                }
+                self.elements.insert(key, element);
+                changed
+            }
+        };
+        self.clock = self.clock.max(op.stamp.clock);
+        Ok(changed)
+    }
+
+    /// Stored files the board's layers refer to now.
+    fn media(&self) -> HashSet<String> {
+        self.elements
+            .iter()
+            .filter(|((collection, _), _)| *collection == Collection::Layers)
+            .filter_map(|(_, element)| element.value())
+            .flat_map(|value| {
+                ["src", "originalSrc"].map(|field| value.get(field).and_then(Value::as_str).map(String::from))
+            })
+            .flatten()
+            .collect()
+    }
+
+    fn current(&self, collection: Collection, id: &Value) -> Value {
+        match collection {
+            Collection::Board => Value::Object(self.fields.iter().map(|(name, (_, value))| (name.clone(), value.clone())).collect()),
+            collection => self.elements.get(&(collection, key(id))).and_then(Element::value).unwrap_or(Value::Null),
+        }
+    }
+
+    /// An op setting what `op` touched to where it settled here, for the
+    /// board's change history.
+    fn settled(&self, op: &Op) -> Op {
+        let change = match (op.collection, self.current(op.collection, &op.id)) {
+            (Collection::Board, Value::Object(fields)) => Change::Patch { fields },
+            (_, Value::Null) => Change::Remove,
+            (_, value) => Change::Put { value },
+        };
+        Op { stamp: op.stamp.clone(), collection: op.collection, id: op.id.clone(), change }
+    }
+
+    /// The replica as a board, taking everything else from `onto`.
+    pub fn export(&self, onto: &Board) -> Result<Board, String> {
+        let mut json = serde_json::to_value(onto).map_err(|e| e.to_string())?;
+        let board = json.as_object_mut().ok_or("Invalid board")?;
+        for collection in Collection::ELEMENTS {
+            let mut items: Vec<_> = self
+                .elements
+                .iter()
+                .filter(|((c, _), _)| *c == collection)
+                .filter_map(|((_, key), element)| Some((element.order.clone()?, key, element.value()?)))
+                .collect();
+            items.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+            board.insert(collection.field().to_string(), Value::Array(items.into_iter().map(|(_, _, v)| v).collect()));
+        }
+        for (name, (_, value)) in &self.fields {
+            board.insert(name.clone(), value.clone());
+        }
+        serde_json::from_value(json).map_err(|e| e.to_string())
+    }
+}
+
+/// Media an op refers to, stored files and URLs alike.
+fn op_media(op: &Op) -> Vec<&str> {
+    let get = |field: &str| match &op.change {
+        Change::Put { value } => value.get(field),
+        Change::Patch { fields } => fields.get(field),
+        Change::Remove => None,
+    };
+    if op.collection != Collection::Layers {
+        return Vec::new();
+    }
+    ["src", "originalSrc"]
+        .into_iter()
+        .filter_map(|field| get(field)?.as_str())
+        .filter(|src| !src.is_empty())
+        .collect()
+}
+
+fn frame(message: &Message) -> Frame {
+    Frame::text(serde_json::to_string(message).unwrap_or_default())
+}
+
+fn socket_config() -> WebSocketConfig {
+    // Media goes as base64 in one message
+    let limit = (MAX_MEDIA as usize) * 4 / 3 + 64 * 1024;
+    WebSocketConfig::default().max_message_size(Some(limit)).max_frame_size(Some(limit))
+}
+
+fn read_message(socket: &mut WebSocket<TcpStream>) -> Result<Message, String> {
+    loop {
+        match socket.read().map_err(|e| e.to_string())? {
+            Frame::Text(text) => return serde_json::from_str(text.as_str()).map_err(|e| e.to_string()),
+            Frame::Close(_) => return Err("Connection closed".to_string()),
+            _ => {}
+        }
+    }
+}
+
+/// A join code such as `7KQ2-M9XD-04TB`, 60 random bits.
+fn new_code() -> Result<String, String> {
+    let token = database::random_token()?;
+    let letters: Vec<char> = (0..12)
+        .map(|i| u8::from_str_radix(&token[i * 2..i * 2 + 2], 16).map(|b| CODE_ALPHABET[usize::from(b) % 32] as char))
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(letters.chunks(4).map(|group| group.iter().collect::<String>()).collect::<Vec<_>>().join("-"))
+}
+
+/// A code as typed: case and separators don't matter.
+fn normalize_code(code: &str) -> Vec<u8> {
+    code.bytes().filter(u8::is_ascii_alphanumeric).map(|b| b.to_ascii_uppercase()).collect()
+}
+
+/// Compares codes without stopping at the first differing letter.
+fn same_code(given: &str, code: &str) -> bool {
+    let (given, code) = (normalize_code(given), normalize_code(code));
+    given.len() == code.len() && given.iter().zip(&code).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// The address other machines on the network reach this one at. Connecting
+/// a UDP socket sends nothing; it only picks the outgoing interface.
+fn lan_address() -> String {
+    UdpSocket::bind(("0.0.0.0", 0))
+        .and_then(|socket| {
+            socket.connect(("192.0.2.1", 9))?;
+            socket.local_addr()
+        })
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|_| "127.0.0.1".to_string())
+}
+
+struct Shared<D> {
+    app: D,
+    emit: Emit,
+    board_id: u64,
+    host: bool,
+    code: Mutex<String>,
+    /// Wrong codes given since the code was last replaced.
+    wrong_codes: AtomicUsize,
+    connections: AtomicUsize,
+    me: Peer,
+    peers: Mutex<Vec<Peer>>,
+    replica: Mutex<Replica>,
+    /// Outgoing messages per connection: one per peer for the host, one
+    /// keyed `""` to the host for a guest.
+    links: Mutex<HashMap<String, Sender<Message>>>,
+    /// Media the host was asked for before it arrived, and by whom.
+    waiting: Mutex<HashMap<String, HashSet<String>>>,
+    requested: Mutex<HashSet<String>>,
+    joined: AtomicUsize,
+    /// Edits settled since the board was last saved.
+    unsaved: Mutex<Vec<Op>>,
+    stop: AtomicBool,
+}
+
+impl<D: DataDir + Send + Sync + 'static> Shared<D> {
+    fn images_dir(&self) -> std::path::PathBuf {
+        database::get_images_dir(&self.app)
+    }
+
+    fn send_to(&self, peer_id: &str, message: Message) {
+        if let Some(link) = self.links.lock().unwrap().get(peer_id) {
+            let _ = link.send(message);
+        }
+    }
+
+    fn broadcast(&self, except: Option<&str>, message: &Message) {
+        for (peer_id, link) in self.links.lock().unwrap().iter() {
+            if except != Some(peer_id.as_str()) {
+                let _ = link.send(message.clone());
+            }
+        }
+    }
+
+    fn emit_presence(&self) {
+        let peers = self.peers.lock().unwrap().clone();
+        (self.emit)("collab-presence", serde_json::json!({ "boardId": self.board_id, "peers": peers }));
+    }
+
+    /// `board-updated`, for windows not taking part in the session.
+    fn emit_saved(&self, board: &Board) {
+        let event = events::BoardUpdated::new(board, Vec::new(), None);
+        (self.emit)("board-updated", serde_json::to_value(event).unwrap_or_default());
+    }
+
+    /// Applies the session's edits to the board's change history, so edits
+    /// saved outside the session meanwhile are merged rather than replaced.
+    fn save(&self) {
+        let ops = std::mem::take(&mut *self.unsaved.lock().unwrap());
+        if ops.is_empty() {
+            return;
+        }
+        match crdt::apply_ops(&self.app, self.board_id, &ops) {
+            Ok(board) => self.emit_saved(&board),
+            Err(e) => log::error!("Failed to save shared board {}: {}", self.board_id, e),
+        }
+    }
+
+    fn settle(&self, replica: &Replica, op: &Op) {
+        self.unsaved.lock().unwrap().push(replica.settled(op));
+    }
+
+    fn has_media(&self, src: &str) -> bool {
+        database::media_path(&self.images_dir(), src).is_ok_and(|path| path.is_file())
+    }
+
+    /// Asks `from` for stored media this instance lacks.
+    fn request_media<'a>(&self, from: &str, srcs: impl IntoIterator<Item = &'a str>) {
+        for src in srcs.into_iter().filter(|src| database::is_stored_media(src)) {
+            if !self.has_media(src) && self.requested.lock().unwrap().insert(src.to_string()) {
+                self.send_to(from, Message::NeedMedia { src: src.to_string() });
+            }
+        }
+    }
+
+    fn read_media(&self, src: &str) -> Option<String> {
+        let path = database::media_path(&self.images_dir(), src).ok()?;
+        let mut file = fs::File::open(path).ok()?;
+        if file.metadata().ok()?.len() > MAX_MEDIA {
+            log::warn!("Not sharing {}: too large", src);
+            return None;
+        }
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).ok()?;
+        Some(base64::engine::general_purpose::STANDARD.encode(bytes))
+    }
+
+    /// Whether media arriving from a peer is something this instance asked
+    /// for, or, on the host, something a guest is waiting for.
+    fn expects_media(&self, src: &str) -> bool {
+        self.requested.lock().unwrap().contains(src)
+            || (self.host && self.waiting.lock().unwrap().contains_key(src) && self.replica.lock().unwrap().media().contains(src))
+    }
+
+    fn store_media(&self, src: &str, data: &str) -> Result<(), String> {
+        let path = database::media_path(&self.images_dir(), src)?;
+        if path.exists() {
+            return Ok(());
+        }
+        if data.len() as u64 / 4 * 3 > MAX_MEDIA {
+            return Err("Too large".to_string());
+        }
+        let bytes = base64::engine::general_purpose::STANDARD.decode(data).map_err(|e| e.to_string())?;
+        crate::import::sniff_media_type(&bytes)
+            .and_then(|mime| crate::import::media_extension(Some(mime), src, &bytes))
+            .ok_or("Not a supported media file")?;
+        let partial = path.with_extension(format!("{}.part", database::now_millis()));
+        fs::write(&partial, bytes).map_err(|e| e.to_string())?;
+        fs::rename(&partial, &path).map_err(|e| e.to_string())
+    }
+
+    /// Handles a message from a peer; `from` is its id on the host and
+    /// `""` (the host) on a guest.
+    fn receive(&self, from: &str, message: Message) {
+        match message {
+            Message::Op { mut op } => {
+                if self.host {
+                    op.stamp.peer = from.to_string();
+                }
+                let images_dir = self.images_dir();
+                if let Err(e) = database::check_media_srcs(&images_dir, op_media(&op)) {
+                    log::warn!("Ignoring op from {}: {}", from, e);
+                    return;
+                }
+                let mut replica = self.replica.lock().unwrap();
+                let changed = match replica.apply(&op) {
+                    Ok(changed) => changed,
+                    Err(e) => {
+                        log::warn!("Ignoring op from {}: {}", from, e);
+                        return;
+                    }
+                };
+                let value = replica.current(op.collection, &op.id);
+                if changed {
+                    self.settle(&replica, &op);
+                }
+                drop(replica);
+                if self.host {
+                    self.broadcast(Some(from), &Message::Op { op: op.clone() });
+                }
+                self.request_media(from, op_media(&op));
+                if changed {
+                    let remote = RemoteOp { board_id: self.board_id, op, value };
+                    (self.emit)("collab-op", serde_json::to_value(remote).unwrap_or_default());
+                }
+            }
+            Message::Cursor { mut cursor } => {
+                if self.host {
+                    cursor.peer_id = from.to_string();
+                    self.broadcast(Some(from), &Message::Cursor { cursor: cursor.clone() });
+                }
+                (self.emit)("collab-cursor", serde_json::to_value(cursor).unwrap_or_default());
+            }
+            Message::PeerJoined { peer } if !self.host => {
+                self.peers.lock().unwrap().push(peer);
+                self.emit_presence();
+            }
+            Message::PeerLeft { peer_id } if !self.host => {
+                self.peers.lock().unwrap().retain(|p| p.id != peer_id);
+                self.emit_presence();
+            }
+            // Only the board's own media is handed out, not anything stored
+            Message::NeedMedia { src } if !self.replica.lock().unwrap().media().contains(&src) => {
+                log::warn!("Not sharing {} with {}: not on the board", src, from);
+            }
+            Message::NeedMedia { src } => match self.read_media(&src) {
+                Some(data) => self.send_to(from, Message::Media { src, data }),
+                None if self.host => {
+                    self.waiting.lock().unwrap().entry(src).or_default().insert(from.to_string());
+                }
+                None => {}
+            },
+            Message::Media { src, .. } if !self.expects_media(&src) => {
+                log::warn!("Ignoring media {} from {}: not requested", src, from);
+            }
+            Message::Media { src, data } => {
+                if let Err(e) = self.store_media(&src, &data) {
+                    log::warn!("Failed to store shared media {}: {}", src, e);
+                    return;
+                }
+                self.requested.lock().unwrap().remove(&src);
+                let waiting = self.waiting.lock().unwrap().remove(&src).unwrap_or_default();
+                for peer_id in waiting {
+                    self.send_to(&peer_id, Message::Media { src: src.clone(), data: data.clone() });
+                }
+                (self.emit)("collab-media", serde_json::json!({ "boardId": self.board_id, "src": src }));
+            }
+            Message::Ended if !self.host => {
+                self.stop.store(true, Ordering::SeqCst);
+                (self.emit)("collab-ended", serde_json::json!({ "boardId": self.board_id }));
+            }
+            _ => {}
+        }
+    }
+
+    /// Moves messages both ways on one connection until it closes or the
+    /// session stops.
+    fn pump(&self, from: &str, mut socket: WebSocket<TcpStream>, outbox: Receiver<Message>) {
+        loop {
+            if self.stop.load(Ordering::SeqCst) {
+                if self.host {
+                    let _ = socket.send(frame(&Message::Ended));
+                }
+                let _ = socket.close(None);
+                let _ = socket.flush();
+                return;
+            }
+            while let Ok(message) = outbox.try_recv() {
+                if socket.send(frame(&message)).is_err() {
+                    return;
+                }
+            }
+            match socket.read() {
+                Ok(Frame::Text(text)) => match serde_json::from_str(text.as_str()) {
+                    Ok(message) => self.receive(from, message),
+                    Err(e) => log::warn!("Invalid message from {}: {}", from, e),
+                },
+                Ok(Frame::Close(_)) => return,
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(e)) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(_) => return,
+            }
+        }
+    }
+
+    /// Checks a joining peer's code. After a few wrong ones the code is
+    /// replaced, so it can't be guessed, and the host is told the new one
+    /// through `collab-code-changed`.
+    fn check_code(&self, given: &str) -> Result<bool, String> {
+        let mut code = self.code.lock().unwrap();
+        if same_code(given, &code) {
+            return Ok(true);
+        }
+        if self.wrong_codes.fetch_add(1, Ordering::SeqCst) + 1 >= MAX_WRONG_CODES {
+            *code = new_code()?;
+            self.wrong_codes.store(0, Ordering::SeqCst);
+            log::warn!("Replaced the join code for board {} after {} wrong ones", self.board_id, MAX_WRONG_CODES);
+            (self.emit)("collab-code-changed", serde_json::json!({ "boardId": self.board_id, "code": *code }));
+        }
+        Ok(false)
+    }
+
+    /// Runs one joining peer's connection on the host.
+    fn serve(&self, stream: TcpStream) -> Result<(), String> {
+        stream.set_nonblocking(false).map_err(|e| e.to_string())?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).map_err(|e| e.to_string())?;
+        let mut socket = tungstenite::accept_with_config(stream, Some(socket_config())).map_err(|e| e.to_string())?;
+        let Message::Hello { code, name } = read_message(&mut socket)? else {
+            return Err("Expected a hello".to_string());
+        };
+        if !self.check_code(&code)? {
+            let _ = socket.send(frame(&Message::Rejected { reason: "Wrong code".to_string() }));
+            let _ = socket.close(None);
+            return Err("Wrong code".to_string());
+        }
+        socket.get_ref().set_read_timeout(Some(POLL)).map_err(|e| e.to_string())?;
+
+        let index = self.joined.fetch_add(1, Ordering::SeqCst) + 1;
+        let peer = Peer {
+            id: database::random_token()?[..12].to_string(),
+            name: if name.trim().is_empty() { format!("Guest {}", index) } else { name },
+            color: PEER_COLORS[index % PEER_COLORS.len()].to_string(),
+        };
+        let (outbox, inbox) = mpsc::channel();
+        {
+            // Holding the replica keeps ops from slipping between the
+            // snapshot and the link that relays later ones
+            let replica = self.replica.lock().unwrap();
+            let mut peers = vec![self.me.clone()];
+            peers.extend(self.peers.lock().unwrap().iter().cloned());
+            let _ = outbox.send(Message::Welcome { me: peer.clone(), snapshot: Box::new(replica.snapshot()), peers });
+            self.links.lock().unwrap().insert(peer.id.clone(), outbox);
+        }
+        self.broadcast(Some(&peer.id), &Message::PeerJoined { peer: peer.clone() });
+        self.peers.lock().unwrap().push(peer.clone());
+        self.emit_presence();
+
+        self.pump(&peer.id, socket, inbox);
+
+        self.links.lock().unwrap().remove(&peer.id);
+        self.peers.lock().unwrap().retain(|p| p.id != peer.id);
+        self.broadcast(None, &Message::PeerLeft { peer_id: peer.id.clone() });
+        self.emit_presence();
+        Ok(())
+    }
+}
+
+/// A hosted or joined session; dropping it leaves.
+pub struct Session<D: DataDir + Send + Sync + 'static> {
+    shared: Arc<Shared<D>>,
+    address: Option<String>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl<D: DataDir + Send + Sync + 'static> Session<D> {
+    fn new(shared: Shared<D>, address: Option<String>) -> Self {
+        let shared = Arc::new(shared);
+        let saver = shared.clone();
+        let workers = vec![thread::spawn(move || loop {
+            let stopping = saver.stop.load(Ordering::SeqCst);
+            saver.save();
+            if stopping {
+                return;
+            }
+            thread::sleep(SAVE_EVERY);
+        })];
+        Session { shared, address, workers }
+    }
+
+    /// Hosts a session for a board on `port` (any free port when 0).
+    pub fn host(app: D, emit: Emit, board_id: u64, name: &str, port: u16) -> Result<Self, String> {
+        if crate::vault::is_encrypted(&app, board_id) {
+            return Err("Encrypted boards can't be shared".to_string());
+        }
+        let board = database::load_board(&app, board_id)?;
+        let listener = TcpListener::bind(("0.0.0.0", port)).map_err(|e| format!("Failed to listen on port {}: {}", port, e))?;
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+        let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+
+        let me = Peer {
+            id: database::random_token()?[..12].to_string(),
+            name: if name.trim().is_empty() { "Host".to_string() } else { name.to_string() },
+            color: PEER_COLORS[0].to_string(),
+        };
+        let shared = Shared {
+            app,
+            emit,
+            board_id,
+            host: true,
+            code: Mutex::new(new_code()?),
+            wrong_codes: AtomicUsize::new(0),
+            connections: AtomicUsize::new(0),
+            me,
+            peers: Mutex::new(Vec::new()),
+            replica: Mutex::new(Replica::new(board)?),
+            links: Mutex::new(HashMap::new()),
+            waiting: Mutex::new(HashMap::new()),
+            requested: Mutex::new(HashSet::new()),
+            joined: AtomicUsize::new(0),
+            unsaved: Mutex::new(Vec::new()),
+            stop: AtomicBool::new(false),
+        };
+        let mut session = Session::new(shared, Some(format!("{}:{}", lan_address(), port)));
+
+        let shared = session.shared.clone();
+        session.workers.push(thread::spawn(move || {
+            while !shared.stop.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        if shared.connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                            shared.connections.fetch_sub(1, Ordering::SeqCst);
+                            continue;
+                        }
+                        let shared = shared.clone();
+                        thread::spawn(move || {
+                            if let Err(e) = shared.serve(stream) {
+                                log::warn!("Collaboration peer failed: {}", e);
+                            }
+                            shared.connections.fetch_sub(1, Ordering::SeqCst);
+                        });
+                    }
+                    Err(_) => thread::sleep(POLL),
+                }
+            }
+        }));
+        Ok(session)
+    }
+
+    /// Joins the session at `address` (`host:port`), saving its board here.
+    pub fn join(app: D, emit: Emit, address: &str, code: &str, name: &str) -> Result<Self, String> {
+        let addr = address
+            .to_socket_addrs()
+            .map_err(|e| format!("Invalid address {}: {}", address, e))?
+            .next()
+            .ok_or_else(|| format!("Invalid address {}", address))?;
+        let stream = TcpStream::connect_timeout(&addr, HANDSHAKE_TIMEOUT).map_err(|e| format!("Failed to connect to {}: {}", address, e))?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).map_err(|e| e.to_string())?;
+        let (mut socket, _) = tungstenite::client::client_with_config(format!("ws://{}/", address), stream, Some(socket_config()))
+            .map_err(|e| format!("Failed to join: {}", e))?;
+        socket
+            .send(frame(&Message::Hello { code: code.trim().to_string(), name: name.to_string() }))
+            .map_err(|e| e.to_string())?;
+        let (me, snapshot, peers) = match read_message(&mut socket)? {
+            Message::Welcome { me, snapshot, peers } => (me, snapshot, peers),
+            Message::Rejected { reason } => return Err(reason),
+            _ => return Err("Unexpected reply from the host".to_string()),
+        };
+        let mut replica = Replica::from_snapshot(*snapshot);
+        database::check_media_srcs(&database::get_images_dir(&app), replica.media().iter().map(String::as_str))?;
+        // A board of its own here, so one with the host's id is left alone
+        replica.initial.id = database::unused_board_id(&app)?;
+        let board = replica.initial.clone();
+        let joined = replica.export(&board)?;
+        database::save_board(&app, &joined)?;
+        socket.get_ref().set_read_timeout(Some(POLL)).map_err(|e| e.to_string())?;
+
+        let (outbox, inbox) = mpsc::channel();
+        let shared = Shared {
+            app,
+            emit,
+            board_id: board.id,
+            host: false,
+            code: Mutex::new(code.to_string()),
+            wrong_codes: AtomicUsize::new(0),
+            connections: AtomicUsize::new(0),
+            me,
+            peers: Mutex::new(peers),
+            replica: Mutex::new(replica),
+            links: Mutex::new(HashMap::from([(String::new(), outbox)])),
+            waiting: Mutex::new(HashMap::new()),
+            requested: Mutex::new(HashSet::new()),
+            joined: AtomicUsize::new(0),
+            unsaved: Mutex::new(Vec::new()),
+            stop: AtomicBool::new(false),
+        };
+        let mut session = Session::new(shared, None);
+        session.shared.emit_saved(&joined);
+        session.shared.request_media("", joined.layers.iter().map(|l| l.src.as_str()));
+
+        let shared = session.shared.clone();
+        session.workers.push(thread::spawn(move || {
+            shared.pump("", socket, inbox);
+            if !shared.stop.swap(true, Ordering::SeqCst) {
+                (shared.emit)("collab-ended", serde_json::json!({ "boardId": shared.board_id }));
+            }
+        }));
+        Ok(session)
+    }
+
+    pub fn info(&self) -> SessionInfo {
+        let shared = &self.shared;
+        SessionInfo {
+            board_id: shared.board_id,
+            host: shared.host,
+            address: self.address.clone(),
+            code: shared.host.then(|| shared.code.lock().unwrap().clone()),
+            me: shared.me.clone(),
+            peers: shared.peers.lock().unwrap().clone(),
+            active: !shared.stop.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Stamps and applies local edits, then sends them to the other peers.
+    pub fn submit(&self, ops: Vec<Op>) -> Result<(), String> {
+        let shared = &self.shared;
+        if shared.stop.load(Ordering::SeqCst) {
+            return Err("The session has ended".to_string());
+        }
+        let images_dir = shared.images_dir();
+        for mut op in ops {
+            database::check_media_srcs(&images_dir, op_media(&op))?;
+            let mut replica = shared.replica.lock().unwrap();
+            op.stamp = replica.stamp(&shared.me.id);
+            if replica.apply(&op)? {
+                shared.settle(&replica, &op);
+            }
+            drop(replica);
+            shared.broadcast(None, &Message::Op { op });
+        }
+        Ok(())
+    }
+
+    pub fn cursor(&self, x: f64, y: f64) {
+        let cursor = Cursor { peer_id: self.shared.me.id.clone(), x, y };
+        self.shared.broadcast(None, &Message::Cursor { cursor });
+    }
+}
+
+impl<D: DataDir + Send + Sync + 'static> Drop for Session<D> {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::SeqCst);
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn app_emit(app: &AppHandle) -> Emit {
+    let app = app.clone();
+    Arc::new(move |event, payload| {
+        let _ = app.emit(event, payload);
+    })
+}
+
+/// Hosts a session for a board, leaving any current one.
+pub fn start(app: &AppHandle, board_id: u64, name: &str, port: u16) -> Result<SessionInfo, String> {
+    leave(app);
+    let session = Session::host(app.clone(), app_emit(app), board_id, name, port)?;
+    let info = session.info();
+    *app.state::<CollabState>().session.lock().unwrap() = Some(session);
+    Ok(info)
+}
+
+pub fn join(app: &AppHandle, address: &str, code: &str, name: &str) -> Result<SessionInfo, String> {
+    leave(app);
+    let session = Session::join(app.clone(), app_emit(app), address, code, name)?;
+    let info = session.info();
+    *app.state::<CollabState>().session.lock().unwrap() = Some(session);
+    Ok(info)
+}
+
+pub fn info(app: &AppHandle) -> Option<SessionInfo> {
+    app.state::<CollabState>().session.lock().unwrap().as_ref().map(Session::info)
+}
+
+pub fn submit(app: &AppHandle, ops: Vec<Op>) -> Result<(), String> {
+    let state = app.state::<CollabState>();
+    let session = state.session.lock().unwrap();
+    session.as_ref().ok_or("Not in a session")?.submit(ops)
+}
+
+pub fn cursor(app: &AppHandle, x: f64, y: f64) {
+    if let Some(session) = app.state::<CollabState>().session.lock().unwrap().as_ref() {
+        session.cursor(x, y);
+    }
+}
+
+pub fn leave(app: &AppHandle) {
+    let session = app.state::<CollabState>().session.lock().unwrap().take();
+    drop(session);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::time::Instant;
+
+    fn data_dir(label: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("eyedea-collab-{}-{}", label, database::random_token().unwrap()));
+        database::init_storage(&dir).unwrap();
+        dir
+    }
+
+    fn png() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image::RgbaImage::new(2, 2)
+            .write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    fn board(dir: &PathBuf) -> Board {
+        let src = database::write_media_file(&database::get_images_dir(dir), &png(), "photo", "png").unwrap();
+        let mut board = database::new_board("Shared".to_string(), "#ffffff".to_string());
+        board.layers = serde_json::from_value(serde_json::json!([
+            { "id": 1.0, "name": "photo", "src": src, "x": 0.0, "y": 0.0, "width": 10.0, "height": 10.0 }
+        ]))
+        .unwrap();
+        database::save_board(dir, &board).unwrap();
+        board
+    }
+
+    fn patch_layer(id: f64, fields: Value) -> Op {
+        Op {
+            stamp: Stamp::default(),
+            collection: Collection::Layers,
+            id: serde_json::json!(id),
+            change: Change::Patch { fields: fields.as_object().unwrap().clone() },
+        }
+    }
+
+    fn stamped(mut op: Op, clock: u64, peer: &str) -> Op {
+        op.stamp = Stamp { clock, peer: peer.to_string() };
+        op
+    }
+
+    fn shared_board(session: &Session<PathBuf>) -> Board {
+        let replica = session.shared.replica.lock().unwrap();
+        replica.export(&replica.initial).unwrap()
+    }
+
+    fn quiet() -> Emit {
+        Arc::new(|_, _| {})
+    }
+
+    fn eventually(what: &str, mut check: impl FnMut() -> bool) {
+        let started = Instant::now();
+        while !check() {
+            assert!(started.elapsed() < Duration::from_secs(10), "timed out waiting for {}", what);
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn replicas_converge_whatever_the_order() {
+        let dir = data_dir("replica");
+        let board = board(&dir);
+        let stroke = serde_json::json!({ "id": 7, "tool": "pen", "points": [] });
+        let ops = vec![
+            stamped(patch_layer(1.0, serde_json::json!({ "x": 5.0 })), 1, "a"),
+            stamped(patch_layer(1.0, serde_json::json!({ "x": 9.0, "y": 2.0 })), 1, "b"),
+            stamped(Op { stamp: Stamp::default(), collection: Collection::Strokes, id: serde_json::json!(7), change: Change::Put { value: stroke } }, 2, "a"),
+            stamped(Op { stamp: Stamp::default(), collection: Collection::Strokes, id: serde_json::json!(7), change: Change::Remove }, 3, "b"),
+            stamped(patch_layer(1.0, serde_json::json!({ "rotation": 90.0 })), 4, "a"),
+        ];
+
+        let mut forward = Replica::new(board.clone()).unwrap();
+        let mut backward = Replica::new(board.clone()).unwrap();
+        for op in &ops {
+            forward.apply(op).unwrap();
+        }
+        for op in ops.iter().rev() {
+            backward.apply(op).unwrap();
+        }
+        let (a, b) = (forward.export(&board).unwrap(), backward.export(&board).unwrap());
+        assert_eq!(serde_json::to_value(&a).unwrap(), serde_json::to_value(&b).unwrap());
+        // Same clock: the higher peer id wins
+        assert_eq!((a.layers[0].x, a.layers[0].y, a.layers[0].rotation), (9.0, 2.0, Some(90.0)));
+        assert_eq!(a.strokes, Some(serde_json::json!([])));
+
+        let invalid = stamped(patch_layer(1.0, serde_json::json!({ "x": "left" })), 5, "a");
+        assert!(forward.apply(&invalid).is_err());
+        // A peer can't run the clock out
+        let future = stamped(patch_layer(1.0, serde_json::json!({ "x": 1.0 })), u64::MAX, "z");
+        assert!(forward.apply(&future).is_err());
+        assert_eq!(forward.stamp("a").clock, 5);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn codes_are_replaced_after_wrong_guesses() {
+        let code = new_code().unwrap();
+        assert_eq!(code.len(), 14);
+        assert!(same_code(&code.to_lowercase().replace('-', " "), &code));
+        assert!(!same_code(&code[..9], &code));
+
+        let dir = data_dir("codes");
+        let board = board(&dir);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let log = events.clone();
+        let emit: Emit = Arc::new(move |event, _| log.lock().unwrap().push(event.to_string()));
+        let host = Session::host(dir.clone(), emit, board.id, "Ana", 0).unwrap();
+        let first = host.info().code.unwrap();
+        for _ in 0..MAX_WRONG_CODES {
+            assert!(!host.shared.check_code("0000-0000-0000").unwrap());
+        }
+        let second = host.info().code.unwrap();
+        assert_ne!(first, second);
+        assert!(!host.shared.check_code(&first).unwrap());
+        assert!(host.shared.check_code(&second).unwrap());
+        assert!(events.lock().unwrap().iter().any(|e| e == "collab-code-changed"));
+    }
+
+    #[test]
+    fn only_shares_media_on_the_board() {
+        let dir = data_dir("media");
+        let board = board(&dir);
+        let private = database::write_media_file(&database::get_images_dir(&dir), b"private", "secret", "png").unwrap();
+        let host = Session::host(dir.clone(), quiet(), board.id, "Ana", 0).unwrap();
+        let (outbox, inbox) = mpsc::channel();
+        host.shared.links.lock().unwrap().insert("peer".to_string(), outbox);
+
+        host.shared.receive("peer", Message::NeedMedia { src: private });
+        assert!(inbox.try_recv().is_err());
+        host.shared.receive("peer", Message::NeedMedia { src: board.layers[0].src.clone() });
+        assert!(matches!(inbox.try_recv(), Ok(Message::Media { .. })));
+    }
+
+    #[test]
+    fn refuses_unsafe_and_unrequested_media() {
+        let dir = data_dir("untrusted");
+        let board = board(&dir);
+        let host = Session::host(dir.clone(), quiet(), board.id, "Ana", 0).unwrap();
+        let (outbox, _inbox) = mpsc::channel();
+        host.shared.links.lock().unwrap().insert("peer".to_string(), outbox);
+        let encode = |bytes: &[u8]| base64::engine::general_purpose::STANDARD.encode(bytes);
+
+        // Srcs with a scheme are checked like any other
+        for src in ["file:///etc/passwd", "asset://localhost/secret.png"] {
+            host.shared.receive("peer", Message::Op { op: patch_layer(1.0, serde_json::json!({ "src": src })) });
+            assert_eq!(shared_board(&host).layers[0].src, board.layers[0].src);
+        }
+
+        // Media nobody asked for is dropped
+        host.shared.receive("peer", Message::Media { src: "pushed.png".to_string(), data: encode(&png()) });
+        assert!(!database::get_images_dir(&dir).join("pushed.png").exists());
+
+        // Asked for, but not media
+        host.shared.receive("peer", Message::Op { op: patch_layer(1.0, serde_json::json!({ "src": "wanted.png" })) });
+        assert!(host.shared.requested.lock().unwrap().contains("wanted.png"));
+        host.shared.receive("peer", Message::Media { src: "wanted.png".to_string(), data: encode(b"#!/bin/sh") });
+        assert!(!database::get_images_dir(&dir).join("wanted.png").exists());
+        host.shared.receive("peer", Message::Media { src: "wanted.png".to_string(), data: encode(&png()) });
+        assert!(database::get_images_dir(&dir).join("wanted.png").is_file());
+        assert!(host.shared.requested.lock().unwrap().is_empty());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn keeps_edits_saved_outside_the_session() {
+        let dir = data_dir("outside");
+        let board = board(&dir);
+        let host = Session::host(dir.clone(), quiet(), board.id, "Ana", 0).unwrap();
+
+        // Say a floating window adds a stroke while the session runs
+        let stroke = Op {
+            stamp: Stamp::default(),
+            collection: Collection::Strokes,
+            id: serde_json::json!(7),
+            change: Change::Put { value: serde_json::json!({ "tool": "pen", "points": [] }) },
+        };
+        crdt::apply_ops(&dir, board.id, &[stroke]).unwrap();
+        host.submit(vec![patch_layer(1.0, serde_json::json!({ "x": 5.0 }))]).unwrap();
+        drop(host);
+
+        let saved = database::load_board(&dir, board.id).unwrap();
+        assert_eq!(saved.layers[0].x, 5.0);
+        assert_eq!(saved.strokes, Some(serde_json::json!([{ "id": 7, "tool": "pen", "points": [] }])));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn peers_share_a_board_on_localhost() {
+        let (host_dir, guest_dir) = (data_dir("host"), data_dir("guest"));
+        let board = board(&host_dir);
+        let src = board.layers[0].src.clone();
+
+        let host = Session::host(host_dir.clone(), quiet(), board.id, "Ana", 0).unwrap();
+        let info = host.info();
+        let port = info.address.unwrap().rsplit(':').next().unwrap().to_string();
+        let address = format!("127.0.0.1:{}", port);
+        assert!(Session::join(guest_dir.clone(), quiet(), &address, "wrong", "Ben").is_err());
+        // Joiners get edits made before they came
+        host.submit(vec![patch_layer(1.0, serde_json::json!({ "x": 5.0 }))]).unwrap();
+        // A board of the guest's own that happens to share the id
+        let mut mine = database::new_board("Mine".to_string(), "#000000".to_string());
+        mine.id = board.id;
+        database::save_board(&guest_dir, &mine).unwrap();
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let log = events.clone();
+        let emit: Emit = Arc::new(move |event, _| log.lock().unwrap().push(event.to_string()));
+        let guest = Session::join(guest_dir.clone(), emit, &address, info.code.as_deref().unwrap(), "Ben").unwrap();
+        assert_eq!(guest.info().peers[0].name, "Ana");
+        eventually("presence", || host.info().peers.len() == 1);
+
+        // The guest gets the board under its own id, and then its media
+        let guest_id = guest.info().board_id;
+        assert_ne!(guest_id, board.id);
+        assert_eq!(database::load_board(&guest_dir, board.id).unwrap().name, "Mine");
+        let joined = database::load_board(&guest_dir, guest_id).unwrap();
+        assert_eq!((joined.name.as_str(), joined.layers[0].x), ("Shared", 5.0));
+        eventually("media", || database::get_images_dir(&guest_dir).join(&src).is_file());
+
+        // Edits flow both ways, and concurrent ones settle the same on both
+        guest.submit(vec![patch_layer(1.0, serde_json::json!({ "x": 40.0 }))]).unwrap();
+        eventually("guest edit", || shared_board(&host).layers[0].x == 40.0);
+        host.submit(vec![patch_layer(1.0, serde_json::json!({ "y": 12.0 }))]).unwrap();
+        eventually("host edit", || shared_board(&guest).layers[0].y == 12.0);
+        assert!(events.lock().unwrap().iter().any(|e| e == "collab-op"));
+        host.submit(vec![patch_layer(1.0, serde_json::json!({ "rotation": 10.0 }))]).unwrap();
+        guest.submit(vec![patch_layer(1.0, serde_json::json!({ "rotation": 20.0 }))]).unwrap();
+        eventually("convergence", || {
+            let (a, b) = (shared_board(&host), shared_board(&guest));
+            a.layers[0].rotation == b.layers[0].rotation && serde_json::to_value(&a.layers).unwrap() == serde_json::to_value(&b.layers).unwrap()
+        });
+
+        guest.cursor(3.0, 4.0);
+        drop(host);
+        eventually("end", || !guest.info().active);
+        assert!(events.lock().unwrap().iter().any(|e| e == "collab-ended"));
+        // Edits were saved on both sides
+        assert_eq!(database::load_board(&host_dir, board.id).unwrap().layers[0].x, 40.0);
+        drop(guest);
+        assert_eq!(database::load_board(&guest_dir, guest_id).unwrap().layers[0].x, 40.0);
+
+        for dir in [host_dir, guest_dir] {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
@@ -1,8 +1,36 @@
-use crate::database::{self, Board, BoardMetadata, BoardUpdate, Asset};
-use tauri::AppHandle;
-use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
-use reqwest::blocking::Client;
-use std::time::Duration;
+use crate::arrange::{self, ArrangeOptions, LayerPlacement};
+use crate::attribution;
+use crate::backup::{self, BackupInfo, RestoreMode, RestoreSummary};
+use crate::capture;
+use crate::collab::{self, Op, SessionInfo};
+use crate::crdt::{self, BoardChanges};
+use crate::database::{self, Board, BoardMetadata, BoardUpdate, Asset, BackupSettings, CaptureSettings, FetchSettings, FloatingWindow, SourceInfo, SyncSettings, WatchFolder};
+use crate::downloads;
+use crate::events;
+use crate::fetch::{self, Expect, FetchError};
+use crate::floating::{self, FloatingOptions};
+use crate::http_cache::{self, CacheInfo};
+use crate::import::{self, FolderImport, FolderImportOptions};
+use crate::media_protocol;
+use crate::page_images::{self, ImageCandidate};
+use crate::pdf::{self, ExportedPdf, PdfOptions};
+use crate::pureref;
+use crate::pyramid::{self, LayerResolution};
+use crate::render::{self, RenderOptions, RenderedImage};
+use crate::svg::{self, ExportedSvg, SvgOptions};
+use crate::sync::{self, SyncReport};
+use crate::url_import::{self, UrlImportOptions, UrlImportSummary, UrlStage};
+use crate::vault;
+use crate::watch;
+use tauri::{AppHandle, Emitter, Window};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Runs network-bound work on the blocking pool so it doesn't hold up one
+/// of the async runtime's workers.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Result<T, String> {
+    tauri::async_runtime::spawn_blocking(f).await.map_err(|e| e.to_string())
+}
 
 #[tauri::command]
 pub fn get_all_boards(app: AppHandle) -> Result<Vec<BoardMetadata>, String> {
@@ -15,29 +43,19 @@ pub fn get_board(app: AppHandle, id: u64) -> Result<Board, String> {
 }
 
 #[tauri::command]
-pub fn create_board(app: AppHandle, name: String, bg_color: String) -> Result<Board, String> {
-    let now = database::now_millis();
-    let board = Board {
-        id: now,
-        name,
-        bg_color,
-        created_at: now,
-        updated_at: now,
-        layers: Vec::new(),
-        assets: Vec::new(),
-        thumbnail: None,
-        view_state: None,
-        strokes: None,
-        objects: None,
-        groups: None,
-    };
+pub fn create_board(app: AppHandle, window: Window, name: String, bg_color: String) -> Result<Board, String> {
+    let board = database::new_board(name, bg_color);
     database::save_board(&app, &board)?;
+    events::board_updated(&app, &board, Vec::new(), Some(window.label()));
     Ok(board)
 }
 
 #[tauri::command]
-pub fn update_board(app: AppHandle, id: u64, updates: BoardUpdate) -> Result<Board, String> {
+pub fn update_board(app: AppHandle, window: Window, id: u64, updates: BoardUpdate) -> Result<Board, String> {
     let mut board = database::load_board(&app, id)?;
+    let srcs = updates.layers.iter().flatten().map(|l| l.src.as_str());
+    let asset_srcs = updates.assets.iter().flatten().map(|a| a.src.as_str());
+    database::check_media_srcs(&database::get_images_dir(&app), srcs.chain(asset_srcs))?;
 
     if let Some(name) = updates.name {
         board.name = name;
@@ -69,12 +87,40 @@ pub fn update_board(app: AppHandle, id: u64, updates: BoardUpdate) -> Result<Boa
 
     board.updated_at = database::now_millis();
     database::save_board(&app, &board)?;
+    if let Err(e) = crdt::record_board(&app, &board) {
+        log::warn!("Failed to record changes to board {}: {}", id, e);
+    }
+    events::board_updated(&app, &board, Vec::new(), Some(window.label()));
     Ok(board)
 }
 
 #[tauri::command]
-pub fn delete_board(app: AppHandle, id: u64) -> Result<(), String> {
-    database::delete_board(&app, id)
+pub fn delete_board(app: AppHandle, window: Window, id: u64) -> Result<(), String> {
+    database::delete_board(&app, id)?;
+    crdt::forget(&app, id);
+    events::board_deleted(&app, id, Some(window.label()));
+    floating::forget_board(&app, id);
+    watch::remove_folders(&app, |f| f.board_id != id)
+}
+
+/// Encrypts a board with a passphrase, or locks an unlocked encrypted board
+/// again (the passphrase is then ignored).
+#[tauri::command(async)]
+pub fn lock_board(app: AppHandle, id: u64, passphrase: Option<String>) -> Result<(), String> {
+    vault::lock(&app, id, passphrase.as_deref())?;
+    crdt::forget(&app, id);
+    Ok(())
+}
+
+/// Unlocks an encrypted board until it's locked again or the app quits.
+#[tauri::command(async)]
+pub fn unlock_board(app: AppHandle, id: u64, passphrase: String) -> Result<Board, String> {
+    vault::unlock(&app, id, &passphrase)
+}
+
+#[tauri::command(async)]
+pub fn change_board_passphrase(app: AppHandle, id: u64, old_passphrase: String, new_passphrase: String) -> Result<(), String> {
+    vault::change_passphrase(&app, id, &old_passphrase, &new_passphrase)
 }
 
 #[tauri::command]
@@ -85,27 +131,36 @@ pub fn get_all_assets(app: AppHandle) -> Result<Vec<Asset>, String> {
 #[tauri::command]
 pub fn add_to_all_assets(
     app: AppHandle,
+    window: Window,
     name: String,
     src: String,
     tags: Option<Vec<String>>,
     metadata: Option<serde_json::Value>,
 ) -> Result<Asset, String> {
-    database::add_to_all_assets(&app, name, src, tags, metadata)
+    let asset = database::add_to_all_assets(&app, name, src, tags, metadata)?;
+    events::asset_library_changed(&app, vec![asset.clone()], Vec::new(), Some(window.label()));
+    Ok(asset)
 }
 
 #[tauri::command]
-pub fn delete_from_all_assets(app: AppHandle, id: f64) -> Result<(), String> {
-    database::delete_from_all_assets(&app, id)
+pub fn delete_from_all_assets(app: AppHandle, window: Window, id: f64) -> Result<(), String> {
+    database::delete_from_all_assets(&app, id)?;
+    events::asset_library_changed(&app, Vec::new(), vec![id], Some(window.label()));
+    Ok(())
 }
 
 #[tauri::command]
-pub fn delete_board_asset(app: AppHandle, board_id: u64, asset_id: f64) -> Result<Board, String> {
-    database::delete_board_asset(&app, board_id, asset_id)
+pub fn delete_board_asset(app: AppHandle, window: Window, board_id: u64, asset_id: f64) -> Result<Board, String> {
+    let board = database::delete_board_asset(&app, board_id, asset_id)?;
+    events::board_updated(&app, &board, Vec::new(), Some(window.label()));
+    Ok(board)
 }
 
 #[tauri::command]
-pub fn update_asset(app: AppHandle, asset: Asset) -> Result<(), String> {
-    database::update_asset(&app, asset)
+pub fn update_asset(app: AppHandle, window: Window, asset: Asset) -> Result<(), String> {
+    database::update_asset(&app, asset.clone())?;
+    events::asset_library_changed(&app, vec![asset], Vec::new(), Some(window.label()));
+    Ok(())
 }
 
 #[tauri::command]
@@ -114,8 +169,10 @@ pub fn get_tag_presets(app: AppHandle) -> Result<Vec<String>, String> {
 }
 
 #[tauri::command]
-pub fn save_tag_presets(app: AppHandle, presets: Vec<String>) -> Result<(), String> {
-    database::save_tag_presets(&app, presets)
+pub fn save_tag_presets(app: AppHandle, window: Window, presets: Vec<String>) -> Result<(), String> {
+    database::save_tag_presets(&app, presets.clone())?;
+    events::tag_presets_changed(&app, presets, Some(window.label()));
+    Ok(())
 }
 
 #[tauri::command]
@@ -139,62 +196,436 @@ pub fn get_image_file_path(app: AppHandle, filename: String) -> Result<String, S
     database::get_image_file_path(&app, filename)
 }
 
+/// Content hash of a stored file, for `eyedea://localhost/hash/<hash>` URLs.
+#[tauri::command(async)]
+pub fn get_media_hash(app: AppHandle, filename: String) -> Result<String, String> {
+    media_protocol::media_hash(&app, &filename)
+}
+
+/// Queues pyramid builds for a board's still images; returns how many were queued.
+#[tauri::command]
+pub fn build_pyramids(app: AppHandle, board_id: u64) -> Result<usize, String> {
+    let board = database::load_board(&app, board_id)?;
+    // Pyramids would be plain copies of encrypted media
+    if vault::is_encrypted(&app, board_id) {
+        return Ok(0);
+    }
+    Ok(pyramid::queue(&app, board.layers.into_iter().map(|l| l.src)))
+}
+
+/// The smallest stored resolution covering a layer at `scale` screen pixels
+/// per board unit. Queues the pyramid when it isn't built yet.
+#[tauri::command(async)]
+pub fn get_layer_resolution(app: AppHandle, board_id: u64, layer_id: f64, scale: f64) -> Result<LayerResolution, String> {
+    let resolution = pyramid::resolve(&app, board_id, layer_id, scale)?;
+    if !resolution.ready && !vault::is_encrypted(&app, board_id) {
+        pyramid::queue(&app, [resolution.src.clone()]);
+    }
+    Ok(resolution)
+}
+
+#[tauri::command(async)]
+pub fn export_board_image(
+    app: AppHandle,
+    board_id: u64,
+    path: String,
+    options: Option<RenderOptions>,
+    quality: Option<u8>,
+) -> Result<RenderedImage, String> {
+    let board = database::load_board(&app, board_id)?;
+    let images_dir = database::get_images_dir(&app);
+    render::export_board_image(&board, &images_dir, &options.unwrap_or_default(), Path::new(&path), quality)
+}
+
+#[tauri::command(async)]
+pub fn export_board_pdf(
+    app: AppHandle,
+    board_id: u64,
+    path: String,
+    options: Option<PdfOptions>,
+) -> Result<ExportedPdf, String> {
+    let board = database::load_board(&app, board_id)?;
+    let images_dir = database::get_images_dir(&app);
+    pdf::export_board_pdf(&board, &images_dir, &options.unwrap_or_default(), Path::new(&path))
+}
+
+#[tauri::command(async)]
+pub fn export_board_svg(
+    app: AppHandle,
+    board_id: u64,
+    path: String,
+    options: Option<SvgOptions>,
+) -> Result<ExportedSvg, String> {
+    let board = database::load_board(&app, board_id)?;
+    let images_dir = database::get_images_dir(&app);
+    svg::export_board_svg(&board, &images_dir, &options.unwrap_or_default(), Path::new(&path))
+}
+
+#[tauri::command(async)]
+pub fn import_pureref(
+    app: AppHandle,
+    window: Window,
+    path: String,
+    name: Option<String>,
+    bg_color: Option<String>,
+) -> Result<Board, String> {
+    let images_dir = database::get_images_dir(&app);
+    let board = pureref::import_pur(Path::new(&path), &images_dir, name, bg_color)?;
+    database::save_board(&app, &board)?;
+    events::board_updated(&app, &board, board.layers.clone(), Some(window.label()));
+    Ok(board)
+}
+
+/// Runs off the main thread; progress is reported through `import-progress`
+/// events.
+#[tauri::command(async)]
+pub fn import_folder(
+    app: AppHandle,
+    window: Window,
+    path: String,
+    board_id: Option<u64>,
+    board_name: Option<String>,
+    options: Option<FolderImportOptions>,
+) -> Result<FolderImport, String> {
+    let dir = Path::new(&path);
+    let board = match board_id {
+        Some(id) => database::load_board(&app, id)?,
+        None => {
+            let name = board_name.unwrap_or_else(|| {
+                dir.file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_else(|| "Imported folder".to_string())
+            });
+            database::new_board(name, "#ffffff".to_string())
+        }
+    };
+
+    let existing = board.layers.len();
+    let images_dir = database::get_images_dir(&app);
+    let options = options.unwrap_or_default();
+    let mut result = import::import_folder(&images_dir, dir, board, &options, |progress| {
+        let _ = app.emit("import-progress", progress);
+    })?;
+
+    result.board.updated_at = database::now_millis();
+    database::save_board(&app, &result.board)?;
+    let added = result.board.layers[existing..].to_vec();
+    events::board_updated(&app, &result.board, added, Some(window.label()));
+    if !result.library.is_empty() {
+        result.library = database::add_assets_to_library(&app, result.library)?;
+        events::asset_library_changed(&app, result.library.clone(), Vec::new(), Some(window.label()));
+    }
+    Ok(result)
+}
+
+/// Runs on the blocking pool; each URL reports through `url-import-progress`
+/// and, when it fails, `url-import-failed`.
 #[tauri::command]
-pub fn fetch_page_html(url: String) -> Result<String, String> {
-    let client = Client::builder()
-        .timeout(Duration::from_secs(30))
-        .user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
-        .build()
-        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+pub async fn import_urls(
+    app: AppHandle,
+    window: Window,
+    urls: Vec<String>,
+    board_id: Option<u64>,
+    options: Option<UrlImportOptions>,
+) -> Result<UrlImportSummary, String> {
+    let options = options.unwrap_or_default();
+    let handle = app.clone();
+    let summary = blocking(move || {
+        url_import::import_urls(&handle, &urls, board_id, &options, |progress| {
+            if progress.stage == UrlStage::Failed {
+                let _ = handle.emit("url-import-failed", progress.clone());
+            }
+            let _ = handle.emit("url-import-progress", progress);
+        })
+    })
+    .await??;
 
-    let response = client
-        .get(&url)
-        .header("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8")
-        .send()
-        .map_err(|e| format!("Failed to fetch URL: {}", e))?;
+    match summary.board_id {
+        Some(id) if !summary.imported.is_empty() => {
+            let layers = summary.imported.iter().map(|i| i.layer.clone()).collect();
+            let board = database::load_board(&app, id)?;
+            events::board_updated(&app, &board, layers, Some(window.label()));
+        }
+        _ if !summary.library.is_empty() => {
+            events::asset_library_changed(&app, summary.library.clone(), Vec::new(), Some(window.label()));
+        }
+        _ => {}
+    }
+    Ok(summary)
+}
 
-    if !response.status().is_success() {
-        return Err(format!("HTTP error: {}", response.status()));
+#[tauri::command(async)]
+pub fn arrange_layers(
+    app: AppHandle,
+    window: Window,
+    board_id: u64,
+    layer_ids: Vec<f64>,
+    options: Option<ArrangeOptions>,
+) -> Result<Vec<LayerPlacement>, String> {
+    let mut board = database::load_board(&app, board_id)?;
+    let options = options.unwrap_or_default();
+    let images_dir = database::get_images_dir(&app);
+    let placements = arrange::arrange_board(&mut board, &layer_ids, &options, &images_dir)?;
+    if options.persist {
+        board.updated_at = database::now_millis();
+        database::save_board(&app, &board)?;
+        events::board_updated(&app, &board, Vec::new(), Some(window.label()));
     }
+    Ok(placements)
+}
+
+#[tauri::command]
+pub fn get_watch_folders(app: AppHandle, board_id: Option<u64>) -> Result<Vec<WatchFolder>, String> {
+    let folders = database::load_watch_folders(&app)?;
+    Ok(folders
+        .into_iter()
+        .filter(|f| board_id.is_none_or(|id| f.board_id == id))
+        .collect())
+}
 
-    response.text().map_err(|e| format!("Failed to read response: {}", e))
+#[tauri::command]
+pub fn add_watch_folder(
+    app: AppHandle,
+    board_id: u64,
+    path: String,
+    recursive: Option<bool>,
+) -> Result<WatchFolder, String> {
+    watch::add_folder(&app, board_id, path, recursive.unwrap_or(false))
+}
+
+#[tauri::command]
+pub fn remove_watch_folder(app: AppHandle, id: u64) -> Result<(), String> {
+    watch::remove_folders(&app, |f| f.id != id)
 }
 
 #[tauri::command]
-pub fn fetch_image_url(url: String) -> Result<String, String> {
-    let client = Client::builder()
-        .timeout(Duration::from_secs(30))
-        .user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
-        .build()
-        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+pub fn pause_watch_folders(app: AppHandle) {
+    watch::pause(&app);
+}
 
-    let response = client
-        .get(&url)
-        .header("Accept", "image/*,*/*;q=0.8")
-        .header("Referer", &url)
-        .send()
-        .map_err(|e| format!("Failed to fetch URL: {}", e))?;
+#[tauri::command]
+pub fn resume_watch_folders(app: AppHandle) -> Result<(), String> {
+    watch::resume(&app)
+}
 
-    if !response.status().is_success() {
-        return Err(format!("HTTP error: {}", response.status()));
+#[tauri::command]
+pub fn get_capture_settings(app: AppHandle) -> Result<CaptureSettings, String> {
+    database::load_capture_settings(&app)
+}
+
+#[tauri::command]
+pub fn set_capture_server(app: AppHandle, enabled: bool, port: Option<u16>) -> Result<CaptureSettings, String> {
+    let mut settings = database::load_capture_settings(&app)?;
+    settings.enabled = enabled;
+    if let Some(port) = port {
+        settings.port = port;
     }
+    database::save_capture_settings(&app, &settings)?;
+    capture::apply_settings(&app)?;
+    Ok(settings)
+}
 
-    let content_type = response
-        .headers()
-        .get("content-type")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("image/png")
-        .split(';')
-        .next()
-        .unwrap_or("image/png")
-        .to_string();
+#[tauri::command]
+pub fn regenerate_capture_token(app: AppHandle) -> Result<CaptureSettings, String> {
+    let mut settings = database::load_capture_settings(&app)?;
+    settings.token = database::random_token()?;
+    database::save_capture_settings(&app, &settings)?;
+    Ok(settings)
+}
 
-    let bytes = response
-        .bytes()
-        .map_err(|e| format!("Failed to read response: {}", e))?;
+#[tauri::command]
+pub async fn fetch_page_html(app: AppHandle, url: String) -> Result<String, FetchError> {
+    blocking(move || fetch::fetch_text(&app, &url)).await.map_err(|message| FetchError::Network { message })?
+}
 
-    let base64_data = BASE64.encode(&bytes);
-    let data_url = format!("data:{};base64,{}", content_type, base64_data);
+#[tauri::command]
+pub async fn fetch_image_url(app: AppHandle, url: String) -> Result<String, FetchError> {
+    let image = blocking(move || fetch::fetch(&app, &url, Expect::Media, Some(&url)))
+        .await
+        .map_err(|message| FetchError::Network { message })??;
+    Ok(image.data_url())
+}
 
-    Ok(data_url)
-}
\ No newline at end of file
+#[tauri::command]
+pub async fn resolve_page_images(app: AppHandle, url: String) -> Result<Vec<ImageCandidate>, FetchError> {
+    blocking(move || page_images::resolve(&app, &url)).await.map_err(|message| FetchError::Network { message })?
+}
+
+#[tauri::command]
+pub async fn describe_image_source(app: AppHandle, url: String, page_url: Option<String>) -> Result<SourceInfo, String> {
+    blocking(move || attribution::describe(&app, &url, page_url.as_deref())).await
+}
+
+#[tauri::command]
+pub fn get_source(app: AppHandle, board_id: Option<u64>, id: f64) -> Result<Option<SourceInfo>, String> {
+    attribution::find(&app, board_id, id)
+}
+
+#[tauri::command]
+pub fn start_fetch(app: AppHandle, url: String, kind: Expect) -> u64 {
+    downloads::start(&app, url, kind)
+}
+
+#[tauri::command]
+pub fn cancel_fetch(app: AppHandle, request_id: u64) -> bool {
+    downloads::cancel(&app, request_id)
+}
+
+#[tauri::command]
+pub fn get_fetch_settings(app: AppHandle) -> Result<FetchSettings, String> {
+    database::load_fetch_settings(&app)
+}
+
+#[tauri::command]
+pub fn set_fetch_settings(app: AppHandle, settings: FetchSettings) -> Result<FetchSettings, String> {
+    settings.check()?;
+    database::save_fetch_settings(&app, &settings)?;
+    Ok(settings)
+}
+
+/// Writes a backup of the whole library, to `path` or the backup folder.
+#[tauri::command(async)]
+pub fn create_backup(app: AppHandle, path: Option<String>) -> Result<BackupInfo, String> {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => backup::backup_dir(&app).join(backup::backup_file_name(false, database::now_millis())),
+    };
+    backup::create_backup(&app, &path)
+}
+
+#[tauri::command(async)]
+pub fn restore_backup(app: AppHandle, window: Window, path: String, mode: RestoreMode) -> Result<RestoreSummary, String> {
+    let boards_before = database::load_all_boards(&app)?;
+    let assets_before = database::load_all_assets(&app)?;
+    let summary = backup::restore_backup(&app, Path::new(&path), mode)?;
+
+    let origin = Some(window.label());
+    let boards: HashSet<u64> = database::load_all_boards(&app)?.into_iter().map(|b| b.id).collect();
+    for gone in boards_before.iter().filter(|b| !boards.contains(&b.id)) {
+        events::board_deleted(&app, gone.id, origin);
+    }
+    events::boards_updated(&app, summary.restored.iter().copied(), origin);
+    let assets = database::load_all_assets(&app)?;
+    let kept: HashSet<u64> = assets.iter().map(|a| a.id.to_bits()).collect();
+    let removed = assets_before.iter().map(|a| a.id).filter(|id| !kept.contains(&id.to_bits())).collect();
+    events::asset_library_changed(&app, assets, removed, origin);
+    events::tag_presets_changed(&app, database::load_tag_presets(&app)?, origin);
+    Ok(summary)
+}
+
+#[tauri::command]
+pub fn get_backup_settings(app: AppHandle) -> Result<BackupSettings, String> {
+    database::load_backup_settings(&app)
+}
+
+#[tauri::command]
+pub fn set_backup_settings(app: AppHandle, settings: BackupSettings) -> Result<BackupSettings, String> {
+    database::save_backup_settings(&app, &settings)?;
+    Ok(settings)
+}
+
+/// Syncs with the shared folder now instead of waiting for the schedule.
+#[tauri::command(async)]
+pub fn sync_now(app: AppHandle) -> Result<SyncReport, String> {
+    let report = sync::sync_now(&app)?;
+    sync::notify(&app, &report);
+    Ok(report)
+}
+
+#[tauri::command]
+pub fn get_sync_settings(app: AppHandle) -> Result<SyncSettings, String> {
+    database::load_sync_settings(&app)
+}
+
+#[tauri::command]
+pub fn set_sync_settings(app: AppHandle, settings: SyncSettings) -> Result<SyncSettings, String> {
+    database::save_sync_settings(&app, &settings)?;
+    Ok(settings)
+}
+
+/// Hosts a collaboration session for a board on the local network.
+#[tauri::command]
+pub fn start_collab(app: AppHandle, board_id: u64, name: Option<String>, port: Option<u16>) -> Result<SessionInfo, String> {
+    collab::start(&app, board_id, name.as_deref().unwrap_or_default(), port.unwrap_or(0))
+}
+
+/// Joins a session by its `host:port` address and code.
+#[tauri::command(async)]
+pub fn join_collab(app: AppHandle, address: String, code: String, name: Option<String>) -> Result<SessionInfo, String> {
+    collab::join(&app, &address, &code, name.as_deref().unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn get_collab_session(app: AppHandle) -> Option<SessionInfo> {
+    collab::info(&app)
+}
+
+#[tauri::command]
+pub fn send_collab_ops(app: AppHandle, ops: Vec<Op>) -> Result<(), String> {
+    collab::submit(&app, ops)
+}
+
+#[tauri::command]
+pub fn send_collab_cursor(app: AppHandle, x: f64, y: f64) {
+    collab::cursor(&app, x, y)
+}
+
+#[tauri::command]
+pub fn leave_collab(app: AppHandle) {
+    collab::leave(&app)
+}
+
+/// Applies edits to a board's change history and returns the board as it
+/// now stands.
+#[tauri::command]
+pub fn apply_board_ops(app: AppHandle, window: Window, board_id: u64, ops: Vec<Op>) -> Result<Board, String> {
+    let board = crdt::apply_ops(&app, board_id, &ops)?;
+    events::board_updated(&app, &board, Vec::new(), Some(window.label()));
+    Ok(board)
+}
+
+/// Changes to a board since `heads`, or all of them, for another copy to
+/// merge.
+#[tauri::command]
+pub fn get_board_changes(app: AppHandle, board_id: u64, heads: Option<Vec<String>>) -> Result<BoardChanges, String> {
+    crdt::changes(&app, board_id, heads.as_deref())
+}
+
+#[tauri::command]
+pub fn merge_board_changes(app: AppHandle, window: Window, board_id: u64, data: String) -> Result<Board, String> {
+    let board = crdt::merge(&app, board_id, &data)?;
+    events::board_updated(&app, &board, Vec::new(), Some(window.label()));
+    Ok(board)
+}
+
+/// Opens a floating window for a board, or one of its layers, where it was
+/// last left; an open one is brought forward and made clickable again.
+#[tauri::command]
+pub fn open_floating_window(app: AppHandle, board_id: u64, layer_id: Option<f64>) -> Result<FloatingWindow, String> {
+    floating::open(&app, board_id, layer_id)
+}
+
+#[tauri::command]
+pub fn set_floating_window_options(app: AppHandle, board_id: u64, layer_id: Option<f64>, options: FloatingOptions) -> Result<FloatingWindow, String> {
+    floating::set_options(&app, board_id, layer_id, options)
+}
+
+#[tauri::command]
+pub fn get_floating_windows(app: AppHandle) -> Vec<FloatingWindow> {
+    floating::list(&app)
+}
+
+#[tauri::command]
+pub fn get_http_cache_info(app: AppHandle) -> CacheInfo {
+    http_cache::info(&app)
+}
+
+#[tauri::command]
+pub fn clear_http_cache(app: AppHandle) -> Result<CacheInfo, String> {
+    http_cache::clear(&app)
+}
+
+#[tauri::command]
+pub fn set_http_cache_limit(app: AppHandle, max_bytes: u64) -> Result<CacheInfo, String> {
+    http_cache::set_limit(&app, max_bytes)
+}
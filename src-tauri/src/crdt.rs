@@ -0,0 +1,521 @@
+//! Boards as Automerge documents, so edits from several windows, sync
+//! folders and collaborators merge instead of overwriting each other.
+//!
+//! A document holds the board's `name` and `bgColor`, one map per
+//! collection (layers, strokes, objects, groups) from element id to a map
+//! of the element's fields, and `order`, the position of each element per
+//! collection. Fields holding objects or arrays (stroke points, crop data)
+//! are stored whole as JSON bytes: edits to different fields merge, and
+//! concurrent edits to one field settle on one of them.
+//!
+//! Documents live in `crdt/<id>.automerge`, created from the board file
+//! when first needed. Anything saved to the board file since is recorded
+//! as a change before ops or remote changes are applied, and the result is
+//! written back to the board file in its usual shape. A copy without a
+//! document starts from the first whole document merged into it, so the
+//! two share their history. Encrypted boards don't get a document, since
+//! it would keep their history in the clear.
+
+use crate::collab::{self, Change, Collection, Op};
+use crate::database::{self, Board, DataDir};
+use crate::vault;
+use automerge::transaction::{CommitOptions, Transactable};
+use automerge::{ActorId, AutoCommit, ChangeHash, ObjId, ObjType, ReadDoc, ScalarValue, Value as DocValue, ROOT};
+use base64::Engine;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Board fields kept in the document besides the collections.
+const BOARD_FIELDS: [&str; 2] = ["name", "bgColor"];
+
+/// Serializes loading, changing and saving documents.
+static DOCS: Mutex<()> = Mutex::new(());
+
+/// Changes to send to another copy of a board.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BoardChanges {
+    /// Where the document is now; pass back to get only later changes.
+    pub heads: Vec<String>,
+    /// Base64 Automerge changes.
+    pub data: String,
+}
+
+fn doc_path(app: &impl DataDir, id: u64) -> PathBuf {
+    app.data_dir().join("crdt").join(format!("{}.automerge", id))
+}
+
+fn err(e: impl std::fmt::Display) -> String {
+    e.to_string()
+}
+
+/// JSON as a document scalar; objects and arrays go whole as JSON bytes.
+fn encode(value: &Value) -> ScalarValue {
+    match value {
+        Value::Null => ScalarValue::Null,
+        Value::Bool(b) => ScalarValue::Boolean(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => ScalarValue::Int(i),
+            None => ScalarValue::F64(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => ScalarValue::Str(s.as_str().into()),
+        other => ScalarValue::Bytes(serde_json::to_vec(other).unwrap_or_default()),
+    }
+}
+
+fn decode(value: &ScalarValue) -> Value {
+    match value {
+        ScalarValue::Boolean(b) => Value::Bool(*b),
+        ScalarValue::Int(i) => Value::from(*i),
+        ScalarValue::Uint(u) => Value::from(*u),
+        ScalarValue::F64(f) => Value::from(*f),
+        ScalarValue::Str(s) => Value::String(s.to_string()),
+        ScalarValue::Bytes(bytes) => serde_json::from_slice(bytes).unwrap_or(Value::Null),
+        _ => Value::Null,
+    }
+}
+
+/// The map at `key` under `parent`, created when missing.
+fn map_at(doc: &mut AutoCommit, parent: &ObjId, key: &str) -> Result<ObjId, String> {
+    match doc.get(parent, key).map_err(err)? {
+        Some((DocValue::Object(ObjType::Map), id)) => Ok(id),
+        _ => doc.put_object(parent, key, ObjType::Map).map_err(err),
+    }
+}
+
+fn scalar(doc: &AutoCommit, obj: &ObjId, key: &str) -> Result<Option<ScalarValue>, String> {
+    Ok(match doc.get(obj, key).map_err(err)? {
+        Some((DocValue::Scalar(value), _)) => Some(value.into_owned()),
+        _ => None,
+    })
+}
+
+/// Writes `value` unless the document already holds it, so unchanged
+/// fields don't become new changes.
+fn put_if_changed(doc: &mut AutoCommit, obj: &ObjId, key: &str, value: &Value) -> Result<(), String> {
+    let encoded = encode(value);
+    if scalar(doc, obj, key)?.as_ref() != Some(&encoded) {
+        doc.put(obj, key, encoded).map_err(err)?;
+    }
+    Ok(())
+}
+
+fn element_map(doc: &AutoCommit, obj: &ObjId) -> Result<Map<String, Value>, String> {
+    let mut map = Map::new();
+    for key in doc.keys(obj) {
+        if let Some(value) = scalar(doc, obj, &key)? {
+            map.insert(key, decode(&value));
+        }
+    }
+    Ok(map)
+}
+
+/// Replaces an element's fields with `value`'s.
+fn write_element(doc: &mut AutoCommit, collection: &ObjId, key: &str, value: &Map<String, Value>) -> Result<(), String> {
+    let element = map_at(doc, collection, key)?;
+    let stale: Vec<String> = doc.keys(&element).filter(|k| !value.contains_key(k)).collect();
+    for field in stale {
+        doc.delete(&element, field.as_str()).map_err(err)?;
+    }
+    for (field, value) in value {
+        put_if_changed(doc, &element, field, value)?;
+    }
+    Ok(())
+}
+
+/// Keys of a collection in board order.
+fn ordered_keys(doc: &AutoCommit, collection: Collection) -> Result<Vec<String>, String> {
+    let Some((_, items)) = doc.get(ROOT, collection.field()).map_err(err)? else {
+        return Ok(Vec::new());
+    };
+    let order = match doc.get(ROOT, "order").map_err(err)? {
+        Some((_, order)) => doc.get(&order, collection.field()).map_err(err)?.map(|(_, o)| o),
+        None => None,
+    };
+    let mut keys: Vec<(f64, String)> = doc
+        .keys(&items)
+        .map(|key| {
+            let position = order
+                .as_ref()
+                .and_then(|o| scalar(doc, o, &key).ok().flatten())
+                .map(|p| match decode(&p) {
+                    Value::Number(n) => n.as_f64().unwrap_or(f64::MAX),
+                    _ => f64::MAX,
+                })
+                .unwrap_or(f64::MAX);
+            (position, key)
+        })
+        .collect();
+    keys.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+    Ok(keys.into_iter().map(|(_, key)| key).collect())
+}
+
+/// The highest position in an order map, or -1 when it's empty.
+fn last_position(doc: &AutoCommit, order: &ObjId) -> Result<f64, String> {
+    let mut last = -1.0_f64;
+    for key in doc.keys(order) {
+        if let Some(Value::Number(n)) = scalar(doc, order, &key)?.map(|p| decode(&p)) {
+            last = last.max(n.as_f64().unwrap_or(last));
+        }
+    }
+    Ok(last)
+}
+
+/// An empty document. Its top-level maps are created by the same change
+/// everywhere, so documents started separately for one board still keep
+/// each other's elements when merged, rather than one side's whole
+/// collections replacing the other's.
+fn new_doc() -> Result<AutoCommit, String> {
+    let mut doc = AutoCommit::new().with_actor(ActorId::from(b"eyedea-board".as_slice()));
+    let order = doc.put_object(ROOT, "order", ObjType::Map).map_err(err)?;
+    for collection in Collection::ELEMENTS {
+        doc.put_object(ROOT, collection.field(), ObjType::Map).map_err(err)?;
+        doc.put_object(&order, collection.field(), ObjType::Map).map_err(err)?;
+    }
+    doc.commit_with(CommitOptions::default().with_time(0));
+    doc.set_actor(ActorId::random());
+    Ok(doc)
+}
+
+/// Records what differs between the board and the document as a change.
+fn record(doc: &mut AutoCommit, board: &Board) -> Result<(), String> {
+    let json = serde_json::to_value(board).map_err(err)?;
+    for field in BOARD_FIELDS {
+        put_if_changed(doc, &ROOT, field, &json[field])?;
+    }
+    let order_root = map_at(doc, &ROOT, "order")?;
+    for collection in Collection::ELEMENTS {
+        let name = collection.field();
+        let items = match &json[name] {
+            Value::Array(items) => items.iter().filter_map(|item| Some((collab::key(item.get("id")?), item.as_object()?))).collect(),
+            _ => Vec::new(),
+        };
+        let before = ordered_keys(doc, collection)?;
+        let obj = map_at(doc, &ROOT, name)?;
+        let order = map_at(doc, &order_root, name)?;
+
+        let keep: HashSet<&str> = items.iter().map(|(key, _)| key.as_str()).collect();
+        for key in before.iter().filter(|key| !keep.contains(key.as_str())) {
+            doc.delete(&obj, key.as_str()).map_err(err)?;
+            doc.delete(&order, key.as_str()).map_err(err)?;
+        }
+        for (key, value) in &items {
+            write_element(doc, &obj, key, value)?;
+        }
+        // Elements new to the document go last; positions are only
+        // rewritten when the board moved elements around
+        let mut expected: Vec<&String> = before.iter().filter(|key| keep.contains(key.as_str())).collect();
+        let known: HashSet<&String> = expected.iter().copied().collect();
+        let added: Vec<&String> = items.iter().map(|(key, _)| key).filter(|key| !known.contains(key)).collect();
+        expected.extend(&added);
+        if items.iter().map(|(key, _)| key).eq(expected.iter().copied()) {
+            let mut next = last_position(doc, &order)?;
+            for key in added {
+                next += 1.0;
+                put_if_changed(doc, &order, key, &Value::from(next))?;
+            }
+        } else {
+            for (position, (key, _)) in items.iter().enumerate() {
+                put_if_changed(doc, &order, key, &Value::from(position as f64))?;
+            }
+        }
+    }
+    doc.commit();
+    Ok(())
+}
+
+/// Applies ops the way [`collab::Replica`] does, as document changes.
+fn apply(doc: &mut AutoCommit, ops: &[Op]) -> Result<(), String> {
+    let order_root = map_at(doc, &ROOT, "order")?;
+    for op in ops {
+        if op.collection == Collection::Board {
+            let Change::Patch { fields } = &op.change else {
+                return Err("Only patches apply to the board".to_string());
+            };
+            for (field, value) in fields {
+                if !BOARD_FIELDS.contains(&field.as_str()) || !value.is_string() {
+                    return Err(format!("Can't set board field {}", field));
+                }
+                put_if_changed(doc, &ROOT, field, value)?;
+            }
+            continue;
+        }
+        let name = op.collection.field();
+        let key = collab::key(&op.id);
+        let obj = map_at(doc, &ROOT, name)?;
+        let order = map_at(doc, &order_root, name)?;
+        let exists = doc.get(&obj, key.as_str()).map_err(err)?.is_some();
+        match &op.change {
+            Change::Put { value } => {
+                let mut value = value.as_object().ok_or("An element must be an object")?.clone();
+                value.insert("id".to_string(), op.id.clone());
+                if !exists {
+                    let next = last_position(doc, &order)? + 1.0;
+                    put_if_changed(doc, &order, &key, &Value::from(next))?;
+                }
+                write_element(doc, &obj, &key, &value)?;
+            }
+            Change::Patch { fields } => {
+                if !exists {
+                    continue;
+                }
+                let element = map_at(doc, &obj, &key)?;
+                for (field, value) in fields.iter().filter(|(field, _)| *field != "id") {
+                    put_if_changed(doc, &element, field, value)?;
+                }
+            }
+            Change::Remove => {
+                if exists {
+                    doc.delete(&obj, key.as_str()).map_err(err)?;
+                    doc.delete(&order, key.as_str()).map_err(err)?;
+                }
+            }
+        }
+    }
+    doc.commit();
+    Ok(())
+}
+
+/// The document as a board, taking everything else from `onto`.
+pub fn export(doc: &AutoCommit, onto: &Board) -> Result<Board, String> {
+    let mut json = serde_json::to_value(onto).map_err(err)?;
+    let board = json.as_object_mut().ok_or("Invalid board")?;
+    for field in BOARD_FIELDS {
+        if let Some(value) = scalar(doc, &ROOT, field)? {
+            board.insert(field.to_string(), decode(&value));
+        }
+    }
+    for collection in Collection::ELEMENTS {
+        let name = collection.field();
+        let mut items = Vec::new();
+        if let Some((_, obj)) = doc.get(ROOT, name).map_err(err)? {
+            for key in ordered_keys(doc, collection)? {
+                if let Some((DocValue::Object(ObjType::Map), element)) = doc.get(&obj, key.as_str()).map_err(err)? {
+                    items.push(Value::Object(element_map(doc, &element)?));
+                }
+            }
+        }
+        board.insert(name.to_string(), Value::Array(items));
+    }
+    serde_json::from_value(json).map_err(|e| format!("Invalid board in document: {}", e))
+}
+
+/// Opens a board's document, catching it up with the board file. Without
+/// a document yet, `base` (another copy's whole document) is used when
+/// given, so both copies share a history from then on.
+fn open(app: &impl DataDir, id: u64, base: Option<&[u8]>) -> Result<(AutoCommit, Board), String> {
+    if vault::is_encrypted(app, id) {
+        return Err("Encrypted boards don't keep a change history".to_string());
+    }
+    let board = database::load_board(app, id)?;
+    let mut doc = match fs::read(doc_path(app, id)) {
+        Ok(bytes) => AutoCommit::load(&bytes).map_err(|e| format!("Invalid document for board {}: {}", id, e))?,
+        Err(_) => match base {
+            Some(bytes) => AutoCommit::load(bytes).map_err(|e| format!("Invalid changes: {}", e))?,
+            None => new_doc()?,
+        },
+    };
+    record(&mut doc, &board)?;
+    Ok((doc, board))
+}
+
+fn save_doc(app: &impl DataDir, id: u64, doc: &mut AutoCommit) -> Result<(), String> {
+    let path = doc_path(app, id);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(err)?;
+    }
+    let partial = path.with_extension("automerge.part");
+    fs::write(&partial, doc.save()).map_err(err)?;
+    fs::rename(&partial, &path).map_err(err)
+}
+
+/// Writes the merged document back to the board file.
+fn commit(app: &impl DataDir, id: u64, doc: &mut AutoCommit, board: &Board) -> Result<Board, String> {
+    let mut merged = export(doc, board)?;
+    database::check_media_srcs(&database::get_images_dir(app), merged.layers.iter().map(|l| l.src.as_str()))?;
+    merged.updated_at = database::now_millis();
+    database::save_board(app, &merged)?;
+    save_doc(app, id, doc)?;
+    Ok(merged)
+}
+
+/// Records a board as saved, e.g. by `update_board`. Encrypted boards are
+/// skipped.
+pub fn record_board(app: &impl DataDir, board: &Board) -> Result<(), String> {
+    let _guard = DOCS.lock().unwrap();
+    if vault::is_encrypted(app, board.id) {
+        return Ok(());
+    }
+    let mut doc = match fs::read(doc_path(app, board.id)) {
+        Ok(bytes) => AutoCommit::load(&bytes).map_err(err)?,
+        Err(_) => new_doc()?,
+    };
+    record(&mut doc, board)?;
+    save_doc(app, board.id, &mut doc)
+}
+
+/// Applies local ops and returns the board as it now stands.
+pub fn apply_ops(app: &impl DataDir, id: u64, ops: &[Op]) -> Result<Board, String> {
+    let _guard = DOCS.lock().unwrap();
+    let (mut doc, board) = open(app, id, None)?;
+    apply(&mut doc, ops)?;
+    commit(app, id, &mut doc, &board)
+}
+
+/// Changes made since `heads`, or the whole document without them.
+pub fn changes(app: &impl DataDir, id: u64, heads: Option<&[String]>) -> Result<BoardChanges, String> {
+    let _guard = DOCS.lock().unwrap();
+    let (mut doc, _) = open(app, id, None)?;
+    save_doc(app, id, &mut doc)?;
+    let data = match heads {
+        Some(heads) => {
+            let heads = heads.iter().map(|h| h.parse::<ChangeHash>().map_err(err)).collect::<Result<Vec<_>, _>>()?;
+            doc.save_after(&heads)
+        }
+        None => doc.save(),
+    };
+    Ok(BoardChanges {
+        heads: doc.get_heads().iter().map(|h| h.to_string()).collect(),
+        data: base64::engine::general_purpose::STANDARD.encode(data),
+    })
+}
+
+/// Merges changes from another copy of the board and returns the result.
+pub fn merge(app: &impl DataDir, id: u64, data: &str) -> Result<Board, String> {
+    let _guard = DOCS.lock().unwrap();
+    let bytes = base64::engine::general_purpose::STANDARD.decode(data).map_err(err)?;
+    let (mut doc, board) = open(app, id, Some(&bytes))?;
+    doc.load_incremental(&bytes).map_err(|e| format!("Invalid changes: {}", e))?;
+    commit(app, id, &mut doc, &board)
+}
+
+/// Drops a board's document, when the board is deleted or encrypted.
+pub fn forget(app: &impl DataDir, id: u64) {
+    let _guard = DOCS.lock().unwrap();
+    let _ = fs::remove_file(doc_path(app, id));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn machine(label: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("eyedea-crdt-{}-{}", label, database::random_token().unwrap()));
+        database::init_storage(&dir).unwrap();
+        dir
+    }
+
+    /// The same board saved on two machines, as sync or an import would.
+    fn shared_board(machines: &[&PathBuf]) {
+        let src = database::write_media_file(&database::get_images_dir(machines[0]), b"not really a png", "photo", "png").unwrap();
+        for dir in machines {
+            fs::write(database::get_images_dir(*dir).join(&src), b"not really a png").unwrap();
+            let mut board = database::new_board("Moodboard".to_string(), "#ffffff".to_string());
+            board.id = 1;
+            board.layers = serde_json::from_value(json!([
+                { "id": 1.0, "name": "photo", "src": src, "x": 0.0, "y": 0.0, "width": 10.0, "height": 10.0 },
+                { "id": 2.0, "name": "photo", "src": src, "x": 20.0, "y": 0.0, "width": 10.0, "height": 10.0 }
+            ]))
+            .unwrap();
+            database::save_board(*dir, &board).unwrap();
+        }
+    }
+
+    fn op(collection: Collection, id: Value, change: Value) -> Op {
+        serde_json::from_value(json!({ "collection": collection, "id": id, "change": change })).unwrap()
+    }
+
+    fn exchange(from: &PathBuf, to: &PathBuf) -> Board {
+        let changes = changes(from, 1, None).unwrap();
+        merge(to, 1, &changes.data).unwrap()
+    }
+
+    #[test]
+    fn merges_concurrent_edits() {
+        let (desktop, laptop) = (machine("desktop"), machine("laptop"));
+        shared_board(&[&desktop, &laptop]);
+        exchange(&desktop, &laptop);
+
+        apply_ops(&desktop, 1, &[op(Collection::Layers, json!(1), json!({ "type": "patch", "fields": { "x": 50.0 } }))]).unwrap();
+        apply_ops(
+            &laptop,
+            1,
+            &[
+                op(Collection::Board, Value::Null, json!({ "type": "patch", "fields": { "name": "Renamed" } })),
+                op(Collection::Layers, json!(1), json!({ "type": "patch", "fields": { "rotation": 90.0 } })),
+                op(Collection::Layers, json!(2), json!({ "type": "remove" })),
+                op(Collection::Strokes, json!(7), json!({ "type": "put", "value": { "points": [[0, 0], [5, 5]] } })),
+            ],
+        )
+        .unwrap();
+
+        exchange(&desktop, &laptop);
+        let merged = exchange(&laptop, &desktop);
+        let other = database::load_board(&laptop, 1).unwrap();
+        assert_eq!(serde_json::to_value(&merged.layers).unwrap(), serde_json::to_value(&other.layers).unwrap());
+        assert_eq!(merged.name, "Renamed");
+        assert_eq!(merged.layers.len(), 1);
+        assert_eq!(merged.layers[0].x, 50.0);
+        assert_eq!(merged.layers[0].rotation, Some(90.0));
+        assert_eq!(merged.strokes, Some(json!([{ "id": 7, "points": [[0, 0], [5, 5]] }])));
+    }
+
+    #[test]
+    fn records_saved_boards_before_merging() {
+        let (desktop, laptop) = (machine("desktop"), machine("laptop"));
+        shared_board(&[&desktop, &laptop]);
+        exchange(&desktop, &laptop);
+        exchange(&laptop, &desktop);
+        let since = changes(&desktop, 1, None).unwrap().heads;
+
+        // Saved straight to the board file, reordering the layers
+        let mut board = database::load_board(&desktop, 1).unwrap();
+        board.layers.reverse();
+        board.bg_color = "#000000".to_string();
+        record_board(&desktop, &board).unwrap();
+        database::save_board(&desktop, &board).unwrap();
+
+        let update = changes(&desktop, 1, Some(&since)).unwrap();
+        let merged = merge(&laptop, 1, &update.data).unwrap();
+        assert_eq!(merged.bg_color, "#000000");
+        assert_eq!(merged.layers.iter().map(|l| l.id).collect::<Vec<_>>(), vec![2.0, 1.0]);
+    }
+
+    #[test]
+    fn keeps_edits_from_two_windows() {
+        let dir = machine("windows");
+        shared_board(&[&dir]);
+        let src = database::load_board(&dir, 1).unwrap().layers[0].src.clone();
+
+        // Both windows opened the board before either saved
+        let editor = [
+            op(Collection::Layers, json!(1), json!({ "type": "patch", "fields": { "x": 50.0 } })),
+            op(Collection::Layers, json!(3), json!({ "type": "put", "value": { "name": "new", "src": src, "x": 0.0, "y": 40.0, "width": 10.0, "height": 10.0 } })),
+        ];
+        let floating = [
+            op(Collection::Layers, json!(1), json!({ "type": "patch", "fields": { "rotation": 90.0 } })),
+            op(Collection::Layers, json!(2), json!({ "type": "remove" })),
+        ];
+        apply_ops(&dir, 1, &editor).unwrap();
+        apply_ops(&dir, 1, &floating).unwrap();
+
+        let board = database::load_board(&dir, 1).unwrap();
+        assert_eq!(board.layers.iter().map(|l| l.id).collect::<Vec<_>>(), vec![1.0, 3.0]);
+        assert_eq!(board.layers[0].x, 50.0);
+        assert_eq!(board.layers[0].rotation, Some(90.0));
+    }
+
+    #[test]
+    fn refuses_encrypted_boards() {
+        let dir = machine("vault");
+        shared_board(&[&dir]);
+        vault::lock(&dir, 1, Some("secret")).unwrap();
+        assert!(apply_ops(&dir, 1, &[]).is_err());
+        assert!(!doc_path(&dir, 1).exists());
+    }
+}
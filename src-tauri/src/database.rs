@@ -1,7 +1,11 @@
 use base64::Engine;
+use crate::vault;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fs;
-use std::path::PathBuf;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use tauri::{AppHandle, Manager};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -50,12 +54,33 @@ pub struct Layer {
     pub gif_current_frame: Option<u32>,
     #[serde(default)]
     pub gif_playing: Option<bool>,
+    /// Where web-imported media came from.
+    #[serde(default)]
+    pub source: Option<SourceInfo>,
 }
 
 fn default_visible() -> bool {
     true
 }
 
+/// Attribution for media imported from the web, also kept under
+/// `source` in the asset metadata.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SourceInfo {
+    /// The media itself.
+    pub source_url: String,
+    /// The canonical URL of the page it was found on.
+    pub page_url: Option<String>,
+    pub page_title: Option<String>,
+    pub site_name: Option<String>,
+    pub author: Option<String>,
+    /// License URL or name, when the page declares one.
+    pub license: Option<String>,
+    pub copyright: Option<String>,
+    pub fetched_at: u64,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Asset {
@@ -88,6 +113,10 @@ pub struct Board {
     pub objects: Option<serde_json::Value>,
     #[serde(default)]
     pub groups: Option<serde_json::Value>,
+    /// Media left out on load because it points outside the store. The
+    /// entries stay in the file; this tells the user they exist.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rejected_media: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -100,6 +129,145 @@ pub struct BoardMetadata {
     pub updated_at: u64,
     #[serde(default)]
     pub thumbnail: Option<String>,
+    /// Encrypted and not unlocked this session.
+    #[serde(default)]
+    pub locked: bool,
+    #[serde(default)]
+    pub encrypted: bool,
+}
+
+/// A directory whose new media files are imported into a board.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchFolder {
+    pub id: u64,
+    pub board_id: u64,
+    pub path: String,
+    #[serde(default)]
+    pub recursive: bool,
+}
+
+/// Settings for the local capture server.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureSettings {
+    pub enabled: bool,
+    pub port: u16,
+    pub token: String,
+}
+
+/// Limits applied to remote fetches.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FetchSettings {
+    pub max_media_bytes: u64,
+    pub max_page_bytes: u64,
+    pub max_redirects: usize,
+    pub max_concurrent_downloads: usize,
+    /// Allows loopback, link-local and private network addresses.
+    pub allow_private_addresses: bool,
+}
+
+impl Default for FetchSettings {
+    fn default() -> Self {
+        FetchSettings {
+            max_media_bytes: 100 * 1024 * 1024,
+            max_page_bytes: 10 * 1024 * 1024,
+            max_redirects: 5,
+            max_concurrent_downloads: 4,
+            allow_private_addresses: false,
+        }
+    }
+}
+
+impl FetchSettings {
+    /// Refuses settings that would stall or break every fetch.
+    pub fn check(&self) -> Result<(), String> {
+        if self.max_media_bytes == 0 || self.max_page_bytes == 0 {
+            return Err("Size limits must be above zero".to_string());
+        }
+        if !(1..=16).contains(&self.max_concurrent_downloads) {
+            return Err("Concurrent downloads must be between 1 and 16".to_string());
+        }
+        if self.max_redirects > 20 {
+            return Err("At most 20 redirects can be followed".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Scheduled backups of the whole library.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BackupSettings {
+    pub enabled: bool,
+    pub interval_hours: u32,
+    /// Scheduled backups kept; older ones are deleted.
+    pub keep: usize,
+    /// Where scheduled backups go; `backups/` in the data directory when unset.
+    pub directory: Option<String>,
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        BackupSettings { enabled: false, interval_hours: 24, keep: 7, directory: None }
+    }
+}
+
+/// Syncing through a shared folder with other machines.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SyncSettings {
+    pub enabled: bool,
+    /// The shared folder; syncing is off without one.
+    pub directory: Option<String>,
+    pub interval_minutes: u32,
+    /// Shown to other machines in conflicted copies; the host name when unset.
+    pub device_name: Option<String>,
+}
+
+impl Default for SyncSettings {
+    fn default() -> Self {
+        SyncSettings { enabled: false, directory: None, interval_minutes: 5, device_name: None }
+    }
+}
+
+/// A floating reference window, remembered so it reopens as it was left.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FloatingWindow {
+    pub board_id: u64,
+    /// Shows just this layer when set.
+    pub layer_id: Option<f64>,
+    /// Logical position; centred on the screen when unset.
+    pub x: Option<f64>,
+    pub y: Option<f64>,
+    pub width: f64,
+    pub height: f64,
+    /// 0–1, applied to the board canvas.
+    pub opacity: f64,
+    pub always_on_top: bool,
+    /// Passes clicks through to whatever is underneath.
+    pub click_through: bool,
+    /// Still open when the app quit, so it's restored on launch.
+    pub open: bool,
+}
+
+impl Default for FloatingWindow {
+    fn default() -> Self {
+        FloatingWindow {
+            board_id: 0,
+            layer_id: None,
+            x: None,
+            y: None,
+            width: 800.0,
+            height: 600.0,
+            opacity: 1.0,
+            always_on_top: false,
+            click_through: false,
+            open: false,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -116,18 +284,36 @@ pub struct BoardUpdate {
     pub groups: Option<serde_json::Value>,
 }
 
-fn get_boards_dir(app: &AppHandle) -> PathBuf {
-    let data_dir = app.path().app_data_dir().expect("Failed to get app data dir");
+/// Where the app's data lives: resolved through Tauri for the app, given
+/// directly in command-line mode.
+pub trait DataDir {
+    fn data_dir(&self) -> PathBuf;
+}
+
+impl DataDir for AppHandle {
+    fn data_dir(&self) -> PathBuf {
+        self.path().app_data_dir().expect("Failed to get app data dir")
+    }
+}
+
+impl DataDir for PathBuf {
+    fn data_dir(&self) -> PathBuf {
+        self.clone()
+    }
+}
+
+pub fn get_boards_dir(app: &impl DataDir) -> PathBuf {
+    let data_dir = app.data_dir();
     data_dir.join("boards")
 }
 
-fn get_all_assets_path(app: &AppHandle) -> PathBuf {
-    let data_dir = app.path().app_data_dir().expect("Failed to get app data dir");
+fn get_all_assets_path(app: &impl DataDir) -> PathBuf {
+    let data_dir = app.data_dir();
     data_dir.join("all_assets.json")
 }
 
-pub fn get_images_dir(app: &AppHandle) -> PathBuf {
-    let data_dir = app.path().app_data_dir().expect("Failed to get app data dir");
+pub fn get_images_dir(app: &impl DataDir) -> PathBuf {
+    let data_dir = app.data_dir();
     data_dir.join("images")
 }
 
@@ -138,13 +324,13 @@ fn sanitize_filename(name: &str) -> String {
         .to_lowercase()
 }
 
-fn get_board_path(app: &AppHandle, name: &str, id: u64) -> PathBuf {
+fn get_board_path(app: &impl DataDir, name: &str, id: u64) -> PathBuf {
     let sanitized = sanitize_filename(name);
     let filename = format!("{}-{}.json", sanitized, id);
     get_boards_dir(app).join(filename)
 }
 
-pub fn init_storage(app: &AppHandle) -> Result<(), String> {
+pub fn init_storage(app: &impl DataDir) -> Result<(), String> {
     let boards_dir = get_boards_dir(app);
     fs::create_dir_all(&boards_dir).map_err(|e| e.to_string())?;
 
@@ -161,7 +347,7 @@ pub fn init_storage(app: &AppHandle) -> Result<(), String> {
     Ok(())
 }
 
-pub fn load_all_boards(app: &AppHandle) -> Result<Vec<BoardMetadata>, String> {
+pub fn load_all_boards(app: &impl DataDir) -> Result<Vec<BoardMetadata>, String> {
     let boards_dir = get_boards_dir(app);
     let mut boards = Vec::new();
     
@@ -177,25 +363,60 @@ pub fn load_all_boards(app: &AppHandle) -> Result<Vec<BoardMetadata>, String> {
                             created_at: board.created_at,
                             updated_at: board.updated_at,
                             thumbnail: board.thumbnail,
+                            locked: false,
+                            encrypted: false,
                         });
                     }
                 }
             }
         }
     }
+    boards.extend(vault::list(app));
     
     Ok(boards)
 }
 
-pub fn load_board(app: &AppHandle, id: u64) -> Result<Board, String> {
+/// An id for a board made here, after the time like [`new_board`]'s, that
+/// no stored board has.
+pub fn unused_board_id(app: &impl DataDir) -> Result<u64, String> {
+    let existing: HashSet<u64> = load_all_boards(app)?.into_iter().map(|b| b.id).collect();
+    let mut id = now_millis();
+    while existing.contains(&id) {
+        id += 1;
+    }
+    Ok(id)
+}
+
+/// An empty board stamped with the current time.
+pub fn new_board(name: String, bg_color: String) -> Board {
+    let now = now_millis();
+    Board {
+        id: now,
+        name,
+        bg_color,
+        created_at: now,
+        updated_at: now,
+        layers: Vec::new(),
+        assets: Vec::new(),
+        thumbnail: None,
+        view_state: None,
+        strokes: None,
+        objects: None,
+        groups: None,
+        rejected_media: Vec::new(),
+    }
+}
+
+pub fn load_board(app: &impl DataDir, id: u64) -> Result<Board, String> {
     let boards_dir = get_boards_dir(app);
     
     if let Ok(entries) = fs::read_dir(&boards_dir) {
         for entry in entries.flatten() {
             if entry.path().extension().map_or(false, |e| e == "json") {
                 if let Ok(content) = fs::read_to_string(entry.path()) {
-                    if let Ok(board) = serde_json::from_str::<Board>(&content) {
+                    if let Ok(mut board) = serde_json::from_str::<Board>(&content) {
                         if board.id == id {
+                            board.rejected_media = retain_safe_media(&get_images_dir(app), &mut board.layers, &mut board.assets);
                             return Ok(board);
                         }
                     }
@@ -203,11 +424,23 @@ pub fn load_board(app: &AppHandle, id: u64) -> Result<Board, String> {
             }
         }
     }
+    if let Some(mut board) = vault::load(app, id)? {
+        board.rejected_media = retain_safe_media(&get_images_dir(app), &mut board.layers, &mut board.assets);
+        return Ok(board);
+    }
     
     Err(format!("Board {} not found", id))
 }
 
-pub fn save_board(app: &AppHandle, board: &Board) -> Result<(), String> {
+pub fn save_board(app: &impl DataDir, board: &Board) -> Result<(), String> {
+    let images_dir = get_images_dir(app);
+    let mut board = Board { rejected_media: Vec::new(), ..board.clone() };
+    if vault::is_encrypted(app, board.id) {
+        if let Some(stored) = vault::load(app, board.id)? {
+            keep_rejected_media(&images_dir, stored.layers, &mut board.layers, stored.assets, &mut board.assets);
+        }
+        return vault::save(app, &board);
+    }
     let boards_dir = get_boards_dir(app);
     
     if let Ok(entries) = fs::read_dir(&boards_dir) {
@@ -216,8 +449,11 @@ pub fn save_board(app: &AppHandle, board: &Board) -> Result<(), String> {
             if path.extension().map_or(false, |e| e == "json") {
                 if let Ok(content) = fs::read_to_string(&path) {
                     if let Ok(existing) = serde_json::from_str::<Board>(&content) {
-                        if existing.id == board.id && existing.name != board.name {
-                            let _ = fs::remove_file(&path);
+                        if existing.id == board.id {
+                            if existing.name != board.name {
+                                let _ = fs::remove_file(&path);
+                            }
+                            keep_rejected_media(&images_dir, existing.layers, &mut board.layers, existing.assets, &mut board.assets);
                             break;
                         }
                     }
@@ -227,12 +463,20 @@ pub fn save_board(app: &AppHandle, board: &Board) -> Result<(), String> {
     }
     
     let path = get_board_path(app, &board.name, board.id);
-    let content = serde_json::to_string_pretty(board).map_err(|e| e.to_string())?;
+    let content = serde_json::to_string_pretty(&board).map_err(|e| e.to_string())?;
     fs::write(&path, content).map_err(|e| e.to_string())?;
     Ok(())
 }
 
-pub fn delete_board(app: &AppHandle, id: u64) -> Result<(), String> {
+pub fn delete_board(app: &impl DataDir, id: u64) -> Result<(), String> {
+    if vault::delete(app, id)? {
+        return Ok(());
+    }
+    delete_plain_board(app, id)
+}
+
+/// Deletes the unencrypted JSON of a board.
+pub fn delete_plain_board(app: &impl DataDir, id: u64) -> Result<(), String> {
     let boards_dir = get_boards_dir(app);
     
     if let Ok(entries) = fs::read_dir(&boards_dir) {
@@ -254,27 +498,35 @@ pub fn delete_board(app: &AppHandle, id: u64) -> Result<(), String> {
     Err(format!("Board {} not found", id))
 }
 
-pub fn load_all_assets(app: &AppHandle) -> Result<Vec<Asset>, String> {
+pub fn load_all_assets(app: &impl DataDir) -> Result<Vec<Asset>, String> {
     let path = get_all_assets_path(app);
     let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
-    let assets = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+    let mut assets = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+    retain_safe_media(&get_images_dir(app), &mut Vec::new(), &mut assets);
     Ok(assets)
 }
 
-fn save_all_assets(app: &AppHandle, assets: &Vec<Asset>) -> Result<(), String> {
+fn save_all_assets(app: &impl DataDir, assets: &[Asset]) -> Result<(), String> {
     let path = get_all_assets_path(app);
-    let content = serde_json::to_string_pretty(assets).map_err(|e| e.to_string())?;
+    let stored: Vec<Asset> = fs::read_to_string(&path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+    let mut assets = assets.to_vec();
+    keep_rejected_media(&get_images_dir(app), Vec::new(), &mut Vec::new(), stored, &mut assets);
+    let content = serde_json::to_string_pretty(&assets).map_err(|e| e.to_string())?;
     fs::write(&path, content).map_err(|e| e.to_string())?;
     Ok(())
 }
 
 pub fn add_to_all_assets(
-    app: &AppHandle,
+    app: &impl DataDir,
     name: String,
     src: String,
     tags: Option<Vec<String>>,
     metadata: Option<serde_json::Value>,
 ) -> Result<Asset, String> {
+    check_media_src(&get_images_dir(app), &src)?;
     let mut all_assets = load_all_assets(app)?;
 
     if let Some(existing) = all_assets.iter().find(|a| a.name == name && a.src == src) {
@@ -294,14 +546,33 @@ pub fn add_to_all_assets(
     Ok(asset)
 }
 
-pub fn delete_from_all_assets(app: &AppHandle, id: f64) -> Result<(), String> {
+/// Adds several assets to the library in one write, skipping any whose
+/// name and src are already present. Returns the assets that were added.
+pub fn add_assets_to_library(app: &impl DataDir, assets: Vec<Asset>) -> Result<Vec<Asset>, String> {
+    check_media_srcs(&get_images_dir(app), assets.iter().map(|a| a.src.as_str()))?;
+    let mut all_assets = load_all_assets(app)?;
+    let mut added = Vec::new();
+    for asset in assets {
+        if all_assets.iter().any(|a| a.name == asset.name && a.src == asset.src) {
+            continue;
+        }
+        all_assets.push(asset.clone());
+        added.push(asset);
+    }
+    if !added.is_empty() {
+        save_all_assets(app, &all_assets)?;
+    }
+    Ok(added)
+}
+
+pub fn delete_from_all_assets(app: &impl DataDir, id: f64) -> Result<(), String> {
     let mut all_assets = load_all_assets(app)?;
     all_assets.retain(|a| a.id != id);
     save_all_assets(app, &all_assets)?;
     Ok(())
 }
 
-pub fn delete_board_asset(app: &AppHandle, board_id: u64, asset_id: f64) -> Result<Board, String> {
+pub fn delete_board_asset(app: &impl DataDir, board_id: u64, asset_id: f64) -> Result<Board, String> {
     let mut board = load_board(app, board_id)?;
     board.assets.retain(|a| a.id != asset_id);
     board.updated_at = now_millis();
@@ -309,7 +580,8 @@ pub fn delete_board_asset(app: &AppHandle, board_id: u64, asset_id: f64) -> Resu
     Ok(board)
 }
 
-pub fn update_asset(app: &AppHandle, asset: Asset) -> Result<(), String> {
+pub fn update_asset(app: &impl DataDir, asset: Asset) -> Result<(), String> {
+    check_media_src(&get_images_dir(app), &asset.src)?;
     let mut all_assets = load_all_assets(app)?;
 
     if let Some(index) = all_assets.iter().position(|a| a.id == asset.id) {
@@ -321,12 +593,12 @@ pub fn update_asset(app: &AppHandle, asset: Asset) -> Result<(), String> {
     }
 }
 
-fn get_tag_presets_path(app: &AppHandle) -> PathBuf {
-    let data_dir = app.path().app_data_dir().expect("Failed to get app data dir");
+fn get_tag_presets_path(app: &impl DataDir) -> PathBuf {
+    let data_dir = app.data_dir();
     data_dir.join("tag_presets.json")
 }
 
-pub fn load_tag_presets(app: &AppHandle) -> Result<Vec<String>, String> {
+pub fn load_tag_presets(app: &impl DataDir) -> Result<Vec<String>, String> {
     let path = get_tag_presets_path(app);
 
     if !path.exists() {
@@ -341,13 +613,170 @@ pub fn load_tag_presets(app: &AppHandle) -> Result<Vec<String>, String> {
     Ok(presets)
 }
 
-pub fn save_tag_presets(app: &AppHandle, presets: Vec<String>) -> Result<(), String> {
+pub fn save_tag_presets(app: &impl DataDir, presets: Vec<String>) -> Result<(), String> {
     let path = get_tag_presets_path(app);
     let content = serde_json::to_string_pretty(&presets).map_err(|e| e.to_string())?;
     fs::write(&path, content).map_err(|e| e.to_string())?;
     Ok(())
 }
 
+fn get_watch_folders_path(app: &impl DataDir) -> PathBuf {
+    let data_dir = app.data_dir();
+    data_dir.join("watch_folders.json")
+}
+
+pub fn load_watch_folders(app: &impl DataDir) -> Result<Vec<WatchFolder>, String> {
+    let path = get_watch_folders_path(app);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&content).map_err(|e| e.to_string())
+}
+
+pub fn save_watch_folders(app: &impl DataDir, folders: &[WatchFolder]) -> Result<(), String> {
+    let path = get_watch_folders_path(app);
+    let content = serde_json::to_string_pretty(folders).map_err(|e| e.to_string())?;
+    fs::write(&path, content).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn get_capture_settings_path(app: &impl DataDir) -> PathBuf {
+    let data_dir = app.data_dir();
+    data_dir.join("capture_settings.json")
+}
+
+/// Loads the capture settings, creating disabled defaults with a fresh
+/// token on first use.
+pub fn load_capture_settings(app: &impl DataDir) -> Result<CaptureSettings, String> {
+    let path = get_capture_settings_path(app);
+
+    if !path.exists() {
+        let settings = CaptureSettings {
+            enabled: false,
+            port: 41595,
+            token: random_token()?,
+        };
+        save_capture_settings(app, &settings)?;
+        return Ok(settings);
+    }
+
+    let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&content).map_err(|e| e.to_string())
+}
+
+pub fn save_capture_settings(app: &impl DataDir, settings: &CaptureSettings) -> Result<(), String> {
+    let path = get_capture_settings_path(app);
+    let content = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    fs::write(&path, content).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn get_fetch_settings_path(app: &impl DataDir) -> PathBuf {
+    let data_dir = app.data_dir();
+    data_dir.join("fetch_settings.json")
+}
+
+pub fn load_fetch_settings(app: &impl DataDir) -> Result<FetchSettings, String> {
+    let path = get_fetch_settings_path(app);
+    if !path.exists() {
+        return Ok(FetchSettings::default());
+    }
+    let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&content).map_err(|e| e.to_string())
+}
+
+pub fn save_fetch_settings(app: &impl DataDir, settings: &FetchSettings) -> Result<(), String> {
+    let path = get_fetch_settings_path(app);
+    let content = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    fs::write(&path, content).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn get_backup_settings_path(app: &impl DataDir) -> PathBuf {
+    let data_dir = app.data_dir();
+    data_dir.join("backup_settings.json")
+}
+
+pub fn load_backup_settings(app: &impl DataDir) -> Result<BackupSettings, String> {
+    let path = get_backup_settings_path(app);
+    if !path.exists() {
+        return Ok(BackupSettings::default());
+    }
+    let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&content).map_err(|e| e.to_string())
+}
+
+pub fn save_backup_settings(app: &impl DataDir, settings: &BackupSettings) -> Result<(), String> {
+    let path = get_backup_settings_path(app);
+    let content = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    fs::write(&path, content).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn get_sync_settings_path(app: &impl DataDir) -> PathBuf {
+    let data_dir = app.data_dir();
+    data_dir.join("sync_settings.json")
+}
+
+pub fn load_sync_settings(app: &impl DataDir) -> Result<SyncSettings, String> {
+    let path = get_sync_settings_path(app);
+    if !path.exists() {
+        return Ok(SyncSettings::default());
+    }
+    let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&content).map_err(|e| e.to_string())
+}
+
+pub fn save_sync_settings(app: &impl DataDir, settings: &SyncSettings) -> Result<(), String> {
+    let path = get_sync_settings_path(app);
+    let content = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    fs::write(&path, content).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn get_floating_windows_path(app: &impl DataDir) -> PathBuf {
+    let data_dir = app.data_dir();
+    data_dir.join("floating_windows.json")
+}
+
+pub fn load_floating_windows(app: &impl DataDir) -> Result<Vec<FloatingWindow>, String> {
+    let path = get_floating_windows_path(app);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&content).map_err(|e| e.to_string())
+}
+
+pub fn save_floating_windows(app: &impl DataDir, windows: &[FloatingWindow]) -> Result<(), String> {
+    let path = get_floating_windows_path(app);
+    let content = serde_json::to_string_pretty(windows).map_err(|e| e.to_string())?;
+    fs::write(&path, content).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// 32 random bytes as hex.
+pub fn random_token() -> Result<String, String> {
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes).map_err(|e| format!("Failed to generate token: {}", e))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// The first of `count` consecutive ids for new layers or assets. Ids start
+/// at the current time and never repeat within a run, so imports running
+/// side by side can't hand out the same ones.
+pub fn new_ids(count: usize) -> f64 {
+    static LAST: AtomicU64 = AtomicU64::new(0);
+    let count = count.max(1) as u64;
+    let mut first = 0;
+    let _ = LAST.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |last| {
+        first = now_millis().max(last + 1);
+        Some(first + count - 1)
+    });
+    first as f64
+}
+
 pub fn now_millis() -> u64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -355,7 +784,7 @@ pub fn now_millis() -> u64 {
         .as_millis() as u64
 }
 
-pub fn save_image_file(app: &AppHandle, data: String, name: String) -> Result<String, String> {
+pub fn save_image_file(app: &impl DataDir, data: String, name: String) -> Result<String, String> {
     let images_dir = get_images_dir(app);
 
     // Parse data URL: "data:image/png;base64,iVBOR..."
@@ -412,26 +841,326 @@ pub fn save_image_file(app: &AppHandle, data: String, name: String) -> Result<St
     Ok(filename)
 }
 
-pub fn save_media_file_from_path(app: &AppHandle, source_path: String, name: String) -> Result<String, String> {
+/// Copies a media file picked in the webview into the store. Only files
+/// whose contents are a supported image or video are accepted, since the
+/// store is readable from the webview.
+pub fn save_media_file_from_path(app: &impl DataDir, source_path: String, name: String) -> Result<String, String> {
+    let source = Path::new(&source_path)
+        .canonicalize()
+        .map_err(|e| format!("Failed to read {}: {}", source_path, e))?;
+    if !source.is_file() {
+        return Err(format!("Not a file: {}", source_path));
+    }
+    let mut head = Vec::with_capacity(1024);
+    fs::File::open(&source)
+        .and_then(|file| file.take(1024).read_to_end(&mut head))
+        .map_err(|e| format!("Failed to read {}: {}", source_path, e))?;
+    let ext = crate::import::sniff_media_type(&head)
+        .and_then(|mime| crate::import::media_extension(Some(mime), &source_path, &head))
+        .ok_or_else(|| format!("Not a supported media file: {}", source_path))?;
+
     let images_dir = get_images_dir(app);
-    let ext = std::path::Path::new(&source_path)
+    let filename = unique_media_filename(&images_dir, &name, ext);
+    fs::copy(&source, images_dir.join(&filename)).map_err(|e| format!("Failed to copy media file: {}", e))?;
+    Ok(filename)
+}
+
+/// Writes imported media bytes into the images dir under a fresh
+/// `{timestamp}_{name}.{ext}` filename, returning the filename.
+pub fn write_media_file(images_dir: &Path, bytes: &[u8], name: &str, ext: &str) -> Result<String, String> {
+    let filename = unique_media_filename(images_dir, name, ext);
+    fs::write(images_dir.join(&filename), bytes).map_err(|e| format!("Failed to write media file: {}", e))?;
+    Ok(filename)
+}
+
+/// Copies a file into the images dir the same way, keeping its extension.
+pub fn copy_media_file(images_dir: &Path, source: &Path) -> Result<String, String> {
+    let name = source.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let ext = source
         .extension()
-        .and_then(|e| e.to_str())
-        .unwrap_or("mp4");
-    let sanitized = sanitize_filename(&name);
-    let stem = sanitized
-        .rsplit_once('.')
-        .map(|(s, _)| s)
-        .unwrap_or(&sanitized);
-    let filename = format!("{}_{}.{}", now_millis(), stem, ext);
-    let dest_path = images_dir.join(&filename);
-    fs::copy(&source_path, &dest_path)
-        .map_err(|e| format!("Failed to copy media file: {}", e))?;
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let filename = unique_media_filename(images_dir, &name, &ext);
+    fs::copy(source, images_dir.join(&filename)).map_err(|e| format!("Failed to copy media file: {}", e))?;
     Ok(filename)
 }
 
-pub fn get_image_file_path(app: &AppHandle, filename: String) -> Result<String, String> {
-    let images_dir = get_images_dir(app);
-    let path = images_dir.join(&filename);
+fn unique_media_filename(images_dir: &Path, name: &str, ext: &str) -> String {
+    let stem = Path::new(name)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let stem = sanitize_filename(&stem);
+    let now = now_millis();
+    let mut filename = format!("{}_{}.{}", now, stem, ext);
+    let mut n = 1;
+    while images_dir.join(&filename).exists() {
+        filename = format!("{}_{}_{}.{}", now, stem, n, ext);
+        n += 1;
+    }
+    filename
+}
+
+pub fn get_image_file_path(app: &impl DataDir, filename: String) -> Result<String, String> {
+    let path = media_path(&get_images_dir(app), &filename)?;
     Ok(path.to_string_lossy().to_string())
-}
\ No newline at end of file
+}
+
+/// The path of a file in the media store, refusing names that could reach
+/// outside it: absolute paths, `..` and other special components, and
+/// symlinks leading out of the store.
+pub fn media_path(images_dir: &Path, src: &str) -> Result<PathBuf, String> {
+    let unsafe_path = || format!("Unsafe media path: {}", src);
+    if src.is_empty() || src.contains(['\\', '\0']) {
+        return Err(unsafe_path());
+    }
+    let relative = Path::new(src);
+    if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(unsafe_path());
+    }
+    let path = images_dir.join(relative);
+
+    // Without a store on disk there is no link to follow
+    let Ok(root) = images_dir.canonicalize() else {
+        return Ok(path);
+    };
+    // Wherever the deepest existing part leads must still be in the store;
+    // a dangling link fails to canonicalize and is refused
+    let existing = path
+        .ancestors()
+        .find(|p| fs::symlink_metadata(p).is_ok())
+        .unwrap_or(images_dir);
+    match existing.canonicalize() {
+        Ok(resolved) if resolved.starts_with(&root) => Ok(path),
+        _ => Err(unsafe_path()),
+    }
+}
+
+/// The scheme of a src that is a URL rather than a stored file name.
+fn url_scheme(src: &str) -> Option<&str> {
+    src.split_once(':').map(|(s, _)| s).filter(|s| {
+        s.len() > 1
+            && s.starts_with(|c: char| c.is_ascii_alphabetic())
+            && s.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+/// Whether a src names a file in the media store rather than a URL.
+pub fn is_stored_media(src: &str) -> bool {
+    !src.is_empty() && url_scheme(src).is_none()
+}
+
+/// Checks a layer or asset `src`: inline data and web URLs pass, other URL
+/// schemes are refused, and anything else must name a file in the store.
+pub fn check_media_src(images_dir: &Path, src: &str) -> Result<(), String> {
+    match url_scheme(src).map(|s| s.to_ascii_lowercase()).as_deref() {
+        Some("data" | "blob" | "http" | "https") => Ok(()),
+        Some(_) => Err(format!("Unsupported media URL: {}", src)),
+        None => media_path(images_dir, src).map(|_| ()),
+    }
+}
+
+/// Checks every `src` about to be stored on a board or in the library.
+pub fn check_media_srcs<'a>(images_dir: &Path, srcs: impl IntoIterator<Item = &'a str>) -> Result<(), String> {
+    srcs.into_iter().try_for_each(|src| check_media_src(images_dir, src))
+}
+
+/// Drops layers and assets whose `src` fails [`check_media_src`], so a
+/// crafted board file can't point the app outside the store, and returns
+/// the srcs dropped.
+fn retain_safe_media(images_dir: &Path, layers: &mut Vec<Layer>, assets: &mut Vec<Asset>) -> Vec<String> {
+    let mut rejected = Vec::new();
+    let mut safe = |src: &str| match check_media_src(images_dir, src) {
+        Ok(()) => true,
+        Err(e) => {
+            log::warn!("Skipping media: {}", e);
+            if !rejected.iter().any(|r| r == src) {
+                rejected.push(src.to_string());
+            }
+            false
+        }
+    };
+    layers.retain(|l| safe(&l.src));
+    assets.retain(|a| safe(&a.src));
+    rejected
+}
+
+/// Puts back the stored entries [`retain_safe_media`] left out on load, so
+/// saving what was loaded never erases them.
+fn keep_rejected_media(
+    images_dir: &Path,
+    stored_layers: Vec<Layer>,
+    layers: &mut Vec<Layer>,
+    stored_assets: Vec<Asset>,
+    assets: &mut Vec<Asset>,
+) {
+    let rejected = |src: &str| check_media_src(images_dir, src).is_err();
+    for layer in stored_layers {
+        if rejected(&layer.src) && !layers.iter().any(|l| l.id == layer.id && l.src == layer.src) {
+            layers.push(layer);
+        }
+    }
+    for asset in stored_assets {
+        if rejected(&asset.src) && !assets.iter().any(|a| a.id == asset.id && a.src == asset.src) {
+            assets.push(asset);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    /// A fresh data dir with storage initialized.
+    fn data_dir() -> PathBuf {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "eyedea-db-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        init_storage(&dir).unwrap();
+        dir
+    }
+
+    fn layer(src: &str) -> Layer {
+        serde_json::from_value(serde_json::json!({
+            "id": 1.0, "name": "l", "src": src, "x": 0.0, "y": 0.0, "width": 1.0, "height": 1.0
+        }))
+        .unwrap()
+    }
+
+    const PNG: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+    #[test]
+    fn accepts_files_in_the_store() {
+        let dir = data_dir();
+        let images = get_images_dir(&dir);
+        fs::write(images.join("1_cat.png"), PNG).unwrap();
+
+        assert_eq!(media_path(&images, "1_cat.png").unwrap(), images.join("1_cat.png"));
+        assert_eq!(media_path(&images, "not-yet.png").unwrap(), images.join("not-yet.png"));
+        assert_eq!(media_path(&images, "sub/2_dog.png").unwrap(), images.join("sub/2_dog.png"));
+    }
+
+    #[test]
+    fn rejects_traversal_and_absolute_paths() {
+        let dir = data_dir();
+        let images = get_images_dir(&dir);
+        for src in [
+            "",
+            "../all_assets.json",
+            "sub/../../all_assets.json",
+            "./1_cat.png",
+            "/etc/passwd",
+            "..\\..\\secret.txt",
+            "cat\0.png",
+        ] {
+            assert!(media_path(&images, src).is_err(), "{:?} was accepted", src);
+        }
+        assert!(get_image_file_path(&dir, "../../etc/passwd".to_string()).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinks_leaving_the_store() {
+        use std::os::unix::fs::symlink;
+        let dir = data_dir();
+        let images = get_images_dir(&dir);
+        let outside = dir.join("outside");
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("secret.txt"), "secret").unwrap();
+        fs::write(images.join("1_cat.png"), PNG).unwrap();
+
+        symlink(outside.join("secret.txt"), images.join("leak.png")).unwrap();
+        symlink(&outside, images.join("linked")).unwrap();
+        symlink(dir.join("missing"), images.join("dangling.png")).unwrap();
+        symlink(images.join("1_cat.png"), images.join("alias.png")).unwrap();
+
+        assert!(media_path(&images, "leak.png").is_err());
+        assert!(media_path(&images, "linked/secret.txt").is_err());
+        assert!(media_path(&images, "linked/new.png").is_err());
+        assert!(media_path(&images, "dangling.png").is_err());
+        assert!(media_path(&images, "alias.png").is_ok());
+    }
+
+    #[test]
+    fn checks_urls_by_scheme() {
+        let images = get_images_dir(&data_dir());
+        for src in ["data:image/png;base64,AAAA", "blob:http://localhost/1", "https://example.com/a.png", "HTTP://x/y"] {
+            assert!(check_media_src(&images, src).is_ok(), "{:?} was refused", src);
+        }
+        for src in ["file:///etc/passwd", "asset://localhost/%2Fetc%2Fpasswd", "javascript:alert(1)"] {
+            assert!(check_media_src(&images, src).is_err(), "{:?} was accepted", src);
+        }
+    }
+
+    #[test]
+    fn drops_unsafe_media_on_board_load() {
+        let dir = data_dir();
+        let mut board = new_board("Crafted".to_string(), "#fff".to_string());
+        board.layers = vec![layer("1_cat.png"), layer("../../../etc/passwd"), layer("file:///etc/hosts")];
+        board.assets = vec![Asset {
+            id: 2.0,
+            name: "a".to_string(),
+            src: "/etc/shadow".to_string(),
+            tags: Vec::new(),
+            metadata: None,
+        }];
+        save_board(&dir, &board).unwrap();
+
+        let mut loaded = load_board(&dir, board.id).unwrap();
+        let srcs: Vec<&str> = loaded.layers.iter().map(|l| l.src.as_str()).collect();
+        assert_eq!(srcs, ["1_cat.png"]);
+        assert!(loaded.assets.is_empty());
+        assert_eq!(loaded.rejected_media, ["../../../etc/passwd", "file:///etc/hosts", "/etc/shadow"]);
+
+        // Saving what was loaded keeps the entries it never showed
+        loaded.name = "Renamed".to_string();
+        save_board(&dir, &loaded).unwrap();
+        let content = fs::read_to_string(get_board_path(&dir, "Renamed", board.id)).unwrap();
+        let stored: Board = serde_json::from_str(&content).unwrap();
+        assert_eq!(stored.layers.len(), 3);
+        assert_eq!(stored.assets.len(), 1);
+        assert!(stored.rejected_media.is_empty() && !content.contains("rejectedMedia"));
+        assert_eq!(load_board(&dir, board.id).unwrap().rejected_media.len(), 3);
+    }
+
+    #[test]
+    fn keeps_unsafe_library_entries_on_save() {
+        let dir = data_dir();
+        let crafted = serde_json::json!([{ "id": 1.0, "name": "x", "src": "../secret.png", "tags": [] }]);
+        fs::write(get_all_assets_path(&dir), crafted.to_string()).unwrap();
+
+        add_to_all_assets(&dir, "a".to_string(), "1_cat.png".to_string(), None, None).unwrap();
+        assert_eq!(load_all_assets(&dir).unwrap().len(), 1);
+        let stored: Vec<Asset> = serde_json::from_str(&fs::read_to_string(get_all_assets_path(&dir)).unwrap()).unwrap();
+        assert_eq!(stored.len(), 2);
+    }
+
+    #[test]
+    fn refuses_unsafe_library_assets() {
+        let dir = data_dir();
+        assert!(add_to_all_assets(&dir, "a".to_string(), "../boards/x.json".to_string(), None, None).is_err());
+        assert!(add_to_all_assets(&dir, "a".to_string(), "1_cat.png".to_string(), None, None).is_ok());
+    }
+
+    #[test]
+    fn copies_only_media_files_from_paths() {
+        let dir = data_dir();
+        let disguised = dir.join("notes.mp4");
+        fs::write(&disguised, "not a video").unwrap();
+        let picture = dir.join("picture.png");
+        fs::write(&picture, PNG).unwrap();
+
+        let path = |p: &Path| p.to_string_lossy().to_string();
+        assert!(save_media_file_from_path(&dir, path(&disguised), "notes.mp4".to_string()).is_err());
+        assert!(save_media_file_from_path(&dir, path(&dir), "dir.png".to_string()).is_err());
+
+        let stored = save_media_file_from_path(&dir, path(&picture), "../../picture.png".to_string()).unwrap();
+        assert!(stored.ends_with(".png") && !stored.contains('/'));
+        assert_eq!(fs::read(get_images_dir(&dir).join(stored)).unwrap(), PNG);
+    }
+}
@@ -0,0 +1,122 @@
+//! Background fetches started by id, so slow sites never hold up a command
+//! and the user can cancel them.
+//!
+//! Each request emits `fetch-progress` while its body streams in and ends
+//! with `fetch-completed` (page text or a media data URL) or `fetch-failed`.
+//! At most `maxConcurrentDownloads` run at once; the rest wait their turn.
+
+use crate::database;
+use crate::fetch::{self, Expect, FetchError};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+
+/// Progress events are spaced at least this far apart per request.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Fetches in flight or waiting, held in Tauri state.
+#[derive(Default)]
+pub struct DownloadState {
+    next_id: AtomicU64,
+    cancelled: Mutex<HashMap<u64, Arc<AtomicBool>>>,
+    running: Mutex<usize>,
+    slot_freed: Condvar,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FetchProgress {
+    pub request_id: u64,
+    pub received: u64,
+    pub total: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FetchCompleted {
+    pub request_id: u64,
+    pub url: String,
+    /// Page text, or a data URL for media.
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FetchFailed {
+    pub request_id: u64,
+    pub url: String,
+    pub error: FetchError,
+}
+
+/// Queues a fetch and returns its request id straight away.
+pub fn start(app: &AppHandle, url: String, expect: Expect) -> u64 {
+    let state = app.state::<DownloadState>();
+    let request_id = state.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+    let cancelled = Arc::new(AtomicBool::new(false));
+    state.cancelled.lock().unwrap().insert(request_id, cancelled.clone());
+
+    let app = app.clone();
+    std::thread::spawn(move || {
+        let result = run(&app, request_id, &url, expect, &cancelled);
+        let state = app.state::<DownloadState>();
+        state.cancelled.lock().unwrap().remove(&request_id);
+
+        let _ = match result {
+            Ok(data) => app.emit("fetch-completed", FetchCompleted { request_id, url, data }),
+            Err(error) => app.emit("fetch-failed", FetchFailed { request_id, url, error }),
+        };
+    });
+    request_id
+}
+
+/// Cancels a queued or running fetch. Returns false if it already finished.
+pub fn cancel(app: &AppHandle, request_id: u64) -> bool {
+    let state = app.state::<DownloadState>();
+    let Some(cancelled) = state.cancelled.lock().unwrap().get(&request_id).cloned() else {
+        return false;
+    };
+    cancelled.store(true, Ordering::Relaxed);
+    // Wake anything waiting for a slot so it can notice; taking the lock
+    // first means a waiter either sees the flag or is already waiting
+    drop(state.running.lock().unwrap());
+    state.slot_freed.notify_all();
+    true
+}
+
+fn run(app: &AppHandle, request_id: u64, url: &str, expect: Expect, cancelled: &AtomicBool) -> Result<String, FetchError> {
+    let state = app.state::<DownloadState>();
+    let limit = database::load_fetch_settings(app).unwrap_or_default().max_concurrent_downloads.max(1);
+    {
+        let mut running = state.running.lock().unwrap();
+        while *running >= limit && !cancelled.load(Ordering::Relaxed) {
+            running = state.slot_freed.wait(running).unwrap();
+        }
+        if cancelled.load(Ordering::Relaxed) {
+            return Err(FetchError::Cancelled);
+        }
+        *running += 1;
+    }
+
+    let mut last_emit: Option<Instant> = None;
+    let mut progress = |received: u64, total: Option<u64>| {
+        if last_emit.is_none_or(|t| t.elapsed() >= PROGRESS_INTERVAL) || total == Some(received) {
+            last_emit = Some(Instant::now());
+            let _ = app.emit("fetch-progress", FetchProgress { request_id, received, total });
+        }
+        !cancelled.load(Ordering::Relaxed)
+    };
+    let referer = (expect == Expect::Media).then_some(url);
+    let result = fetch::fetch_with_progress(app, url, expect, referer, &mut progress);
+
+    *state.running.lock().unwrap() -= 1;
+    state.slot_freed.notify_all();
+
+    let fetched = result?;
+    Ok(match expect {
+        Expect::Page => fetched.text(),
+        Expect::Media | Expect::MediaOrPage => fetched.data_url(),
+    })
+}
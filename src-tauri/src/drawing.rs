@@ -0,0 +1,450 @@
+use serde::{Deserialize, Serialize};
+
+/// An axis-aligned rectangle in board (world) coordinates.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rect {
+    pub fn right(&self) -> f64 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> f64 {
+        self.y + self.height
+    }
+
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect {
+            x,
+            y,
+            width: self.right().max(other.right()) - x,
+            height: self.bottom().max(other.bottom()) - y,
+        }
+    }
+
+    pub fn inflate(&self, by: f64) -> Rect {
+        Rect {
+            x: self.x - by,
+            y: self.y - by,
+            width: self.width + by * 2.0,
+            height: self.height + by * 2.0,
+        }
+    }
+
+    /// Bounding box of this rectangle rotated by `degrees` around its center.
+    pub fn rotated_bounds(&self, degrees: f64) -> Rect {
+        if degrees == 0.0 {
+            return *self;
+        }
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let width = (self.width * cos).abs() + (self.height * sin).abs();
+        let height = (self.width * sin).abs() + (self.height * cos).abs();
+        Rect {
+            x: self.x + (self.width - width) / 2.0,
+            y: self.y + (self.height - height) / 2.0,
+            width,
+            height,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct StrokePoint {
+    pub x: f64,
+    pub y: f64,
+    /// Pen pressure from 0 to 1, when the input device reported it.
+    #[serde(default)]
+    pub pressure: Option<f64>,
+}
+
+/// A pen, highlighter or eraser stroke as stored in `Board.strokes`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Stroke {
+    pub tool: String,
+    #[serde(default)]
+    pub mode: Option<String>,
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub opacity: Option<f64>,
+    #[serde(default)]
+    pub size: Option<f64>,
+    #[serde(default)]
+    pub points: Vec<StrokePoint>,
+}
+
+impl Stroke {
+    pub fn is_pixel_eraser(&self) -> bool {
+        self.tool == "eraser" && self.mode.as_deref() == Some("pixels")
+    }
+
+    pub fn is_stroke_eraser(&self) -> bool {
+        self.tool == "eraser" && !self.is_pixel_eraser()
+    }
+
+    pub fn width(&self) -> f64 {
+        self.size.unwrap_or(2.0)
+    }
+
+    pub fn has_pressure(&self) -> bool {
+        self.points.iter().any(|p| p.pressure.is_some())
+    }
+
+    /// Line width at a point, scaled by its pressure when recorded.
+    pub fn width_at(&self, point: &StrokePoint) -> f64 {
+        match point.pressure {
+            Some(pressure) => self.width() * pressure.clamp(0.1, 1.0),
+            None => self.width(),
+        }
+    }
+
+    /// Alpha the canvas applies when drawing this stroke.
+    pub fn alpha(&self) -> f64 {
+        let opacity = self.opacity.unwrap_or(1.0);
+        if self.tool == "highlighter" {
+            0.3 * opacity
+        } else {
+            opacity
+        }
+    }
+
+    pub fn bounds(&self) -> Option<Rect> {
+        let first = self.points.first()?;
+        let mut rect = Rect { x: first.x, y: first.y, width: 0.0, height: 0.0 };
+        for p in &self.points[1..] {
+            rect = rect.union(&Rect { x: p.x, y: p.y, width: 0.0, height: 0.0 });
+        }
+        Some(rect.inflate(self.width() / 2.0))
+    }
+}
+
+/// A run of ink strokes followed by the pixel erasers drawn after them.
+/// Each eraser cuts into everything drawn before it, so vector outputs apply
+/// the erasers of a pass as a mask over all previous passes.
+pub struct InkPass<'a> {
+    pub strokes: Vec<&'a Stroke>,
+    pub erasers: Vec<&'a Stroke>,
+}
+
+/// Splits strokes into passes in drawing order. Stroke-mode erasers are
+/// dropped since the editor already removed the strokes they touched.
+pub fn ink_passes(strokes: &[Stroke]) -> Vec<InkPass<'_>> {
+    let mut passes: Vec<InkPass> = Vec::new();
+    for stroke in strokes.iter().filter(|s| !s.is_stroke_eraser() && s.points.len() >= 2) {
+        let starts_pass = match passes.last() {
+            Some(pass) => !stroke.is_pixel_eraser() && !pass.erasers.is_empty(),
+            None => true,
+        };
+        if starts_pass {
+            passes.push(InkPass { strokes: Vec::new(), erasers: Vec::new() });
+        }
+        if let Some(pass) = passes.last_mut() {
+            if stroke.is_pixel_eraser() {
+                pass.erasers.push(stroke);
+            } else {
+                pass.strokes.push(stroke);
+            }
+        }
+    }
+    passes
+}
+
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TextStyle {
+    #[serde(default)]
+    pub font_size: Option<f64>,
+    #[serde(default)]
+    pub font_family: Option<String>,
+    #[serde(default)]
+    pub font_weight: Option<serde_json::Value>,
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub text_decoration: Option<String>,
+}
+
+impl TextStyle {
+    pub fn size(&self) -> f64 {
+        self.font_size.unwrap_or(32.0)
+    }
+
+    pub fn family(&self) -> &str {
+        self.font_family.as_deref().unwrap_or("Arial")
+    }
+
+    pub fn is_bold(&self) -> bool {
+        match &self.font_weight {
+            Some(serde_json::Value::String(w)) => w == "bold" || w == "bolder" || w.parse::<u32>().is_ok_and(|w| w >= 600),
+            Some(serde_json::Value::Number(w)) => w.as_f64().is_some_and(|w| w >= 600.0),
+            _ => false,
+        }
+    }
+
+    pub fn color(&self) -> &str {
+        self.color.as_deref().unwrap_or("#000000")
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TextSpan {
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub style: TextStyle,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PaletteColor {
+    pub hex: String,
+}
+
+/// A shape, text box or color palette from `Board.objects`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CanvasObject {
+    #[serde(default)]
+    pub id: serde_json::Value,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub x: f64,
+    #[serde(default)]
+    pub y: f64,
+    #[serde(default)]
+    pub width: f64,
+    #[serde(default)]
+    pub height: f64,
+    #[serde(default)]
+    pub x2: Option<f64>,
+    #[serde(default)]
+    pub y2: Option<f64>,
+    #[serde(default)]
+    pub rotation: Option<f64>,
+    #[serde(default)]
+    pub visible: Option<bool>,
+    #[serde(default)]
+    pub z_index: Option<f64>,
+    #[serde(default)]
+    pub shape_type: Option<String>,
+    #[serde(default)]
+    pub fill_color: Option<String>,
+    #[serde(default)]
+    pub has_stroke: Option<bool>,
+    #[serde(default)]
+    pub stroke_color: Option<String>,
+    #[serde(default)]
+    pub stroke_width: Option<f64>,
+    #[serde(default)]
+    pub corner_radius: Option<f64>,
+    #[serde(default)]
+    pub content: Option<Vec<TextSpan>>,
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub font_size: Option<f64>,
+    #[serde(default)]
+    pub font_family: Option<String>,
+    #[serde(default)]
+    pub font_weight: Option<serde_json::Value>,
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub text_align: Option<String>,
+    #[serde(default)]
+    pub cell_size: Option<f64>,
+    #[serde(default)]
+    pub grid_cols: Option<u32>,
+    #[serde(default)]
+    pub grid_rows: Option<u32>,
+    #[serde(default)]
+    pub has_wide_cell: Option<bool>,
+    #[serde(default)]
+    pub colors: Option<Vec<PaletteColor>>,
+}
+
+impl CanvasObject {
+    pub fn is_visible(&self) -> bool {
+        self.visible != Some(false)
+    }
+
+    pub fn is_line(&self) -> bool {
+        self.kind == "shape" && matches!(self.shape_type.as_deref(), Some("line") | Some("arrow"))
+    }
+
+    /// End point of a line or arrow, falling back to the bounding box corner.
+    pub fn end_point(&self) -> (f64, f64) {
+        (
+            self.x2.unwrap_or(self.x + self.width),
+            self.y2.unwrap_or(self.y + self.height),
+        )
+    }
+
+    /// Point the canvas rotates this object around.
+    pub fn rotation_center(&self) -> (f64, f64) {
+        if self.is_line() {
+            let (x2, y2) = self.end_point();
+            ((self.x + x2) / 2.0, (self.y + y2) / 2.0)
+        } else {
+            (self.x + self.width / 2.0, self.y + self.height / 2.0)
+        }
+    }
+
+    pub fn stroke_width(&self) -> f64 {
+        self.stroke_width.unwrap_or(2.0)
+    }
+
+    pub fn draws_stroke(&self) -> bool {
+        self.has_stroke != Some(false) && self.stroke_width() > 0.0
+    }
+
+    /// Text content in the rich-text span format, converting legacy
+    /// single-style text objects the same way the editor does.
+    pub fn spans(&self) -> Vec<TextSpan> {
+        if let Some(content) = &self.content {
+            return content.clone();
+        }
+        vec![TextSpan {
+            text: self.text.clone().unwrap_or_default(),
+            style: TextStyle {
+                font_size: Some(self.font_size.unwrap_or(32.0)),
+                font_family: Some(self.font_family.clone().unwrap_or_else(|| "Arial".to_string())),
+                font_weight: self.font_weight.clone(),
+                color: Some(self.color.clone().unwrap_or_else(|| "#000000".to_string())),
+                text_decoration: None,
+            },
+        }]
+    }
+
+    /// Size of a color palette, which is derived from its grid rather than
+    /// stored width/height.
+    pub fn palette_size(&self) -> (f64, f64) {
+        let cell = self.cell_size.unwrap_or(60.0);
+        let cols = self.grid_cols.unwrap_or(1) as f64;
+        let rows = self.grid_rows.unwrap_or(1) as f64 + if self.has_wide_cell == Some(true) { 1.0 } else { 0.0 };
+        (cols * cell, rows * cell)
+    }
+
+    pub fn bounds(&self) -> Rect {
+        let rect = if self.is_line() {
+            let (x2, y2) = self.end_point();
+            Rect {
+                x: self.x.min(x2),
+                y: self.y.min(y2),
+                width: (x2 - self.x).abs(),
+                height: (y2 - self.y).abs(),
+            }
+            .inflate(self.stroke_width().max(10.0))
+        } else if self.kind == "colorPalette" {
+            let (width, height) = self.palette_size();
+            Rect { x: self.x, y: self.y, width, height }
+        } else {
+            Rect { x: self.x, y: self.y, width: self.width, height: self.height }.inflate(self.stroke_width() / 2.0)
+        };
+        rect.rotated_bounds(self.rotation.unwrap_or(0.0))
+    }
+}
+
+/// A layer panel group from `Board.groups`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LayerGroup {
+    #[serde(default)]
+    pub id: serde_json::Value,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub layer_ids: Vec<f64>,
+    #[serde(default)]
+    pub object_ids: Vec<serde_json::Value>,
+}
+
+/// Parses `Board.strokes`, skipping entries that don't look like strokes.
+pub fn parse_strokes(value: &Option<serde_json::Value>) -> Vec<Stroke> {
+    parse_list(value)
+}
+
+/// Parses `Board.objects`, skipping entries that don't look like objects.
+pub fn parse_objects(value: &Option<serde_json::Value>) -> Vec<CanvasObject> {
+    parse_list(value)
+}
+
+/// Parses `Board.groups`, skipping entries that don't look like groups.
+pub fn parse_groups(value: &Option<serde_json::Value>) -> Vec<LayerGroup> {
+    parse_list(value)
+}
+
+fn parse_list<T: serde::de::DeserializeOwned>(value: &Option<serde_json::Value>) -> Vec<T> {
+    match value {
+        Some(serde_json::Value::Array(items)) => items
+            .iter()
+            .filter_map(|item| serde_json::from_value(item.clone()).ok())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Parses a CSS color (`#rgb`, `#rrggbb`, `#rrggbbaa`, `rgb()`/`rgba()` or a
+/// handful of names) into RGBA bytes.
+pub fn parse_color(value: &str) -> Option<[u8; 4]> {
+    let value = value.trim();
+    if let Some(hex) = value.strip_prefix('#') {
+        let digits: Vec<u8> = hex
+            .chars()
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<_>>()?;
+        return match digits.len() {
+            3 => Some([digits[0] * 17, digits[1] * 17, digits[2] * 17, 255]),
+            4 => Some([digits[0] * 17, digits[1] * 17, digits[2] * 17, digits[3] * 17]),
+            6 => Some([digits[0] * 16 + digits[1], digits[2] * 16 + digits[3], digits[4] * 16 + digits[5], 255]),
+            8 => Some([
+                digits[0] * 16 + digits[1],
+                digits[2] * 16 + digits[3],
+                digits[4] * 16 + digits[5],
+                digits[6] * 16 + digits[7],
+            ]),
+            _ => None,
+        };
+    }
+
+    if let Some(args) = value
+        .strip_prefix("rgba(")
+        .or_else(|| value.strip_prefix("rgb("))
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        let parts: Vec<f64> = args
+            .split([',', '/', ' '])
+            .filter(|p| !p.is_empty())
+            .map(|p| p.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        if parts.len() < 3 {
+            return None;
+        }
+        let alpha = parts.get(3).copied().unwrap_or(1.0);
+        return Some([
+            parts[0].clamp(0.0, 255.0) as u8,
+            parts[1].clamp(0.0, 255.0) as u8,
+            parts[2].clamp(0.0, 255.0) as u8,
+            (alpha.clamp(0.0, 1.0) * 255.0).round() as u8,
+        ]);
+    }
+
+    match value.to_lowercase().as_str() {
+        "black" => Some([0, 0, 0, 255]),
+        "white" => Some([255, 255, 255, 255]),
+        "red" => Some([255, 0, 0, 255]),
+        "green" => Some([0, 128, 0, 255]),
+        "blue" => Some([0, 0, 255, 255]),
+        "gray" | "grey" => Some([128, 128, 128, 255]),
+        "transparent" => Some([0, 0, 0, 0]),
+        _ => None,
+    }
+}
@@ -0,0 +1,80 @@
+//! Events sent to every window when stored boards, the asset library or
+//! tag presets change. Each carries the label of the window whose command
+//! made the change (none for background work), so that window can skip
+//! its own echo.
+
+use crate::database::{self, Asset, Board, Layer};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+/// Payload of `board-updated`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BoardUpdated {
+    pub id: u64,
+    /// The board's `updatedAt`, to tell newer saves from ones already seen.
+    pub revision: u64,
+    /// Layers the backend added, e.g. from a watch folder or capture.
+    pub layers: Vec<Layer>,
+    pub origin: Option<String>,
+}
+
+/// Payload of `board-deleted`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BoardDeleted {
+    pub id: u64,
+    pub origin: Option<String>,
+}
+
+/// Payload of `asset-library-changed`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetLibraryChanged {
+    /// Assets added or updated.
+    pub assets: Vec<Asset>,
+    /// Ids of assets removed.
+    pub removed: Vec<f64>,
+    pub origin: Option<String>,
+}
+
+/// Payload of `tag-presets-changed`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagPresetsChanged {
+    pub presets: Vec<String>,
+    pub origin: Option<String>,
+}
+
+impl BoardUpdated {
+    pub fn new(board: &Board, layers: Vec<Layer>, origin: Option<&str>) -> Self {
+        BoardUpdated { id: board.id, revision: board.updated_at, layers, origin: origin.map(String::from) }
+    }
+}
+
+pub fn board_updated(app: &AppHandle, board: &Board, layers: Vec<Layer>, origin: Option<&str>) {
+    let _ = app.emit("board-updated", BoardUpdated::new(board, layers, origin));
+}
+
+/// `board-updated` for boards rewritten on disk, e.g. by a sync or a
+/// restore. Boards that can't be loaded, such as locked ones, are skipped.
+pub fn boards_updated(app: &AppHandle, ids: impl IntoIterator<Item = u64>, origin: Option<&str>) {
+    for id in ids {
+        if let Ok(board) = database::load_board(app, id) {
+            board_updated(app, &board, Vec::new(), origin);
+        }
+    }
+}
+
+pub fn board_deleted(app: &AppHandle, id: u64, origin: Option<&str>) {
+    let _ = app.emit("board-deleted", BoardDeleted { id, origin: origin.map(String::from) });
+}
+
+pub fn asset_library_changed(app: &AppHandle, assets: Vec<Asset>, removed: Vec<f64>, origin: Option<&str>) {
+    let event = AssetLibraryChanged { assets, removed, origin: origin.map(String::from) };
+    let _ = app.emit("asset-library-changed", event);
+}
+
+pub fn tag_presets_changed(app: &AppHandle, presets: Vec<String>, origin: Option<&str>) {
+    let _ = app.emit("tag-presets-changed", TagPresetsChanged { presets, origin: origin.map(String::from) });
+}
@@ -0,0 +1,521 @@
+//! HTTP fetching shared by the URL commands and importers.
+//!
+//! GETs go through the on-disk [`http_cache`]: fresh entries are served
+//! without touching the network, stale ones are revalidated, and cached
+//! content is used when the network is down.
+//!
+//! Fetches are limited by the [`FetchSettings`]: bodies are capped while
+//! streaming, redirects are counted, and unless allowed, hosts resolving to
+//! loopback, link-local or private addresses are refused, including after
+//! redirects. Media is checked against its magic bytes rather than trusting
+//! the declared content type.
+
+use crate::database::{self, DataDir, FetchSettings};
+use crate::http_cache;
+use crate::import;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::header::{HeaderMap, CONTENT_TYPE, IF_MODIFIED_SINCE, IF_NONE_MATCH};
+use reqwest::redirect::Policy;
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Read;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::Duration;
+
+const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+
+const ACCEPT_HTML: &str = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+const ACCEPT_MEDIA: &str = "image/*,*/*;q=0.8";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the server may go quiet while sending headers or any one read
+/// of the body; there's no limit on the whole transfer.
+const READ_TIMEOUT: Duration = Duration::from_secs(20);
+/// How often a fetch still waiting for its response checks for cancel.
+const CANCEL_POLL: Duration = Duration::from_millis(100);
+
+/// What a fetch is for, which decides the size limit and validation.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Expect {
+    Page,
+    Media,
+    /// Media, or a page within the page limit when the body isn't media.
+    #[serde(skip)]
+    MediaOrPage,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum FetchError {
+    InvalidUrl { url: String },
+    UnsupportedScheme { scheme: String },
+    BlockedAddress { host: String, address: String },
+    TooManyRedirects { limit: usize },
+    TooLarge { limit: u64 },
+    NotMedia { content_type: Option<String> },
+    Http { status: u16 },
+    Network { message: String },
+    Cancelled,
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::InvalidUrl { url } => write!(f, "Invalid URL: {}", url),
+            FetchError::UnsupportedScheme { scheme } => write!(f, "Unsupported URL scheme: {}", scheme),
+            FetchError::BlockedAddress { host, address } => {
+                write!(f, "Refusing to fetch {}: it resolves to the private address {}", host, address)
+            }
+            FetchError::TooManyRedirects { limit } => write!(f, "Too many redirects (limit {})", limit),
+            FetchError::TooLarge { limit } => write!(f, "Response is larger than the {} byte limit", limit),
+            FetchError::NotMedia { content_type } => match content_type {
+                Some(t) => write!(f, "Response is not a supported image or video ({})", t),
+                None => write!(f, "Response is not a supported image or video"),
+            },
+            FetchError::Http { status } => write!(f, "HTTP error: {}", status),
+            FetchError::Network { message } => write!(f, "Failed to fetch URL: {}", message),
+            FetchError::Cancelled => write!(f, "Fetch cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+impl From<FetchError> for String {
+    fn from(error: FetchError) -> String {
+        error.to_string()
+    }
+}
+
+pub struct Fetched {
+    pub bytes: Vec<u8>,
+    /// Media type without parameters; sniffed from the bytes for media.
+    pub content_type: Option<String>,
+    pub charset: Option<String>,
+}
+
+impl Fetched {
+    /// Checks a body against the limits for `expect`.
+    fn new(bytes: Vec<u8>, content_type_header: Option<&str>, expect: Expect, settings: &FetchSettings) -> Result<Self, FetchError> {
+        let limit = body_limit(settings, expect);
+        if bytes.len() as u64 > limit {
+            return Err(FetchError::TooLarge { limit });
+        }
+        let mut params = content_type_header.unwrap_or_default().split(';').map(str::trim);
+        let mut content_type = params.next().filter(|t| !t.is_empty()).map(|t| t.to_string());
+        let charset = params
+            .filter_map(|p| p.split_once('='))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case("charset"))
+            .map(|(_, value)| value.trim().trim_matches('"').to_string());
+
+        match (expect, import::sniff_media_type(&bytes)) {
+            (Expect::Page, _) => {}
+            (_, Some(sniffed)) => content_type = Some(sniffed.to_string()),
+            (Expect::Media, None) => return Err(FetchError::NotMedia { content_type }),
+            (Expect::MediaOrPage, None) => {
+                if bytes.len() as u64 > settings.max_page_bytes {
+                    return Err(FetchError::TooLarge { limit: settings.max_page_bytes });
+                }
+            }
+        }
+        Ok(Fetched { bytes, content_type, charset })
+    }
+
+    /// Whether the body is media, judged by its magic bytes.
+    pub fn is_media(&self) -> bool {
+        import::sniff_media_type(&self.bytes).is_some()
+    }
+
+    /// The body decoded with its declared charset.
+    pub fn text(&self) -> String {
+        let encoding = self
+            .charset
+            .as_deref()
+            .and_then(|label| encoding_rs::Encoding::for_label(label.as_bytes()))
+            .unwrap_or(encoding_rs::UTF_8);
+        let (text, _, _) = encoding.decode(&self.bytes);
+        text.into_owned()
+    }
+
+    pub fn data_url(&self) -> String {
+        let content_type = self.content_type.as_deref().unwrap_or("application/octet-stream");
+        format!("data:{};base64,{}", content_type, BASE64.encode(&self.bytes))
+    }
+}
+
+/// The most a body fetched for `expect` may hold.
+fn body_limit(settings: &FetchSettings, expect: Expect) -> u64 {
+    match expect {
+        Expect::Page => settings.max_page_bytes,
+        Expect::Media => settings.max_media_bytes,
+        Expect::MediaOrPage => settings.max_media_bytes.max(settings.max_page_bytes),
+    }
+}
+
+/// Whether an address is loopback, link-local, private or otherwise not
+/// on the public internet.
+fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            v4.is_loopback()
+                || v4.is_private()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || v4.is_multicast()
+                // Shared address space used by carrier-grade NAT
+                || (a == 100 && (64..128).contains(&b))
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_private(IpAddr::V4(v4));
+            }
+            let first = v6.segments()[0];
+            v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                // Unique local fc00::/7 and link-local fe80::/10
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+/// Rejects URLs that can't be fetched, and literal private addresses when
+/// they aren't allowed. Host names are checked as they resolve.
+fn check_url(url: &Url, allow_private: bool) -> Result<(), FetchError> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(FetchError::UnsupportedScheme { scheme: url.scheme().to_string() });
+    }
+    let ip = match url.host() {
+        Some(url::Host::Ipv4(ip)) => IpAddr::V4(ip),
+        Some(url::Host::Ipv6(ip)) => IpAddr::V6(ip),
+        Some(url::Host::Domain(_)) => return Ok(()),
+        None => return Err(FetchError::InvalidUrl { url: url.to_string() }),
+    };
+    if !allow_private && is_private(ip) {
+        return Err(FetchError::BlockedAddress { host: ip.to_string(), address: ip.to_string() });
+    }
+    Ok(())
+}
+
+/// Resolves host names, refusing any that point at a private address.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            if let Some(private) = addrs.iter().find(|a| is_private(a.ip())) {
+                let error = FetchError::BlockedAddress { host, address: private.ip().to_string() };
+                return Err(error.into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+fn client(settings: &FetchSettings) -> Result<Client, FetchError> {
+    let limit = settings.max_redirects;
+    let allow_private = settings.allow_private_addresses;
+    let policy = Policy::custom(move |attempt| {
+        if attempt.previous().len() > limit {
+            return attempt.error(FetchError::TooManyRedirects { limit });
+        }
+        match check_url(attempt.url(), allow_private) {
+            Ok(()) => attempt.follow(),
+            Err(e) => attempt.error(e),
+        }
+    });
+
+    let mut builder = Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        // The blocking client applies this to each wait, not the whole body
+        .timeout(READ_TIMEOUT)
+        .user_agent(USER_AGENT)
+        .redirect(policy);
+    if !allow_private {
+        builder = builder.dns_resolver(Arc::new(PublicResolver));
+    }
+    builder
+        .build()
+        .map_err(|e| FetchError::Network { message: format!("Failed to create HTTP client: {}", e) })
+}
+
+/// Finds our own error inside one reqwest wraps around redirect and
+/// resolver failures.
+fn classify(error: reqwest::Error) -> FetchError {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(&error);
+    while let Some(inner) = source {
+        if let Some(own) = inner.downcast_ref::<FetchError>() {
+            return own.clone();
+        }
+        source = inner.source();
+    }
+    FetchError::Network { message: error.to_string() }
+}
+
+/// Sends a request on its own thread so `progress` can cancel it while the
+/// host resolves, connects or sends headers; it's polled with nothing
+/// received yet. An abandoned send ends on its own within the timeouts.
+fn send(request: RequestBuilder, progress: &mut dyn FnMut(u64, Option<u64>) -> bool) -> Result<Response, FetchError> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(request.send());
+    });
+    loop {
+        match rx.recv_timeout(CANCEL_POLL) {
+            Ok(result) => return result.map_err(classify),
+            Err(RecvTimeoutError::Timeout) => {
+                if !progress(0, None) {
+                    return Err(FetchError::Cancelled);
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(FetchError::Network { message: "Request thread stopped".to_string() });
+            }
+        }
+    }
+}
+
+/// GETs a URL, sending it as its own referer since some image hosts refuse
+/// hotlinks without one.
+pub fn fetch(app: &impl DataDir, url: &str, expect: Expect, referer: Option<&str>) -> Result<Fetched, FetchError> {
+    fetch_with_progress(app, url, expect, referer, &mut |_, _| true)
+}
+
+/// Like [`fetch`], reporting bytes received and the total when known as the
+/// body streams in. Returning `false` from `progress` cancels the fetch.
+pub fn fetch_with_progress(
+    app: &impl DataDir,
+    url: &str,
+    expect: Expect,
+    referer: Option<&str>,
+    progress: &mut dyn FnMut(u64, Option<u64>) -> bool,
+) -> Result<Fetched, FetchError> {
+    let settings = database::load_fetch_settings(app).unwrap_or_default();
+    let limit = body_limit(&settings, expect);
+    let parsed = Url::parse(url).map_err(|_| FetchError::InvalidUrl { url: url.to_string() })?;
+    check_url(&parsed, settings.allow_private_addresses)?;
+
+    let cached = http_cache::lookup(app, url);
+    if let Some((entry, bytes)) = &cached {
+        if entry.is_fresh() {
+            http_cache::touch(app, url);
+            return Fetched::new(bytes.clone(), entry.content_type.as_deref(), expect, &settings);
+        }
+    }
+
+    let accept = match expect {
+        Expect::Page => ACCEPT_HTML,
+        Expect::Media | Expect::MediaOrPage => ACCEPT_MEDIA,
+    };
+    let mut request = client(&settings)?.get(parsed).header("Accept", accept);
+    if let Some(referer) = referer {
+        request = request.header("Referer", referer);
+    }
+    if let Some((entry, _)) = &cached {
+        if let Some(etag) = &entry.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(modified) = &entry.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, modified);
+        }
+    }
+
+    let response = match send(request, progress) {
+        Ok(response) => response,
+        // Offline or unreachable: stale content beats nothing
+        Err(e @ FetchError::Network { .. }) => {
+            return stale(app, url, cached, expect, &settings).unwrap_or(Err(e));
+        }
+        Err(e) => return Err(e),
+    };
+
+    let status = response.status();
+    if status == StatusCode::NOT_MODIFIED {
+        if let Some((_, bytes)) = cached {
+            let entry = http_cache::revalidated(app, url, response.headers());
+            let content_type = entry.and_then(|e| e.content_type);
+            return Fetched::new(bytes, content_type.as_deref(), expect, &settings);
+        }
+    }
+    if status.is_server_error() {
+        if let Some(fetched) = stale(app, url, cached, expect, &settings) {
+            return fetched;
+        }
+    }
+    if !status.is_success() {
+        return Err(FetchError::Http { status: status.as_u16() });
+    }
+    let total = response.content_length();
+    if total.is_some_and(|len| len > limit) {
+        return Err(FetchError::TooLarge { limit });
+    }
+
+    let headers: HeaderMap = response.headers().clone();
+    let mut bytes = Vec::new();
+    let mut reader = response.take(limit + 1);
+    let mut chunk = vec![0u8; 64 * 1024];
+    loop {
+        let read = reader
+            .read(&mut chunk)
+            .map_err(|e| FetchError::Network { message: format!("Failed to read response: {}", e) })?;
+        if read == 0 {
+            break;
+        }
+        bytes.extend_from_slice(&chunk[..read]);
+        if !progress(bytes.len() as u64, total) {
+            return Err(FetchError::Cancelled);
+        }
+    }
+
+    let content_type = headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok());
+    let fetched = Fetched::new(bytes, content_type, expect, &settings)?;
+    if let Err(e) = http_cache::store(app, url, &headers, &fetched.bytes) {
+        log::warn!("Failed to cache {}: {}", url, e);
+    }
+    Ok(fetched)
+}
+
+fn stale(
+    app: &impl DataDir,
+    url: &str,
+    cached: Option<(http_cache::CacheEntry, Vec<u8>)>,
+    expect: Expect,
+    settings: &FetchSettings,
+) -> Option<Result<Fetched, FetchError>> {
+    let (entry, bytes) = cached?;
+    http_cache::touch(app, url);
+    Some(Fetched::new(bytes, entry.content_type.as_deref(), expect, settings))
+}
+
+/// GETs a page as text, decoded with the charset the server declares.
+pub fn fetch_text(app: &impl DataDir, url: &str) -> Result<String, FetchError> {
+    Ok(fetch(app, url, Expect::Page, None)?.text())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::time::Instant;
+
+    fn data_dir(settings: FetchSettings) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("eyedea-fetch-{}", database::random_token().unwrap()));
+        database::init_storage(&dir).unwrap();
+        database::save_fetch_settings(&dir, &FetchSettings { allow_private_addresses: true, ..settings }).unwrap();
+        dir
+    }
+
+    /// Serves `/image.png`, `/page.html`, `/big` (streamed without a
+    /// length) and `/loop/<n>` (which redirects forever) on localhost.
+    fn serve() -> String {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", server.server_addr().to_ip().unwrap());
+        std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let no_store = tiny_http::Header::from_bytes("Cache-Control", "no-store").unwrap();
+                let url = request.url().to_string();
+                let _ = match url.as_str() {
+                    "/image.png" => {
+                        let png = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n', 0, 0];
+                        let content_type = tiny_http::Header::from_bytes("Content-Type", "text/plain").unwrap();
+                        request.respond(tiny_http::Response::from_data(png.to_vec()).with_header(content_type).with_header(no_store))
+                    }
+                    "/page.html" => {
+                        let content_type = tiny_http::Header::from_bytes("Content-Type", "image/png").unwrap();
+                        let page = tiny_http::Response::from_string("<html><body>Not an image</body></html>");
+                        request.respond(page.with_header(content_type).with_header(no_store))
+                    }
+                    "/big" => {
+                        let body = std::io::Cursor::new(vec![0u8; 4096]);
+                        request.respond(tiny_http::Response::new(200.into(), vec![no_store], body, None, None))
+                    }
+                    _ => {
+                        let next: usize = url.trim_start_matches("/loop/").parse().unwrap_or(0) + 1;
+                        let location = tiny_http::Header::from_bytes("Location", format!("/loop/{}", next)).unwrap();
+                        request.respond(tiny_http::Response::empty(302).with_header(location).with_header(no_store))
+                    }
+                };
+            }
+        });
+        base
+    }
+
+    #[test]
+    fn knows_private_addresses() {
+        for private in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "::ffff:192.168.1.1", "fe80::1", "fd00::1"] {
+            assert!(is_private(private.parse().unwrap()), "{}", private);
+        }
+        for public in ["8.8.8.8", "172.32.0.1", "100.128.0.1", "2606:4700::1111", "::ffff:8.8.8.8"] {
+            assert!(!is_private(public.parse().unwrap()), "{}", public);
+        }
+    }
+
+    #[test]
+    fn checks_schemes_and_literal_addresses() {
+        let check = |url: &str, allow_private| check_url(&Url::parse(url).unwrap(), allow_private);
+        assert!(matches!(check("ftp://example.com/a.png", false), Err(FetchError::UnsupportedScheme { scheme }) if scheme == "ftp"));
+        assert!(matches!(check("file:///etc/passwd", true), Err(FetchError::UnsupportedScheme { .. })));
+        assert!(matches!(check("http://127.0.0.1:8080/", false), Err(FetchError::BlockedAddress { .. })));
+        assert!(matches!(check("http://[::1]/", false), Err(FetchError::BlockedAddress { .. })));
+        assert!(check("http://127.0.0.1:8080/", true).is_ok());
+        assert!(check("https://example.com/a.png", false).is_ok());
+    }
+
+    #[test]
+    fn caps_sniffs_and_limits_redirects() {
+        let dir = data_dir(FetchSettings { max_media_bytes: 1024, max_redirects: 2, ..Default::default() });
+        let base = serve();
+
+        // Media is judged by its bytes, not the declared type
+        let image = fetch(&dir, &format!("{}/image.png", base), Expect::Media, None).unwrap();
+        assert_eq!(image.content_type.as_deref(), Some("image/png"));
+        let page = fetch(&dir, &format!("{}/page.html", base), Expect::Media, None);
+        assert!(matches!(page, Err(FetchError::NotMedia { content_type: Some(t) }) if t == "image/png"));
+
+        // No length up front: cut off while streaming
+        let big = fetch(&dir, &format!("{}/big", base), Expect::Media, None);
+        assert!(matches!(big, Err(FetchError::TooLarge { limit: 1024 })));
+        assert!(fetch(&dir, &format!("{}/big", base), Expect::Page, None).is_ok());
+
+        let looping = fetch(&dir, &format!("{}/loop/0", base), Expect::Page, None);
+        assert!(matches!(looping, Err(FetchError::TooManyRedirects { limit: 2 })), "{:?}", looping.err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn refuses_settings_that_break_fetching() {
+        assert!(FetchSettings::default().check().is_ok());
+        assert!(FetchSettings { max_concurrent_downloads: 0, ..Default::default() }.check().is_err());
+        assert!(FetchSettings { max_concurrent_downloads: 64, ..Default::default() }.check().is_err());
+        assert!(FetchSettings { max_media_bytes: 0, ..Default::default() }.check().is_err());
+        assert!(FetchSettings { max_page_bytes: 0, ..Default::default() }.check().is_err());
+        assert!(FetchSettings { max_redirects: 1000, ..Default::default() }.check().is_err());
+    }
+
+    #[test]
+    fn cancels_while_waiting_for_headers() {
+        let dir = data_dir(FetchSettings::default());
+
+        // Accepts the connection and never answers
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/slow.png", listener.local_addr().unwrap());
+
+        let started = Instant::now();
+        let result = fetch_with_progress(&dir, &url, Expect::Media, None, &mut |_, _| started.elapsed() < Duration::from_millis(300));
+        assert!(matches!(result, Err(FetchError::Cancelled)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        drop(listener);
+    }
+}
@@ -0,0 +1,316 @@
+//! Floating reference windows: frameless windows showing a board, or one of
+//! its layers, that remember their placement and reopen on launch.
+
+use crate::database::{self, Board, FloatingWindow};
+use serde::Deserialize;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, LogicalPosition, LogicalSize, Manager, WebviewUrl, WebviewWindow, WebviewWindowBuilder, WindowEvent};
+
+/// Floating windows as last placed, held in Tauri state and written to
+/// `floating_windows.json` when one opens, closes or changes settings.
+#[derive(Default)]
+pub struct FloatingState {
+    windows: Mutex<Vec<FloatingWindow>>,
+}
+
+/// Settings changed through [`set_options`]; unset fields are kept.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FloatingOptions {
+    pub opacity: Option<f64>,
+    pub always_on_top: Option<bool>,
+    pub click_through: Option<bool>,
+}
+
+/// Window label, e.g. `floating-17` or `floating-17-layer-3_5`; labels
+/// can't contain dots.
+fn label(board_id: u64, layer_id: Option<f64>) -> String {
+    match layer_id {
+        Some(layer) => format!("floating-{}-layer-{}", board_id, layer.to_string().replace('.', "_")),
+        None => format!("floating-{}", board_id),
+    }
+}
+
+fn label_of(window: &FloatingWindow) -> String {
+    label(window.board_id, window.layer_id)
+}
+
+/// Fades the whole page; windows are transparent where the platform allows,
+/// so the desktop shows through.
+fn opacity_script(opacity: f64) -> String {
+    format!("document.documentElement.style.opacity = '{}';", opacity)
+}
+
+/// What happened to an open window, as far as its saved placement goes.
+enum Change {
+    Moved(LogicalPosition<f64>),
+    Resized(LogicalSize<f64>),
+    Closed,
+}
+
+impl Change {
+    fn apply(self, window: &mut FloatingWindow) {
+        match self {
+            Change::Moved(position) => {
+                window.x = Some(position.x);
+                window.y = Some(position.y);
+            }
+            Change::Resized(size) => {
+                window.width = size.width;
+                window.height = size.height;
+            }
+            Change::Closed => window.open = false,
+        }
+    }
+}
+
+/// The saved window for a board or layer, added when there's none yet,
+/// marked open.
+fn mark_open(windows: &mut Vec<FloatingWindow>, board_id: u64, layer_id: Option<f64>) -> FloatingWindow {
+    let index = match windows.iter().position(|w| w.board_id == board_id && w.layer_id == layer_id) {
+        Some(index) => index,
+        None => {
+            windows.push(FloatingWindow { board_id, layer_id, ..Default::default() });
+            windows.len() - 1
+        }
+    };
+    windows[index].open = true;
+    windows[index].clone()
+}
+
+fn apply_options(window: &mut FloatingWindow, options: &FloatingOptions) {
+    if let Some(opacity) = options.opacity {
+        window.opacity = opacity.clamp(0.1, 1.0);
+    }
+    if let Some(always_on_top) = options.always_on_top {
+        window.always_on_top = always_on_top;
+    }
+    if let Some(click_through) = options.click_through {
+        window.click_through = click_through;
+    }
+}
+
+/// Saved windows to reopen on launch.
+fn left_open(saved: &[FloatingWindow]) -> impl Iterator<Item = &FloatingWindow> {
+    saved.iter().filter(|w| w.open)
+}
+
+/// The board's name, or the layer's; none when the layer is gone.
+fn title(board: Board, layer_id: Option<f64>) -> Option<String> {
+    match layer_id {
+        Some(id) => board.layers.into_iter().find(|l| l.id == id).map(|l| l.name),
+        None => Some(board.name),
+    }
+}
+
+fn persist(app: &AppHandle, windows: &[FloatingWindow]) {
+    if let Err(e) = database::save_floating_windows(app, windows) {
+        log::warn!("Failed to save floating windows: {}", e);
+    }
+}
+
+/// Runs `f` on the saved window with `label`, if any, and returns it.
+fn update(app: &AppHandle, label: &str, save: bool, f: impl FnOnce(&mut FloatingWindow)) -> Option<FloatingWindow> {
+    let state = app.state::<FloatingState>();
+    let mut windows = state.windows.lock().unwrap();
+    let window = windows.iter_mut().find(|w| label_of(w) == label)?;
+    f(window);
+    let updated = window.clone();
+    if save {
+        persist(app, &windows);
+    }
+    Some(updated)
+}
+
+/// Keeps the saved placement in step with the window. Closing it by hand
+/// forgets it was open; windows that go when the app quits reopen.
+fn track(app: &AppHandle, label: &str, event: &WindowEvent) {
+    let scale = || {
+        app.get_webview_window(label)
+            .filter(|w| !w.is_minimized().unwrap_or(false))
+            .and_then(|w| w.scale_factor().ok())
+    };
+    let change = match event {
+        WindowEvent::Moved(position) => scale().map(|scale| Change::Moved(position.to_logical(scale))),
+        WindowEvent::Resized(size) if size.width > 0 && size.height > 0 => {
+            scale().map(|scale| Change::Resized(size.to_logical(scale)))
+        }
+        WindowEvent::CloseRequested { .. } => Some(Change::Closed),
+        WindowEvent::Destroyed => {
+            save(app);
+            None
+        }
+        _ => None,
+    };
+    if let Some(change) = change {
+        let closed = matches!(change, Change::Closed);
+        update(app, label, closed, |w| change.apply(w));
+    }
+}
+
+fn build(app: &AppHandle, window: &FloatingWindow, title: &str) -> Result<WebviewWindow, String> {
+    let label = label_of(window);
+    let mut url = format!("floating.html?id={}", window.board_id);
+    if let Some(layer) = window.layer_id {
+        url.push_str(&format!("&layer={}", layer));
+    }
+    let builder = WebviewWindowBuilder::new(app, &label, WebviewUrl::App(url.into()))
+        .title(title)
+        .inner_size(window.width, window.height)
+        .decorations(false)
+        .resizable(true)
+        .always_on_top(window.always_on_top)
+        .initialization_script(opacity_script(window.opacity))
+        .disable_drag_drop_handler();
+    // Opacity shows the desktop through where windows can be transparent;
+    // on macOS that needs the private API, so the board just fades there
+    #[cfg(not(target_os = "macos"))]
+    let builder = builder.transparent(true);
+    let builder = match (window.x, window.y) {
+        (Some(x), Some(y)) => builder.position(x, y),
+        _ => builder.center(),
+    };
+    let webview = builder.build().map_err(|e| format!("Failed to open floating window: {}", e))?;
+    if window.click_through {
+        webview.set_ignore_cursor_events(true).map_err(|e| e.to_string())?;
+    }
+
+    let handle = app.clone();
+    webview.on_window_event(move |event| track(&handle, &label, event));
+    Ok(webview)
+}
+
+/// Opens a floating window for a board, or just one of its layers, where it
+/// was last left. When it's already open it's brought forward and made
+/// clickable again, which is the way back out of click-through.
+pub fn open(app: &AppHandle, board_id: u64, layer_id: Option<f64>) -> Result<FloatingWindow, String> {
+    let title = title(database::load_board(app, board_id)?, layer_id).ok_or("Layer not found")?;
+    let label = label(board_id, layer_id);
+
+    if let Some(webview) = app.get_webview_window(&label) {
+        let _ = webview.unminimize();
+        let _ = webview.set_focus();
+        return set_options(app, board_id, layer_id, FloatingOptions { click_through: Some(false), ..Default::default() });
+    }
+
+    let state = app.state::<FloatingState>();
+    let mut windows = state.windows.lock().unwrap();
+    let window = mark_open(&mut windows, board_id, layer_id);
+    persist(app, &windows);
+    drop(windows);
+
+    build(app, &window, &title)?;
+    Ok(window)
+}
+
+/// Changes a floating window's settings, applying them to it when open.
+pub fn set_options(app: &AppHandle, board_id: u64, layer_id: Option<f64>, options: FloatingOptions) -> Result<FloatingWindow, String> {
+    let label = label(board_id, layer_id);
+    let window = update(app, &label, true, |w| apply_options(w, &options)).ok_or("No floating window for this board")?;
+
+    if let Some(webview) = app.get_webview_window(&label) {
+        webview.set_always_on_top(window.always_on_top).map_err(|e| e.to_string())?;
+        webview.set_ignore_cursor_events(window.click_through).map_err(|e| e.to_string())?;
+        webview.eval(opacity_script(window.opacity)).map_err(|e| e.to_string())?;
+        let _ = app.emit_to(&label, "floating-window-changed", &window);
+    }
+    Ok(window)
+}
+
+pub fn list(app: &AppHandle) -> Vec<FloatingWindow> {
+    app.state::<FloatingState>().windows.lock().unwrap().clone()
+}
+
+/// Closes and forgets a deleted board's floating windows.
+pub fn forget_board(app: &AppHandle, board_id: u64) {
+    let state = app.state::<FloatingState>();
+    let mut windows = state.windows.lock().unwrap();
+    let (gone, kept): (Vec<_>, Vec<_>) = windows.drain(..).partition(|w| w.board_id == board_id);
+    *windows = kept;
+    persist(app, &windows);
+    drop(windows);
+    for window in gone {
+        if let Some(webview) = app.get_webview_window(&label_of(&window)) {
+            let _ = webview.destroy();
+        }
+    }
+}
+
+/// Writes the current placements, e.g. when the app quits.
+pub fn save(app: &AppHandle) {
+    persist(app, &app.state::<FloatingState>().windows.lock().unwrap());
+}
+
+/// Loads saved floating windows and reopens the ones left open. Boards that
+/// can't be loaded any more, such as locked encrypted ones, are skipped.
+pub fn restore(app: &AppHandle) -> Result<(), String> {
+    let saved = database::load_floating_windows(app)?;
+    *app.state::<FloatingState>().windows.lock().unwrap() = saved.clone();
+    for window in left_open(&saved) {
+        let Some(title) = database::load_board(app, window.board_id).ok().and_then(|b| title(b, window.layer_id)) else {
+            continue;
+        };
+        if let Err(e) = build(app, window, &title) {
+            log::warn!("Failed to restore floating window: {}", e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn labels_avoid_dots() {
+        assert_eq!(label(17, None), "floating-17");
+        assert_eq!(label(17, Some(3.5)), "floating-17-layer-3_5");
+        assert_eq!(label(17, Some(1712345678901.0)), "floating-17-layer-1712345678901");
+    }
+
+    #[test]
+    fn remembers_windows() {
+        let dir: PathBuf = std::env::temp_dir().join(format!("eyedea-floating-{}", database::random_token().unwrap()));
+        database::init_storage(&dir).unwrap();
+        assert!(database::load_floating_windows(&dir).unwrap().is_empty());
+
+        let window = FloatingWindow { board_id: 1, layer_id: Some(2.0), x: Some(40.0), y: Some(60.0), opacity: 0.5, open: true, ..Default::default() };
+        database::save_floating_windows(&dir, std::slice::from_ref(&window)).unwrap();
+        assert_eq!(database::load_floating_windows(&dir).unwrap(), vec![window]);
+    }
+
+    #[test]
+    fn reopens_where_it_was_left() {
+        let mut windows = Vec::new();
+        let opened = mark_open(&mut windows, 1, None);
+        assert!(opened.open);
+        assert_eq!((opened.x, opened.y), (None, None));
+
+        Change::Moved(LogicalPosition::new(40.0, 60.0)).apply(&mut windows[0]);
+        Change::Resized(LogicalSize::new(300.0, 200.0)).apply(&mut windows[0]);
+        let layer = mark_open(&mut windows, 1, Some(2.0));
+        assert_eq!(windows.len(), 2);
+        assert_eq!(left_open(&windows).count(), 2);
+
+        // Closing by hand keeps the placement but not the open flag
+        Change::Closed.apply(&mut windows[0]);
+        assert_eq!(left_open(&windows).collect::<Vec<_>>(), vec![&layer]);
+
+        let reopened = mark_open(&mut windows, 1, None);
+        assert_eq!(windows.len(), 2);
+        assert!(reopened.open);
+        assert_eq!((reopened.x, reopened.y, reopened.width, reopened.height), (Some(40.0), Some(60.0), 300.0, 200.0));
+    }
+
+    #[test]
+    fn changes_only_the_options_given() {
+        let mut window = FloatingWindow { always_on_top: true, ..Default::default() };
+        apply_options(&mut window, &FloatingOptions { opacity: Some(0.01), click_through: Some(true), ..Default::default() });
+        assert_eq!((window.opacity, window.always_on_top, window.click_through), (0.1, true, true));
+
+        apply_options(&mut window, &FloatingOptions { opacity: Some(3.0), always_on_top: Some(false), ..Default::default() });
+        assert_eq!((window.opacity, window.always_on_top, window.click_through), (1.0, false, true));
+        assert_eq!(opacity_script(0.5), "document.documentElement.style.opacity = '0.5';");
+    }
+}
@@ -0,0 +1,708 @@
+use crate::drawing::{CanvasObject, TextStyle};
+use ab_glyph::{Font, FontArc};
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// A font file shipped with the frontend in `src/fonts`.
+pub struct BundledFont {
+    pub family: &'static str,
+    pub bold: bool,
+    pub file: &'static str,
+    data: &'static [u8],
+}
+
+macro_rules! bundled {
+    ($family:expr, $bold:expr, $file:expr) => {
+        BundledFont {
+            family: $family,
+            bold: $bold,
+            file: $file,
+            data: include_bytes!(concat!("../../src/fonts/", $file)),
+        }
+    };
+}
+
+static BUNDLED_FONTS: &[BundledFont] = &[
+    bundled!("Roboto", false, "roboto-regular.woff2"),
+    bundled!("Roboto", true, "roboto-bold.woff2"),
+    bundled!("Open Sans", false, "opensans-regular.woff2"),
+    bundled!("Open Sans", true, "opensans-bold.woff2"),
+    bundled!("Lato", false, "lato-regular.woff2"),
+    bundled!("Lato", true, "lato-bold.woff2"),
+    bundled!("Montserrat", false, "montserrat-regular.woff2"),
+    bundled!("Montserrat", true, "montserrat-bold.woff2"),
+    bundled!("Oswald", false, "oswald-regular.woff2"),
+    bundled!("Oswald", true, "oswald-bold.woff2"),
+    bundled!("Raleway", false, "raleway-regular.woff2"),
+    bundled!("Raleway", true, "raleway-bold.woff2"),
+    bundled!("Poppins", false, "poppins-regular.woff2"),
+    bundled!("Poppins", true, "poppins-bold.woff2"),
+    bundled!("Ubuntu", false, "ubuntu-regular.woff2"),
+    bundled!("Ubuntu", true, "ubuntu-bold.woff2"),
+    bundled!("Playfair Display", false, "playfairdisplay-regular.woff2"),
+    bundled!("Playfair Display", true, "playfairdisplay-bold.woff2"),
+    bundled!("Merriweather", false, "merriweather-regular.woff2"),
+    bundled!("Merriweather", true, "merriweather-bold.woff2"),
+    bundled!("Cinzel", false, "cinzel-regular.woff2"),
+    bundled!("Cinzel", true, "cinzel-bold.woff2"),
+    bundled!("Abril Fatface", false, "abrilfatface-regular.woff2"),
+    bundled!("Bebas Neue", false, "bebasneue-regular.woff2"),
+    bundled!("Righteous", false, "righteous-regular.woff2"),
+    bundled!("Lobster", false, "lobster-regular.woff2"),
+    bundled!("Permanent Marker", false, "permanentmarker-regular.woff2"),
+    bundled!("Pacifico", false, "pacifico-regular.woff2"),
+    bundled!("Dancing Script", false, "dancingscript-regular.woff2"),
+    bundled!("Dancing Script", true, "dancingscript-bold.woff2"),
+    bundled!("Caveat", false, "caveat-regular.woff2"),
+    bundled!("Indie Flower", false, "indieflower-regular.woff2"),
+    bundled!("Shadows Into Light", false, "shadowsintolight-regular.woff2"),
+    bundled!("Architects Daughter", false, "architectsdaughter-regular.woff2"),
+    bundled!("Roboto Mono", false, "robotomono-regular.woff2"),
+    bundled!("Roboto Mono", true, "robotomono-bold.woff2"),
+    bundled!("Source Code Pro", false, "sourcecodepro-regular.woff2"),
+    bundled!("Source Code Pro", true, "sourcecodepro-bold.woff2"),
+];
+
+/// Picks the bundled font for a CSS `font-family` value such as
+/// `"'Roboto', sans-serif"`. Families we don't ship (the editor defaults to
+/// Arial) fall back by generic family, then to Roboto.
+pub fn resolve(font_family: &str, bold: bool) -> &'static BundledFont {
+    let families: Vec<String> = font_family
+        .split(',')
+        .map(|f| f.trim().trim_matches(|c| c == '\'' || c == '"').to_lowercase())
+        .filter(|f| !f.is_empty())
+        .collect();
+
+    let fallback = families
+        .iter()
+        .find_map(|f| match f.as_str() {
+            "serif" | "georgia" | "times" | "times new roman" => Some("Merriweather"),
+            "monospace" | "courier" | "courier new" | "consolas" => Some("Roboto Mono"),
+            "cursive" | "comic sans ms" => Some("Caveat"),
+            _ => None,
+        })
+        .unwrap_or("Roboto");
+
+    let find = |family: &str| {
+        let matches: Vec<&'static BundledFont> = BUNDLED_FONTS
+            .iter()
+            .filter(|f| f.family.eq_ignore_ascii_case(family))
+            .collect();
+        matches
+            .iter()
+            .find(|f| f.bold == bold)
+            .or_else(|| matches.first())
+            .copied()
+    };
+
+    families
+        .iter()
+        .find_map(|f| find(f))
+        .or_else(|| find(fallback))
+        .unwrap_or(&BUNDLED_FONTS[0])
+}
+
+type Cache<T> = OnceLock<Mutex<HashMap<&'static str, T>>>;
+
+impl BundledFont {
+    /// The font as a plain TrueType file, decoded from WOFF2 on first use.
+    pub fn sfnt(&self) -> Result<Arc<Vec<u8>>, String> {
+        static CACHE: Cache<Arc<Vec<u8>>> = OnceLock::new();
+        let cache = CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+
+        if let Some(data) = cache.lock().unwrap().get(self.file) {
+            return Ok(data.clone());
+        }
+
+        let data = Arc::new(
+            decode_woff2(self.data).map_err(|e| format!("Failed to decode font {}: {}", self.file, e))?,
+        );
+        cache.lock().unwrap().insert(self.file, data.clone());
+        Ok(data)
+    }
+
+    pub fn font(&self) -> Result<FontArc, String> {
+        static CACHE: Cache<FontArc> = OnceLock::new();
+        let cache = CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+
+        if let Some(font) = cache.lock().unwrap().get(self.file) {
+            return Ok(font.clone());
+        }
+
+        let data = self.sfnt()?;
+        let font = FontArc::try_from_vec(data.as_ref().clone())
+            .map_err(|e| format!("Failed to load font {}: {}", self.file, e))?;
+        cache.lock().unwrap().insert(self.file, font.clone());
+        Ok(font)
+    }
+}
+
+/// Font size to font units conversion factor.
+pub fn units_scale(font: &FontArc, size: f64) -> f64 {
+    size / font.units_per_em().unwrap_or(1000.0) as f64
+}
+
+/// Advance width of `text` set in `font` at `size` px, including kerning.
+pub fn measure(font: &FontArc, text: &str, size: f64) -> f64 {
+    let mut width = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = font.glyph_id(c);
+        if let Some(prev) = previous {
+            width += font.kern_unscaled(prev, id) as f64;
+        }
+        width += font.h_advance_unscaled(id) as f64;
+        previous = Some(id);
+    }
+    width * units_scale(font, size)
+}
+
+/// A run of text with a single style, positioned in board coordinates.
+pub struct PlacedRun {
+    pub text: String,
+    pub x: f64,
+    pub baseline: f64,
+    pub width: f64,
+    pub style: TextStyle,
+    pub face: &'static BundledFont,
+}
+
+/// Lays out a text object the way `renderText` in `canvas-objects.js` does:
+/// 10px padding, word wrapping on whitespace, per-line baselines at 80% of
+/// the tallest font and `textAlign` applied per line. Runs falling below the
+/// box are dropped, matching the canvas clip.
+pub fn layout_text(obj: &CanvasObject) -> Result<Vec<PlacedRun>, String> {
+    const PADDING: f64 = 10.0;
+    let max_width = obj.width - PADDING * 2.0;
+    let align = obj.text_align.as_deref().unwrap_or("left");
+    let spans = obj.spans();
+    let base_size = spans.first().map(|s| s.style.size()).unwrap_or(32.0);
+
+    struct Word {
+        text: String,
+        width: f64,
+        style: TextStyle,
+        face: &'static BundledFont,
+    }
+
+    let mut lines: Vec<Vec<Word>> = Vec::new();
+    let mut line: Vec<Word> = Vec::new();
+    let mut line_width = 0.0;
+
+    for span in &spans {
+        let face = resolve(span.style.family(), span.style.is_bold());
+        let font = face.font()?;
+        let parts: Vec<&str> = span.text.split('\n').collect();
+        for (i, part) in parts.iter().enumerate() {
+            for word in split_keep_whitespace(part) {
+                let width = measure(&font, word, span.style.size());
+                if line_width + width > max_width && !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                    line_width = 0.0;
+                }
+                line.push(Word { text: word.to_string(), width, style: span.style.clone(), face });
+                line_width += width;
+            }
+            if i < parts.len() - 1 {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0.0;
+            }
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    let mut runs = Vec::new();
+    let mut y = obj.y + PADDING;
+    for line in lines {
+        if line.is_empty() {
+            y += base_size * 1.2;
+            continue;
+        }
+
+        let max_size = line.iter().map(|w| w.style.size()).fold(0.0, f64::max);
+        let baseline = y + max_size * 0.8;
+        let width: f64 = line.iter().map(|w| w.width).sum();
+        let mut x = match align {
+            "center" => obj.x + (obj.width - width) / 2.0,
+            "right" => obj.x + obj.width - PADDING - width,
+            _ => obj.x + PADDING,
+        };
+
+        if baseline - max_size * 0.8 < obj.y + obj.height {
+            for word in line {
+                runs.push(PlacedRun {
+                    text: word.text,
+                    x,
+                    baseline,
+                    width: word.width,
+                    style: word.style,
+                    face: word.face,
+                });
+                x += word.width;
+            }
+        }
+        y += max_size * 1.2;
+    }
+
+    Ok(runs)
+}
+
+/// Splits like JS `text.split(/(\s+)/)`, keeping whitespace runs as words.
+fn split_keep_whitespace(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut in_space = None;
+    for (i, c) in text.char_indices() {
+        let space = c.is_whitespace();
+        if in_space.is_some_and(|s| s != space) {
+            words.push(&text[start..i]);
+            start = i;
+        }
+        in_space = Some(space);
+    }
+    if start < text.len() {
+        words.push(&text[start..]);
+    }
+    words
+}
+
+/// Converts a WOFF2 file back into the TrueType font it was built from,
+/// following the reconstruction steps of the W3C WOFF2 spec.
+pub fn decode_woff2(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut header = Reader::new(data);
+    if header.u32()? != 0x774F_4632 {
+        return Err("not a WOFF2 file".to_string());
+    }
+    let flavor = header.u32()?;
+    if flavor == 0x7474_6366 {
+        return Err("font collections are not supported".to_string());
+    }
+    header.skip(4)?;
+    let num_tables = header.u16()? as usize;
+    header.skip(6)?;
+    let compressed_size = header.u32()? as usize;
+    header.skip(24)?;
+
+    let mut entries = Vec::with_capacity(num_tables);
+    for _ in 0..num_tables {
+        let flags = header.u8()?;
+        let tag = if flags & 0x3f == 0x3f {
+            header.tag()?
+        } else {
+            *KNOWN_TAGS
+                .get((flags & 0x3f) as usize)
+                .ok_or("invalid table tag index")?
+        };
+        let version = flags >> 6;
+        let orig_length = header.base128()? as usize;
+        let transformed = if &tag == b"glyf" || &tag == b"loca" {
+            version == 0
+        } else {
+            version != 0
+        };
+        let stored_length = if transformed {
+            header.base128()? as usize
+        } else {
+            orig_length
+        };
+        entries.push((tag, transformed, orig_length, stored_length));
+    }
+
+    let compressed = data
+        .get(header.pos..header.pos + compressed_size)
+        .ok_or("truncated compressed data")?;
+    let mut stream = Vec::new();
+    brotli::Decompressor::new(compressed, 4096)
+        .read_to_end(&mut stream)
+        .map_err(|e| format!("brotli error: {}", e))?;
+
+    let mut tables: Vec<([u8; 4], Vec<u8>)> = Vec::with_capacity(num_tables);
+    let mut offset = 0;
+    let mut glyf_transformed = None;
+    for (tag, transformed, _, stored_length) in &entries {
+        let bytes = stream
+            .get(offset..offset + stored_length)
+            .ok_or("truncated table data")?;
+        offset += stored_length;
+        match (tag, transformed) {
+            (b"glyf", true) => glyf_transformed = Some(bytes),
+            (b"loca", true) => {}
+            (_, true) => return Err(format!("unsupported transform for {}", String::from_utf8_lossy(tag))),
+            _ => tables.push((*tag, bytes.to_vec())),
+        }
+    }
+
+    if let Some(glyf) = glyf_transformed {
+        let (glyf, loca, index_format) = reconstruct_glyf(glyf)?;
+        if let Some((_, head)) = tables.iter_mut().find(|(tag, _)| tag == b"head") {
+            if head.len() >= 52 {
+                head[50..52].copy_from_slice(&index_format.to_be_bytes());
+            }
+        }
+        tables.push((*b"glyf", glyf));
+        tables.push((*b"loca", loca));
+    }
+
+    Ok(build_sfnt(flavor, tables))
+}
+
+const KNOWN_TAGS: [[u8; 4]; 63] = [
+    *b"cmap", *b"head", *b"hhea", *b"hmtx", *b"maxp", *b"name", *b"OS/2", *b"post", *b"cvt ",
+    *b"fpgm", *b"glyf", *b"loca", *b"prep", *b"CFF ", *b"VORG", *b"EBDT", *b"EBLC", *b"gasp",
+    *b"hdmx", *b"kern", *b"LTSH", *b"PCLT", *b"VDMX", *b"vhea", *b"vmtx", *b"BASE", *b"GDEF",
+    *b"GPOS", *b"GSUB", *b"EBSC", *b"JSTF", *b"MATH", *b"CBDT", *b"CBLC", *b"COLR", *b"CPAL",
+    *b"SVG ", *b"sbix", *b"acnt", *b"avar", *b"bdat", *b"bloc", *b"bsln", *b"cvar", *b"fdsc",
+    *b"feat", *b"fmtx", *b"fvar", *b"gvar", *b"hsty", *b"just", *b"lcar", *b"mort", *b"morx",
+    *b"opbd", *b"prop", *b"trak", *b"Zapf", *b"Silf", *b"Glat", *b"Gloc", *b"Feat", *b"Sill",
+];
+
+fn reconstruct_glyf(data: &[u8]) -> Result<(Vec<u8>, Vec<u8>, u16), String> {
+    let mut header = Reader::new(data);
+    header.skip(2)?;
+    let option_flags = header.u16()?;
+    let num_glyphs = header.u16()? as usize;
+    let index_format = header.u16()?;
+    let mut sizes = [0usize; 7];
+    for size in sizes.iter_mut() {
+        *size = header.u32()? as usize;
+    }
+
+    let mut start = header.pos;
+    let mut streams = Vec::with_capacity(7);
+    for size in sizes {
+        streams.push(Reader::new(data.get(start..start + size).ok_or("truncated glyf stream")?));
+        start += size;
+    }
+    let overlap_bitmap = if option_flags & 1 != 0 {
+        data.get(start..start + num_glyphs.div_ceil(8))
+    } else {
+        None
+    };
+
+    let mut streams = streams.into_iter();
+    let mut n_contours = streams.next().unwrap();
+    let mut n_points = streams.next().unwrap();
+    let mut flags = streams.next().unwrap();
+    let mut glyphs = streams.next().unwrap();
+    let mut composites = streams.next().unwrap();
+    let mut bboxes = streams.next().unwrap();
+    let mut instructions = streams.next().unwrap();
+
+    let bitmap_len = num_glyphs.div_ceil(32) * 4;
+    let bbox_bitmap = bboxes.bytes(bitmap_len)?.to_vec();
+    let has_bbox = |i: usize| bbox_bitmap[i >> 3] & (0x80 >> (i & 7)) != 0;
+
+    let mut glyf = Vec::new();
+    let mut offsets = Vec::with_capacity(num_glyphs + 1);
+
+    for i in 0..num_glyphs {
+        offsets.push(glyf.len());
+        let contours = n_contours.i16()?;
+
+        if contours == 0 {
+            continue;
+        }
+
+        if contours < 0 {
+            if !has_bbox(i) {
+                return Err("composite glyph without bbox".to_string());
+            }
+            glyf.extend_from_slice(&contours.to_be_bytes());
+            glyf.extend_from_slice(bboxes.bytes(8)?);
+
+            let component_start = composites.pos;
+            let mut have_instructions = false;
+            loop {
+                let flag = composites.u16()?;
+                have_instructions |= flag & 0x0100 != 0;
+                let mut len = 2 + if flag & 0x0001 != 0 { 4 } else { 2 };
+                if flag & 0x0008 != 0 {
+                    len += 2;
+                } else if flag & 0x0040 != 0 {
+                    len += 4;
+                } else if flag & 0x0080 != 0 {
+                    len += 8;
+                }
+                composites.skip(len)?;
+                if flag & 0x0020 == 0 {
+                    break;
+                }
+            }
+            glyf.extend_from_slice(&composites.data[component_start..composites.pos]);
+
+            if have_instructions {
+                let len = glyphs.u255()? as usize;
+                glyf.extend_from_slice(&(len as u16).to_be_bytes());
+                glyf.extend_from_slice(instructions.bytes(len)?);
+            }
+        } else {
+            let mut end_points = Vec::with_capacity(contours as usize);
+            let mut total = 0usize;
+            for _ in 0..contours {
+                total += n_points.u255()? as usize;
+                end_points.push(total as u16 - 1);
+            }
+
+            let mut points = Vec::with_capacity(total);
+            let (mut x, mut y) = (0i32, 0i32);
+            for _ in 0..total {
+                let flag = flags.u8()?;
+                let on_curve = flag & 0x80 == 0;
+                let (dx, dy) = decode_triplet(flag & 0x7f, &mut glyphs)?;
+                x += dx;
+                y += dy;
+                points.push((x, y, on_curve));
+            }
+
+            let instruction_len = glyphs.u255()? as usize;
+
+            glyf.extend_from_slice(&contours.to_be_bytes());
+            if has_bbox(i) {
+                glyf.extend_from_slice(bboxes.bytes(8)?);
+            } else {
+                let x_min = points.iter().map(|p| p.0).min().unwrap_or(0);
+                let y_min = points.iter().map(|p| p.1).min().unwrap_or(0);
+                let x_max = points.iter().map(|p| p.0).max().unwrap_or(0);
+                let y_max = points.iter().map(|p| p.1).max().unwrap_or(0);
+                for v in [x_min, y_min, x_max, y_max] {
+                    glyf.extend_from_slice(&(v as i16).to_be_bytes());
+                }
+            }
+            for end in end_points {
+                glyf.extend_from_slice(&end.to_be_bytes());
+            }
+            glyf.extend_from_slice(&(instruction_len as u16).to_be_bytes());
+            glyf.extend_from_slice(instructions.bytes(instruction_len)?);
+
+            let overlaps = overlap_bitmap.is_some_and(|b| b[i >> 3] & (0x80 >> (i & 7)) != 0);
+            encode_simple_points(&points, overlaps, &mut glyf);
+        }
+
+        while glyf.len() % 4 != 0 {
+            glyf.push(0);
+        }
+    }
+    offsets.push(glyf.len());
+
+    let mut loca = Vec::with_capacity(offsets.len() * 4);
+    for offset in offsets {
+        if index_format == 0 {
+            loca.extend_from_slice(&((offset / 2) as u16).to_be_bytes());
+        } else {
+            loca.extend_from_slice(&(offset as u32).to_be_bytes());
+        }
+    }
+
+    Ok((glyf, loca, index_format))
+}
+
+fn decode_triplet(flag: u8, glyphs: &mut Reader) -> Result<(i32, i32), String> {
+    let with_sign = |flag: u8, value: i32| if flag & 1 != 0 { value } else { -value };
+    let flag_i = flag as i32;
+
+    Ok(if flag < 10 {
+        let b0 = glyphs.u8()? as i32;
+        (0, with_sign(flag, ((flag_i & 14) << 7) + b0))
+    } else if flag < 20 {
+        let b0 = glyphs.u8()? as i32;
+        (with_sign(flag, (((flag_i - 10) & 14) << 7) + b0), 0)
+    } else if flag < 84 {
+        let b0 = flag_i - 20;
+        let b1 = glyphs.u8()? as i32;
+        (
+            with_sign(flag, 1 + (b0 & 0x30) + (b1 >> 4)),
+            with_sign(flag >> 1, 1 + ((b0 & 0x0c) << 2) + (b1 & 0x0f)),
+        )
+    } else if flag < 120 {
+        let b0 = flag_i - 84;
+        let b1 = glyphs.u8()? as i32;
+        let b2 = glyphs.u8()? as i32;
+        (
+            with_sign(flag, 1 + ((b0 / 12) << 8) + b1),
+            with_sign(flag >> 1, 1 + (((b0 % 12) >> 2) << 8) + b2),
+        )
+    } else if flag < 124 {
+        let b1 = glyphs.u8()? as i32;
+        let b2 = glyphs.u8()? as i32;
+        let b3 = glyphs.u8()? as i32;
+        (
+            with_sign(flag, (b1 << 4) + (b2 >> 4)),
+            with_sign(flag >> 1, ((b2 & 0x0f) << 8) + b3),
+        )
+    } else {
+        let b1 = glyphs.u8()? as i32;
+        let b2 = glyphs.u8()? as i32;
+        let b3 = glyphs.u8()? as i32;
+        let b4 = glyphs.u8()? as i32;
+        (
+            with_sign(flag, (b1 << 8) + b2),
+            with_sign(flag >> 1, (b3 << 8) + b4),
+        )
+    })
+}
+
+fn encode_simple_points(points: &[(i32, i32, bool)], overlaps: bool, out: &mut Vec<u8>) {
+    let mut flags = Vec::with_capacity(points.len());
+    let mut xs = Vec::new();
+    let mut ys = Vec::new();
+    let (mut last_x, mut last_y) = (0, 0);
+
+    for (i, &(x, y, on_curve)) in points.iter().enumerate() {
+        let mut flag = if on_curve { 0x01 } else { 0x00 };
+        if i == 0 && overlaps {
+            flag |= 0x40;
+        }
+
+        let dx = x - last_x;
+        if dx == 0 {
+            flag |= 0x10;
+        } else if (-255..=255).contains(&dx) {
+            flag |= 0x02 | if dx > 0 { 0x10 } else { 0 };
+            xs.push(dx.unsigned_abs() as u8);
+        } else {
+            xs.extend_from_slice(&(dx as i16).to_be_bytes());
+        }
+
+        let dy = y - last_y;
+        if dy == 0 {
+            flag |= 0x20;
+        } else if (-255..=255).contains(&dy) {
+            flag |= 0x04 | if dy > 0 { 0x20 } else { 0 };
+            ys.push(dy.unsigned_abs() as u8);
+        } else {
+            ys.extend_from_slice(&(dy as i16).to_be_bytes());
+        }
+
+        flags.push(flag);
+        last_x = x;
+        last_y = y;
+    }
+
+    out.extend_from_slice(&flags);
+    out.extend_from_slice(&xs);
+    out.extend_from_slice(&ys);
+}
+
+fn build_sfnt(flavor: u32, mut tables: Vec<([u8; 4], Vec<u8>)>) -> Vec<u8> {
+    tables.sort_by_key(|(tag, _)| *tag);
+
+    let num_tables = tables.len() as u16;
+    let entry_selector = 15 - num_tables.max(1).leading_zeros() as u16;
+    let search_range = (1u16 << entry_selector) * 16;
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&flavor.to_be_bytes());
+    out.extend_from_slice(&num_tables.to_be_bytes());
+    out.extend_from_slice(&search_range.to_be_bytes());
+    out.extend_from_slice(&entry_selector.to_be_bytes());
+    out.extend_from_slice(&(num_tables * 16 - search_range).to_be_bytes());
+
+    let mut offset = 12 + 16 * tables.len();
+    let mut head_offset = None;
+    for (tag, table) in tables.iter_mut() {
+        if tag == b"head" && table.len() >= 12 {
+            table[8..12].copy_from_slice(&[0; 4]);
+            head_offset = Some(offset);
+        }
+        out.extend_from_slice(tag);
+        out.extend_from_slice(&checksum(table).to_be_bytes());
+        out.extend_from_slice(&(offset as u32).to_be_bytes());
+        out.extend_from_slice(&(table.len() as u32).to_be_bytes());
+        offset += table.len().div_ceil(4) * 4;
+    }
+    for (_, table) in &tables {
+        out.extend_from_slice(table);
+        while out.len() % 4 != 0 {
+            out.push(0);
+        }
+    }
+
+    if let Some(head) = head_offset {
+        let adjustment = 0xB1B0_AFBAu32.wrapping_sub(checksum(&out));
+        out[head + 8..head + 12].copy_from_slice(&adjustment.to_be_bytes());
+    }
+
+    out
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or("unexpected end of font data")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), String> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn i16(&mut self) -> Result<i16, String> {
+        Ok(self.u16()? as i16)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn tag(&mut self) -> Result<[u8; 4], String> {
+        let b = self.bytes(4)?;
+        Ok([b[0], b[1], b[2], b[3]])
+    }
+
+    fn base128(&mut self) -> Result<u32, String> {
+        let mut value = 0u32;
+        for _ in 0..5 {
+            let byte = self.u8()?;
+            if value & 0xFE00_0000 != 0 {
+                return Err("UIntBase128 overflow".to_string());
+            }
+            value = (value << 7) | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("UIntBase128 too long".to_string())
+    }
+
+    fn u255(&mut self) -> Result<u16, String> {
+        Ok(match self.u8()? {
+            253 => self.u16()?,
+            254 => self.u8()? as u16 + 506,
+            255 => self.u8()? as u16 + 253,
+            code => code as u16,
+        })
+    }
+}
@@ -0,0 +1,368 @@
+//! Persistent cache for fetched URLs under `http_cache/` in the data dir.
+//!
+//! Responses are stored whole, keyed by URL, with their validators so stale
+//! entries can be revalidated with a conditional request. The index records
+//! when each entry was last used; the least recently used entries are
+//! evicted once the total size passes the limit.
+//!
+//! The index is kept in memory. Changes to entries are written at once,
+//! while last-used times are written at most every `TOUCH_FLUSH` and on
+//! exit.
+
+use crate::database::{self, DataDir};
+use reqwest::header::{HeaderMap, AGE, CACHE_CONTROL, CONTENT_TYPE, ETAG, EXPIRES, LAST_MODIFIED};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+const DEFAULT_MAX_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Longest a last-used time waits in memory before the index is written.
+const TOUCH_FLUSH: u64 = 30_000;
+
+/// The index of each data folder, loaded on first use.
+static INDEXES: Mutex<BTreeMap<PathBuf, Loaded>> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheEntry {
+    pub file: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// The full `Content-Type` header, charset included.
+    pub content_type: Option<String>,
+    pub size: u64,
+    pub stored_at: u64,
+    /// Served without revalidating until then; always revalidated when absent.
+    pub fresh_until: Option<u64>,
+    pub last_used: u64,
+}
+
+impl CacheEntry {
+    pub fn is_fresh(&self) -> bool {
+        self.fresh_until.is_some_and(|t| database::now_millis() < t)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Index {
+    max_bytes: u64,
+    entries: HashMap<String, CacheEntry>,
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Index { max_bytes: DEFAULT_MAX_BYTES, entries: HashMap::new() }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheInfo {
+    pub path: String,
+    pub entries: usize,
+    pub total_bytes: u64,
+    pub max_bytes: u64,
+}
+
+fn cache_dir(app: &impl DataDir) -> PathBuf {
+    app.data_dir().join("http_cache")
+}
+
+struct Loaded {
+    index: Index,
+    /// Set when last-used times changed since the index was written.
+    touched: bool,
+    saved_at: u64,
+}
+
+impl Loaded {
+    fn save(&mut self, app: &impl DataDir) -> Result<(), String> {
+        let dir = cache_dir(app);
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let content = serde_json::to_string(&self.index).map_err(|e| e.to_string())?;
+        fs::write(dir.join("index.json"), content).map_err(|e| e.to_string())?;
+        self.touched = false;
+        self.saved_at = database::now_millis();
+        Ok(())
+    }
+}
+
+/// Runs `f` on the index of a data folder, holding it for the duration.
+fn with_index<T>(app: &impl DataDir, f: impl FnOnce(&mut Loaded) -> T) -> T {
+    let mut indexes = INDEXES.lock().unwrap();
+    let loaded = indexes.entry(app.data_dir()).or_insert_with(|| Loaded {
+        index: fs::read_to_string(cache_dir(app).join("index.json"))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default(),
+        touched: false,
+        saved_at: database::now_millis(),
+    });
+    f(loaded)
+}
+
+/// Writes last-used times still held in memory.
+pub fn flush(app: &impl DataDir) {
+    with_index(app, |loaded| {
+        if loaded.touched {
+            if let Err(e) = loaded.save(app) {
+                log::warn!("Failed to save the HTTP cache index: {}", e);
+            }
+        }
+    });
+}
+
+fn file_name(url: &str) -> String {
+    let digest = Sha256::digest(url.as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The cached entry and body for a URL, if both are still on disk.
+pub fn lookup(app: &impl DataDir, url: &str) -> Option<(CacheEntry, Vec<u8>)> {
+    let entry = with_index(app, |loaded| loaded.index.entries.get(url).cloned())?;
+    let bytes = fs::read(cache_dir(app).join(&entry.file)).ok()?;
+    Some((entry, bytes))
+}
+
+/// Marks an entry as used so eviction keeps it around.
+pub fn touch(app: &impl DataDir, url: &str) {
+    with_index(app, |loaded| {
+        let now = database::now_millis();
+        if let Some(entry) = loaded.index.entries.get_mut(url) {
+            entry.last_used = now;
+            loaded.touched = true;
+        }
+        if loaded.touched && now.saturating_sub(loaded.saved_at) >= TOUCH_FLUSH {
+            let _ = loaded.save(app);
+        }
+    })
+}
+
+/// Applies the headers of a 304 to an entry and returns the updated entry.
+pub fn revalidated(app: &impl DataDir, url: &str, headers: &HeaderMap) -> Option<CacheEntry> {
+    with_index(app, |loaded| {
+        let entry = loaded.index.entries.get_mut(url)?;
+        let now = database::now_millis();
+        entry.fresh_until = freshness(headers).and_then(|f| f.map(|d| now + d.as_millis() as u64));
+        entry.last_used = now;
+        if let Some(etag) = header(headers, ETAG) {
+            entry.etag = Some(etag);
+        }
+        if let Some(modified) = header(headers, LAST_MODIFIED) {
+            entry.last_modified = Some(modified);
+        }
+        let entry = entry.clone();
+        let _ = loaded.save(app);
+        Some(entry)
+    })
+}
+
+/// Stores a successful response unless it forbids storing, then evicts
+/// down to the size limit.
+pub fn store(app: &impl DataDir, url: &str, headers: &HeaderMap, bytes: &[u8]) -> Result<(), String> {
+    let Some(fresh_for) = freshness(headers) else {
+        return remove(app, url);
+    };
+
+    let dir = cache_dir(app);
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let file = file_name(url);
+    // Write then rename so concurrent fetches of the same URL never see a partial body
+    let partial = dir.join(format!("{}.{}.part", file, database::now_millis()));
+    fs::write(&partial, bytes).map_err(|e| e.to_string())?;
+
+    with_index(app, |loaded| {
+        if bytes.len() as u64 > loaded.index.max_bytes {
+            let _ = fs::remove_file(&partial);
+            return Ok(());
+        }
+        fs::rename(&partial, dir.join(&file)).map_err(|e| e.to_string())?;
+
+        let now = database::now_millis();
+        loaded.index.entries.insert(
+            url.to_string(),
+            CacheEntry {
+                file,
+                etag: header(headers, ETAG),
+                last_modified: header(headers, LAST_MODIFIED),
+                content_type: header(headers, CONTENT_TYPE),
+                size: bytes.len() as u64,
+                stored_at: now,
+                fresh_until: fresh_for.map(|d| now + d.as_millis() as u64),
+                last_used: now,
+            },
+        );
+        evict(app, &mut loaded.index);
+        loaded.save(app)
+    })
+}
+
+fn remove(app: &impl DataDir, url: &str) -> Result<(), String> {
+    with_index(app, |loaded| {
+        if let Some(entry) = loaded.index.entries.remove(url) {
+            let _ = fs::remove_file(cache_dir(app).join(entry.file));
+            loaded.save(app)?;
+        }
+        Ok(())
+    })
+}
+
+fn evict(app: &impl DataDir, index: &mut Index) {
+    let mut total: u64 = index.entries.values().map(|e| e.size).sum();
+    if total <= index.max_bytes {
+        return;
+    }
+    let mut by_age: Vec<(String, u64)> = index.entries.iter().map(|(url, e)| (url.clone(), e.last_used)).collect();
+    by_age.sort_by_key(|(_, used)| *used);
+    for (url, _) in by_age {
+        if total <= index.max_bytes {
+            break;
+        }
+        if let Some(entry) = index.entries.remove(&url) {
+            total -= entry.size;
+            let _ = fs::remove_file(cache_dir(app).join(entry.file));
+        }
+    }
+}
+
+pub fn info(app: &impl DataDir) -> CacheInfo {
+    with_index(app, |loaded| CacheInfo {
+        path: cache_dir(app).to_string_lossy().to_string(),
+        entries: loaded.index.entries.len(),
+        total_bytes: loaded.index.entries.values().map(|e| e.size).sum(),
+        max_bytes: loaded.index.max_bytes,
+    })
+}
+
+/// Deletes every cached response, keeping the size limit.
+pub fn clear(app: &impl DataDir) -> Result<CacheInfo, String> {
+    with_index(app, |loaded| {
+        let dir = cache_dir(app);
+        if dir.exists() {
+            fs::remove_dir_all(&dir).map_err(|e| format!("Failed to clear cache: {}", e))?;
+        }
+        loaded.index.entries.clear();
+        loaded.save(app)
+    })?;
+    Ok(info(app))
+}
+
+pub fn set_limit(app: &impl DataDir, max_bytes: u64) -> Result<CacheInfo, String> {
+    with_index(app, |loaded| {
+        loaded.index.max_bytes = max_bytes;
+        evict(app, &mut loaded.index);
+        loaded.save(app)
+    })?;
+    Ok(info(app))
+}
+
+fn header(headers: &HeaderMap, name: reqwest::header::HeaderName) -> Option<String> {
+    headers.get(name).and_then(|v| v.to_str().ok()).map(|v| v.trim().to_string())
+}
+
+/// How long a response may be served without revalidation: `None` when it
+/// must not be stored at all, `Some(None)` when it must always be revalidated.
+fn freshness(headers: &HeaderMap) -> Option<Option<Duration>> {
+    let cache_control = header(headers, CACHE_CONTROL).unwrap_or_default().to_lowercase();
+    let mut max_age = None;
+    for directive in cache_control.split(',').map(str::trim) {
+        match directive.split_once('=') {
+            _ if directive == "no-store" => return None,
+            _ if directive == "no-cache" => return Some(None),
+            Some(("max-age", value)) => max_age = value.trim_matches('"').parse::<u64>().ok(),
+            _ => {}
+        }
+    }
+
+    if let Some(max_age) = max_age {
+        let age = header(headers, AGE).and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+        return Some(Some(Duration::from_secs(max_age.saturating_sub(age))));
+    }
+    let expires = header(headers, EXPIRES)
+        .and_then(|v| httpdate::parse_http_date(&v).ok())
+        .and_then(|t| t.duration_since(SystemTime::now()).ok());
+    Some(expires)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(pairs: &[(reqwest::header::HeaderName, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(name.clone(), HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn data_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("eyedea-http-cache-{}", database::random_token().unwrap()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn reads_freshness_from_headers() {
+        assert_eq!(freshness(&headers(&[(CACHE_CONTROL, "no-store, max-age=60")])), None);
+        assert_eq!(freshness(&headers(&[(CACHE_CONTROL, "max-age=60, no-cache")])), Some(None));
+        assert_eq!(
+            freshness(&headers(&[(CACHE_CONTROL, "public, Max-Age=\"60\""), (AGE, "10")])),
+            Some(Some(Duration::from_secs(50)))
+        );
+        assert_eq!(freshness(&headers(&[(CACHE_CONTROL, "max-age=5"), (AGE, "10")])), Some(Some(Duration::ZERO)));
+        // Nothing to go by, or already expired: revalidate every time
+        assert_eq!(freshness(&HeaderMap::new()), Some(None));
+        assert_eq!(freshness(&headers(&[(EXPIRES, "Thu, 01 Jan 1970 00:00:00 GMT")])), Some(None));
+
+        let later = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(3600));
+        let fresh_for = freshness(&headers(&[(EXPIRES, &later)])).flatten().unwrap();
+        assert!(fresh_for > Duration::from_secs(3500) && fresh_for <= Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used() {
+        let dir = data_dir();
+        let cacheable = headers(&[(CACHE_CONTROL, "max-age=600")]);
+        for url in ["https://a.test/", "https://b.test/", "https://c.test/"] {
+            store(&dir, url, &cacheable, &[0; 10]).unwrap();
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        touch(&dir, "https://a.test/");
+
+        let info = set_limit(&dir, 20).unwrap();
+        assert_eq!((info.entries, info.total_bytes), (2, 20));
+        assert!(lookup(&dir, "https://b.test/").is_none());
+        assert!(!cache_dir(&dir).join(file_name("https://b.test/")).exists());
+        assert!(lookup(&dir, "https://a.test/").is_some_and(|(entry, _)| entry.is_fresh()));
+
+        // Too big for the cache at all
+        store(&dir, "https://d.test/", &cacheable, &[0; 30]).unwrap();
+        assert!(lookup(&dir, "https://d.test/").is_none());
+        store(&dir, "https://c.test/", &headers(&[(CACHE_CONTROL, "no-store")]), &[0; 10]).unwrap();
+        assert!(lookup(&dir, "https://c.test/").is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn defers_writing_last_used_times() {
+        let dir = data_dir();
+        store(&dir, "https://a.test/", &headers(&[(CACHE_CONTROL, "max-age=600")]), b"body").unwrap();
+        let written = || fs::read_to_string(cache_dir(&dir).join("index.json")).unwrap();
+        let before = written();
+
+        std::thread::sleep(Duration::from_millis(2));
+        touch(&dir, "https://a.test/");
+        assert_eq!(written(), before);
+        flush(&dir);
+        assert_ne!(written(), before);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
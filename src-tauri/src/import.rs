@@ -0,0 +1,562 @@
+//! Importing media files from disk into boards and the asset library.
+
+use crate::database::{self, Asset, Board, Layer};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "webp", "bmp", "svg"];
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "mov", "webm"];
+
+/// Screen-space row height and gap for media dropped into a board's view,
+/// divided by the zoom.
+const VIEW_ROW_HEIGHT: f64 = 300.0;
+const VIEW_GAP: f64 = 20.0;
+
+/// Size given to media whose dimensions can't be read without decoding it.
+const FALLBACK_SIZE: (f64, f64) = (640.0, 360.0);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportLayout {
+    /// Uniform cells, each item fitted inside its cell.
+    #[default]
+    Grid,
+    /// Rows of equal height, widths following each item's aspect ratio.
+    Packed,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FolderImportOptions {
+    pub recursive: bool,
+    pub layout: ImportLayout,
+    /// Cell size for the grid, row height for the packed layout.
+    pub cell_size: f64,
+    pub gap: f64,
+    pub add_to_library: bool,
+    /// Tag library assets with the names of the subfolders they came from.
+    pub folder_tags: bool,
+}
+
+impl Default for FolderImportOptions {
+    fn default() -> Self {
+        Self {
+            recursive: true,
+            layout: ImportLayout::Grid,
+            cell_size: 400.0,
+            gap: 20.0,
+            add_to_library: false,
+            folder_tags: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportProgress {
+    pub done: usize,
+    pub total: usize,
+    pub file: String,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderImport {
+    pub board: Board,
+    pub imported: usize,
+    pub failed: Vec<String>,
+    /// Assets to register in the global library, when requested.
+    pub library: Vec<Asset>,
+}
+
+/// Media kind of a file as the frontend names it, from its extension.
+pub fn media_kind(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_string_lossy().to_lowercase();
+    if ext == "gif" {
+        Some("gif")
+    } else if IMAGE_EXTENSIONS.contains(&ext.as_str()) {
+        Some("image")
+    } else if VIDEO_EXTENSIONS.contains(&ext.as_str()) {
+        Some("video")
+    } else {
+        None
+    }
+}
+
+/// Supported media files under `dir`, sorted by path. Hidden files and
+/// folders are skipped, and links to folders are never followed, so a link
+/// back up the tree can't make the walk endless.
+pub fn collect_media(dir: &Path, recursive: bool) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let entries = fs::read_dir(&current).map_err(|e| format!("Failed to read {}: {}", current.display(), e))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            if file_type.is_dir() {
+                if recursive {
+                    pending.push(path);
+                }
+            } else if (file_type.is_file() || path.is_file()) && media_kind(&path).is_some() {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Copies every supported file under `dir` into the media store and lays
+/// them out on `board` below its existing content.
+pub fn import_folder(
+    images_dir: &Path,
+    dir: &Path,
+    board: Board,
+    options: &FolderImportOptions,
+    mut progress: impl FnMut(ImportProgress),
+) -> Result<FolderImport, String> {
+    if !dir.is_dir() {
+        return Err(format!("Not a folder: {}", dir.display()));
+    }
+    let files = collect_media(dir, options.recursive)?;
+    let total = files.len();
+
+    let mut layers = Vec::new();
+    let mut library = Vec::new();
+    let mut failed = Vec::new();
+    for (i, path) in files.iter().enumerate() {
+        let display = path.strip_prefix(dir).unwrap_or(path).to_string_lossy().to_string();
+        let error = match import_file(images_dir, path) {
+            Ok(layer) => {
+                if options.add_to_library {
+                    let tags = if options.folder_tags { folder_tags(dir, path) } else { Vec::new() };
+                    library.push(asset_for(&layer, tags));
+                }
+                layers.push(layer);
+                None
+            }
+            Err(e) => {
+                failed.push(display.clone());
+                Some(e)
+            }
+        };
+        progress(ImportProgress { done: i + 1, total, file: display, error });
+    }
+
+    let imported = layers.len();
+    let board = add_layers(board, layers, options.layout, options.cell_size, options.gap, None);
+    let ids: Vec<f64> = board.layers[board.layers.len() - imported..].iter().map(|l| l.id).collect();
+    for (asset, id) in library.iter_mut().zip(ids) {
+        asset.id = id;
+    }
+
+    Ok(FolderImport { board, imported, failed, library })
+}
+
+/// Copies one file into the media store and returns an unplaced layer at
+/// its natural size.
+pub fn import_file(images_dir: &Path, path: &Path) -> Result<Layer, String> {
+    let kind = media_kind(path).ok_or_else(|| format!("Unsupported file type: {}", path.display()))?;
+    let src = database::copy_media_file(images_dir, path)?;
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    Ok(stored_layer(images_dir, src, name, kind))
+}
+
+/// Writes media bytes into the store and returns an unplaced layer at its
+/// natural size.
+pub fn import_bytes(images_dir: &Path, bytes: &[u8], name: &str, ext: &str) -> Result<Layer, String> {
+    let kind = media_kind(Path::new(&format!("media.{}", ext))).ok_or_else(|| format!("Unsupported file type: {}", ext))?;
+    let src = database::write_media_file(images_dir, bytes, name, ext)?;
+    Ok(stored_layer(images_dir, src, name.to_string(), kind))
+}
+
+/// The last path segment of a URL, for naming downloaded media.
+pub fn url_file_name(url: &str) -> Option<String> {
+    let parsed = reqwest::Url::parse(url).ok()?;
+    let name = parsed.path_segments()?.next_back()?.to_string();
+    (!name.is_empty()).then_some(name)
+}
+
+/// File extension for downloaded or uploaded media, from its content type,
+/// then its name, then its leading bytes.
+pub fn media_extension(content_type: Option<&str>, name: &str, bytes: &[u8]) -> Option<&'static str> {
+    let from_mime = content_type.and_then(mime_extension);
+    let from_name = || {
+        let path = name.split(['?', '#']).next().unwrap_or(name);
+        let ext = Path::new(path).extension()?.to_string_lossy().to_lowercase();
+        IMAGE_EXTENSIONS
+            .iter()
+            .chain(VIDEO_EXTENSIONS)
+            .chain(&["gif"])
+            .find(|e| **e == ext)
+            .copied()
+    };
+    let from_bytes = || sniff_media_type(bytes).and_then(mime_extension);
+    from_mime.or_else(from_name).or_else(from_bytes)
+}
+
+fn mime_extension(content_type: &str) -> Option<&'static str> {
+    let mime = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+    match mime.as_str() {
+        "image/png" => Some("png"),
+        "image/jpeg" | "image/jpg" => Some("jpg"),
+        "image/gif" => Some("gif"),
+        "image/webp" => Some("webp"),
+        "image/svg+xml" => Some("svg"),
+        "image/bmp" => Some("bmp"),
+        "video/mp4" => Some("mp4"),
+        "video/quicktime" => Some("mov"),
+        "video/webm" => Some("webm"),
+        _ => None,
+    }
+}
+
+/// The media type of a supported image or video, judged by its leading bytes.
+pub fn sniff_media_type(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        [b'B', b'M', ..] => Some("image/bmp"),
+        [0x1A, 0x45, 0xDF, 0xA3, ..] => Some("video/webm"),
+        [_, _, _, _, b'f', b't', b'y', b'p', b'q', b't', b' ', b' ', ..] => Some("video/quicktime"),
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => Some("video/mp4"),
+        _ => {
+            let head = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]);
+            let head = head.trim_start_matches('\u{feff}').trim_start();
+            let svg = head.starts_with("<svg")
+                || ((head.starts_with("<?xml") || head.starts_with("<!--") || head.starts_with("<!DOCTYPE svg"))
+                    && head.contains("<svg"));
+            svg.then_some("image/svg+xml")
+        }
+    }
+}
+
+fn stored_layer(images_dir: &Path, src: String, name: String, kind: &str) -> Layer {
+    let (width, height) = media_size(&images_dir.join(&src), kind);
+    Layer {
+        id: 0.0,
+        name,
+        src,
+        x: 0.0,
+        y: 0.0,
+        width,
+        height,
+        visible: true,
+        z_index: 0.0,
+        rotation: None,
+        brightness: None,
+        contrast: None,
+        saturation: None,
+        hue: None,
+        blur: None,
+        opacity: None,
+        grayscale: None,
+        invert: None,
+        mirror: None,
+        media_type: (kind != "image").then(|| kind.to_string()),
+        current_time: None,
+        volume: None,
+        muted: None,
+        gif_current_frame: None,
+        gif_playing: None,
+        source: None,
+    }
+}
+
+/// Appends layers to a board: assigns ids and z-order above existing
+/// content, lays them out from `origin` (below the existing content when
+/// `None`) and records them as board assets.
+pub fn add_layers(
+    mut board: Board,
+    mut layers: Vec<Layer>,
+    layout: ImportLayout,
+    cell: f64,
+    gap: f64,
+    origin: Option<(f64, f64)>,
+) -> Board {
+    let origin = origin.unwrap_or_else(|| {
+        board
+            .layers
+            .iter()
+            .fold(None, |acc: Option<(f64, f64)>, l| {
+                let bottom = l.y + l.height;
+                Some(acc.map_or((l.x, bottom), |(x, y)| (x.min(l.x), y.max(bottom))))
+            })
+            .map_or((0.0, 0.0), |(x, y)| (x, y + gap * 2.0))
+    });
+    match layout {
+        ImportLayout::Grid => layout_grid(&mut layers, origin, cell, gap),
+        ImportLayout::Packed => layout_packed(&mut layers, origin, cell, gap),
+    }
+
+    let first_id = board
+        .layers
+        .iter()
+        .map(|l| l.id.floor() + 1.0)
+        .fold(database::new_ids(layers.len()), f64::max);
+    let first_z = board.layers.iter().map(|l| l.z_index).fold(0.0, f64::max) + 1.0;
+    for (i, mut layer) in layers.into_iter().enumerate() {
+        layer.id = first_id + i as f64;
+        layer.z_index = first_z + i as f64;
+        board.assets.push(asset_for(&layer, Vec::new()));
+        board.layers.push(layer);
+    }
+    board
+}
+
+/// Appends layers in a packed block at the board's saved viewport, sized
+/// for its zoom.
+pub fn add_layers_at_view(board: Board, layers: Vec<Layer>) -> Board {
+    let (origin, zoom) = view_origin(&board);
+    add_layers(
+        board,
+        layers,
+        ImportLayout::Packed,
+        VIEW_ROW_HEIGHT / zoom,
+        VIEW_GAP / zoom,
+        Some(origin),
+    )
+}
+
+/// Top-left of the board's saved viewport, inset a little and stepped
+/// diagonally past anything already imported there, plus the zoom.
+fn view_origin(board: &Board) -> ((f64, f64), f64) {
+    let view = board.view_state.as_ref();
+    let number = |v: Option<&serde_json::Value>| v.and_then(|v| v.as_f64());
+    let zoom = number(view.and_then(|v| v.get("zoom")))
+        .filter(|z| *z > 0.0)
+        .unwrap_or(1.0);
+    let pan = view.and_then(|v| v.get("pan"));
+    let pan_x = number(pan.and_then(|p| p.get("x"))).unwrap_or(0.0);
+    let pan_y = number(pan.and_then(|p| p.get("y"))).unwrap_or(0.0);
+
+    let inset = 40.0 / zoom;
+    let step = 30.0 / zoom;
+    let (mut x, mut y) = (-pan_x / zoom + inset, -pan_y / zoom + inset);
+    for _ in 0..20 {
+        let taken = board
+            .layers
+            .iter()
+            .any(|l| (l.x - x).abs() < 0.5 && (l.y - y).abs() < 0.5);
+        if !taken {
+            break;
+        }
+        x += step;
+        y += step;
+    }
+    ((x, y), zoom)
+}
+
+/// The board or library asset entry the editor keeps for a layer's media.
+pub fn asset_for(layer: &Layer, tags: Vec<String>) -> Asset {
+    let mut metadata = serde_json::json!({ "created": database::now_millis() });
+    if let Some(kind) = &layer.media_type {
+        metadata["mediaType"] = serde_json::Value::String(kind.clone());
+    }
+    if let Some(source) = layer.source.as_ref().and_then(|s| serde_json::to_value(s).ok()) {
+        metadata["source"] = source;
+    }
+    Asset {
+        id: layer.id,
+        name: layer.name.clone(),
+        src: layer.src.clone(),
+        tags,
+        metadata: Some(metadata),
+    }
+}
+
+/// Names of the folders between the import root and the file.
+pub fn folder_tags(root: &Path, path: &Path) -> Vec<String> {
+    path.parent()
+        .and_then(|parent| parent.strip_prefix(root).ok())
+        .map(|rel| {
+            rel.components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .filter(|name| !name.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn layout_grid(layers: &mut [Layer], origin: (f64, f64), cell: f64, gap: f64) {
+    let columns = (layers.len() as f64).sqrt().ceil().max(1.0) as usize;
+    for (i, layer) in layers.iter_mut().enumerate() {
+        let scale = (cell / layer.width).min(cell / layer.height).min(1.0);
+        let (w, h) = (layer.width * scale, layer.height * scale);
+        let (col, row) = (i % columns, i / columns);
+        layer.width = w;
+        layer.height = h;
+        layer.x = origin.0 + col as f64 * (cell + gap) + (cell - w) / 2.0;
+        layer.y = origin.1 + row as f64 * (cell + gap) + (cell - h) / 2.0;
+    }
+}
+
+fn layout_packed(layers: &mut [Layer], origin: (f64, f64), row_height: f64, gap: f64) {
+    // Aim for a roughly square block
+    let total_width: f64 = layers
+        .iter()
+        .map(|l| l.width / l.height * row_height + gap)
+        .sum();
+    let max_width = (total_width * (row_height + gap)).sqrt().max(row_height);
+
+    let (mut x, mut y) = (0.0, 0.0);
+    for layer in layers.iter_mut() {
+        let w = layer.width / layer.height * row_height;
+        if x > 0.0 && x + w > max_width {
+            x = 0.0;
+            y += row_height + gap;
+        }
+        layer.width = w;
+        layer.height = row_height;
+        layer.x = origin.0 + x;
+        layer.y = origin.1 + y;
+        x += w + gap;
+    }
+}
+
+fn media_size(path: &Path, kind: &str) -> (f64, f64) {
+    let size = match kind {
+        "video" => fs::read(path).ok().and_then(|data| mp4_track_size(&data)),
+        _ if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("svg")) => svg_size(path),
+        _ => image::image_dimensions(path).ok().map(|(w, h)| (w as f64, h as f64)),
+    };
+    size.filter(|(w, h)| *w > 0.0 && *h > 0.0).unwrap_or(FALLBACK_SIZE)
+}
+
+/// Display size from the first video track header of an MP4/MOV file.
+fn mp4_track_size(data: &[u8]) -> Option<(f64, f64)> {
+    fn boxes(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+        let mut pos = 0;
+        std::iter::from_fn(move || {
+            let size = u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?) as usize;
+            let kind = data.get(pos + 4..pos + 8)?;
+            let (header, size) = match size {
+                0 => (8, data.len() - pos),
+                1 => (16, u64::from_be_bytes(data.get(pos + 8..pos + 16)?.try_into().ok()?) as usize),
+                n => (8, n),
+            };
+            let body = data.get(pos + header..pos.checked_add(size)?)?;
+            pos += size.max(header);
+            Some((kind, body))
+        })
+    }
+
+    let (_, moov) = boxes(data).find(|(kind, _)| *kind == b"moov")?;
+    boxes(moov).filter(|(kind, _)| *kind == b"trak").find_map(|(_, trak)| {
+        let (_, tkhd) = boxes(trak).find(|(kind, _)| *kind == b"tkhd")?;
+        let offset = if tkhd.first() == Some(&1) { 88 } else { 76 };
+        let fixed = |at: usize| Some(u32::from_be_bytes(tkhd.get(at..at + 4)?.try_into().ok()?) as f64 / 65536.0);
+        let (w, h) = (fixed(offset)?, fixed(offset + 4)?);
+        (w > 0.0 && h > 0.0).then_some((w, h))
+    })
+}
+
+/// Size from the root element's width/height or viewBox.
+fn svg_size(path: &Path) -> Option<(f64, f64)> {
+    let text = fs::read_to_string(path).ok()?;
+    let start = text.find("<svg")?;
+    let tag = &text[start..start + text[start..].find('>')?];
+    let attr = |name: &str| {
+        let at = tag.find(&format!(" {}=", name))? + name.len() + 2;
+        let quote = tag[at..].chars().next()?;
+        let value = &tag[at + 1..];
+        Some(value[..value.find(quote)?].to_string())
+    };
+    let number = |value: String| value.trim_end_matches("px").trim().parse::<f64>().ok();
+    if let (Some(w), Some(h)) = (attr("width").and_then(number), attr("height").and_then(number)) {
+        return Some((w, h));
+    }
+    let view_box: Vec<f64> = attr("viewBox")?
+        .split([' ', ','])
+        .filter_map(|v| v.parse().ok())
+        .collect();
+    (view_box.len() == 4).then(|| (view_box[2], view_box[3]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(label: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("eyedea-import-{}-{}", label, database::random_token().unwrap()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_png(path: &Path, width: u32, height: u32) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        image::RgbImage::new(width, height).save(path).unwrap();
+    }
+
+    #[test]
+    fn collects_media_without_hidden_files() {
+        let dir = temp_dir("collect");
+        write_png(&dir.join("b.png"), 4, 4);
+        write_png(&dir.join("sub").join("a.png"), 4, 4);
+        write_png(&dir.join(".hidden").join("c.png"), 4, 4);
+        write_png(&dir.join(".d.png"), 4, 4);
+        fs::write(dir.join("notes.txt"), "not media").unwrap();
+
+        assert_eq!(collect_media(&dir, true).unwrap(), vec![dir.join("b.png"), dir.join("sub").join("a.png")]);
+        assert_eq!(collect_media(&dir, false).unwrap(), vec![dir.join("b.png")]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn never_follows_links_to_folders() {
+        let dir = temp_dir("links");
+        write_png(&dir.join("sub").join("a.png"), 4, 4);
+        std::os::unix::fs::symlink(&dir, dir.join("sub").join("loop")).unwrap();
+        std::os::unix::fs::symlink(dir.join("sub").join("a.png"), dir.join("linked.png")).unwrap();
+
+        assert_eq!(collect_media(&dir, true).unwrap(), vec![dir.join("linked.png"), dir.join("sub").join("a.png")]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn imports_a_folder_below_existing_content() {
+        let data = temp_dir("data");
+        database::init_storage(&data).unwrap();
+        let dir = temp_dir("folder");
+        write_png(&dir.join("wide.png"), 800, 200);
+        write_png(&dir.join("trips").join("rome").join("tall.png"), 100, 400);
+        fs::write(dir.join("broken.jpg"), b"not a jpeg").unwrap();
+        fs::create_dir_all(dir.join("empty")).unwrap();
+
+        let mut board = database::new_board("Board".to_string(), "#ffffff".to_string());
+        board.layers = serde_json::from_value(serde_json::json!([
+            { "id": 5.0, "name": "old", "src": "old.png", "x": 0.0, "y": 0.0, "width": 10.0, "height": 100.0, "zIndex": 3.0 }
+        ]))
+        .unwrap();
+        let options = FolderImportOptions { add_to_library: true, ..Default::default() };
+        let mut seen = Vec::new();
+        let images_dir = database::get_images_dir(&data);
+        let result = import_folder(&images_dir, &dir, board, &options, |p| seen.push((p.done, p.total))).unwrap();
+
+        // Undecodable files still import at a fallback size
+        assert_eq!(result.imported, 3);
+        assert!(result.failed.is_empty());
+        assert_eq!(seen, vec![(1, 3), (2, 3), (3, 3)]);
+        let added = &result.board.layers[1..];
+        assert!(added.iter().all(|l| l.y >= 100.0 && l.z_index > 3.0 && images_dir.join(&l.src).is_file()));
+        let ids: Vec<f64> = added.iter().map(|l| l.id).collect();
+        assert_eq!(result.library.iter().map(|a| a.id).collect::<Vec<_>>(), ids);
+        let tall = result.library.iter().find(|a| a.name == "tall.png").unwrap();
+        assert_eq!(tall.tags, vec!["trips", "rome"]);
+
+        assert!(import_folder(&images_dir, &dir.join("wide.png"), result.board, &options, |_| {}).is_err());
+        fs::remove_dir_all(&data).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,16 +1,66 @@
+mod arrange;
+mod attribution;
+mod backup;
+mod capture;
+mod cli;
+mod collab;
 mod commands;
+mod crdt;
 mod database;
+mod downloads;
+mod drawing;
+mod events;
+mod fetch;
+mod floating;
+mod fonts;
+mod http_cache;
+mod import;
+mod media_protocol;
+mod page_images;
+mod pdf;
+mod pool;
+mod pureref;
+mod pyramid;
+mod render;
+mod svg;
+mod sync;
+mod url_import;
+mod vault;
+mod watch;
+
+/// Threads answering `eyedea://` requests; more requests wait their turn.
+const MEDIA_THREADS: usize = 4;
+
+/// Runs the command-line interface when the arguments ask for it and
+/// returns its exit code; `None` means the app should start as usual.
+pub fn run_cli() -> Option<i32> {
+    cli::run_if_requested()
+}
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    let media_pool = pool::Pool::new("media", MEDIA_THREADS);
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .manage(watch::WatchState::default())
+        .manage(capture::CaptureState::default())
+        .manage(downloads::DownloadState::default())
+        .manage(pyramid::PyramidState::default())
+        .manage(collab::CollabState::default())
+        .manage(floating::FloatingState::default())
+        .register_asynchronous_uri_scheme_protocol(media_protocol::SCHEME, move |ctx, request, responder| {
+            let app = ctx.app_handle().clone();
+            media_pool.run(move || responder.respond(media_protocol::respond(&app, &request)));
+        })
         .invoke_handler(tauri::generate_handler![
             commands::get_all_boards,
             commands::get_board,
             commands::create_board,
             commands::update_board,
             commands::delete_board,
+            commands::lock_board,
+            commands::unlock_board,
+            commands::change_board_passphrase,
             commands::get_all_assets,
             commands::add_to_all_assets,
             commands::delete_from_all_assets,
@@ -22,11 +72,69 @@ pub fn run() {
             commands::save_media_file_from_path,
             commands::get_images_dir,
             commands::get_image_file_path,
+            commands::get_media_hash,
+            commands::build_pyramids,
+            commands::get_layer_resolution,
             commands::fetch_page_html,
             commands::fetch_image_url,
+            commands::resolve_page_images,
+            commands::describe_image_source,
+            commands::get_source,
+            commands::start_fetch,
+            commands::cancel_fetch,
+            commands::export_board_image,
+            commands::export_board_pdf,
+            commands::export_board_svg,
+            commands::import_pureref,
+            commands::import_folder,
+            commands::import_urls,
+            commands::arrange_layers,
+            commands::get_watch_folders,
+            commands::add_watch_folder,
+            commands::remove_watch_folder,
+            commands::pause_watch_folders,
+            commands::resume_watch_folders,
+            commands::get_capture_settings,
+            commands::set_capture_server,
+            commands::regenerate_capture_token,
+            commands::get_fetch_settings,
+            commands::set_fetch_settings,
+            commands::get_http_cache_info,
+            commands::clear_http_cache,
+            commands::set_http_cache_limit,
+            commands::create_backup,
+            commands::restore_backup,
+            commands::get_backup_settings,
+            commands::set_backup_settings,
+            commands::sync_now,
+            commands::get_sync_settings,
+            commands::set_sync_settings,
+            commands::start_collab,
+            commands::join_collab,
+            commands::get_collab_session,
+            commands::send_collab_ops,
+            commands::send_collab_cursor,
+            commands::leave_collab,
+            commands::apply_board_ops,
+            commands::get_board_changes,
+            commands::merge_board_changes,
+            commands::open_floating_window,
+            commands::set_floating_window_options,
+            commands::get_floating_windows,
         ])
         .setup(|app| {
             database::init_storage(app.handle())?;
+            if let Err(e) = watch::resume(app.handle()) {
+                log::warn!("Failed to start watch folders: {}", e);
+            }
+            if let Err(e) = capture::apply_settings(app.handle()) {
+                log::warn!("Failed to start capture server: {}", e);
+            }
+            backup::start_scheduler(app.handle());
+            sync::start_scheduler(app.handle());
+            if let Err(e) = floating::restore(app.handle()) {
+                log::warn!("Failed to restore floating windows: {}", e);
+            }
 
             // Enable rounded corners for macOS windows
             #[cfg(target_os = "macos")]
@@ -71,6 +179,20 @@ pub fn run() {
 
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| match event {
+            // Watchers follow the app's lifecycle
+            tauri::RunEvent::Resumed => {
+                let _ = watch::resume(app);
+            }
+            tauri::RunEvent::Exit => {
+                watch::pause(app);
+                capture::stop(app);
+                collab::leave(app);
+                floating::save(app);
+                http_cache::flush(app);
+            }
+            _ => {}
+        });
 }
\ No newline at end of file
@@ -1,5 +1,8 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    if let Some(code) = tauri_eyedea_lib::run_cli() {
+        std::process::exit(code);
+    }
     tauri_eyedea_lib::run()
 }
\ No newline at end of file
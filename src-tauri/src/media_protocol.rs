@@ -0,0 +1,498 @@
+//! The `eyedea://` protocol: media from the store by filename or content
+//! hash, with byte ranges for video seeking and downscaled variants of
+//! still images (`?w=512`) made on first request and cached.
+//!
+//! - `eyedea://localhost/media/<filename>`
+//! - `eyedea://localhost/hash/<sha256>`
+//! - `eyedea://localhost/mip/<level>/<filename>` and
+//!   `eyedea://localhost/tile/<level>/<col>/<row>/<filename>` from the
+//!   image pyramids (see `pyramid`)
+//!
+//! Windows webviews reach the same routes at `http://eyedea.localhost/`.
+
+use crate::database::{self, DataDir};
+use crate::pyramid;
+use crate::vault;
+use image::{GenericImageView, ImageFormat};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+use tauri::http::{header, Method, Request, Response, StatusCode};
+
+pub const SCHEME: &str = "eyedea";
+
+/// Widths variants are made at; a requested width rounds up to the next.
+const VARIANT_WIDTHS: &[u32] = &[64, 128, 256, 512, 1024, 2048, 4096];
+
+/// Most bytes sent for one range, so seeking a long video never reads
+/// the whole file.
+const MAX_RANGE: u64 = 4 * 1024 * 1024;
+
+/// Files past this are never read whole: a request without a range gets
+/// the first `MAX_RANGE` bytes, and players go on with ranges.
+const MAX_BODY: u64 = 64 * 1024 * 1024;
+
+/// The hash index of each data folder, loaded on first use.
+static HASH_INDEXES: Mutex<BTreeMap<PathBuf, HashIndex>> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HashEntry {
+    size: u64,
+    modified: u64,
+    hash: String,
+}
+
+#[derive(Default)]
+struct HashIndex {
+    entries: HashMap<String, HashEntry>,
+    /// Hash to the last file seen with it.
+    by_hash: HashMap<String, String>,
+}
+
+impl HashIndex {
+    fn load(app: &impl DataDir) -> HashIndex {
+        let entries: HashMap<String, HashEntry> = fs::read_to_string(hash_index_path(app))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        let by_hash = entries.iter().map(|(src, e)| (e.hash.clone(), src.clone())).collect();
+        HashIndex { entries, by_hash }
+    }
+
+    fn save(&self, app: &impl DataDir) {
+        if let Ok(content) = serde_json::to_string(&self.entries) {
+            let _ = fs::write(hash_index_path(app), content);
+        }
+    }
+
+    fn remove(&mut self, src: &str) -> bool {
+        let Some(entry) = self.entries.remove(src) else {
+            return false;
+        };
+        if self.by_hash.get(&entry.hash).is_some_and(|s| s == src) {
+            self.by_hash.remove(&entry.hash);
+        }
+        true
+    }
+
+    /// The entry for a file, hashing it when its size or modification time
+    /// changed. The flag is set when the index changed.
+    fn entry(&mut self, src: &str, path: &Path) -> Result<(HashEntry, bool), String> {
+        let metadata = fs::metadata(path).map_err(|e| e.to_string())?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        if let Some(entry) = self.entries.get(src).filter(|e| e.size == metadata.len() && e.modified == modified) {
+            return Ok((entry.clone(), false));
+        }
+
+        let mut file = fs::File::open(path).map_err(|e| e.to_string())?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher).map_err(|e| e.to_string())?;
+        let entry = HashEntry { size: metadata.len(), modified, hash: hex(&hasher.finalize()) };
+        self.remove(src);
+        self.by_hash.insert(entry.hash.clone(), src.to_string());
+        self.entries.insert(src.to_string(), entry.clone());
+        Ok((entry, true))
+    }
+}
+
+/// Runs `f` on the hash index of a data folder, saving it when `f` says
+/// it changed.
+fn with_hash_index<T>(app: &impl DataDir, f: impl FnOnce(&mut HashIndex) -> (T, bool)) -> T {
+    let mut indexes = HASH_INDEXES.lock().unwrap();
+    let index = indexes.entry(app.data_dir()).or_insert_with(|| HashIndex::load(app));
+    let (result, changed) = f(index);
+    if changed {
+        index.save(app);
+    }
+    result
+}
+
+/// Drops the hash index of a data folder, for when its files were replaced.
+pub fn clear_hash_index(app: &impl DataDir) {
+    HASH_INDEXES.lock().unwrap().remove(&app.data_dir());
+    let _ = fs::remove_file(hash_index_path(app));
+}
+
+fn variants_dir(app: &impl DataDir) -> PathBuf {
+    app.data_dir().join("media_variants")
+}
+
+fn hash_index_path(app: &impl DataDir) -> PathBuf {
+    app.data_dir().join("media_hashes.json")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Answers one protocol request.
+pub fn respond(app: &impl DataDir, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return status(StatusCode::METHOD_NOT_ALLOWED, "Only GET and HEAD are supported");
+    }
+
+    let path = percent_encoding::percent_decode_str(request.uri().path()).decode_utf8_lossy();
+    let images_dir = database::get_images_dir(app);
+    let route = path.trim_start_matches('/').split_once('/');
+    let file = match route {
+        Some(("media", src)) => database::media_path(&images_dir, src),
+        Some(("hash", hash)) => match find_by_hash(app, hash) {
+            Some(src) => database::media_path(&images_dir, &src),
+            None => return status(StatusCode::NOT_FOUND, "No media with that hash"),
+        },
+        Some(("mip", rest)) => match rest.split_once('/') {
+            Some((level, src)) => match level.parse() {
+                Ok(level) => pyramid::level_path(app, src, level),
+                Err(_) => return status(StatusCode::BAD_REQUEST, "Invalid level"),
+            },
+            None => return status(StatusCode::NOT_FOUND, "Unknown route"),
+        },
+        Some(("tile", rest)) => {
+            let parts: Vec<&str> = rest.splitn(4, '/').collect();
+            let numbers: Option<Vec<u32>> = parts.iter().take(3).map(|p| p.parse().ok()).collect();
+            match (numbers.as_deref(), parts.get(3)) {
+                (Some(&[level, col, row]), Some(src)) => pyramid::tile_path(app, src, level, col, row),
+                _ => return status(StatusCode::BAD_REQUEST, "Invalid tile"),
+            }
+        }
+        _ => return status(StatusCode::NOT_FOUND, "Unknown route"),
+    };
+    let file = match file {
+        Ok(file) if file.is_file() => file,
+        Ok(_) => {
+            // Media of an unlocked encrypted board is only ever decrypted in memory
+            let locked = match route {
+                Some(("media", src)) => vault::media_bytes(&images_dir, src).map(|bytes| (src, bytes)),
+                _ => None,
+            };
+            return match locked {
+                Some((src, bytes)) => {
+                    let range = request.headers().get(header::RANGE).and_then(|v| v.to_str().ok());
+                    let len = bytes.len() as u64;
+                    serve(Cursor::new(bytes), len, content_type(Path::new(src)), range, request.method() == Method::HEAD)
+                        .unwrap_or_else(|e| status(StatusCode::INTERNAL_SERVER_ERROR, &e))
+                }
+                None => status(StatusCode::NOT_FOUND, "Media not found"),
+            };
+        }
+        Err(e) => return status(StatusCode::BAD_REQUEST, &e),
+    };
+
+    let width = request.uri().query().and_then(|query| {
+        url::form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == "w")
+            .and_then(|(_, value)| value.parse::<u32>().ok())
+    });
+    let file = match width {
+        Some(width) => variant(app, &file, width).unwrap_or_else(|e| {
+            log::warn!("Failed to make a {}px variant of {}: {}", width, file.display(), e);
+            file
+        }),
+        None => file,
+    };
+
+    let range = request.headers().get(header::RANGE).and_then(|v| v.to_str().ok());
+    match serve_file(&file, range, request.method() == Method::HEAD) {
+        Ok(response) => response,
+        Err(e) => status(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    }
+}
+
+fn status(code: StatusCode, message: &str) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(code)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(message.as_bytes().to_vec())
+        .unwrap()
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let ext = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    match ext.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "svg" => "image/svg+xml",
+        "mp4" => "video/mp4",
+        "mov" => "video/quicktime",
+        "webm" => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+/// The bytes to send for a `Range` header against a body of `len` bytes:
+/// `Ok(None)` for the whole body, `Err(())` when no byte of it is covered.
+/// Only single ranges are honoured; anything else gets the whole body.
+/// Parts are cut to `MAX_RANGE` bytes.
+pub fn parse_range(range: &str, len: u64) -> Result<Option<(u64, u64)>, ()> {
+    let Some(spec) = range.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return Ok(None);
+    };
+    let (start, end) = (start.trim(), end.trim());
+
+    let (first, last) = match (start.parse::<u64>(), end.parse::<u64>()) {
+        // The last n bytes
+        (Err(_), Ok(suffix)) if start.is_empty() => {
+            if suffix == 0 || len == 0 {
+                return Err(());
+            }
+            (len.saturating_sub(suffix.min(MAX_RANGE)), len - 1)
+        }
+        (Ok(first), Err(_)) if end.is_empty() => (first, u64::MAX),
+        (Ok(first), Ok(last)) if first <= last => (first, last),
+        _ => return Ok(None),
+    };
+    if first >= len {
+        return Err(());
+    }
+    // A shorter part than asked for is fine; the client asks again for the rest
+    let last = last.min(len - 1).min(first + MAX_RANGE - 1);
+    Ok(Some((first, last)))
+}
+
+fn serve_file(path: &Path, range: Option<&str>, head: bool) -> Result<Response<Vec<u8>>, String> {
+    let file = fs::File::open(path).map_err(|e| e.to_string())?;
+    let len = file.metadata().map_err(|e| e.to_string())?.len();
+    serve(file, len, content_type(path), range, head)
+}
+
+fn serve(mut file: impl Read + Seek, len: u64, content_type: &str, range: Option<&str>, head: bool) -> Result<Response<Vec<u8>>, String> {
+    let builder = Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::ACCEPT_RANGES, "bytes")
+        // Stored files never change; their names are unique
+        .header(header::CACHE_CONTROL, "max-age=31536000, immutable")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*");
+
+    let range = match range.map(|r| parse_range(r, len)) {
+        Some(Err(())) => {
+            return builder
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+                .body(Vec::new())
+                .map_err(|e| e.to_string());
+        }
+        Some(Ok(range)) => range,
+        None => None,
+    };
+    let range = range.or_else(|| (len > MAX_BODY).then_some((0, MAX_RANGE - 1)));
+
+    let (first, last, builder) = match range {
+        Some((first, last)) => (
+            first,
+            last,
+            builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", first, last, len)),
+        ),
+        None => (0, len.saturating_sub(1), builder.status(StatusCode::OK)),
+    };
+    let count = if len == 0 { 0 } else { last - first + 1 };
+
+    let mut body = Vec::new();
+    if !head {
+        file.seek(SeekFrom::Start(first)).map_err(|e| e.to_string())?;
+        file.take(count).read_to_end(&mut body).map_err(|e| e.to_string())?;
+    }
+    builder
+        .header(header::CONTENT_LENGTH, count)
+        .body(body)
+        .map_err(|e| e.to_string())
+}
+
+/// A downscaled copy of a still image at least `width` wide, made once and
+/// kept under `media_variants/`. The original is returned when it's no
+/// wider than the variant would be, or isn't a still image.
+fn variant(app: &impl DataDir, original: &Path, width: u32) -> Result<PathBuf, String> {
+    let ext = original.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if !matches!(ext.as_str(), "png" | "jpg" | "jpeg" | "webp" | "bmp") {
+        return Ok(original.to_path_buf());
+    }
+    let bucket = VARIANT_WIDTHS
+        .iter()
+        .copied()
+        .find(|w| *w >= width)
+        .unwrap_or(*VARIANT_WIDTHS.last().unwrap());
+
+    let key = hex(&Sha256::digest(original.to_string_lossy().as_bytes()));
+    let dir = variants_dir(app);
+    for ext in ["png", "jpg"] {
+        let cached = dir.join(format!("{}-{}.{}", key, bucket, ext));
+        if cached.is_file() {
+            return Ok(cached);
+        }
+    }
+
+    let image = image::open(original).map_err(|e| e.to_string())?;
+    let (w, h) = image.dimensions();
+    if w <= bucket {
+        return Ok(original.to_path_buf());
+    }
+    let height = ((h as f64 * bucket as f64 / w as f64).round() as u32).max(1);
+    let resized = image.resize_exact(bucket, height, image::imageops::FilterType::Triangle);
+
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let (ext, format) = if resized.color().has_alpha() { ("png", ImageFormat::Png) } else { ("jpg", ImageFormat::Jpeg) };
+    let path = dir.join(format!("{}-{}.{}", key, bucket, ext));
+    // Written aside and renamed so a concurrent request never reads half a file
+    let partial = dir.join(format!("{}-{}.{}.part", key, bucket, database::now_millis()));
+    let resized = if format == ImageFormat::Jpeg { image::DynamicImage::ImageRgb8(resized.to_rgb8()) } else { resized };
+    resized.save_with_format(&partial, format).map_err(|e| e.to_string())?;
+    fs::rename(&partial, &path).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+/// SHA-256 of a stored file, cached by size and modification time.
+pub fn media_hash(app: &impl DataDir, src: &str) -> Result<String, String> {
+    let path = database::media_path(&database::get_images_dir(app), src)?;
+    with_hash_index(app, |index| match index.entry(src, &path) {
+        Ok((entry, changed)) => (Ok(entry.hash), changed),
+        Err(e) => (Err(e), false),
+    })
+}
+
+/// Drops the cached variants, pyramid and hash of a stored file, so no
+/// derived copy outlives it.
+pub fn forget_media(app: &impl DataDir, src: &str) {
+    let Ok(path) = database::media_path(&database::get_images_dir(app), src) else {
+        return;
+    };
+    let prefix = format!("{}-", hex(&Sha256::digest(path.to_string_lossy().as_bytes())));
+    if let Ok(entries) = fs::read_dir(variants_dir(app)) {
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+    if let Ok(hash) = media_hash(app, src) {
+        pyramid::remove(app, &hash);
+    }
+    with_hash_index(app, |index| ((), index.remove(src)));
+}
+
+/// The stored file with this content hash, hashing files not seen before.
+fn find_by_hash(app: &impl DataDir, hash: &str) -> Option<String> {
+    let hash = hash.to_lowercase();
+    let images_dir = database::get_images_dir(app);
+    with_hash_index(app, |index| {
+        let mut changed = false;
+        if let Some(src) = index.by_hash.get(&hash).cloned() {
+            match index.entry(&src, &images_dir.join(&src)) {
+                Ok((entry, updated)) if entry.hash == hash => return (Some(src), updated),
+                Ok((_, updated)) => changed |= updated,
+                Err(_) => changed |= index.remove(&src),
+            }
+        }
+
+        let Ok(entries) = fs::read_dir(&images_dir) else {
+            return (None, changed);
+        };
+        let mut found = None;
+        for entry in entries.flatten() {
+            let src = entry.file_name().to_string_lossy().to_string();
+            if !entry.file_type().is_ok_and(|t| t.is_file()) {
+                continue;
+            }
+            if let Ok((entry, updated)) = index.entry(&src, &entry.path()) {
+                changed |= updated;
+                if entry.hash == hash {
+                    found = Some(src);
+                    break;
+                }
+            }
+        }
+        let gone: Vec<String> = index.entries.keys().filter(|src| !images_dir.join(src).is_file()).cloned().collect();
+        for src in gone {
+            changed |= index.remove(&src);
+        }
+        (found, changed)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_single_ranges() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Ok(Some((0, 99))));
+        assert_eq!(parse_range("bytes=900-2000", 1000), Ok(Some((900, 999))));
+        assert_eq!(parse_range("bytes=-100", 1000), Ok(Some((900, 999))));
+        assert_eq!(parse_range("bytes=-5000", 1000), Ok(Some((0, 999))));
+        assert_eq!(parse_range("bytes=500-", 1000), Ok(Some((500, 999))));
+    }
+
+    #[test]
+    fn caps_every_range() {
+        let len = 100 * 1024 * 1024;
+        assert_eq!(parse_range("bytes=0-", len), Ok(Some((0, MAX_RANGE - 1))));
+        assert_eq!(parse_range("bytes=10-99999999", len), Ok(Some((10, MAX_RANGE + 9))));
+        assert_eq!(parse_range("bytes=-99999999", len), Ok(Some((len - MAX_RANGE, len - 1))));
+    }
+
+    #[test]
+    fn sends_large_files_in_parts() {
+        let body = vec![7u8; (MAX_BODY + 1) as usize];
+        let response = serve(Cursor::new(&body), MAX_BODY + 1, "video/mp4", None, false).unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.body().len() as u64, MAX_RANGE);
+
+        let small = serve(Cursor::new(&body[..100]), 100, "video/mp4", None, false).unwrap();
+        assert_eq!(small.status(), StatusCode::OK);
+        assert_eq!(small.body().len(), 100);
+    }
+
+    #[test]
+    fn finds_media_by_hash() {
+        let dir = std::env::temp_dir().join(format!("eyedea-hashes-{}", database::random_token().unwrap()));
+        database::init_storage(&dir).unwrap();
+        let images_dir = database::get_images_dir(&dir);
+        let a = database::write_media_file(&images_dir, b"first", "a", "png").unwrap();
+        let b = database::write_media_file(&images_dir, b"second", "b", "png").unwrap();
+        let hash = hex(&Sha256::digest(b"second"));
+
+        assert_eq!(find_by_hash(&dir, &hash), Some(b.clone()));
+        assert!(hash_index_path(&dir).is_file());
+        assert_eq!(media_hash(&dir, &a).unwrap(), hex(&Sha256::digest(b"first")));
+        // Served from the index without another scan
+        fs::remove_file(hash_index_path(&dir)).unwrap();
+        assert_eq!(find_by_hash(&dir, &hash.to_uppercase()), Some(b.clone()));
+        assert!(!hash_index_path(&dir).exists());
+
+        forget_media(&dir, &b);
+        fs::remove_file(images_dir.join(&b)).unwrap();
+        assert_eq!(find_by_hash(&dir, &hash), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn falls_back_or_refuses_other_ranges() {
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), Ok(None));
+        assert_eq!(parse_range("items=0-1", 1000), Ok(None));
+        assert_eq!(parse_range("bytes=9-3", 1000), Ok(None));
+        assert_eq!(parse_range("bytes=1000-", 1000), Err(()));
+        assert_eq!(parse_range("bytes=-0", 1000), Err(()));
+        assert_eq!(parse_range("bytes=0-", 0), Err(()));
+    }
+}
@@ -0,0 +1,844 @@
+//! Finds the images a web page is about, so a dropped page link can become
+//! the picture it shows.
+//!
+//! Candidates from site-specific data (Pinterest, ArtStation, DeviantArt,
+//! Behance, Reddit, Imgur) come first in page order, since those point at
+//! the originals. Generic sources follow, best first: JSON-LD, OpenGraph,
+//! Twitter cards, then `<picture>`/`srcset` and plain `<img>`, larger images
+//! first within each.
+
+use crate::database::DataDir;
+use crate::fetch::{self, Expect, FetchError};
+use regex::Regex;
+use scraper::{Html, Selector};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use url::Url;
+
+static BASE: LazyLock<Selector> = LazyLock::new(|| Selector::parse("base[href]").unwrap());
+static META: LazyLock<Selector> = LazyLock::new(|| Selector::parse("meta").unwrap());
+static JSON_LD: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"script[type="application/ld+json"]"#).unwrap());
+static PICTURE_SOURCE: LazyLock<Selector> = LazyLock::new(|| Selector::parse("picture source[srcset]").unwrap());
+static IMG: LazyLock<Selector> = LazyLock::new(|| Selector::parse("img").unwrap());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CandidateSource {
+    Pinterest,
+    ArtStation,
+    DeviantArt,
+    Behance,
+    Reddit,
+    Imgur,
+    JsonLd,
+    OpenGraph,
+    TwitterCard,
+    Picture,
+    Srcset,
+    Img,
+}
+
+impl CandidateSource {
+    fn tier(self) -> u8 {
+        match self {
+            CandidateSource::Pinterest
+            | CandidateSource::ArtStation
+            | CandidateSource::DeviantArt
+            | CandidateSource::Behance
+            | CandidateSource::Reddit
+            | CandidateSource::Imgur => 0,
+            CandidateSource::JsonLd => 1,
+            CandidateSource::OpenGraph => 2,
+            CandidateSource::TwitterCard => 3,
+            CandidateSource::Picture | CandidateSource::Srcset => 4,
+            CandidateSource::Img => 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageCandidate {
+    pub url: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub source: CandidateSource,
+}
+
+impl ImageCandidate {
+    fn new(url: String, width: Option<u32>, height: Option<u32>, source: CandidateSource) -> Self {
+        ImageCandidate { url, width, height, source }
+    }
+
+    fn area(&self) -> u64 {
+        match (self.width, self.height) {
+            (Some(w), Some(h)) => w as u64 * h as u64,
+            // Only one side known: assume square rather than rank it last
+            (Some(side), None) | (None, Some(side)) => side as u64 * side as u64,
+            (None, None) => 0,
+        }
+    }
+}
+
+/// Fetches a page, plus the site's JSON API where the page itself only
+/// carries a preview, and returns its ranked image candidates.
+pub fn resolve(app: &impl DataDir, url: &str) -> Result<Vec<ImageCandidate>, FetchError> {
+    let page = Url::parse(url).map_err(|_| FetchError::InvalidUrl { url: url.to_string() })?;
+
+    let mut candidates = api_candidates(app, &page);
+    match fetch::fetch_text(app, url) {
+        Ok(html) => candidates.extend(extract(&html, &page)),
+        Err(e) if candidates.is_empty() => return Err(e),
+        Err(e) => log::warn!("Failed to fetch {}: {}", url, e),
+    }
+    Ok(rank(candidates))
+}
+
+/// Like [`resolve`] for a page that has already been fetched.
+pub fn resolve_html(app: &impl DataDir, page: &Url, html: &str) -> Vec<ImageCandidate> {
+    let mut candidates = api_candidates(app, page);
+    candidates.extend(extract(html, page));
+    rank(candidates)
+}
+
+fn api_candidates(app: &impl DataDir, page: &Url) -> Vec<ImageCandidate> {
+    let Some(api) = api_url(page) else {
+        return Vec::new();
+    };
+    match fetch::fetch(app, api.as_str(), Expect::Page, None) {
+        Ok(fetched) => extract_api(page, &fetched.text()),
+        Err(e) => {
+            log::warn!("Failed to fetch {}: {}", api, e);
+            Vec::new()
+        }
+    }
+}
+
+/// The JSON endpoint holding full-size media for pages that load it lazily.
+fn api_url(page: &Url) -> Option<Url> {
+    let host = page.host_str()?;
+    let segments: Vec<&str> = page.path_segments()?.filter(|s| !s.is_empty()).collect();
+    if host == "artstation.com" || host.ends_with(".artstation.com") {
+        if let ["artwork", hash] = segments.as_slice() {
+            return Url::parse(&format!("https://www.artstation.com/projects/{}.json", hash)).ok();
+        }
+    }
+    if host == "reddit.com" || host.ends_with(".reddit.com") {
+        if let ["gallery", id] = segments.as_slice() {
+            return Url::parse(&format!("https://www.reddit.com/comments/{}.json", id)).ok();
+        }
+        if segments.contains(&"comments") {
+            return Url::parse(&format!("https://www.reddit.com/{}.json", segments.join("/"))).ok();
+        }
+    }
+    None
+}
+
+/// Candidates from a site API response fetched for `page`.
+pub fn extract_api(page: &Url, json: &str) -> Vec<ImageCandidate> {
+    let Ok(value) = serde_json::from_str::<Value>(json) else {
+        return Vec::new();
+    };
+    let host = page.host_str().unwrap_or_default();
+    if host.ends_with("artstation.com") {
+        artstation_project(&value)
+    } else if host.ends_with("reddit.com") {
+        reddit_listing(&value)
+    } else {
+        Vec::new()
+    }
+}
+
+/// Candidates found in a page's HTML, unranked.
+pub fn extract(html: &str, page: &Url) -> Vec<ImageCandidate> {
+    let document = Html::parse_document(html);
+    let base = document
+        .select(&BASE)
+        .next()
+        .and_then(|b| b.value().attr("href"))
+        .and_then(|href| page.join(href).ok())
+        .unwrap_or_else(|| page.clone());
+    let host = page.host_str().unwrap_or_default();
+
+    let mut candidates = Vec::new();
+    if host.contains("pinterest.") {
+        candidates.extend(pinterest(html));
+    }
+    if host.ends_with("deviantart.com") {
+        candidates.extend(deviantart(html));
+    }
+    if host.ends_with("behance.net") {
+        candidates.extend(behance(html));
+    }
+    if host.ends_with("reddit.com") {
+        candidates.extend(reddit_html(html));
+    }
+    if host.ends_with("imgur.com") {
+        candidates.extend(imgur(html));
+    }
+    candidates.extend(meta_tags(&document));
+    candidates.extend(json_ld(&document));
+    candidates.extend(picture_sources(&document));
+    candidates.extend(img_tags(&document));
+
+    candidates
+        .into_iter()
+        .filter_map(|mut c| {
+            let resolved = base.join(c.url.trim()).ok()?;
+            if !matches!(resolved.scheme(), "http" | "https") {
+                return None;
+            }
+            c.url = resolved.to_string();
+            Some(c)
+        })
+        .collect()
+}
+
+/// Orders candidates best first and drops repeated URLs, keeping the
+/// best-ranked copy and any dimensions only a later copy knew.
+pub fn rank(candidates: Vec<ImageCandidate>) -> Vec<ImageCandidate> {
+    let mut ranked: Vec<(usize, ImageCandidate)> = candidates.into_iter().enumerate().collect();
+    ranked.sort_by(|(ia, a), (ib, b)| {
+        let tier = a.source.tier().cmp(&b.source.tier());
+        if a.source.tier() == 0 {
+            // Site data is already in page order
+            return tier.then(ia.cmp(ib));
+        }
+        tier.then(b.area().cmp(&a.area())).then(ia.cmp(ib))
+    });
+
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut result: Vec<ImageCandidate> = Vec::new();
+    for (_, candidate) in ranked {
+        match seen.get(&candidate.url) {
+            Some(&index) => {
+                let kept = &mut result[index];
+                if kept.width.is_none() && kept.height.is_none() {
+                    kept.width = candidate.width;
+                    kept.height = candidate.height;
+                }
+            }
+            None => {
+                seen.insert(candidate.url.clone(), result.len());
+                result.push(candidate);
+            }
+        }
+    }
+    result
+}
+
+fn dimension(value: Option<&Value>) -> Option<u32> {
+    match value? {
+        Value::Number(n) => n.as_f64().map(|v| v as u32),
+        Value::String(s) => s.trim().trim_end_matches("px").trim().parse::<f64>().ok().map(|v| v as u32),
+        Value::Object(o) => dimension(o.get("value")),
+        _ => None,
+    }
+    .filter(|v| *v > 0)
+}
+
+fn attr_dimension(value: Option<&str>) -> Option<u32> {
+    dimension(value.map(|v| Value::String(v.to_string())).as_ref())
+}
+
+fn meta_tags(document: &Html) -> Vec<ImageCandidate> {
+    let mut candidates: Vec<ImageCandidate> = Vec::new();
+    // Index of the og:image the following width/height tags describe
+    let mut last_og: Option<usize> = None;
+    for meta in document.select(&META) {
+        let element = meta.value();
+        let key = element.attr("property").or_else(|| element.attr("name")).unwrap_or_default().to_lowercase();
+        let Some(content) = element.attr("content").filter(|c| !c.trim().is_empty()) else {
+            continue;
+        };
+        match key.as_str() {
+            "og:image" | "og:image:url" | "og:image:secure_url" => {
+                // The secure URL restates the image just declared
+                if key != "og:image" && last_og.is_some_and(|i| candidates[i].source == CandidateSource::OpenGraph) {
+                    continue;
+                }
+                last_og = Some(candidates.len());
+                candidates.push(ImageCandidate::new(content.to_string(), None, None, CandidateSource::OpenGraph));
+            }
+            "og:image:width" | "og:image:height" => {
+                if let Some(candidate) = last_og.map(|i| &mut candidates[i]) {
+                    let value = attr_dimension(Some(content));
+                    if key.ends_with("width") {
+                        candidate.width = value;
+                    } else {
+                        candidate.height = value;
+                    }
+                }
+            }
+            "twitter:image" | "twitter:image:src" => {
+                candidates.push(ImageCandidate::new(content.to_string(), None, None, CandidateSource::TwitterCard));
+            }
+            _ => {}
+        }
+    }
+    candidates
+}
+
+fn json_ld(document: &Html) -> Vec<ImageCandidate> {
+    let mut candidates = Vec::new();
+    for script in document.select(&JSON_LD) {
+        let text: String = script.text().collect();
+        if let Ok(value) = serde_json::from_str::<Value>(text.trim()) {
+            json_ld_walk(&value, &mut candidates);
+        }
+    }
+    candidates
+}
+
+fn json_ld_walk(value: &Value, candidates: &mut Vec<ImageCandidate>) {
+    match value {
+        Value::Array(items) => items.iter().for_each(|v| json_ld_walk(v, candidates)),
+        Value::Object(object) => {
+            let is_image_object = match object.get("@type") {
+                Some(Value::String(t)) => t == "ImageObject",
+                Some(Value::Array(types)) => types.iter().any(|t| t == "ImageObject"),
+                _ => false,
+            };
+            if is_image_object {
+                json_ld_image(value, candidates);
+                return;
+            }
+            for (key, child) in object {
+                match key.as_str() {
+                    // Site branding rather than content
+                    "logo" | "publisher" | "author" | "creator" => {}
+                    "image" => json_ld_image(child, candidates),
+                    _ => json_ld_walk(child, candidates),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn json_ld_image(value: &Value, candidates: &mut Vec<ImageCandidate>) {
+    match value {
+        Value::String(url) => candidates.push(ImageCandidate::new(url.clone(), None, None, CandidateSource::JsonLd)),
+        Value::Array(items) => items.iter().for_each(|v| json_ld_image(v, candidates)),
+        Value::Object(object) => {
+            let url = object.get("contentUrl").or_else(|| object.get("url")).and_then(Value::as_str);
+            if let Some(url) = url {
+                let (width, height) = (dimension(object.get("width")), dimension(object.get("height")));
+                candidates.push(ImageCandidate::new(url.to_string(), width, height, CandidateSource::JsonLd));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The largest entry in a `srcset`, with its width when given in `w`.
+fn largest_in_srcset(srcset: &str) -> Option<(String, Option<u32>)> {
+    let mut best: Option<(String, f64, Option<u32>)> = None;
+    let mut rest = srcset;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        if rest.is_empty() {
+            break;
+        }
+        // URLs may contain commas (w_100,h_100), so only whitespace ends one
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let mut url = &rest[..end];
+        rest = &rest[end..];
+        let descriptor = if url.ends_with(',') {
+            url = url.trim_end_matches(',');
+            ""
+        } else {
+            let end = rest.find(',').unwrap_or(rest.len());
+            let descriptor = rest[..end].trim();
+            rest = &rest[end..];
+            descriptor
+        };
+
+        let (size, width) = if let Some(w) = descriptor.strip_suffix('w').and_then(|w| w.parse::<u32>().ok()) {
+            (w as f64, Some(w))
+        } else if let Some(x) = descriptor.strip_suffix('x').and_then(|x| x.parse::<f64>().ok()) {
+            // Densities only compare with each other; keep them below any width
+            (x / 1000.0, None)
+        } else {
+            (0.0, None)
+        };
+        if best.as_ref().is_none_or(|(_, s, _)| size > *s) {
+            best = Some((url.to_string(), size, width));
+        }
+    }
+    best.map(|(url, _, width)| (url, width))
+}
+
+fn picture_sources(document: &Html) -> Vec<ImageCandidate> {
+    document.select(&PICTURE_SOURCE)
+        .filter_map(|source| largest_in_srcset(source.value().attr("srcset")?))
+        .map(|(url, width)| ImageCandidate::new(url, width, None, CandidateSource::Picture))
+        .collect()
+}
+
+fn img_tags(document: &Html) -> Vec<ImageCandidate> {
+    let mut candidates = Vec::new();
+    for img in document.select(&IMG) {
+        let element = img.value();
+        let width = attr_dimension(element.attr("width"));
+        let height = attr_dimension(element.attr("height"));
+        // Tracking pixels and icons
+        if width.is_some_and(|w| w < 32) || height.is_some_and(|h| h < 32) {
+            continue;
+        }
+        let srcset = element.attr("srcset").or_else(|| element.attr("data-srcset"));
+        if let Some((url, srcset_width)) = srcset.and_then(largest_in_srcset) {
+            let height = match (srcset_width, width, height) {
+                (Some(sw), Some(w), Some(h)) => Some((h as u64 * sw as u64 / w as u64) as u32),
+                _ => None,
+            };
+            candidates.push(ImageCandidate::new(url, srcset_width, height, CandidateSource::Srcset));
+        }
+        let src = ["data-src", "data-original", "src"]
+            .iter()
+            .filter_map(|name| element.attr(name))
+            .find(|src| !src.trim().is_empty() && !src.starts_with("data:"));
+        if let Some(src) = src {
+            candidates.push(ImageCandidate::new(src.to_string(), width, height, CandidateSource::Img));
+        }
+    }
+    candidates
+}
+
+/// Undoes the escaping of JSON embedded in a JavaScript string.
+fn unescape_script(text: &str) -> String {
+    text.replace("\\\"", "\"")
+        .replace("\\/", "/")
+        .replace("\\u002F", "/")
+        .replace("\\u002f", "/")
+        .replace("\\u0026", "&")
+}
+
+/// Every JSON object in `text` that directly contains `"key"`, for data
+/// embedded in script tags that isn't worth locating exactly.
+fn objects_with_key(text: &str, key: &str) -> Vec<Value> {
+    let needle = format!("\"{}\"", key);
+    let bytes = text.as_bytes();
+    let mut objects = Vec::new();
+    let mut last_start = None;
+
+    for (position, _) in text.match_indices(&needle) {
+        // Walk back to the brace that opens the object holding this key
+        let mut depth = 0;
+        let mut start = None;
+        for i in (0..position).rev() {
+            match bytes[i] {
+                b'}' | b']' => depth += 1,
+                b'{' if depth == 0 => {
+                    start = Some(i);
+                    break;
+                }
+                b'{' | b'[' => depth -= 1,
+                _ => {}
+            }
+        }
+        let Some(start) = start else {
+            continue;
+        };
+        if last_start == Some(start) {
+            continue;
+        }
+        last_start = Some(start);
+
+        // And forward to the brace that closes it, skipping strings
+        let (mut depth, mut in_string, mut escaped) = (0, false, false);
+        let mut end = None;
+        for (i, &b) in bytes.iter().enumerate().skip(start) {
+            if in_string {
+                match b {
+                    _ if escaped => escaped = false,
+                    b'\\' => escaped = true,
+                    b'"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match b {
+                b'"' => in_string = true,
+                b'{' | b'[' => depth += 1,
+                b'}' | b']' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = Some(i);
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some(value) = end.and_then(|end| serde_json::from_str::<Value>(&text[start..=end]).ok()) {
+            if value.get(key).is_some() {
+                objects.push(value);
+            }
+        }
+    }
+    objects
+}
+
+static PINIMG_URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"https://i\.pinimg\.com/(originals|(\d+)x)/[a-f0-9/]+\.[a-zA-Z]+").unwrap());
+
+fn pinterest(html: &str) -> Vec<ImageCandidate> {
+    let text = unescape_script(html);
+    let originals: Vec<ImageCandidate> = objects_with_key(&text, "orig")
+        .iter()
+        .filter_map(|images| {
+            let orig = images.get("orig")?;
+            let url = orig.get("url")?.as_str()?;
+            let (width, height) = (dimension(orig.get("width")), dimension(orig.get("height")));
+            Some(ImageCandidate::new(url.to_string(), width, height, CandidateSource::Pinterest))
+        })
+        .collect();
+    if !originals.is_empty() {
+        return originals;
+    }
+
+    // Older markup: bare CDN links where the path gives the width. Small
+    // thumbnails are left to the generic sources.
+    let mut found: Vec<(u32, String)> = PINIMG_URL
+        .captures_iter(&text)
+        .filter_map(|c| {
+            let size = match c.get(2) {
+                Some(width) => width.as_str().parse().ok()?,
+                None => u32::MAX,
+            };
+            (size >= 474).then(|| (size, c[0].to_string()))
+        })
+        .collect();
+    found.sort_by_key(|(size, _)| std::cmp::Reverse(*size));
+    found
+        .into_iter()
+        .map(|(size, url)| {
+            let width = (size != u32::MAX).then_some(size);
+            ImageCandidate::new(url, width, None, CandidateSource::Pinterest)
+        })
+        .collect()
+}
+
+fn deviantart(html: &str) -> Vec<ImageCandidate> {
+    let text = unescape_script(html);
+    objects_with_key(&text, "baseUri")
+        .iter()
+        .filter_map(|media| {
+            let base = media.get("baseUri")?.as_str()?;
+            let pretty_name = media.get("prettyName").and_then(Value::as_str).unwrap_or_default();
+            let token = media.get("token").and_then(|t| t.get(0)).and_then(Value::as_str);
+            let fullview = media
+                .get("types")
+                .and_then(Value::as_array)
+                .and_then(|types| types.iter().find(|t| t.get("t").and_then(Value::as_str) == Some("fullview")));
+
+            let mut url = base.to_string();
+            if let Some(path) = fullview.and_then(|f| f.get("c")).and_then(Value::as_str) {
+                url.push_str(&path.replace("<prettyName>", pretty_name));
+            }
+            if let Some(token) = token {
+                url.push_str("?token=");
+                url.push_str(token);
+            }
+            let width = dimension(fullview.and_then(|f| f.get("w")));
+            let height = dimension(fullview.and_then(|f| f.get("h")));
+            Some(ImageCandidate::new(url, width, height, CandidateSource::DeviantArt))
+        })
+        .collect()
+}
+
+static BEHANCE_MODULE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"https://mir-s3-cdn-cf\.behance\.net/project_modules/([^/"'\s]+)/([^"'\s?,]+)"#).unwrap()
+});
+
+fn behance(html: &str) -> Vec<ImageCandidate> {
+    // Each module image appears at several sizes; keep the largest of each
+    let mut order: Vec<String> = Vec::new();
+    let mut best: HashMap<String, (u32, String)> = HashMap::new();
+    for captures in BEHANCE_MODULE.captures_iter(&unescape_script(html)) {
+        let size = match &captures[1] {
+            "source" => u32::MAX,
+            "fs" => 1920,
+            "disp" => 600,
+            other => other.trim_start_matches("max_").parse().unwrap_or(0),
+        };
+        let file = captures[2].to_string();
+        match best.get(&file) {
+            Some((kept, _)) if *kept >= size => {}
+            Some(_) => {
+                best.insert(file, (size, captures[0].to_string()));
+            }
+            None => {
+                order.push(file.clone());
+                best.insert(file, (size, captures[0].to_string()));
+            }
+        }
+    }
+    order
+        .iter()
+        .filter_map(|file| best.remove(file))
+        .map(|(_, url)| ImageCandidate::new(url, None, None, CandidateSource::Behance))
+        .collect()
+}
+
+static REDDIT_IMAGE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"https://(?:i|preview)\.redd\.it/([A-Za-z0-9_-]+\.(?:jpe?g|png|gif|webp))"#).unwrap()
+});
+
+fn reddit_html(html: &str) -> Vec<ImageCandidate> {
+    let mut seen = Vec::new();
+    for captures in REDDIT_IMAGE.captures_iter(&unescape_script(html)) {
+        // Previews are resized copies; the same name on i.redd.it is the original
+        let url = format!("https://i.redd.it/{}", &captures[1]);
+        if !seen.contains(&url) {
+            seen.push(url);
+        }
+    }
+    seen.into_iter()
+        .map(|url| ImageCandidate::new(url, None, None, CandidateSource::Reddit))
+        .collect()
+}
+
+fn reddit_listing(value: &Value) -> Vec<ImageCandidate> {
+    let Some(post) = value.pointer("/0/data/children/0/data") else {
+        return Vec::new();
+    };
+    let unescape = |url: &str| url.replace("&amp;", "&");
+
+    if let (Some(items), Some(metadata)) = (
+        post.pointer("/gallery_data/items").and_then(Value::as_array),
+        post.get("media_metadata"),
+    ) {
+        return items
+            .iter()
+            .filter_map(|item| {
+                let media = metadata.get(item.get("media_id")?.as_str()?)?;
+                let source = media.get("s")?;
+                let url = source.get("u").or_else(|| source.get("gif"))?.as_str()?;
+                let (width, height) = (dimension(source.get("x")), dimension(source.get("y")));
+                Some(ImageCandidate::new(unescape(url), width, height, CandidateSource::Reddit))
+            })
+            .collect();
+    }
+
+    let source = post.pointer("/preview/images/0/source");
+    let (width, height) = (dimension(source.and_then(|s| s.get("width"))), dimension(source.and_then(|s| s.get("height"))));
+    let direct = post
+        .get("url_overridden_by_dest")
+        .or_else(|| post.get("url"))
+        .and_then(Value::as_str)
+        .filter(|url| REDDIT_IMAGE.is_match(url) || url.starts_with("https://i.imgur.com/"));
+    match (direct, source.and_then(|s| s.get("url")).and_then(Value::as_str)) {
+        (Some(url), _) => vec![ImageCandidate::new(url.to_string(), width, height, CandidateSource::Reddit)],
+        (None, Some(preview)) => vec![ImageCandidate::new(unescape(preview), width, height, CandidateSource::Reddit)],
+        (None, None) => Vec::new(),
+    }
+}
+
+fn imgur(html: &str) -> Vec<ImageCandidate> {
+    objects_with_key(&unescape_script(html), "url")
+        .iter()
+        .filter_map(|media| {
+            let url = media.get("url")?.as_str()?;
+            if !url.starts_with("https://i.imgur.com/") {
+                return None;
+            }
+            let (width, height) = (dimension(media.get("width")), dimension(media.get("height")));
+            Some(ImageCandidate::new(url.to_string(), width, height, CandidateSource::Imgur))
+        })
+        .collect()
+}
+
+fn artstation_project(value: &Value) -> Vec<ImageCandidate> {
+    value
+        .get("assets")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|asset| asset.get("has_image").and_then(Value::as_bool).unwrap_or(true))
+        .filter(|asset| asset.get("asset_type").and_then(Value::as_str).is_none_or(|t| t == "image" || t == "cover"))
+        .filter_map(|asset| {
+            let url = asset.get("image_url")?.as_str()?;
+            let (width, height) = (dimension(asset.get("width")), dimension(asset.get("height")));
+            Some(ImageCandidate::new(url.to_string(), width, height, CandidateSource::ArtStation))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> String {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/pages").join(name);
+        std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+    }
+
+    fn resolve_fixture(name: &str, page: &str) -> Vec<ImageCandidate> {
+        rank(extract(&fixture(name), &Url::parse(page).unwrap()))
+    }
+
+    fn urls(candidates: &[ImageCandidate]) -> Vec<&str> {
+        candidates.iter().map(|c| c.url.as_str()).collect()
+    }
+
+    #[test]
+    fn generic_article_ranks_structured_data_first() {
+        let candidates = resolve_fixture("article.html", "https://example.com/news/lighthouse");
+
+        let first = &candidates[0];
+        assert_eq!(first.url, "https://cdn.example.com/news/full/lighthouse.jpg");
+        assert_eq!((first.width, first.height), (Some(3000), Some(2000)));
+        assert_eq!(first.source, CandidateSource::JsonLd);
+
+        let og = candidates.iter().find(|c| c.source == CandidateSource::OpenGraph).unwrap();
+        assert_eq!(og.url, "https://cdn.example.com/news/og/lighthouse-1200.jpg");
+        assert_eq!((og.width, og.height), (Some(1200), Some(630)));
+        assert_eq!(candidates.iter().filter(|c| c.url == og.url).count(), 1);
+
+        let urls = urls(&candidates);
+        assert!(urls.contains(&"https://cdn.example.com/news/tw/lighthouse-800.jpg"));
+        assert!(!urls.iter().any(|u| u.contains("logo")), "publisher logo: {:?}", urls);
+        assert!(!urls.iter().any(|u| u.contains("pixel")), "tracking pixel: {:?}", urls);
+        assert!(!urls.iter().any(|u| u.starts_with("data:")));
+        // Relative URLs resolve against <base href>
+        assert!(urls.contains(&"https://cdn.example.com/news/img/lamp-lazy.jpg"));
+    }
+
+    #[test]
+    fn srcset_and_picture_pick_the_largest() {
+        let candidates = resolve_fixture("article.html", "https://example.com/news/lighthouse");
+
+        let picture = candidates.iter().find(|c| c.source == CandidateSource::Picture).unwrap();
+        assert_eq!(picture.url, "https://cdn.example.com/news/img/keeper-1600.webp");
+        assert_eq!(picture.width, Some(1600));
+
+        // Commas inside the URL don't split the entry
+        let srcset = candidates.iter().find(|c| c.source == CandidateSource::Srcset).unwrap();
+        assert_eq!(srcset.url, "https://res.example.com/image/upload/w_1200,h_1800/stairs.jpg");
+        assert_eq!((srcset.width, srcset.height), (Some(1200), Some(1800)));
+    }
+
+    #[test]
+    fn largest_in_srcset_handles_densities_and_bare_urls() {
+        assert_eq!(
+            largest_in_srcset("a.jpg 1x, b.jpg 2x, c.jpg 1.5x"),
+            Some(("b.jpg".to_string(), None))
+        );
+        assert_eq!(largest_in_srcset("only.jpg"), Some(("only.jpg".to_string(), None)));
+        assert_eq!(
+            largest_in_srcset("small.jpg 100w,big.jpg 900w"),
+            Some(("big.jpg".to_string(), Some(900)))
+        );
+        assert_eq!(largest_in_srcset("  "), None);
+    }
+
+    #[test]
+    fn pinterest_prefers_the_original() {
+        let candidates = resolve_fixture("pinterest_pin.html", "https://www.pinterest.com/pin/1234567890/");
+        let first = &candidates[0];
+        assert_eq!(first.url, "https://i.pinimg.com/originals/3f/9a/c2/3f9ac2d1e0b4a7f6.jpg");
+        assert_eq!((first.width, first.height), (Some(1200), Some(1800)));
+        assert_eq!(first.source, CandidateSource::Pinterest);
+    }
+
+    #[test]
+    fn pinterest_falls_back_to_the_largest_cdn_size() {
+        let candidates = resolve_fixture("pinterest_legacy.html", "https://pinterest.co.uk/pin/42/");
+        assert_eq!(candidates[0].url, "https://i.pinimg.com/736x/aa/bb/cc/aabbcc99.jpg");
+        assert_eq!(candidates[0].width, Some(736));
+        assert!(!urls(&candidates).iter().any(|u| u.contains("/236x/")));
+    }
+
+    #[test]
+    fn deviantart_builds_the_fullview_url() {
+        let candidates = resolve_fixture("deviantart.html", "https://www.deviantart.com/painter/art/Harbor-at-Dusk-1001");
+        let first = &candidates[0];
+        assert_eq!(first.source, CandidateSource::DeviantArt);
+        assert_eq!(
+            first.url,
+            "https://images-wixmp-ed30a86b8c4ca887773594c2.wixmp.com/f/9b1c/d4e5-f6a7.jpg/v1/fill/w_1920,h_1280,q_75,strp/harbor_at_dusk_by_painter_d4e5f6a7-fullview.jpg?token=eyJ0eXAiOiJKV1QifQ.full"
+        );
+        assert_eq!((first.width, first.height), (Some(1920), Some(1280)));
+    }
+
+    #[test]
+    fn behance_keeps_the_largest_size_of_each_module() {
+        let candidates = resolve_fixture("behance.html", "https://www.behance.net/gallery/123/Botanical-Posters");
+        let behance: Vec<&str> = candidates
+            .iter()
+            .filter(|c| c.source == CandidateSource::Behance)
+            .map(|c| c.url.as_str())
+            .collect();
+        assert_eq!(
+            behance,
+            vec![
+                "https://mir-s3-cdn-cf.behance.net/project_modules/max_3840/0f1e2d3c4b5a.jpg",
+                "https://mir-s3-cdn-cf.behance.net/project_modules/source/9a8b7c6d5e4f.png",
+            ]
+        );
+    }
+
+    #[test]
+    fn imgur_gallery_lists_every_image() {
+        let candidates = resolve_fixture("imgur.html", "https://imgur.com/gallery/XyZ9");
+        let imgur: Vec<&ImageCandidate> = candidates.iter().filter(|c| c.source == CandidateSource::Imgur).collect();
+        assert_eq!(imgur.len(), 2);
+        assert_eq!(imgur[0].url, "https://i.imgur.com/Ab12Cd3.jpeg");
+        assert_eq!((imgur[0].width, imgur[0].height), (Some(2400), Some(1800)));
+        assert_eq!(imgur[1].url, "https://i.imgur.com/Ef45Gh6.png");
+        // The cover link points at a page, not an image
+        assert!(!urls(&candidates).contains(&"https://imgur.com/Ab12Cd3"));
+    }
+
+    #[test]
+    fn reddit_gallery_follows_gallery_order() {
+        let page = Url::parse("https://www.reddit.com/r/sketchbooks/comments/1abcde/my_sketchbook/").unwrap();
+        assert_eq!(
+            api_url(&page).unwrap().as_str(),
+            "https://www.reddit.com/r/sketchbooks/comments/1abcde/my_sketchbook.json"
+        );
+        let candidates = rank(extract_api(&page, &fixture("reddit_gallery.json")));
+        assert_eq!(
+            urls(&candidates),
+            vec![
+                "https://preview.redd.it/p2x9q.jpg?width=4032&format=pjpg&auto=webp&s=4",
+                "https://preview.redd.it/k7m3n.png?width=1500&format=png&auto=webp&s=2",
+            ]
+        );
+        assert_eq!((candidates[0].width, candidates[0].height), (Some(4032), Some(3024)));
+    }
+
+    #[test]
+    fn reddit_single_image_uses_the_direct_link() {
+        let page = Url::parse("https://old.reddit.com/r/painting/comments/9zz/finished/").unwrap();
+        let mut candidates = extract_api(&page, &fixture("reddit_image.json"));
+        candidates.extend(extract(&fixture("reddit_post.html"), &page));
+        let candidates = rank(candidates);
+
+        assert_eq!(candidates[0].url, "https://i.redd.it/q1w2e3r4t5.jpeg");
+        assert_eq!((candidates[0].width, candidates[0].height), (Some(2048), Some(2560)));
+        // The HTML copy of the same image doesn't appear twice
+        assert_eq!(candidates.iter().filter(|c| c.url == candidates[0].url).count(), 1);
+    }
+
+    #[test]
+    fn artstation_lists_image_assets() {
+        let page = Url::parse("https://www.artstation.com/artwork/Xq3Lm8").unwrap();
+        assert_eq!(api_url(&page).unwrap().as_str(), "https://www.artstation.com/projects/Xq3Lm8.json");
+        let candidates = rank(extract_api(&page, &fixture("artstation_project.json")));
+        assert_eq!(candidates.len(), 2);
+        assert!(candidates[0].url.contains("/large/forest-shrine.jpg"));
+        assert_eq!((candidates[1].width, candidates[1].height), (Some(3840), Some(2160)));
+        assert!(candidates.iter().all(|c| c.source == CandidateSource::ArtStation));
+    }
+
+    #[test]
+    fn non_site_pages_have_no_api() {
+        assert!(api_url(&Url::parse("https://example.com/comments/1").unwrap()).is_none());
+        assert!(api_url(&Url::parse("https://www.artstation.com/artist").unwrap()).is_none());
+    }
+}
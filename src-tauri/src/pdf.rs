@@ -0,0 +1,872 @@
+use crate::database::{Board, Layer};
+use crate::drawing::{self, CanvasObject, Rect, Stroke};
+use crate::fonts::{self, BundledFont};
+use crate::render::{self, BoardItem};
+use ab_glyph::Font;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use pdf_writer::types::{CidFontType, FontFlags, LineCapStyle, LineJoinStyle, MaskType, SystemInfo, UnicodeCmap};
+use pdf_writer::writers::Resources;
+use pdf_writer::{Content, Filter, Finish, Name, Pdf, Ref, Str, TextStr};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Cursor, Write};
+use std::path::Path;
+use tiny_skia::{PathSegment, Transform};
+
+/// PDF points per board unit at 100% print scale (72pt per 96 CSS px).
+const PT_PER_PX: f64 = 0.75;
+
+/// Largest page side most viewers accept (200 inches).
+const MAX_PAGE_PT: f64 = 14400.0;
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PaperSize {
+    A4,
+    Letter,
+}
+
+impl PaperSize {
+    /// Portrait width and height in points.
+    fn points(self) -> (f64, f64) {
+        match self {
+            PaperSize::A4 => (595.28, 841.89),
+            PaperSize::Letter => (612.0, 792.0),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfOptions {
+    /// Tile the board across pages of this size. Without it the whole board
+    /// goes on one page sized to fit.
+    #[serde(default)]
+    pub paper: Option<PaperSize>,
+    #[serde(default)]
+    pub landscape: bool,
+    /// Print scale; 1.0 prints one board unit as one CSS pixel (1/96 inch).
+    #[serde(default)]
+    pub scale: Option<f64>,
+    /// Page margin in points when tiling. Defaults to half an inch.
+    #[serde(default)]
+    pub margin: Option<f64>,
+    /// Area of the board to export. Defaults to the bounds of all content.
+    #[serde(default)]
+    pub region: Option<Rect>,
+    /// Margin added around the content bounds when no region is given.
+    #[serde(default)]
+    pub padding: Option<f64>,
+    /// Leave the background unpainted instead of filling `bgColor`.
+    #[serde(default)]
+    pub transparent: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedPdf {
+    pub path: String,
+    pub pages: usize,
+}
+
+/// Renders a board to a PDF file at `path`.
+pub fn export_board_pdf(
+    board: &Board,
+    images_dir: &Path,
+    options: &PdfOptions,
+    path: &Path,
+) -> Result<ExportedPdf, String> {
+    let (bytes, pages) = board_to_pdf(board, images_dir, options)?;
+    std::fs::write(path, bytes).map_err(|e| format!("Failed to write PDF: {}", e))?;
+    Ok(ExportedPdf {
+        path: path.to_string_lossy().to_string(),
+        pages,
+    })
+}
+
+/// Builds the PDF in memory, returning its bytes and page count.
+///
+/// The board is drawn once into a form XObject in board coordinates and every
+/// page places that form, so tiled exports don't repeat the content.
+pub fn board_to_pdf(board: &Board, images_dir: &Path, options: &PdfOptions) -> Result<(Vec<u8>, usize), String> {
+    let region = render::export_region(board, options.region, options.padding)?;
+    let print_scale = options.scale.filter(|s| *s > 0.0).unwrap_or(1.0);
+
+    let mut doc = Document::new(images_dir, region);
+    let board_form = doc.draw_board(board, options.transparent)?;
+
+    // (page width, page height, clip rect, board -> page matrix) per page
+    let mut pages = Vec::new();
+    match options.paper {
+        None => {
+            let mut s = PT_PER_PX * print_scale;
+            let longest = region.width.max(region.height) * s;
+            if longest > MAX_PAGE_PT {
+                s *= MAX_PAGE_PT / longest;
+            }
+            let (w, h) = ((region.width * s).max(1.0), (region.height * s).max(1.0));
+            let matrix = [s, 0.0, 0.0, -s, -region.x * s, h + region.y * s];
+            pages.push((w, h, [0.0, 0.0, w, h], matrix));
+        }
+        Some(paper) => {
+            let (pw, ph) = match (paper.points(), options.landscape) {
+                ((w, h), true) => (h, w),
+                (size, false) => size,
+            };
+            let margin = options.margin.unwrap_or(36.0).clamp(0.0, pw.min(ph) / 4.0);
+            let (aw, ah) = (pw - margin * 2.0, ph - margin * 2.0);
+            let s = PT_PER_PX * print_scale;
+            let cols = (region.width * s / aw).ceil().max(1.0) as usize;
+            let rows = (region.height * s / ah).ceil().max(1.0) as usize;
+            if cols * rows > 1000 {
+                return Err(format!(
+                    "Board would need {} pages at this scale; lower the print scale",
+                    cols * rows
+                ));
+            }
+            for row in 0..rows {
+                for col in 0..cols {
+                    let bx = region.x + col as f64 * aw / s;
+                    let by = region.y + row as f64 * ah / s;
+                    let matrix = [s, 0.0, 0.0, -s, margin - bx * s, ph - margin + by * s];
+                    pages.push((pw, ph, [margin, margin, aw, ah], matrix));
+                }
+            }
+        }
+    }
+
+    let page_count = pages.len();
+    let catalog_id = doc.alloc();
+    let tree_id = doc.alloc();
+    let info_id = doc.alloc();
+    let mut page_ids = Vec::new();
+    for (w, h, clip, matrix) in pages {
+        let page_id = doc.alloc();
+        let content_id = doc.alloc();
+        let mut content = Content::new();
+        content.save_state();
+        content.rect(clip[0] as f32, clip[1] as f32, clip[2] as f32, clip[3] as f32);
+        content.clip_nonzero().end_path();
+        content.transform(matrix.map(|v| v as f32));
+        content.x_object(Name(board_form.as_bytes()));
+        content.restore_state();
+        let data = deflate(&content.finish());
+        doc.pdf.stream(content_id, &data).filter(Filter::FlateDecode);
+
+        let mut page = doc.pdf.page(page_id);
+        page.media_box(pdf_writer::Rect::new(0.0, 0.0, w as f32, h as f32))
+            .parent(tree_id)
+            .contents(content_id);
+        page.pair(Name(b"Resources"), doc.resources_id);
+        page.finish();
+        page_ids.push(page_id);
+    }
+
+    doc.pdf.catalog(catalog_id).pages(tree_id);
+    doc.pdf.pages(tree_id).kids(page_ids).count(page_count as i32);
+    doc.pdf
+        .document_info(info_id)
+        .title(TextStr(&board.name))
+        .producer(TextStr("EyeDea"));
+
+    Ok((doc.finish()?, page_count))
+}
+
+/// A bundled font embedded as a CID-keyed TrueType font, along with the
+/// glyphs used so far for its widths and ToUnicode map.
+struct EmbeddedFont {
+    face: &'static BundledFont,
+    name: String,
+    id: Ref,
+    glyphs: BTreeMap<u16, (f32, char)>,
+}
+
+/// Collects the objects of a board PDF. All content streams share a single
+/// resource dictionary, written once every image, font and state is known.
+struct Document<'a> {
+    pdf: Pdf,
+    next_id: i32,
+    images_dir: &'a Path,
+    region: Rect,
+    resources_id: Ref,
+    x_objects: Vec<(String, Ref)>,
+    states: Vec<(String, Ref)>,
+    alpha_states: HashMap<u32, String>,
+    images: HashMap<String, Option<String>>,
+    fonts: Vec<EmbeddedFont>,
+}
+
+impl<'a> Document<'a> {
+    fn new(images_dir: &'a Path, region: Rect) -> Self {
+        Document {
+            pdf: Pdf::new(),
+            next_id: 2,
+            images_dir,
+            region,
+            resources_id: Ref::new(1),
+            x_objects: Vec::new(),
+            states: Vec::new(),
+            alpha_states: HashMap::new(),
+            images: HashMap::new(),
+            fonts: Vec::new(),
+        }
+    }
+
+    fn alloc(&mut self) -> Ref {
+        let id = Ref::new(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    /// Draws the whole board into a form XObject and returns its name.
+    fn draw_board(&mut self, board: &Board, transparent: bool) -> Result<String, String> {
+        let mut content = Content::new();
+        if !transparent {
+            let region = self.region;
+            content.save_state();
+            self.set_color(&mut content, &board.bg_color, 1.0, false);
+            content.rect(region.x as f32, region.y as f32, region.width as f32, region.height as f32);
+            content.fill_nonzero();
+            content.restore_state();
+        }
+
+        for item in render::board_items(board) {
+            match item {
+                BoardItem::Layer(layer) => self.draw_layer(&mut content, layer),
+                BoardItem::Object(obj) => self.draw_object(&mut content, &obj)?,
+            }
+        }
+
+        // Strokes go above everything else, like the canvas ink layer
+        let mut data = content.finish();
+        data.push(b'\n');
+        data.extend(self.draw_ink(&drawing::parse_strokes(&board.strokes)));
+        Ok(self.form(&data, false).0)
+    }
+
+    /// Writes a form XObject covering the export region.
+    fn form(&mut self, content: &[u8], luminosity_group: bool) -> (String, Ref) {
+        let id = self.alloc();
+        let region = self.region;
+        let data = deflate(content);
+        let mut form = self.pdf.form_xobject(id, &data);
+        form.filter(Filter::FlateDecode);
+        form.bbox(pdf_writer::Rect::new(
+            region.x as f32,
+            region.y as f32,
+            region.right() as f32,
+            region.bottom() as f32,
+        ));
+        if luminosity_group {
+            form.group().transparency().color_space().device_rgb();
+        }
+        form.pair(Name(b"Resources"), self.resources_id);
+        form.finish();
+
+        let name = format!("X{}", id.get());
+        self.x_objects.push((name.clone(), id));
+        (name, id)
+    }
+
+    /// Graphics state applying a constant fill and stroke alpha, or `None`
+    /// when fully opaque.
+    fn alpha_state(&mut self, alpha: f64) -> Option<String> {
+        let key = (alpha.clamp(0.0, 1.0) * 1000.0).round() as u32;
+        if key >= 1000 {
+            return None;
+        }
+        if let Some(name) = self.alpha_states.get(&key) {
+            return Some(name.clone());
+        }
+        let id = self.alloc();
+        let value = key as f32 / 1000.0;
+        self.pdf.ext_graphics(id).non_stroking_alpha(value).stroking_alpha(value);
+        let name = format!("Gs{}", id.get());
+        self.states.push((name.clone(), id));
+        self.alpha_states.insert(key, name.clone());
+        Some(name)
+    }
+
+    /// Sets a CSS color as the fill or stroke color. Callers wrap this in
+    /// save/restore since it may also change the alpha state.
+    fn set_color(&mut self, content: &mut Content, color: &str, alpha: f64, stroke: bool) {
+        let [r, g, b, a] = drawing::parse_color(color).unwrap_or([0, 0, 0, 255]);
+        let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+        if stroke {
+            content.set_stroke_rgb(r, g, b);
+        } else {
+            content.set_fill_rgb(r, g, b);
+        }
+        if let Some(state) = self.alpha_state(a as f64 / 255.0 * alpha) {
+            content.set_parameters(Name(state.as_bytes()));
+        }
+    }
+
+    fn draw_layer(&mut self, content: &mut Content, layer: &Layer) {
+        if layer.width <= 0.0 || layer.height <= 0.0 {
+            return;
+        }
+        let (w, h) = (layer.width as f32, layer.height as f32);
+        let opacity = layer.opacity.map(|o| o / 100.0).unwrap_or(1.0).clamp(0.0, 1.0);
+
+        content.save_state();
+        content.transform(pdf_matrix(render::layer_transform(layer, Transform::identity())));
+        match self.image(layer) {
+            Some(image) => {
+                if let Some(state) = self.alpha_state(opacity) {
+                    content.set_parameters(Name(state.as_bytes()));
+                }
+                // Image space is y-up, board space is y-down
+                content.transform([w, 0.0, 0.0, -h, 0.0, h]);
+                content.x_object(Name(image.as_bytes()));
+            }
+            None => {
+                // Same dark box and play triangle as the raster renderer
+                content.save_state();
+                content.set_fill_rgb(0.12, 0.12, 0.12);
+                if let Some(state) = self.alpha_state(opacity) {
+                    content.set_parameters(Name(state.as_bytes()));
+                }
+                content.rect(0.0, 0.0, w, h).fill_nonzero();
+                content.restore_state();
+
+                if layer.media_type.as_deref() == Some("video") {
+                    let (cx, cy, r) = (w / 2.0, h / 2.0, w.min(h) * 0.12);
+                    content.set_fill_rgb(1.0, 1.0, 1.0);
+                    if let Some(state) = self.alpha_state(0.9 * opacity) {
+                        content.set_parameters(Name(state.as_bytes()));
+                    }
+                    content.move_to(cx - r * 0.35, cy - r * 0.5);
+                    content.line_to(cx - r * 0.35, cy + r * 0.5);
+                    content.line_to(cx + r * 0.55, cy);
+                    content.close_path().fill_nonzero();
+                }
+            }
+        }
+        content.restore_state();
+    }
+
+    /// Name of the image XObject for a layer's media, embedding it on first
+    /// use. Layers sharing a source and filters share one image.
+    fn image(&mut self, layer: &Layer) -> Option<String> {
+        let key = format!(
+            "{}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}",
+            layer.src,
+            layer.gif_current_frame,
+            layer.brightness,
+            layer.contrast,
+            layer.saturation,
+            layer.hue,
+            layer.blur,
+            layer.grayscale,
+            layer.invert
+        );
+        if let Some(name) = self.images.get(&key) {
+            return name.clone();
+        }
+        let name = self.embed_image(layer);
+        self.images.insert(key, name.clone());
+        name
+    }
+
+    /// Embeds a layer's media at its native resolution. Unfiltered JPEGs are
+    /// copied through as-is; everything else is decoded, filtered and stored
+    /// losslessly with a soft mask for transparency.
+    fn embed_image(&mut self, layer: &Layer) -> Option<String> {
+        let bytes = render::layer_source_bytes(layer, self.images_dir)?;
+        let id = self.alloc();
+
+        let passthrough = (layer.gif_current_frame.unwrap_or(0) == 0 && !render::has_filters(layer))
+            .then(|| jpeg_components(&bytes))
+            .flatten()
+            .filter(|c| *c == 1 || *c == 3);
+
+        if let Some(components) = passthrough {
+            let (width, height) = image::ImageReader::new(Cursor::new(&bytes))
+                .with_guessed_format()
+                .ok()?
+                .into_dimensions()
+                .ok()?;
+            let mut image = self.pdf.image_xobject(id, &bytes);
+            image.filter(Filter::DctDecode);
+            image.width(width as i32).height(height as i32).bits_per_component(8);
+            if components == 1 {
+                image.color_space().device_gray();
+            } else {
+                image.color_space().device_rgb();
+            }
+        } else {
+            let mut pixels = render::load_layer_image(layer, self.images_dir)?.to_rgba8();
+            render::apply_filters(&mut pixels, layer, 1.0);
+            let (width, height) = pixels.dimensions();
+
+            let mask = if pixels.pixels().any(|p| p[3] < 255) {
+                let mask_id = self.alloc();
+                let alpha: Vec<u8> = pixels.pixels().map(|p| p[3]).collect();
+                let data = deflate(&alpha);
+                let mut mask = self.pdf.image_xobject(mask_id, &data);
+                mask.filter(Filter::FlateDecode);
+                mask.width(width as i32).height(height as i32).bits_per_component(8);
+                mask.color_space().device_gray();
+                mask.finish();
+                Some(mask_id)
+            } else {
+                None
+            };
+
+            let rgb: Vec<u8> = pixels.pixels().flat_map(|p| [p[0], p[1], p[2]]).collect();
+            let data = deflate(&rgb);
+            let mut image = self.pdf.image_xobject(id, &data);
+            image.filter(Filter::FlateDecode);
+            image.width(width as i32).height(height as i32).bits_per_component(8);
+            image.color_space().device_rgb();
+            if let Some(mask_id) = mask {
+                image.s_mask(mask_id);
+            }
+        }
+
+        let name = format!("Im{}", id.get());
+        self.x_objects.push((name.clone(), id));
+        Some(name)
+    }
+
+    fn draw_object(&mut self, content: &mut Content, obj: &CanvasObject) -> Result<(), String> {
+        content.save_state();
+        content.transform(pdf_matrix(render::object_transform(obj, Transform::identity())));
+        match obj.kind.as_str() {
+            "shape" => self.draw_shape(content, obj),
+            "text" => self.draw_text(content, obj)?,
+            "colorPalette" => self.draw_palette(content, obj),
+            _ => {}
+        }
+        content.restore_state();
+        Ok(())
+    }
+
+    fn draw_shape(&mut self, content: &mut Content, obj: &CanvasObject) {
+        let Some((path, filled)) = render::shape_path(obj) else {
+            return;
+        };
+        if filled {
+            content.save_state();
+            self.set_color(content, obj.fill_color.as_deref().unwrap_or("#3b82f6"), 1.0, false);
+            push_path(content, &path);
+            content.fill_nonzero();
+            content.restore_state();
+        }
+        if !filled || obj.draws_stroke() {
+            content.save_state();
+            self.set_color(content, obj.stroke_color.as_deref().unwrap_or("#000000"), 1.0, true);
+            content.set_line_width(obj.stroke_width() as f32);
+            push_path(content, &path);
+            content.stroke();
+            content.restore_state();
+        }
+    }
+
+    /// Text is written as real text in the embedded bundled fonts, laid out
+    /// by the same wrapping code as the raster renderer.
+    fn draw_text(&mut self, content: &mut Content, obj: &CanvasObject) -> Result<(), String> {
+        let runs = fonts::layout_text(obj)?;
+        if runs.is_empty() {
+            return Ok(());
+        }
+
+        content.save_state();
+        content.rect(obj.x as f32, obj.y as f32, obj.width as f32, obj.height as f32);
+        content.clip_nonzero().end_path();
+
+        for run in runs {
+            let font = run.face.font()?;
+            let units_per_em = font.units_per_em().unwrap_or(1000.0);
+            let index = self.font_index(run.face);
+            let size = run.style.size();
+
+            content.save_state();
+            self.set_color(content, run.style.color(), 1.0, false);
+            content.begin_text();
+            content.set_font(Name(self.fonts[index].name.as_bytes()), size as f32);
+            content.set_text_matrix([1.0, 0.0, 0.0, -1.0, run.x as f32, run.baseline as f32]);
+            {
+                let mut shown = content.show_positioned();
+                let mut items = shown.items();
+                let mut glyphs = Vec::new();
+                let mut previous = None;
+                for c in run.text.chars() {
+                    let id = font.glyph_id(c);
+                    if let Some(prev) = previous {
+                        let kern = font.kern_unscaled(prev, id);
+                        if kern != 0.0 {
+                            if !glyphs.is_empty() {
+                                items.show(Str(&glyphs));
+                                glyphs.clear();
+                            }
+                            items.adjust(-kern * 1000.0 / units_per_em);
+                        }
+                    }
+                    glyphs.extend(id.0.to_be_bytes());
+                    let advance = font.h_advance_unscaled(id) * 1000.0 / units_per_em;
+                    self.fonts[index].glyphs.entry(id.0).or_insert((advance, c));
+                    previous = Some(id);
+                }
+                if !glyphs.is_empty() {
+                    items.show(Str(&glyphs));
+                }
+            }
+            content.end_text();
+
+            let decoration = match run.style.text_decoration.as_deref() {
+                Some("underline") => Some(run.baseline + size * 0.1),
+                Some("line-through") => Some(run.baseline - size * 0.3),
+                _ => None,
+            };
+            if let Some(line_y) = decoration {
+                self.set_color(content, run.style.color(), 1.0, true);
+                content.set_line_width(1.0);
+                content.move_to(run.x as f32, line_y as f32);
+                content.line_to((run.x + run.width) as f32, line_y as f32);
+                content.stroke();
+            }
+            content.restore_state();
+        }
+
+        content.restore_state();
+        Ok(())
+    }
+
+    fn draw_palette(&mut self, content: &mut Content, obj: &CanvasObject) {
+        let cell = obj.cell_size.unwrap_or(60.0) as f32;
+        let cols = obj.grid_cols.unwrap_or(1).max(1) as usize;
+        let colors = obj.colors.clone().unwrap_or_default();
+        let wide = obj.has_wide_cell == Some(true) && !colors.is_empty();
+        let regular = if wide { colors.len() - 1 } else { colors.len() };
+        let rows = obj.grid_rows.unwrap_or(1) as usize;
+
+        let mut cells: Vec<(f32, f32, f32, &str)> = colors
+            .iter()
+            .take(regular.min(rows * cols))
+            .enumerate()
+            .map(|(i, color)| {
+                let x = obj.x as f32 + (i % cols) as f32 * cell;
+                let y = obj.y as f32 + (i / cols) as f32 * cell;
+                (x, y, cell, color.hex.as_str())
+            })
+            .collect();
+        if wide {
+            let y = obj.y as f32 + rows as f32 * cell;
+            cells.push((obj.x as f32, y, cols as f32 * cell, colors[colors.len() - 1].hex.as_str()));
+        }
+
+        for (x, y, width, color) in cells {
+            content.save_state();
+            self.set_color(content, color, 1.0, false);
+            content.rect(x, y, width, cell).fill_nonzero();
+            content.restore_state();
+        }
+    }
+
+    /// Draws the strokes layer. Pixel erasers become luminosity soft masks
+    /// over everything inked before them.
+    fn draw_ink(&mut self, strokes: &[Stroke]) -> Vec<u8> {
+        let mut ink = Content::new();
+        for pass in drawing::ink_passes(strokes) {
+            for stroke in &pass.strokes {
+                let color = stroke.color.as_deref().unwrap_or("#000000");
+                self.draw_stroke(&mut ink, stroke, color, stroke.alpha());
+            }
+            if pass.erasers.is_empty() {
+                continue;
+            }
+
+            let drawn = std::mem::replace(&mut ink, Content::new()).finish();
+            let (group, _) = self.form(&drawn, false);
+
+            let mut mask = Content::new();
+            let region = self.region;
+            mask.set_fill_rgb(1.0, 1.0, 1.0);
+            mask.rect(region.x as f32, region.y as f32, region.width as f32, region.height as f32);
+            mask.fill_nonzero();
+            for eraser in &pass.erasers {
+                self.draw_stroke(&mut mask, eraser, "#000000", eraser.opacity.unwrap_or(1.0));
+            }
+            let (_, mask_id) = self.form(&mask.finish(), true);
+
+            let state_id = self.alloc();
+            self.pdf
+                .ext_graphics(state_id)
+                .soft_mask()
+                .subtype(MaskType::Luminosity)
+                .group(mask_id);
+            let state = format!("Gs{}", state_id.get());
+            self.states.push((state.clone(), state_id));
+
+            ink.save_state();
+            ink.set_parameters(Name(state.as_bytes()));
+            ink.x_object(Name(group.as_bytes()));
+            ink.restore_state();
+        }
+        ink.finish()
+    }
+
+    fn draw_stroke(&mut self, content: &mut Content, stroke: &Stroke, color: &str, alpha: f64) {
+        let Some(path) = render::stroke_path(stroke) else {
+            return;
+        };
+        content.save_state();
+        self.set_color(content, color, alpha, true);
+        content.set_line_width(stroke.width() as f32);
+        content.set_line_cap(LineCapStyle::RoundCap);
+        content.set_line_join(LineJoinStyle::RoundJoin);
+        push_path(content, &path);
+        content.stroke();
+        content.restore_state();
+    }
+
+    fn font_index(&mut self, face: &'static BundledFont) -> usize {
+        if let Some(index) = self.fonts.iter().position(|f| f.face.file == face.file) {
+            return index;
+        }
+        let id = self.alloc();
+        self.fonts.push(EmbeddedFont {
+            face,
+            name: format!("F{}", id.get()),
+            id,
+            glyphs: BTreeMap::new(),
+        });
+        self.fonts.len() - 1
+    }
+
+    /// Embeds the whole TrueType file; the bundled fonts are small enough
+    /// that subsetting isn't worth the complexity.
+    fn write_font(&mut self, font: &EmbeddedFont) -> Result<(), String> {
+        let data = font.face.sfnt()?;
+        let metrics = font.face.font()?;
+        let units_per_em = metrics.units_per_em().unwrap_or(1000.0);
+        let ascent = metrics.ascent_unscaled() * 1000.0 / units_per_em;
+        let descent = metrics.descent_unscaled() * 1000.0 / units_per_em;
+        let base_name = format!(
+            "{}-{}",
+            font.face.family.replace(' ', ""),
+            if font.face.bold { "Bold" } else { "Regular" }
+        );
+        let system_info = SystemInfo {
+            registry: Str(b"Adobe"),
+            ordering: Str(b"Identity"),
+            supplement: 0,
+        };
+
+        let cid_id = self.alloc();
+        let descriptor_id = self.alloc();
+        let file_id = self.alloc();
+        let cmap_id = self.alloc();
+
+        self.pdf
+            .type0_font(font.id)
+            .base_font(Name(base_name.as_bytes()))
+            .encoding_predefined(Name(b"Identity-H"))
+            .descendant_font(cid_id)
+            .to_unicode(cmap_id);
+
+        let mut cid = self.pdf.cid_font(cid_id);
+        cid.subtype(CidFontType::Type2)
+            .base_font(Name(base_name.as_bytes()))
+            .system_info(system_info)
+            .font_descriptor(descriptor_id)
+            .cid_to_gid_map_predefined(Name(b"Identity"));
+        let mut widths = cid.widths();
+        for (glyph, (width, _)) in &font.glyphs {
+            widths.consecutive(*glyph, [*width]);
+        }
+        widths.finish();
+        cid.finish();
+
+        self.pdf
+            .font_descriptor(descriptor_id)
+            .name(Name(base_name.as_bytes()))
+            .flags(FontFlags::NON_SYMBOLIC)
+            .bbox(pdf_writer::Rect::new(0.0, descent, 1000.0, ascent))
+            .italic_angle(0.0)
+            .ascent(ascent)
+            .descent(descent)
+            .cap_height(ascent * 0.7)
+            .stem_v(if font.face.bold { 140.0 } else { 80.0 })
+            .font_file2(file_id);
+
+        let compressed = deflate(&data);
+        self.pdf
+            .stream(file_id, &compressed)
+            .filter(Filter::FlateDecode)
+            .pair(Name(b"Length1"), data.len() as i32);
+
+        let mut cmap = UnicodeCmap::new(Name(b"Custom"), system_info);
+        for (glyph, (_, c)) in &font.glyphs {
+            cmap.pair(*glyph, *c);
+        }
+        let cmap = cmap.finish();
+        self.pdf.cmap(cmap_id, &cmap);
+        Ok(())
+    }
+
+    /// Writes the embedded fonts and the shared resource dictionary.
+    fn finish(mut self) -> Result<Vec<u8>, String> {
+        let fonts = std::mem::take(&mut self.fonts);
+        for font in &fonts {
+            self.write_font(font)?;
+        }
+
+        let mut resources = self.pdf.indirect(self.resources_id).start::<Resources>();
+        let mut x_objects = resources.x_objects();
+        for (name, id) in &self.x_objects {
+            x_objects.pair(Name(name.as_bytes()), *id);
+        }
+        x_objects.finish();
+        let mut states = resources.ext_g_states();
+        for (name, id) in &self.states {
+            states.pair(Name(name.as_bytes()), *id);
+        }
+        states.finish();
+        let mut font_dict = resources.fonts();
+        for font in &fonts {
+            font_dict.pair(Name(font.name.as_bytes()), font.id);
+        }
+        font_dict.finish();
+        resources.finish();
+
+        Ok(self.pdf.finish())
+    }
+}
+
+/// Converts a tiny-skia transform into a PDF `cm` matrix.
+fn pdf_matrix(t: Transform) -> [f32; 6] {
+    [t.sx, t.ky, t.kx, t.sy, t.tx, t.ty]
+}
+
+/// Appends a tiny-skia path, raising quadratic segments to cubics.
+fn push_path(content: &mut Content, path: &tiny_skia::Path) {
+    let mut start = (0.0, 0.0);
+    let mut last = (0.0, 0.0);
+    for segment in path.segments() {
+        match segment {
+            PathSegment::MoveTo(p) => {
+                content.move_to(p.x, p.y);
+                start = (p.x, p.y);
+                last = start;
+            }
+            PathSegment::LineTo(p) => {
+                content.line_to(p.x, p.y);
+                last = (p.x, p.y);
+            }
+            PathSegment::QuadTo(c, p) => {
+                let c1 = (last.0 + 2.0 / 3.0 * (c.x - last.0), last.1 + 2.0 / 3.0 * (c.y - last.1));
+                let c2 = (p.x + 2.0 / 3.0 * (c.x - p.x), p.y + 2.0 / 3.0 * (c.y - p.y));
+                content.cubic_to(c1.0, c1.1, c2.0, c2.1, p.x, p.y);
+                last = (p.x, p.y);
+            }
+            PathSegment::CubicTo(c1, c2, p) => {
+                content.cubic_to(c1.x, c1.y, c2.x, c2.y, p.x, p.y);
+                last = (p.x, p.y);
+            }
+            PathSegment::Close => {
+                content.close_path();
+                last = start;
+            }
+        }
+    }
+}
+
+/// Number of color components of a baseline or progressive JPEG, which PDF
+/// readers can decode directly. Returns `None` for anything else.
+fn jpeg_components(bytes: &[u8]) -> Option<u8> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut i = 2;
+    while i + 4 <= bytes.len() {
+        if bytes[i] != 0xFF {
+            return None;
+        }
+        let marker = bytes[i + 1];
+        if marker == 0xFF {
+            i += 1;
+            continue;
+        }
+        match marker {
+            0xC0..=0xC2 => return bytes.get(i + 9).copied(),
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF | 0xDA => return None,
+            _ => {}
+        }
+        let length = u16::from_be_bytes([bytes[i + 2], bytes[i + 3]]) as usize;
+        i += 2 + length;
+    }
+    None
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    // Writing into a Vec can't fail
+    let _ = encoder.write_all(data);
+    encoder.finish().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+
+    fn count(haystack: &[u8], needle: &str) -> usize {
+        haystack.windows(needle.len()).filter(|w| *w == needle.as_bytes()).count()
+    }
+
+    fn board() -> (Board, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("eyedea-pdf-{}", database::random_token().unwrap()));
+        std::fs::create_dir_all(&dir).unwrap();
+        image::RgbaImage::from_pixel(4, 4, image::Rgba([255, 0, 0, 128])).save(dir.join("red.png")).unwrap();
+        let mut board = database::new_board("Print me".to_string(), "#ffffff".to_string());
+        board.layers = serde_json::from_value(serde_json::json!([
+            { "id": 1.0, "name": "red", "src": "red.png", "x": 0.0, "y": 0.0, "width": 800.0, "height": 600.0 }
+        ]))
+        .unwrap();
+        board.objects = Some(serde_json::json!([{
+            "id": "obj_1", "type": "text", "x": 10.0, "y": 10.0, "width": 200.0, "height": 40.0, "zIndex": 2.0,
+            "content": [{ "text": "Hello", "style": { "fontSize": 20.0, "fontFamily": "Arial", "color": "#000000" } }]
+        }]));
+        (board, dir)
+    }
+
+    #[test]
+    fn writes_one_page_sized_to_the_board() {
+        let (board, dir) = board();
+        let (bytes, pages) = board_to_pdf(&board, &dir, &PdfOptions::default()).unwrap();
+        assert_eq!(pages, 1);
+        assert!(bytes.starts_with(b"%PDF-"));
+        assert!(bytes.trim_ascii_end().ends_with(b"%%EOF"));
+        assert_eq!(count(&bytes, "/Type /Page") - count(&bytes, "/Type /Pages"), 1);
+        assert_eq!(count(&bytes, "/MediaBox [0 0 600 450]"), 1);
+        // The image with its alpha as a soft mask, and the note's font
+        assert!(count(&bytes, "/Subtype /Image") >= 2);
+        assert!(count(&bytes, "/SMask") >= 1);
+        assert!(count(&bytes, "/FontFile2") >= 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tiles_across_paper_pages() {
+        let (board, dir) = board();
+        let options = PdfOptions { paper: Some(PaperSize::A4), scale: Some(2.0), ..Default::default() };
+        let (bytes, pages) = board_to_pdf(&board, &dir, &options).unwrap();
+        // 1200x900pt over 523x770pt of printable area
+        assert_eq!(pages, 6);
+        assert_eq!(count(&bytes, "/MediaBox [0 0 595.28 841.89]"), 6);
+
+        let landscape = PdfOptions { landscape: true, ..options.clone() };
+        let path = dir.join("out.pdf");
+        let exported = export_board_pdf(&board, &dir, &landscape, &path).unwrap();
+        assert_eq!(exported.pages, 4);
+        assert!(std::fs::read(&path).unwrap().starts_with(b"%PDF-"));
+
+        let tiny = PdfOptions { scale: Some(100.0), ..options };
+        assert!(board_to_pdf(&board, &dir, &tiny).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
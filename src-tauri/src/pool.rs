@@ -0,0 +1,67 @@
+//! A fixed set of worker threads, so a burst of requests queues up instead
+//! of starting a thread each.
+
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+pub struct Pool {
+    sender: Sender<Job>,
+}
+
+impl Pool {
+    pub fn new(name: &str, threads: usize) -> Pool {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..threads.max(1) {
+            let receiver = receiver.clone();
+            let spawned = thread::Builder::new().name(format!("{}-{}", name, i)).spawn(move || loop {
+                // The lock is released before the job runs
+                let job = receiver.lock().unwrap().recv();
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => return,
+                }
+            });
+            if let Err(e) = spawned {
+                log::error!("Failed to start a {} worker: {}", name, e);
+            }
+        }
+        Pool { sender }
+    }
+
+    /// Queues a job for the next free worker.
+    pub fn run(&self, job: impl FnOnce() + Send + 'static) {
+        let _ = self.sender.send(Box::new(job));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn runs_every_job_on_a_few_threads() {
+        let pool = Pool::new("test", 2);
+        let (running, most) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let (done, finished) = mpsc::channel();
+        for _ in 0..10 {
+            let (running, most, done) = (running.clone(), most.clone(), done.clone());
+            pool.run(move || {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(10));
+                running.fetch_sub(1, Ordering::SeqCst);
+                done.send(()).unwrap();
+            });
+        }
+        for _ in 0..10 {
+            finished.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+        assert!(most.load(Ordering::SeqCst) <= 2);
+    }
+}
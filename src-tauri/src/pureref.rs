@@ -0,0 +1,684 @@
+//! Importer for PureRef `.pur` files.
+//!
+//! The format is undocumented Qt `QDataStream` output (big-endian, strings
+//! as length-prefixed UTF-16). Rather than depending on exact offsets, which
+//! shift between PureRef versions, the reader checks the header, pulls the
+//! embedded PNG/JPEG streams out by their own framing, and locates item
+//! records by their `GraphicsImageItem` / `GraphicsTextItem` type names. The
+//! item transform is the first affine matrix after the item's strings that
+//! is a plain scale/rotation/flip, which is all PureRef lets users apply.
+
+use crate::database::{self, Asset, Board, Layer};
+use crate::fonts;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::Path;
+
+const IMAGE_ITEM: &str = "GraphicsImageItem";
+const TEXT_ITEM: &str = "GraphicsTextItem";
+
+/// Font size PureRef notes use at 100% scale.
+const NOTE_FONT_SIZE: f64 = 20.0;
+
+pub struct PurDocument {
+    pub images: Vec<PurImage>,
+    pub items: Vec<PurItem>,
+}
+
+/// An image stream embedded in the file.
+pub struct PurImage {
+    /// The 32-bit value stored just before the stream, which PureRef uses as
+    /// the image id that items refer to.
+    pub tag: Option<u32>,
+    pub bytes: Vec<u8>,
+    pub ext: &'static str,
+}
+
+pub enum PurItemKind {
+    Image { image_id: Option<u32>, name: String },
+    Text { text: String },
+}
+
+pub struct PurItem {
+    pub kind: PurItemKind,
+    pub transform: Affine,
+    pub z: Option<f64>,
+}
+
+/// A `QTransform` without the projective part: x' = m11*x + m21*y + dx,
+/// y' = m12*x + m22*y + dy.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Affine {
+    pub m11: f64,
+    pub m12: f64,
+    pub m21: f64,
+    pub m22: f64,
+    pub dx: f64,
+    pub dy: f64,
+}
+
+impl Affine {
+    pub fn scale(&self) -> f64 {
+        self.m11.hypot(self.m12)
+    }
+
+    pub fn is_flipped(&self) -> bool {
+        self.m11 * self.m22 - self.m12 * self.m21 < 0.0
+    }
+
+    /// Clockwise rotation in degrees, applied after any horizontal flip the
+    /// way the canvas applies `mirror` and `rotation`.
+    pub fn rotation(&self) -> f64 {
+        let degrees = if self.is_flipped() {
+            self.m12.atan2(-self.m11).to_degrees()
+        } else {
+            self.m12.atan2(self.m11).to_degrees()
+        };
+        if degrees.abs() < 1e-6 {
+            0.0
+        } else {
+            degrees
+        }
+    }
+
+    pub fn map(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            self.m11 * x + self.m21 * y + self.dx,
+            self.m12 * x + self.m22 * y + self.dy,
+        )
+    }
+}
+
+/// Reads a `.pur` file and builds a new board from it, writing the embedded
+/// images into `images_dir`. The board is not saved.
+pub fn import_pur(
+    path: &Path,
+    images_dir: &Path,
+    name: Option<String>,
+    bg_color: Option<String>,
+) -> Result<Board, String> {
+    let data = std::fs::read(path).map_err(|e| format!("Failed to read PureRef file: {}", e))?;
+    let document = parse_pur(&data)?;
+
+    let board_name = name.unwrap_or_else(|| {
+        path.file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "PureRef import".to_string())
+    });
+    let mut board = database::new_board(board_name, bg_color.unwrap_or_else(|| "#ffffff".to_string()));
+
+    let mut order: Vec<usize> = (0..document.items.len()).collect();
+    order.sort_by(|a, b| {
+        let za = document.items[*a].z.unwrap_or(*a as f64);
+        let zb = document.items[*b].z.unwrap_or(*b as f64);
+        za.total_cmp(&zb).then(a.cmp(b))
+    });
+
+    let mut saved: HashMap<usize, (String, u32, u32)> = HashMap::new();
+    let mut objects = Vec::new();
+    let mut next_image = 0;
+    // Apart from the layer count, which skipped items leave behind
+    let mut next_id = board.created_at as f64;
+    for (z, index) in order.into_iter().enumerate() {
+        let item = &document.items[index];
+        let z_index = (z + 1) as f64;
+        let t = item.transform;
+
+        match &item.kind {
+            PurItemKind::Image { image_id, name } => {
+                let Some(image_index) = resolve_image(&document.images, *image_id, &mut next_image) else {
+                    continue;
+                };
+                let (filename, width, height) = match saved.entry(image_index) {
+                    Entry::Occupied(entry) => entry.get().clone(),
+                    Entry::Vacant(entry) => {
+                        let image = &document.images[image_index];
+                        let Some((width, height)) = image_size(&image.bytes) else {
+                            continue;
+                        };
+                        let stem = if name.is_empty() { "pureref" } else { name.as_str() };
+                        let filename = database::write_media_file(images_dir, &image.bytes, stem, image.ext)?;
+                        entry.insert((filename, width, height)).clone()
+                    }
+                };
+
+                // PureRef centers images on the item origin
+                let scale = t.scale();
+                let (w, h) = (width as f64 * scale, height as f64 * scale);
+                let id = next_id;
+                next_id += 1.0;
+                let layer_name = if name.is_empty() {
+                    format!("PureRef image {}", board.layers.len() + 1)
+                } else {
+                    name.clone()
+                };
+                board.layers.push(Layer {
+                    id,
+                    name: layer_name.clone(),
+                    src: filename.clone(),
+                    x: t.dx - w / 2.0,
+                    y: t.dy - h / 2.0,
+                    width: w,
+                    height: h,
+                    visible: true,
+                    z_index,
+                    rotation: Some(t.rotation()),
+                    brightness: None,
+                    contrast: None,
+                    saturation: None,
+                    hue: None,
+                    blur: None,
+                    opacity: None,
+                    grayscale: None,
+                    invert: None,
+                    mirror: Some(t.is_flipped()),
+                    media_type: Some("image".to_string()),
+                    current_time: None,
+                    volume: None,
+                    muted: None,
+                    gif_current_frame: None,
+                    gif_playing: None,
+                    source: None,
+                });
+                if !board.assets.iter().any(|a| a.src == filename) {
+                    board.assets.push(Asset {
+                        id,
+                        name: layer_name,
+                        src: filename,
+                        tags: Vec::new(),
+                        metadata: None,
+                    });
+                }
+            }
+            PurItemKind::Text { text } => {
+                objects.push(text_object(text, &t, z_index, objects.len(), board.created_at)?);
+            }
+        }
+    }
+
+    if !objects.is_empty() {
+        board.objects = Some(serde_json::Value::Array(objects));
+    }
+    if board.layers.is_empty() && board.objects.is_none() {
+        return Err("No images or notes found in the PureRef file".to_string());
+    }
+    Ok(board)
+}
+
+/// Finds the embedded image an item refers to: by stored id, then by index,
+/// then simply the next unused image in file order.
+fn resolve_image(images: &[PurImage], image_id: Option<u32>, next: &mut usize) -> Option<usize> {
+    if let Some(id) = image_id {
+        if let Some(index) = images.iter().position(|i| i.tag == Some(id)) {
+            return Some(index);
+        }
+        if (id as usize) < images.len() {
+            return Some(id as usize);
+        }
+    }
+    let index = *next;
+    *next += 1;
+    (index < images.len()).then_some(index)
+}
+
+fn image_size(bytes: &[u8]) -> Option<(u32, u32)> {
+    image::ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// A note becomes a text object sized to its lines. PureRef transforms
+/// notes around their top-left corner, while the canvas rotates around the
+/// center, so the box is placed by its transformed center.
+fn text_object(
+    text: &str,
+    t: &Affine,
+    z_index: f64,
+    index: usize,
+    stamp: u64,
+) -> Result<serde_json::Value, String> {
+    const PADDING: f64 = 10.0;
+    let font = fonts::resolve("Arial", false).font()?;
+    let lines: Vec<&str> = text.lines().collect();
+    let widest = lines
+        .iter()
+        .map(|line| fonts::measure(&font, line, NOTE_FONT_SIZE))
+        .fold(0.0, f64::max);
+    let local_w = widest + PADDING * 2.0 + 1.0;
+    let local_h = lines.len().max(1) as f64 * NOTE_FONT_SIZE * 1.2 + PADDING * 2.0;
+
+    let scale = t.scale();
+    let (cx, cy) = t.map(local_w / 2.0, local_h / 2.0);
+    let (width, height) = (local_w * scale, local_h * scale);
+
+    Ok(serde_json::json!({
+        "id": format!("obj_{}_pur{}", stamp, index),
+        "type": "text",
+        "x": cx - width / 2.0,
+        "y": cy - height / 2.0,
+        "width": width,
+        "height": height,
+        "rotation": t.rotation(),
+        "content": [{
+            "text": text,
+            "style": {
+                "fontSize": NOTE_FONT_SIZE * scale,
+                "fontFamily": "Arial",
+                "fontWeight": "normal",
+                "fontStyle": "normal",
+                "color": "#000000",
+                "textDecoration": "none"
+            }
+        }],
+        "textAlign": "left",
+        "visible": true,
+        "zIndex": z_index
+    }))
+}
+
+/// Parses the parts of a `.pur` file the importer needs.
+pub fn parse_pur(data: &[u8]) -> Result<PurDocument, String> {
+    let mut header = Reader { data, pos: 0 };
+    if header.string().as_deref() != Some("PureRef") {
+        return Err("Not a PureRef file".to_string());
+    }
+
+    let markers = find_items(data);
+    let images_end = markers.first().map(|(pos, _)| *pos).unwrap_or(data.len());
+    let images = find_images(&data[..images_end]);
+
+    let mut items = Vec::new();
+    for (i, (start, is_image)) in markers.iter().enumerate() {
+        let end = markers.get(i + 1).map(|(pos, _)| *pos).unwrap_or(data.len());
+        let marker_len = 4 + 2 * if *is_image { IMAGE_ITEM.len() } else { TEXT_ITEM.len() };
+        let record = &data[start + marker_len..end];
+        if let Some(item) = parse_item(record, *is_image) {
+            items.push(item);
+        }
+    }
+
+    Ok(PurDocument { images, items })
+}
+
+/// Positions of item records and whether each is an image (else a note).
+fn find_items(data: &[u8]) -> Vec<(usize, bool)> {
+    let image = qstring_bytes(IMAGE_ITEM);
+    let text = qstring_bytes(TEXT_ITEM);
+    let mut found = Vec::new();
+    let mut i = 0;
+    while i + 4 <= data.len() {
+        if data[i..].starts_with(&image) {
+            found.push((i, true));
+            i += image.len();
+        } else if data[i..].starts_with(&text) {
+            found.push((i, false));
+            i += text.len();
+        } else {
+            i += 1;
+        }
+    }
+    found
+}
+
+fn qstring_bytes(value: &str) -> Vec<u8> {
+    let units: Vec<u16> = value.encode_utf16().collect();
+    let mut bytes = ((units.len() * 2) as u32).to_be_bytes().to_vec();
+    bytes.extend(units.iter().flat_map(|u| u.to_be_bytes()));
+    bytes
+}
+
+fn parse_item(record: &[u8], is_image: bool) -> Option<PurItem> {
+    // Leading strings: source path and name for images, the note text for
+    // notes. A flag may precede them depending on the version.
+    let mut reader = Reader { data: record, pos: 0 };
+    let skip = (0..=8).find(|offset| {
+        let mut probe = Reader { data: record, pos: *offset };
+        probe.string().is_some()
+    })?;
+    reader.pos = skip;
+    let first = reader.string()?;
+
+    let kind = if is_image {
+        let second = reader.string();
+        let name = match &second {
+            Some(name) if !name.is_empty() => name.clone(),
+            _ => Path::new(&first)
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .filter(|s| s != "BruteForceLoaded")
+                .unwrap_or_default(),
+        };
+        PurItemKind::Image { image_id: None, name }
+    } else {
+        PurItemKind::Text { text: plain_text(&first) }
+    };
+
+    let (transform, after) = (reader.pos..(reader.pos + 96).min(record.len())).find_map(|offset| {
+        let mut probe = Reader { data: record, pos: offset };
+        let values: Vec<f64> = (0..6).map(|_| probe.f64()).collect::<Option<_>>()?;
+        let t = Affine {
+            m11: values[0],
+            m12: values[1],
+            m21: values[2],
+            m22: values[3],
+            dx: values[4],
+            dy: values[5],
+        };
+        is_similarity(&t).then_some((t, probe.pos))
+    })?;
+
+    // The image id and z-order follow the transform, sometimes after one
+    // more double.
+    let (image_id, z) = [after, after + 8]
+        .into_iter()
+        .find_map(|offset| {
+            let mut probe = Reader { data: record, pos: offset };
+            let id = probe.u32()?;
+            let z = probe.f64()?;
+            (id < 1_000_000 && z.is_finite() && z.abs() < 1e9).then_some((Some(id), Some(z)))
+        })
+        .unwrap_or((None, None));
+
+    let kind = match kind {
+        PurItemKind::Image { name, .. } => PurItemKind::Image { image_id, name },
+        text => text,
+    };
+    Some(PurItem { kind, transform, z })
+}
+
+/// Whether a matrix is a uniform scale with rotation and optional flip and
+/// a sane translation.
+fn is_similarity(t: &Affine) -> bool {
+    let values = [t.m11, t.m12, t.m21, t.m22, t.dx, t.dy];
+    if values.iter().any(|v| !v.is_finite()) {
+        return false;
+    }
+    let s1 = t.m11.hypot(t.m12);
+    let s2 = t.m21.hypot(t.m22);
+    (1e-4..1e4).contains(&s1)
+        && (s1 - s2).abs() < s1 * 1e-3
+        && (t.m11 * t.m21 + t.m12 * t.m22).abs() < s1 * s2 * 1e-3
+        && t.dx.abs() < 1e8
+        && t.dy.abs() < 1e8
+}
+
+/// Notes may be stored as Qt rich text; keep only the text.
+fn plain_text(text: &str) -> String {
+    if !text.contains("<html") && !text.starts_with("<!DOCTYPE") {
+        return text.to_string();
+    }
+    let body = text
+        .split_once("<body")
+        .and_then(|(_, rest)| rest.split_once('>'))
+        .map(|(_, rest)| rest)
+        .unwrap_or(text);
+    let body = body
+        .replace("<br />", "\n")
+        .replace("<br/>", "\n")
+        .replace("</p>", "\n");
+    let mut out = String::new();
+    let mut in_tag = false;
+    for c in body.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+/// Extracts PNG and JPEG streams in file order.
+fn find_images(data: &[u8]) -> Vec<PurImage> {
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n";
+    let mut images = Vec::new();
+    let mut i = 0;
+    while i + 8 <= data.len() {
+        let found = if data[i..].starts_with(PNG) {
+            png_end(data, i).map(|end| (end, "png"))
+        } else if data[i..].starts_with(&[0xFF, 0xD8, 0xFF]) {
+            jpeg_end(data, i).map(|end| (end, "jpg"))
+        } else {
+            None
+        };
+        match found {
+            Some((end, ext)) => {
+                let tag = (i >= 4).then(|| u32::from_be_bytes([data[i - 4], data[i - 3], data[i - 2], data[i - 1]]));
+                images.push(PurImage { tag, bytes: data[i..end].to_vec(), ext });
+                i = end;
+            }
+            None => i += 1,
+        }
+    }
+    images
+}
+
+/// End of a PNG stream, following chunk lengths up to `IEND`.
+fn png_end(data: &[u8], start: usize) -> Option<usize> {
+    let mut pos = start + 8;
+    loop {
+        let length = u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        let kind = data.get(pos + 4..pos + 8)?;
+        let end = pos.checked_add(12 + length)?;
+        if end > data.len() {
+            return None;
+        }
+        if kind == b"IEND" {
+            return Some(end);
+        }
+        pos = end;
+    }
+}
+
+/// End of a JPEG stream: walks marker segments, then scans the entropy-coded
+/// data for the EOI marker.
+fn jpeg_end(data: &[u8], start: usize) -> Option<usize> {
+    let mut pos = start + 2;
+    loop {
+        if *data.get(pos)? != 0xFF {
+            return None;
+        }
+        let marker = *data.get(pos + 1)?;
+        match marker {
+            0xFF => pos += 1,
+            0xD9 => return Some(pos + 2),
+            0xD0..=0xD7 | 0x01 => pos += 2,
+            _ => {
+                let length = u16::from_be_bytes([*data.get(pos + 2)?, *data.get(pos + 3)?]) as usize;
+                pos += 2 + length;
+                if marker == 0xDA {
+                    // Entropy-coded data runs until a marker that isn't a
+                    // stuffed byte or a restart
+                    while pos + 1 < data.len() {
+                        if data[pos] == 0xFF && data[pos + 1] != 0x00 && !(0xD0..=0xD7).contains(&data[pos + 1]) {
+                            break;
+                        }
+                        pos += 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Big-endian `QDataStream` reader.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Option<&[u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn f64(&mut self) -> Option<f64> {
+        Some(f64::from_be_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    /// A `QString`: byte length then UTF-16BE, with `0xFFFFFFFF` for null.
+    fn string(&mut self) -> Option<String> {
+        let length = self.u32()?;
+        if length == u32::MAX {
+            return Some(String::new());
+        }
+        if length % 2 != 0 || length > 1 << 20 {
+            return None;
+        }
+        let units: Vec<u16> = self
+            .take(length as usize)?
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect();
+        let text = String::from_utf16(&units).ok()?;
+        (!text.chars().any(|c| c.is_control() && c != '\n' && c != '\r' && c != '\t')).then_some(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image::RgbImage::new(width, height)
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    fn transform(values: [f64; 6]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_be_bytes()).collect()
+    }
+
+    fn image_item(path: &str, name: &str, t: [f64; 6], id: u32, z: f64) -> Vec<u8> {
+        let mut bytes = qstring_bytes(IMAGE_ITEM);
+        bytes.extend(qstring_bytes(path));
+        bytes.extend(qstring_bytes(name));
+        bytes.extend(transform(t));
+        bytes.extend(id.to_be_bytes());
+        bytes.extend(z.to_be_bytes());
+        bytes
+    }
+
+    fn text_item(text: &str, t: [f64; 6], z: f64) -> Vec<u8> {
+        let mut bytes = qstring_bytes(TEXT_ITEM);
+        bytes.extend(qstring_bytes(text));
+        bytes.extend(transform(t));
+        bytes.extend(0u32.to_be_bytes());
+        bytes.extend(z.to_be_bytes());
+        bytes
+    }
+
+    /// A small file in the layout PureRef writes: header, tagged image
+    /// streams, then item records.
+    fn fixture() -> Vec<u8> {
+        let mut data = qstring_bytes("PureRef");
+        data.extend(qstring_bytes("1.11.1"));
+        data.extend(7u32.to_be_bytes());
+        data.extend(png(40, 20));
+        data.extend(9u32.to_be_bytes());
+        data.extend(png(10, 30));
+        data.extend(image_item("C:/refs/sky.png", "Sky", [2.0, 0.0, 0.0, 2.0, 100.0, 50.0], 9, 2.0));
+        data.extend(image_item("C:/refs/BruteForceLoaded.png", "", [0.0, 1.0, -1.0, 0.0, 0.0, 0.0], 7, 1.0));
+        data.extend(image_item("C:/refs/flipped.png", "", [-1.0, 0.0, 0.0, 1.0, -20.0, 0.0], 7, 3.0));
+        data.extend(text_item("<html><body><p>Look &amp; feel</p></body></html>", [1.0, 0.0, 0.0, 1.0, 5.0, 5.0], 4.0));
+        data
+    }
+
+    fn temp_dir() -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("eyedea-pureref-{}", database::random_token().unwrap()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parses_images_and_items() {
+        let document = parse_pur(&fixture()).unwrap();
+        assert_eq!(document.images.len(), 2);
+        assert_eq!(document.images[0].tag, Some(7));
+        assert_eq!(document.images[1].tag, Some(9));
+        assert_eq!(document.items.len(), 4);
+
+        let sky = &document.items[0];
+        assert!(matches!(&sky.kind, PurItemKind::Image { image_id: Some(9), name } if name == "Sky"));
+        assert_eq!(sky.transform.scale(), 2.0);
+        assert_eq!(sky.z, Some(2.0));
+        assert!(matches!(&document.items[1].kind, PurItemKind::Image { name, .. } if name.is_empty()));
+        assert_eq!(document.items[1].transform.rotation(), 90.0);
+        assert!(document.items[2].transform.is_flipped());
+        assert!(matches!(&document.items[3].kind, PurItemKind::Text { text } if text == "Look & feel"));
+    }
+
+    #[test]
+    fn imports_a_board() {
+        let dir = temp_dir();
+        let path = dir.join("Moodboard.pur");
+        std::fs::write(&path, fixture()).unwrap();
+
+        let board = import_pur(&path, &dir, None, None).unwrap();
+        assert_eq!(board.name, "Moodboard");
+        // Stacked by z, each image stored once
+        let names: Vec<&str> = board.layers.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, vec!["PureRef image 1", "Sky", "flipped"]);
+        assert_eq!(board.assets.len(), 2);
+        assert_eq!(board.layers[0].src, board.layers[2].src);
+
+        let sky = &board.layers[1];
+        assert_eq!((sky.x, sky.y, sky.width, sky.height), (90.0, 20.0, 20.0, 60.0));
+        assert_eq!(board.layers[0].rotation, Some(90.0));
+        assert_eq!(board.layers[2].mirror, Some(true));
+
+        let ids: std::collections::HashSet<u64> = board.layers.iter().map(|l| l.id as u64).collect();
+        assert_eq!(ids.len(), 3);
+        let objects = board.objects.as_ref().and_then(|o| o.as_array()).unwrap();
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0]["zIndex"], 4.0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn survives_truncated_and_corrupt_files() {
+        let data = fixture();
+        for len in (0..data.len()).step_by(7) {
+            let _ = parse_pur(&data[..len]);
+        }
+        let mut corrupt = data.clone();
+        let mut seed = 0x2545_f491_u32;
+        for byte in corrupt.iter_mut().skip(20) {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            if seed.is_multiple_of(5) {
+                *byte = seed as u8;
+            }
+        }
+        let _ = parse_pur(&corrupt);
+
+        assert!(parse_pur(b"").is_err());
+        assert!(parse_pur(&qstring_bytes("NotPureRef")).is_err());
+
+        // A file cut inside the first image has nothing to import
+        let dir = temp_dir();
+        let path = dir.join("cut.pur");
+        std::fs::write(&path, &data[..80]).unwrap();
+        assert!(import_pur(&path, &dir, None, None).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,541 @@
+//! Multi-resolution copies of still images, so a zoomed-out board never
+//! decodes every original at full size. A background job halves each image
+//! down to `MIN_LEVEL_SIZE` and cuts images past `TILE_THRESHOLD` into
+//! deep-zoom tiles at every level.
+//!
+//! Pyramids are keyed by content hash under `pyramids/<sha256>/`: level 1
+//! and up as `<level>.<ext>` (level 0 is the original) and tiles as
+//! `tiles/<level>/<col>_<row>.<ext>`. The protocol serves them as
+//! `eyedea://localhost/mip/<level>/<filename>` and
+//! `eyedea://localhost/tile/<level>/<col>/<row>/<filename>`.
+
+use crate::database::{self, DataDir};
+use crate::media_protocol;
+use image::{DynamicImage, GenericImageView, ImageFormat, ImageReader, Limits};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager};
+
+/// Halving stops once the longer side is this small.
+const MIN_LEVEL_SIZE: u32 = 256;
+
+/// Images with a longer side past this are also cut into tiles.
+const TILE_THRESHOLD: u32 = 8192;
+
+const TILE_SIZE: u32 = 512;
+
+/// Images with a longer side past this are refused.
+const MAX_SIDE: u32 = 65_536;
+
+/// Most decoded pixels held in memory at once. PNGs past it are read a row
+/// at a time; other formats are refused.
+const MAX_DECODE_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Sources waiting for the pyramid worker, held in Tauri state.
+#[derive(Default)]
+pub struct PyramidState {
+    jobs: Mutex<Jobs>,
+}
+
+#[derive(Default)]
+struct Jobs {
+    queue: VecDeque<String>,
+    /// Queued or being built, so a source is never queued twice.
+    pending: HashSet<String>,
+    running: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MipLevel {
+    pub level: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Pyramid {
+    /// "png" when the image has alpha, else "jpg".
+    pub format: String,
+    /// Largest first; level 0 is the original.
+    pub levels: Vec<MipLevel>,
+    /// Set when every level was also cut into tiles this size.
+    pub tile_size: Option<u32>,
+}
+
+/// Payload of `pyramid-ready`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PyramidReady {
+    pub src: String,
+    pub pyramid: Pyramid,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TileGrid {
+    pub tile_size: u32,
+    pub columns: u32,
+    pub rows: u32,
+}
+
+/// What the canvas should load for a layer at some on-screen scale.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LayerResolution {
+    pub src: String,
+    pub level: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Path to request under the `eyedea://` protocol.
+    pub path: String,
+    /// False until the pyramid is built; the original is used meanwhile.
+    pub ready: bool,
+    /// Tiles of the chosen level, for images too big to load whole.
+    pub tiles: Option<TileGrid>,
+}
+
+fn pyramid_dir(app: &impl DataDir, hash: &str) -> PathBuf {
+    app.data_dir().join("pyramids").join(hash)
+}
+
+/// Whether pyramids are made for this source.
+pub fn is_still(src: &str) -> bool {
+    let ext = Path::new(src).extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    matches!(ext.as_str(), "png" | "jpg" | "jpeg" | "webp" | "bmp")
+}
+
+/// Sizes of every level for an image, halving until the longer side is
+/// `MIN_LEVEL_SIZE` or less.
+pub fn level_sizes(width: u32, height: u32) -> Vec<MipLevel> {
+    let mut levels = vec![MipLevel { level: 0, width, height }];
+    let (mut w, mut h) = (width, height);
+    while w.max(h) > MIN_LEVEL_SIZE {
+        w = w.div_ceil(2).max(1);
+        h = h.div_ceil(2).max(1);
+        levels.push(MipLevel { level: levels.len() as u32, width: w, height: h });
+    }
+    levels
+}
+
+/// The smallest level at least `needed` pixels wide, or the largest level
+/// when none is.
+pub fn best_level(pyramid: &Pyramid, needed: f64) -> &MipLevel {
+    pyramid
+        .levels
+        .iter()
+        .rev()
+        .find(|l| l.width as f64 >= needed)
+        .unwrap_or(&pyramid.levels[0])
+}
+
+fn tile_grid(level: &MipLevel, tile_size: u32) -> TileGrid {
+    TileGrid {
+        tile_size,
+        columns: level.width.div_ceil(tile_size),
+        rows: level.height.div_ceil(tile_size),
+    }
+}
+
+/// The built pyramid for a stored source, if there is one.
+pub fn load(app: &impl DataDir, src: &str) -> Option<Pyramid> {
+    let hash = media_protocol::media_hash(app, src).ok()?;
+    let json = fs::read_to_string(pyramid_dir(app, &hash).join("pyramid.json")).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+fn save_image(image: &DynamicImage, path: &Path, format: ImageFormat) -> Result<(), String> {
+    if format == ImageFormat::Jpeg {
+        DynamicImage::ImageRgb8(image.to_rgb8()).save_with_format(path, format)
+    } else {
+        image.save_with_format(path, format)
+    }
+    .map_err(|e| e.to_string())
+}
+
+fn output_format(has_alpha: bool) -> (&'static str, ImageFormat) {
+    if has_alpha { ("png", ImageFormat::Png) } else { ("jpg", ImageFormat::Jpeg) }
+}
+
+/// Cuts `image`, whose top edge is tile row `first_row`, into tiles.
+fn save_tile_rows(image: &DynamicImage, first_row: u32, tile_size: u32, dir: &Path, ext: &str, format: ImageFormat) -> Result<(), String> {
+    let (width, height) = image.dimensions();
+    for row in 0..height.div_ceil(tile_size) {
+        for col in 0..width.div_ceil(tile_size) {
+            let (x, y) = (col * tile_size, row * tile_size);
+            let tile = image.crop_imm(x, y, tile_size.min(width - x), tile_size.min(height - y));
+            save_image(&tile, &dir.join(format!("{}_{}.{}", col, first_row + row, ext)), format)?;
+        }
+    }
+    Ok(())
+}
+
+fn too_large() -> String {
+    "Image is too large to build a pyramid for".to_string()
+}
+
+/// Reads a PNG too large to decode whole a row at a time. Level 0 tiles are
+/// saved from bands of rows as they arrive, and the rows are box-filtered
+/// into level 1, which is returned for the rest of the build.
+fn stream_png(path: &Path, dir: &Path, tile_size: Option<u32>, max_bytes: u64) -> Result<DynamicImage, String> {
+    let file = BufReader::new(fs::File::open(path).map_err(|e| e.to_string())?);
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let (width, height) = (reader.info().width, reader.info().height);
+    if reader.info().interlaced {
+        return Err(too_large());
+    }
+    let channels = match reader.output_color_type().0 {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        png::ColorType::Indexed => return Err("Unsupported PNG color type".to_string()),
+    };
+    let (half_width, half_height) = (width.div_ceil(2), height.div_ceil(2));
+    if half_width as u64 * half_height as u64 * 4 > max_bytes {
+        return Err(too_large());
+    }
+    let (ext, format) = output_format(channels % 2 == 0);
+    let tiles_dir = dir.join("tiles").join("0");
+    if tile_size.is_some() {
+        fs::create_dir_all(&tiles_dir).map_err(|e| e.to_string())?;
+    }
+
+    // Bands are a whole tile row high, or a pair of rows without tiles
+    let band_height = tile_size.unwrap_or(2) as usize;
+    let stride = width as usize * 4;
+    let mut band = Vec::with_capacity(stride * band_height);
+    let mut half = image::RgbaImage::new(half_width, half_height);
+    let mut band_top = 0u32;
+    loop {
+        let done = match reader.next_row().map_err(|e| e.to_string())? {
+            Some(row) => {
+                for px in row.data().chunks_exact(channels) {
+                    band.extend_from_slice(&match *px {
+                        [g] => [g, g, g, 255],
+                        [g, a] => [g, g, g, a],
+                        [r, g, b] => [r, g, b, 255],
+                        [r, g, b, a] => [r, g, b, a],
+                        _ => unreachable!(),
+                    });
+                }
+                false
+            }
+            None => true,
+        };
+        let rows = (band.len() / stride) as u32;
+        if rows == 0 && done {
+            break;
+        }
+        if !done && rows < band_height as u32 {
+            continue;
+        }
+
+        for pair in (0..rows).step_by(2) {
+            let y = (band_top + pair) / 2;
+            for x in 0..half_width {
+                let mut sum = [0u32; 4];
+                let mut count = 0;
+                for dy in pair..(pair + 2).min(rows) {
+                    for dx in x * 2..(x * 2 + 2).min(width) {
+                        let at = dy as usize * stride + dx as usize * 4;
+                        for (total, value) in sum.iter_mut().zip(&band[at..at + 4]) {
+                            *total += *value as u32;
+                        }
+                        count += 1;
+                    }
+                }
+                half.put_pixel(x, y, image::Rgba(sum.map(|total| (total / count) as u8)));
+            }
+        }
+        if let Some(tile_size) = tile_size {
+            let pixels = image::RgbaImage::from_raw(width, rows, std::mem::take(&mut band)).ok_or_else(too_large)?;
+            let pixels = DynamicImage::ImageRgba8(pixels);
+            save_tile_rows(&pixels, band_top / tile_size, tile_size, &tiles_dir, ext, format)?;
+            band = pixels.into_rgba8().into_raw();
+        }
+        band.clear();
+        band_top += rows;
+        if done || band_top >= height {
+            break;
+        }
+    }
+    Ok(if channels % 2 == 0 {
+        DynamicImage::ImageRgba8(half)
+    } else {
+        DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(half).to_rgb8())
+    })
+}
+
+/// Builds the pyramid for a stored still image, or returns the one already
+/// built for the same content.
+pub fn build(app: &impl DataDir, src: &str) -> Result<Pyramid, String> {
+    build_within(app, src, MAX_DECODE_BYTES)
+}
+
+fn build_within(app: &impl DataDir, src: &str, max_bytes: u64) -> Result<Pyramid, String> {
+    if !is_still(src) {
+        return Err("Pyramids are only made for still images".to_string());
+    }
+    if let Some(pyramid) = load(app, src) {
+        return Ok(pyramid);
+    }
+    let hash = media_protocol::media_hash(app, src)?;
+    let path = database::media_path(&database::get_images_dir(app), src)?;
+    let open = || ImageReader::open(&path).and_then(|r| r.with_guessed_format()).map_err(|e| e.to_string());
+    let source_format = open()?.format();
+    let (width, height) = open()?.into_dimensions().map_err(|e| e.to_string())?;
+    if width.max(height) > MAX_SIDE {
+        return Err(too_large());
+    }
+    let tile_size = (width.max(height) > TILE_THRESHOLD).then_some(TILE_SIZE);
+    let levels = level_sizes(width, height);
+
+    // Built aside and renamed so a half-made pyramid is never served
+    let dir = pyramid_dir(app, &hash);
+    let partial = dir.with_extension(format!("{}.part", database::now_millis()));
+    fs::create_dir_all(&partial).map_err(|e| e.to_string())?;
+    let result = (|| {
+        let fits = width as u64 * height as u64 * 4 <= max_bytes;
+        let (mut current, first) = if fits {
+            let mut reader = open()?;
+            let mut limits = Limits::default();
+            limits.max_image_width = Some(MAX_SIDE);
+            limits.max_image_height = Some(MAX_SIDE);
+            limits.max_alloc = Some(max_bytes);
+            reader.limits(limits);
+            (reader.decode().map_err(|e| e.to_string())?, 0)
+        } else if source_format == Some(ImageFormat::Png) {
+            (stream_png(&path, &partial, tile_size, max_bytes)?, 1)
+        } else {
+            return Err(too_large());
+        };
+        let (ext, format) = output_format(current.color().has_alpha());
+        for level in &levels[first..] {
+            if level.level > first as u32 {
+                current = current.resize_exact(level.width, level.height, image::imageops::FilterType::Triangle);
+            }
+            if level.level > 0 {
+                save_image(&current, &partial.join(format!("{}.{}", level.level, ext)), format)?;
+            }
+            if let Some(tile_size) = tile_size {
+                let tiles_dir = partial.join("tiles").join(level.level.to_string());
+                fs::create_dir_all(&tiles_dir).map_err(|e| e.to_string())?;
+                save_tile_rows(&current, 0, tile_size, &tiles_dir, ext, format)?;
+            }
+        }
+        let pyramid = Pyramid { format: ext.to_string(), levels: levels.clone(), tile_size };
+        let json = serde_json::to_string_pretty(&pyramid).map_err(|e| e.to_string())?;
+        fs::write(partial.join("pyramid.json"), json).map_err(|e| e.to_string())?;
+        Ok(pyramid)
+    })();
+    if let Ok(pyramid) = &result {
+        if !dir.exists() && fs::rename(&partial, &dir).is_ok() {
+            return Ok(pyramid.clone());
+        }
+    }
+    let _ = fs::remove_dir_all(&partial);
+    result?;
+    // Another build of the same content finished first
+    load(app, src).ok_or_else(|| "Failed to store the pyramid".to_string())
+}
+
+/// Deletes the pyramid built for some content.
+pub fn remove(app: &impl DataDir, hash: &str) {
+    let _ = fs::remove_dir_all(pyramid_dir(app, hash));
+}
+
+/// File for one level of a source; level 0 is the original.
+pub fn level_path(app: &impl DataDir, src: &str, level: u32) -> Result<PathBuf, String> {
+    let original = database::media_path(&database::get_images_dir(app), src)?;
+    if level == 0 {
+        return Ok(original);
+    }
+    let pyramid = load(app, src).ok_or("No pyramid for this media")?;
+    let hash = media_protocol::media_hash(app, src)?;
+    Ok(pyramid_dir(app, &hash).join(format!("{}.{}", level, pyramid.format)))
+}
+
+/// File for one tile of a level.
+pub fn tile_path(app: &impl DataDir, src: &str, level: u32, col: u32, row: u32) -> Result<PathBuf, String> {
+    database::media_path(&database::get_images_dir(app), src)?;
+    let pyramid = load(app, src).ok_or("No pyramid for this media")?;
+    let hash = media_protocol::media_hash(app, src)?;
+    Ok(pyramid_dir(app, &hash)
+        .join("tiles")
+        .join(level.to_string())
+        .join(format!("{}_{}.{}", col, row, pyramid.format)))
+}
+
+/// The best level for showing a board layer at `scale` screen pixels per
+/// board unit, device pixel ratio included.
+pub fn resolve(app: &impl DataDir, board_id: u64, layer_id: f64, scale: f64) -> Result<LayerResolution, String> {
+    let board = database::load_board(app, board_id)?;
+    let layer = board.layers.iter().find(|l| l.id == layer_id).ok_or("Layer not found")?;
+    let needed = layer.width * scale;
+
+    match is_still(&layer.src).then(|| load(app, &layer.src)).flatten() {
+        Some(pyramid) => {
+            let level = best_level(&pyramid, needed).clone();
+            let path = match level.level {
+                0 => format!("media/{}", layer.src),
+                n => format!("mip/{}/{}", n, layer.src),
+            };
+            Ok(LayerResolution {
+                src: layer.src.clone(),
+                level: level.level,
+                width: level.width,
+                height: level.height,
+                path,
+                ready: true,
+                tiles: pyramid.tile_size.map(|size| tile_grid(&level, size)),
+            })
+        }
+        None => Ok(LayerResolution {
+            src: layer.src.clone(),
+            level: 0,
+            width: layer.width.round() as u32,
+            height: layer.height.round() as u32,
+            path: format!("media/{}", layer.src),
+            ready: false,
+            tiles: None,
+        }),
+    }
+}
+
+/// Queues pyramid builds for the still images among `srcs` and returns how
+/// many were queued. Each one built emits `pyramid-ready`.
+pub fn queue(app: &AppHandle, srcs: impl IntoIterator<Item = String>) -> usize {
+    let state = app.state::<PyramidState>();
+    let mut jobs = state.jobs.lock().unwrap();
+    let mut queued = 0;
+    for src in srcs {
+        if is_still(&src) && jobs.pending.insert(src.clone()) {
+            jobs.queue.push_back(src);
+            queued += 1;
+        }
+    }
+    if queued > 0 && !jobs.running {
+        jobs.running = true;
+        let app = app.clone();
+        std::thread::spawn(move || run_worker(&app));
+    }
+    queued
+}
+
+/// Builds queued pyramids one at a time, so decoding huge originals never
+/// competes with the canvas for more than one core.
+fn run_worker(app: &AppHandle) {
+    let state = app.state::<PyramidState>();
+    loop {
+        let Some(src) = ({
+            let mut jobs = state.jobs.lock().unwrap();
+            let next = jobs.queue.pop_front();
+            jobs.running = next.is_some();
+            next
+        }) else {
+            return;
+        };
+
+        let built = load(app, &src).is_none().then(|| build(app, &src));
+        match built {
+            Some(Ok(pyramid)) => {
+                let _ = app.emit("pyramid-ready", PyramidReady { src: src.clone(), pyramid });
+            }
+            Some(Err(e)) => log::warn!("Failed to build the pyramid for {}: {}", src, e),
+            None => {}
+        }
+        state.jobs.lock().unwrap().pending.remove(&src);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn halves_down_to_the_smallest_level() {
+        let levels = level_sizes(6000, 4001);
+        let sizes: Vec<(u32, u32)> = levels.iter().map(|l| (l.width, l.height)).collect();
+        assert_eq!(
+            sizes,
+            vec![(6000, 4001), (3000, 2001), (1500, 1001), (750, 501), (375, 251), (188, 126)]
+        );
+        assert_eq!(level_sizes(200, 100).len(), 1);
+    }
+
+    #[test]
+    fn picks_the_smallest_level_that_covers_the_screen() {
+        let pyramid = Pyramid { format: "jpg".to_string(), levels: level_sizes(4000, 3000), tile_size: None };
+        assert_eq!(best_level(&pyramid, 900.0).width, 1000);
+        assert_eq!(best_level(&pyramid, 1000.0).width, 1000);
+        assert_eq!(best_level(&pyramid, 10.0).level, 4);
+        assert_eq!(best_level(&pyramid, 9000.0).level, 0);
+    }
+
+    #[test]
+    fn builds_levels_and_tiles() {
+        let dir = std::env::temp_dir().join(format!("eyedea-pyramid-{}", database::now_millis()));
+        database::init_storage(&dir).unwrap();
+        let image = image::RgbImage::from_pixel(TILE_THRESHOLD + 8, 600, image::Rgb([200, 40, 40]));
+        image.save(dir.join("images").join("wide.png")).unwrap();
+
+        let pyramid = build(&dir, "wide.png").unwrap();
+        assert_eq!(pyramid.format, "jpg");
+        assert_eq!(pyramid.tile_size, Some(TILE_SIZE));
+        assert_eq!(pyramid.levels[1].width, (TILE_THRESHOLD + 8) / 2);
+        assert!(level_path(&dir, "wide.png", 1).unwrap().is_file());
+        assert!(tile_path(&dir, "wide.png", 0, 16, 1).unwrap().is_file());
+        assert!(!tile_path(&dir, "wide.png", 0, 17, 0).unwrap().exists());
+        assert_eq!(build(&dir, "wide.png").unwrap(), pyramid);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn streams_pngs_past_the_decode_limit() {
+        let dir = std::env::temp_dir().join(format!("eyedea-pyramid-stream-{}", database::now_millis()));
+        database::init_storage(&dir).unwrap();
+        let image = image::RgbaImage::from_pixel(TILE_THRESHOLD + 8, 601, image::Rgba([200, 40, 40, 128]));
+        image.save(dir.join("images").join("wide.png")).unwrap();
+
+        // Too big to decode whole, small enough once halved
+        let pyramid = build_within(&dir, "wide.png", 8 << 20).unwrap();
+        assert_eq!(pyramid.format, "png");
+        assert_eq!(pyramid.levels[1].width, (TILE_THRESHOLD + 8) / 2);
+        assert_eq!(pyramid.levels[1].height, 301);
+        let half = image::open(level_path(&dir, "wide.png", 1).unwrap()).unwrap().to_rgba8();
+        assert_eq!(half.dimensions(), (4100, 301));
+        assert_eq!(*half.get_pixel(4099, 300), image::Rgba([200, 40, 40, 128]));
+        let tile = image::open(tile_path(&dir, "wide.png", 0, 16, 1).unwrap()).unwrap();
+        assert_eq!(tile.dimensions(), (8, 89));
+        assert!(tile_path(&dir, "wide.png", 1, 8, 0).unwrap().is_file());
+        assert!(!tile_path(&dir, "wide.png", 0, 17, 0).unwrap().exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_images_past_the_limits() {
+        let dir = std::env::temp_dir().join(format!("eyedea-pyramid-limits-{}", database::now_millis()));
+        database::init_storage(&dir).unwrap();
+        let images = dir.join("images");
+        image::RgbImage::new(MAX_SIDE + 1, 1).save(images.join("long.png")).unwrap();
+        image::RgbImage::new(1000, 1000).save(images.join("big.jpg")).unwrap();
+        image::RgbImage::new(1000, 1000).save(images.join("big.png")).unwrap();
+
+        assert!(build(&dir, "long.png").is_err());
+        // Only PNGs can be read a row at a time
+        assert!(build_within(&dir, "big.jpg", 1 << 20).is_err());
+        // Level 1 must still fit
+        assert!(build_within(&dir, "big.png", 100_000).is_err());
+        assert!(build_within(&dir, "big.png", 1 << 20).is_ok());
+        assert!(fs::read_dir(dir.join("pyramids")).map_or(true, |entries| entries.count() == 1));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
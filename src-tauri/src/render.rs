@@ -0,0 +1,797 @@
+use crate::database::{self, Board, Layer};
+use crate::drawing::{self, CanvasObject, Rect, Stroke};
+use crate::fonts;
+use crate::vault;
+use ab_glyph::{Font, OutlineCurve};
+use base64::Engine;
+use image::codecs::jpeg::JpegEncoder;
+use image::{imageops, AnimationDecoder, DynamicImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::path::Path;
+use tiny_skia::{
+    BlendMode, Color, FillRule, FilterQuality, LineCap, LineJoin, Paint, PathBuilder, Pixmap, PixmapPaint,
+    PremultipliedColorU8, Stroke as SkStroke, Transform,
+};
+
+/// Largest image we are willing to allocate (16384 x 16384).
+const MAX_PIXELS: u64 = 16384 * 16384;
+
+/// CSS pixels per inch; one board unit is one CSS pixel at 100% zoom.
+const CSS_DPI: f64 = 96.0;
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderOptions {
+    /// Area of the board to render. Defaults to the bounds of all content.
+    #[serde(default)]
+    pub region: Option<Rect>,
+    /// Output pixels per board unit. Ignored when `dpi` is set.
+    #[serde(default)]
+    pub scale: Option<f64>,
+    /// Output resolution, treating board units as CSS pixels (96 per inch).
+    #[serde(default)]
+    pub dpi: Option<f64>,
+    /// Margin added around the content bounds when no region is given.
+    #[serde(default)]
+    pub padding: Option<f64>,
+    /// Leave the background transparent instead of filling `bgColor`.
+    #[serde(default)]
+    pub transparent: bool,
+}
+
+impl RenderOptions {
+    pub fn pixel_scale(&self) -> f64 {
+        match (self.dpi, self.scale) {
+            (Some(dpi), _) if dpi > 0.0 => dpi / CSS_DPI,
+            (_, Some(scale)) if scale > 0.0 => scale,
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderedImage {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RasterFormat {
+    Png,
+    Jpeg,
+}
+
+impl RasterFormat {
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        match path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .as_deref()
+        {
+            Some("png") => Ok(RasterFormat::Png),
+            Some("jpg") | Some("jpeg") => Ok(RasterFormat::Jpeg),
+            _ => Err("Export path must end in .png, .jpg or .jpeg".to_string()),
+        }
+    }
+}
+
+/// Bounds of everything visible on the board: layers, objects and strokes.
+pub fn board_bounds(board: &Board) -> Option<Rect> {
+    let layers = board
+        .layers
+        .iter()
+        .filter(|l| l.visible)
+        .map(|l| layer_rect(l).rotated_bounds(l.rotation.unwrap_or(0.0)));
+    let objects = drawing::parse_objects(&board.objects)
+        .into_iter()
+        .filter(|o| o.is_visible())
+        .map(|o| o.bounds());
+    let strokes = drawing::parse_strokes(&board.strokes)
+        .into_iter()
+        .filter(|s| s.tool != "eraser")
+        .filter_map(|s| s.bounds());
+
+    layers
+        .chain(objects)
+        .chain(strokes)
+        .reduce(|a, b| a.union(&b))
+}
+
+pub enum BoardItem<'a> {
+    Layer(&'a Layer),
+    Object(Box<CanvasObject>),
+}
+
+/// Visible layers and objects in the order the canvas paints them.
+pub fn board_items(board: &Board) -> Vec<BoardItem<'_>> {
+    let objects = drawing::parse_objects(&board.objects);
+    let mut items: Vec<(f64, BoardItem)> = board
+        .layers
+        .iter()
+        .filter(|l| l.visible)
+        .map(|l| (l.z_index, BoardItem::Layer(l)))
+        .chain(
+            objects
+                .into_iter()
+                .filter(|o| o.is_visible())
+                .map(|o| (o.z_index.unwrap_or(0.0), BoardItem::Object(Box::new(o)))),
+        )
+        .collect();
+    items.sort_by(|a, b| a.0.total_cmp(&b.0));
+    items.into_iter().map(|(_, item)| item).collect()
+}
+
+fn layer_rect(layer: &Layer) -> Rect {
+    Rect { x: layer.x, y: layer.y, width: layer.width, height: layer.height }
+}
+
+/// The explicit region, or the content bounds plus padding.
+pub fn export_region(board: &Board, region: Option<Rect>, padding: Option<f64>) -> Result<Rect, String> {
+    let region = match region {
+        Some(region) => region,
+        None => board_bounds(board)
+            .map(|b| b.inflate(padding.unwrap_or(0.0)))
+            .ok_or("Board is empty")?,
+    };
+    if region.width <= 0.0 || region.height <= 0.0 {
+        return Err("Render region is empty".to_string());
+    }
+    Ok(region)
+}
+
+/// Renders a board into an RGBA image without touching the GPU or webview.
+/// Media is read from `images_dir`; layers whose source can't be decoded
+/// (videos, remote URLs, missing files) are drawn as placeholders.
+pub fn render_board(board: &Board, images_dir: &Path, options: &RenderOptions) -> Result<RgbaImage, String> {
+    let region = export_region(board, options.region, options.padding)?;
+    let scale = options.pixel_scale();
+    let width = (region.width * scale).ceil().max(1.0) as u32;
+    let height = (region.height * scale).ceil().max(1.0) as u32;
+    if width as u64 * height as u64 > MAX_PIXELS {
+        return Err(format!(
+            "Requested image is {}x{} pixels, which exceeds the {} megapixel limit",
+            width,
+            height,
+            MAX_PIXELS / 1_000_000
+        ));
+    }
+
+    let mut canvas = Pixmap::new(width, height).ok_or("Failed to allocate image")?;
+    if !options.transparent {
+        let [r, g, b, a] = drawing::parse_color(&board.bg_color).unwrap_or([255, 255, 255, 255]);
+        canvas.fill(Color::from_rgba8(r, g, b, a));
+    }
+
+    let base = Transform::from_scale(scale as f32, scale as f32)
+        .pre_translate(-region.x as f32, -region.y as f32);
+
+    for item in board_items(board) {
+        match item {
+            BoardItem::Layer(layer) => draw_layer(&mut canvas, layer, images_dir, base, scale),
+            BoardItem::Object(obj) => draw_object(&mut canvas, &obj, base)?,
+        }
+    }
+
+    // Strokes live on their own layer above everything else, so pixel
+    // erasers only cut into other strokes.
+    let strokes = drawing::parse_strokes(&board.strokes);
+    if !strokes.is_empty() {
+        let mut ink = Pixmap::new(width, height).ok_or("Failed to allocate image")?;
+        for stroke in &strokes {
+            draw_stroke(&mut ink, stroke, base);
+        }
+        canvas.draw_pixmap(0, 0, ink.as_ref(), &PixmapPaint::default(), Transform::identity(), None);
+    }
+
+    Ok(pixmap_to_image(&canvas))
+}
+
+/// Renders a board and writes it to `path` as PNG or JPEG, picked by extension.
+pub fn export_board_image(
+    board: &Board,
+    images_dir: &Path,
+    options: &RenderOptions,
+    path: &Path,
+    quality: Option<u8>,
+) -> Result<RenderedImage, String> {
+    let format = RasterFormat::from_path(path)?;
+    let image = render_board(board, images_dir, options)?;
+    let bytes = encode_image(&image, format, quality.unwrap_or(90))?;
+    std::fs::write(path, bytes).map_err(|e| format!("Failed to write image: {}", e))?;
+    Ok(RenderedImage {
+        path: path.to_string_lossy().to_string(),
+        width: image.width(),
+        height: image.height(),
+    })
+}
+
+pub fn encode_image(image: &RgbaImage, format: RasterFormat, quality: u8) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    match format {
+        RasterFormat::Png => image
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .map_err(|e| format!("Failed to encode PNG: {}", e))?,
+        RasterFormat::Jpeg => {
+            // JPEG has no alpha, so flatten onto white like a browser would
+            let mut rgb = image::RgbImage::new(image.width(), image.height());
+            for (src, dst) in image.pixels().zip(rgb.pixels_mut()) {
+                let a = src[3] as u32;
+                for c in 0..3 {
+                    dst[c] = ((src[c] as u32 * a + 255 * (255 - a)) / 255) as u8;
+                }
+            }
+            JpegEncoder::new_with_quality(&mut bytes, quality.clamp(1, 100))
+                .encode_image(&rgb)
+                .map_err(|e| format!("Failed to encode JPEG: {}", e))?;
+        }
+    }
+    Ok(bytes)
+}
+
+/// Decodes a layer's media from a data URL or a file in the images dir.
+/// GIFs are decoded at the frame the layer was saved on.
+pub fn load_layer_image(layer: &Layer, images_dir: &Path) -> Option<DynamicImage> {
+    let bytes = layer_source_bytes(layer, images_dir)?;
+    let frame = layer.gif_current_frame.unwrap_or(0) as usize;
+    if frame > 0 && image::guess_format(&bytes).ok() == Some(image::ImageFormat::Gif) {
+        let decoder = image::codecs::gif::GifDecoder::new(Cursor::new(&bytes)).ok()?;
+        if let Some(Ok(frame)) = decoder.into_frames().nth(frame) {
+            return Some(DynamicImage::ImageRgba8(frame.into_buffer()));
+        }
+    }
+
+    image::load_from_memory(&bytes).ok()
+}
+
+/// Raw bytes of a layer's still-image source, or `None` for videos and
+/// remote URLs.
+pub fn layer_source_bytes(layer: &Layer, images_dir: &Path) -> Option<Vec<u8>> {
+    if layer.media_type.as_deref() == Some("video") {
+        return None;
+    }
+
+    if let Some(rest) = layer.src.strip_prefix("data:") {
+        let (_, data) = rest.split_once(',')?;
+        base64::engine::general_purpose::STANDARD.decode(data).ok()
+    } else if layer.src.contains("://") {
+        None
+    } else {
+        std::fs::read(database::media_path(images_dir, &layer.src).ok()?)
+            .ok()
+            .or_else(|| vault::media_bytes(images_dir, &layer.src))
+    }
+}
+
+/// Whether any of the layer's filters would change its pixels.
+pub fn has_filters(layer: &Layer) -> bool {
+    layer.brightness.is_some_and(|v| v != 100.0)
+        || layer.contrast.is_some_and(|v| v != 100.0)
+        || layer.saturation.is_some_and(|v| v != 100.0)
+        || layer.hue.is_some_and(|v| v != 0.0)
+        || layer.blur.is_some_and(|v| v > 0.0)
+        || layer.grayscale == Some(true)
+        || layer.invert == Some(true)
+}
+
+/// Applies the layer's CSS-style filters in the order the editor builds its
+/// filter string. `blur_scale` converts the blur radius to the (possibly
+/// downsampled) image's pixels.
+pub fn apply_filters(image: &mut RgbaImage, layer: &Layer, blur_scale: f64) {
+    let brightness = layer.brightness.filter(|v| *v != 100.0).map(|v| v / 100.0);
+    let contrast = layer.contrast.filter(|v| *v != 100.0).map(|v| v / 100.0);
+    let saturation = layer.saturation.filter(|v| *v != 100.0).map(|v| v / 100.0);
+    let hue = layer.hue.filter(|v| *v != 0.0);
+    let grayscale = layer.grayscale == Some(true);
+    let invert = layer.invert == Some(true);
+
+    let mut matrices = Vec::new();
+    if let Some(s) = saturation {
+        matrices.push(saturate_matrix(s));
+    }
+    if let Some(deg) = hue {
+        matrices.push(hue_rotate_matrix(deg));
+    }
+
+    if brightness.is_some() || contrast.is_some() || !matrices.is_empty() {
+        for pixel in image.pixels_mut() {
+            let mut rgb = [pixel[0] as f64 / 255.0, pixel[1] as f64 / 255.0, pixel[2] as f64 / 255.0];
+            if let Some(b) = brightness {
+                rgb = rgb.map(|c| (c * b).clamp(0.0, 1.0));
+            }
+            if let Some(k) = contrast {
+                rgb = rgb.map(|c| ((c - 0.5) * k + 0.5).clamp(0.0, 1.0));
+            }
+            for m in &matrices {
+                rgb = apply_matrix(m, rgb);
+            }
+            for c in 0..3 {
+                pixel[c] = (rgb[c] * 255.0).round() as u8;
+            }
+        }
+    }
+
+    if let Some(blur) = layer.blur.filter(|v| *v > 0.0) {
+        *image = imageops::blur(image, (blur * blur_scale) as f32);
+    }
+
+    if grayscale || invert {
+        let gray = saturate_matrix(0.0);
+        for pixel in image.pixels_mut() {
+            let mut rgb = [pixel[0] as f64 / 255.0, pixel[1] as f64 / 255.0, pixel[2] as f64 / 255.0];
+            if grayscale {
+                rgb = apply_matrix(&gray, rgb);
+            }
+            if invert {
+                rgb = rgb.map(|c| 1.0 - c);
+            }
+            for c in 0..3 {
+                pixel[c] = (rgb[c] * 255.0).round() as u8;
+            }
+        }
+    }
+}
+
+fn saturate_matrix(s: f64) -> [[f64; 3]; 3] {
+    [
+        [0.213 + 0.787 * s, 0.715 - 0.715 * s, 0.072 - 0.072 * s],
+        [0.213 - 0.213 * s, 0.715 + 0.285 * s, 0.072 - 0.072 * s],
+        [0.213 - 0.213 * s, 0.715 - 0.715 * s, 0.072 + 0.928 * s],
+    ]
+}
+
+fn hue_rotate_matrix(degrees: f64) -> [[f64; 3]; 3] {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    [
+        [0.213 + cos * 0.787 - sin * 0.213, 0.715 - cos * 0.715 - sin * 0.715, 0.072 - cos * 0.072 + sin * 0.928],
+        [0.213 - cos * 0.213 + sin * 0.143, 0.715 + cos * 0.285 + sin * 0.140, 0.072 - cos * 0.072 - sin * 0.283],
+        [0.213 - cos * 0.213 - sin * 0.787, 0.715 - cos * 0.715 + sin * 0.715, 0.072 + cos * 0.928 + sin * 0.072],
+    ]
+}
+
+fn apply_matrix(m: &[[f64; 3]; 3], rgb: [f64; 3]) -> [f64; 3] {
+    [0, 1, 2].map(|row| (m[row][0] * rgb[0] + m[row][1] * rgb[1] + m[row][2] * rgb[2]).clamp(0.0, 1.0))
+}
+
+/// Transform that places a layer's unit box, applying mirror then rotation
+/// around the layer center like the canvas does.
+pub fn layer_transform(layer: &Layer, base: Transform) -> Transform {
+    let (x, y, w, h) = (layer.x as f32, layer.y as f32, layer.width as f32, layer.height as f32);
+    let mut t = base;
+    if layer.mirror == Some(true) {
+        t = t.pre_translate(x + w, y).pre_scale(-1.0, 1.0).pre_translate(-x, -y);
+    }
+    if let Some(rotation) = layer.rotation.filter(|r| *r != 0.0) {
+        t = t.pre_rotate_at(rotation as f32, x + w / 2.0, y + h / 2.0);
+    }
+    t.pre_translate(x, y)
+}
+
+fn draw_layer(canvas: &mut Pixmap, layer: &Layer, images_dir: &Path, base: Transform, scale: f64) {
+    if layer.width <= 0.0 || layer.height <= 0.0 {
+        return;
+    }
+    let opacity = layer.opacity.map(|o| o / 100.0).unwrap_or(1.0).clamp(0.0, 1.0) as f32;
+    let transform = layer_transform(layer, base);
+
+    let Some(source) = load_layer_image(layer, images_dir) else {
+        draw_placeholder(canvas, layer, transform, opacity);
+        return;
+    };
+
+    // Downsample big originals before filtering so blur and color work stay
+    // proportional to the output size.
+    let mut image = source.to_rgba8();
+    let target_w = (layer.width * scale).ceil().max(1.0) as u32;
+    let target_h = (layer.height * scale).ceil().max(1.0) as u32;
+    let original_w = image.width();
+    if image.width() > target_w * 2 && image.height() > target_h * 2 {
+        image = imageops::resize(&image, target_w, target_h, imageops::FilterType::Triangle);
+    }
+    let blur_scale = image.width() as f64 / original_w.max(1) as f64;
+    apply_filters(&mut image, layer, blur_scale);
+
+    let Some(pixmap) = image_to_pixmap(&image) else {
+        return;
+    };
+    let t = transform.pre_scale(
+        (layer.width / image.width() as f64) as f32,
+        (layer.height / image.height() as f64) as f32,
+    );
+    let paint = PixmapPaint {
+        opacity,
+        blend_mode: BlendMode::SourceOver,
+        quality: FilterQuality::Bicubic,
+    };
+    canvas.draw_pixmap(0, 0, pixmap.as_ref(), &paint, t, None);
+}
+
+/// Dark box with a play triangle, standing in for media we can't decode.
+fn draw_placeholder(canvas: &mut Pixmap, layer: &Layer, transform: Transform, opacity: f32) {
+    let (w, h) = (layer.width as f32, layer.height as f32);
+    let mut paint = Paint { anti_alias: true, ..Paint::default() };
+    paint.set_color(Color::from_rgba(0.12, 0.12, 0.12, opacity).unwrap_or(Color::BLACK));
+    if let Some(rect) = tiny_skia::Rect::from_xywh(0.0, 0.0, w, h) {
+        canvas.fill_rect(rect, &paint, transform, None);
+    }
+
+    if layer.media_type.as_deref() == Some("video") {
+        let (cx, cy, r) = (w / 2.0, h / 2.0, w.min(h) * 0.12);
+        let mut pb = PathBuilder::new();
+        pb.move_to(cx - r * 0.35, cy - r * 0.5);
+        pb.line_to(cx - r * 0.35, cy + r * 0.5);
+        pb.line_to(cx + r * 0.55, cy);
+        pb.close();
+        if let Some(path) = pb.finish() {
+            paint.set_color(Color::from_rgba(1.0, 1.0, 1.0, 0.9 * opacity).unwrap_or(Color::WHITE));
+            canvas.fill_path(&path, &paint, FillRule::Winding, transform, None);
+        }
+    }
+}
+
+fn color_paint(color: &str, alpha: f64) -> Paint<'static> {
+    let [r, g, b, a] = drawing::parse_color(color).unwrap_or([0, 0, 0, 255]);
+    let mut paint = Paint { anti_alias: true, ..Paint::default() };
+    paint.set_color_rgba8(r, g, b, (a as f64 * alpha.clamp(0.0, 1.0)).round() as u8);
+    paint
+}
+
+fn round_stroke(width: f64) -> SkStroke {
+    SkStroke {
+        width: width as f32,
+        line_cap: LineCap::Round,
+        line_join: LineJoin::Round,
+        ..SkStroke::default()
+    }
+}
+
+/// Builds the smoothed stroke path the canvas draws: quadratic curves through
+/// the midpoints between recorded points.
+pub fn stroke_path(stroke: &Stroke) -> Option<tiny_skia::Path> {
+    let points = &stroke.points;
+    if points.len() < 2 {
+        return None;
+    }
+    let mut pb = PathBuilder::new();
+    pb.move_to(points[0].x as f32, points[0].y as f32);
+    for i in 1..points.len() - 1 {
+        let xc = (points[i].x + points[i + 1].x) / 2.0;
+        let yc = (points[i].y + points[i + 1].y) / 2.0;
+        pb.quad_to(points[i].x as f32, points[i].y as f32, xc as f32, yc as f32);
+    }
+    let last = &points[points.len() - 1];
+    pb.line_to(last.x as f32, last.y as f32);
+    pb.finish()
+}
+
+fn draw_stroke(ink: &mut Pixmap, stroke: &Stroke, base: Transform) {
+    if stroke.is_stroke_eraser() {
+        return;
+    }
+    let Some(path) = stroke_path(stroke) else {
+        return;
+    };
+
+    let mut paint = if stroke.is_pixel_eraser() {
+        let mut paint = color_paint("#000000", stroke.opacity.unwrap_or(1.0));
+        paint.blend_mode = BlendMode::DestinationOut;
+        paint
+    } else {
+        color_paint(stroke.color.as_deref().unwrap_or("#000000"), stroke.alpha())
+    };
+    paint.anti_alias = true;
+    ink.stroke_path(&path, &paint, &round_stroke(stroke.width()), base, None);
+}
+
+pub fn object_transform(obj: &CanvasObject, base: Transform) -> Transform {
+    match obj.rotation.filter(|r| *r != 0.0) {
+        Some(rotation) => {
+            let (cx, cy) = obj.rotation_center();
+            base.pre_rotate_at(rotation as f32, cx as f32, cy as f32)
+        }
+        None => base,
+    }
+}
+
+fn draw_object(canvas: &mut Pixmap, obj: &CanvasObject, base: Transform) -> Result<(), String> {
+    let transform = object_transform(obj, base);
+    match obj.kind.as_str() {
+        "shape" => draw_shape(canvas, obj, transform),
+        "text" => draw_text(canvas, obj, transform)?,
+        "colorPalette" => draw_palette(canvas, obj, transform),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Outline of a shape object in board coordinates, plus whether it should be
+/// filled (lines and arrows are stroke-only).
+pub fn shape_path(obj: &CanvasObject) -> Option<(tiny_skia::Path, bool)> {
+    let (x, y, w, h) = (obj.x as f32, obj.y as f32, obj.width as f32, obj.height as f32);
+    let mut pb = PathBuilder::new();
+    let filled = match obj.shape_type.as_deref().unwrap_or("square") {
+        "circle" => {
+            pb.push_circle(x + w / 2.0, y + h / 2.0, w.min(h) / 2.0);
+            true
+        }
+        "line" | "arrow" => {
+            let (x2, y2) = obj.end_point();
+            let (x2, y2) = (x2 as f32, y2 as f32);
+            pb.move_to(x, y);
+            pb.line_to(x2, y2);
+            if obj.shape_type.as_deref() == Some("arrow") {
+                let size = (obj.stroke_width() * 3.0).max(10.0) as f32;
+                let angle = (y2 - y).atan2(x2 - x);
+                let spread = std::f32::consts::PI / 6.0;
+                pb.move_to(x2, y2);
+                pb.line_to(x2 - size * (angle - spread).cos(), y2 - size * (angle - spread).sin());
+                pb.move_to(x2, y2);
+                pb.line_to(x2 - size * (angle + spread).cos(), y2 - size * (angle + spread).sin());
+            }
+            false
+        }
+        _ => {
+            let radius = obj.corner_radius.unwrap_or(0.0).min(obj.width / 2.0).min(obj.height / 2.0) as f32;
+            if radius > 0.0 {
+                // Cubic approximation of the quarter circles drawn by arcTo
+                let k = radius * 0.552_284_8;
+                pb.move_to(x + radius, y);
+                pb.line_to(x + w - radius, y);
+                pb.cubic_to(x + w - radius + k, y, x + w, y + radius - k, x + w, y + radius);
+                pb.line_to(x + w, y + h - radius);
+                pb.cubic_to(x + w, y + h - radius + k, x + w - radius + k, y + h, x + w - radius, y + h);
+                pb.line_to(x + radius, y + h);
+                pb.cubic_to(x + radius - k, y + h, x, y + h - radius + k, x, y + h - radius);
+                pb.line_to(x, y + radius);
+                pb.cubic_to(x, y + radius - k, x + radius - k, y, x + radius, y);
+                pb.close();
+            } else {
+                pb.push_rect(tiny_skia::Rect::from_xywh(x, y, w, h)?);
+            }
+            true
+        }
+    };
+    pb.finish().map(|path| (path, filled))
+}
+
+fn draw_shape(canvas: &mut Pixmap, obj: &CanvasObject, transform: Transform) {
+    let Some((path, filled)) = shape_path(obj) else {
+        return;
+    };
+    if filled {
+        let paint = color_paint(obj.fill_color.as_deref().unwrap_or("#3b82f6"), 1.0);
+        canvas.fill_path(&path, &paint, FillRule::Winding, transform, None);
+    }
+    if !filled || obj.draws_stroke() {
+        let paint = color_paint(obj.stroke_color.as_deref().unwrap_or("#000000"), 1.0);
+        let stroke = SkStroke {
+            width: obj.stroke_width() as f32,
+            ..SkStroke::default()
+        };
+        canvas.stroke_path(&path, &paint, &stroke, transform, None);
+    }
+}
+
+fn draw_text(canvas: &mut Pixmap, obj: &CanvasObject, transform: Transform) -> Result<(), String> {
+    let clip = tiny_skia::Rect::from_xywh(obj.x as f32, obj.y as f32, obj.width as f32, obj.height as f32)
+        .and_then(|rect| {
+            let mut mask = tiny_skia::Mask::new(canvas.width(), canvas.height())?;
+            mask.fill_path(&PathBuilder::from_rect(rect), FillRule::Winding, true, transform);
+            Some(mask)
+        });
+
+    for run in fonts::layout_text(obj)? {
+        let font = run.face.font()?;
+        let size = run.style.size();
+        let units = fonts::units_scale(&font, size) as f32;
+        let paint = color_paint(run.style.color(), 1.0);
+
+        let mut pb = PathBuilder::new();
+        let mut pen = run.x as f32;
+        let mut previous = None;
+        for c in run.text.chars() {
+            let id = font.glyph_id(c);
+            if let Some(prev) = previous {
+                pen += font.kern_unscaled(prev, id) * units;
+            }
+            if let Some(outline) = font.outline(id) {
+                let map = |p: ab_glyph::Point| (pen + p.x * units, run.baseline as f32 - p.y * units);
+                let mut last = None;
+                for curve in &outline.curves {
+                    let (start, end) = match curve {
+                        OutlineCurve::Line(a, b) => (*a, *b),
+                        OutlineCurve::Quad(a, _, c) => (*a, *c),
+                        OutlineCurve::Cubic(a, _, _, d) => (*a, *d),
+                    };
+                    if last != Some(start) {
+                        pb.close();
+                        let (sx, sy) = map(start);
+                        pb.move_to(sx, sy);
+                    }
+                    match curve {
+                        OutlineCurve::Line(_, b) => {
+                            let (bx, by) = map(*b);
+                            pb.line_to(bx, by);
+                        }
+                        OutlineCurve::Quad(_, b, c) => {
+                            let ((bx, by), (cx, cy)) = (map(*b), map(*c));
+                            pb.quad_to(bx, by, cx, cy);
+                        }
+                        OutlineCurve::Cubic(_, b, c, d) => {
+                            let ((bx, by), (cx, cy), (dx, dy)) = (map(*b), map(*c), map(*d));
+                            pb.cubic_to(bx, by, cx, cy, dx, dy);
+                        }
+                    }
+                    last = Some(end);
+                }
+                pb.close();
+            }
+            pen += font.h_advance_unscaled(id) * units;
+            previous = Some(id);
+        }
+
+        if let Some(path) = pb.finish() {
+            canvas.fill_path(&path, &paint, FillRule::Winding, transform, clip.as_ref());
+        }
+
+        let decoration = match run.style.text_decoration.as_deref() {
+            Some("underline") => Some(run.baseline + size * 0.1),
+            Some("line-through") => Some(run.baseline - size * 0.3),
+            _ => None,
+        };
+        if let Some(line_y) = decoration {
+            let mut pb = PathBuilder::new();
+            pb.move_to(run.x as f32, line_y as f32);
+            pb.line_to((run.x + run.width) as f32, line_y as f32);
+            if let Some(path) = pb.finish() {
+                let stroke = SkStroke { width: 1.0, ..SkStroke::default() };
+                canvas.stroke_path(&path, &paint, &stroke, transform, clip.as_ref());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn draw_palette(canvas: &mut Pixmap, obj: &CanvasObject, transform: Transform) {
+    let cell = obj.cell_size.unwrap_or(60.0) as f32;
+    let cols = obj.grid_cols.unwrap_or(1).max(1) as usize;
+    let colors = obj.colors.clone().unwrap_or_default();
+    let wide = obj.has_wide_cell == Some(true) && !colors.is_empty();
+    let regular = if wide { colors.len() - 1 } else { colors.len() };
+    let rows = obj.grid_rows.unwrap_or(1) as usize;
+
+    for (i, color) in colors.iter().take(regular.min(rows * cols)).enumerate() {
+        let x = obj.x as f32 + (i % cols) as f32 * cell;
+        let y = obj.y as f32 + (i / cols) as f32 * cell;
+        if let Some(rect) = tiny_skia::Rect::from_xywh(x, y, cell, cell) {
+            canvas.fill_rect(rect, &color_paint(&color.hex, 1.0), transform, None);
+        }
+    }
+    if wide {
+        let y = obj.y as f32 + rows as f32 * cell;
+        if let Some(rect) = tiny_skia::Rect::from_xywh(obj.x as f32, y, cols as f32 * cell, cell) {
+            canvas.fill_rect(rect, &color_paint(&colors[colors.len() - 1].hex, 1.0), transform, None);
+        }
+    }
+}
+
+pub fn image_to_pixmap(image: &RgbaImage) -> Option<Pixmap> {
+    let mut pixmap = Pixmap::new(image.width(), image.height())?;
+    for (src, dst) in image.pixels().zip(pixmap.pixels_mut()) {
+        *dst = tiny_skia::ColorU8::from_rgba(src[0], src[1], src[2], src[3]).premultiply();
+    }
+    Some(pixmap)
+}
+
+pub fn pixmap_to_image(pixmap: &Pixmap) -> RgbaImage {
+    let mut image = RgbaImage::new(pixmap.width(), pixmap.height());
+    for (src, dst) in pixmap.pixels().iter().zip(image.pixels_mut()) {
+        let c = PremultipliedColorU8::demultiply(src);
+        *dst = image::Rgba([c.red(), c.green(), c.blue(), c.alpha()]);
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn images_dir() -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("eyedea-render-{}", database::random_token().unwrap()));
+        std::fs::create_dir_all(&dir).unwrap();
+        image::RgbaImage::from_pixel(10, 10, image::Rgba([255, 0, 0, 255])).save(dir.join("red.png")).unwrap();
+        dir
+    }
+
+    fn board(layers: serde_json::Value) -> Board {
+        let mut board = database::new_board("Render".to_string(), "#00ff00".to_string());
+        board.layers = serde_json::from_value(layers).unwrap();
+        board
+    }
+
+    fn red_layer(x: f64, y: f64) -> serde_json::Value {
+        serde_json::json!({ "id": x, "name": "red", "src": "red.png", "x": x, "y": y, "width": 10.0, "height": 10.0 })
+    }
+
+    #[test]
+    fn renders_layers_over_the_background() {
+        let dir = images_dir();
+        let board = board(serde_json::json!([red_layer(0.0, 0.0), red_layer(30.0, 0.0)]));
+
+        let image = render_board(&board, &dir, &RenderOptions::default()).unwrap();
+        assert_eq!(image.dimensions(), (40, 10));
+        assert_eq!(*image.get_pixel(5, 5), image::Rgba([255, 0, 0, 255]));
+        assert_eq!(*image.get_pixel(20, 5), image::Rgba([0, 255, 0, 255]));
+
+        let options = RenderOptions { dpi: Some(192.0), padding: Some(5.0), transparent: true, ..Default::default() };
+        let image = render_board(&board, &dir, &options).unwrap();
+        assert_eq!(image.dimensions(), (100, 40));
+        assert_eq!(image.get_pixel(50, 20)[3], 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stacks_by_z_index_and_draws_strokes_on_top() {
+        let dir = images_dir();
+        let mut board = board(serde_json::json!([
+            { "id": 1.0, "name": "missing", "src": "missing.png", "x": 0.0, "y": 0.0, "width": 10.0, "height": 10.0, "zIndex": 2.0 },
+            { "id": 2.0, "name": "red", "src": "red.png", "x": 0.0, "y": 0.0, "width": 10.0, "height": 10.0, "zIndex": 1.0 }
+        ]));
+        board.strokes = Some(serde_json::json!([
+            { "tool": "pen", "color": "#0000ff", "size": 2.0, "points": [{ "x": 0.0, "y": 9.0 }, { "x": 10.0, "y": 9.0 }] }
+        ]));
+
+        let image = render_board(&board, &dir, &RenderOptions::default()).unwrap();
+        // The placeholder for missing media covers the red layer below it
+        let covered = image.get_pixel(5, 2);
+        assert!(covered[0] < 60 && covered[1] < 60 && covered[2] < 60);
+        let ink = image.get_pixel(5, 9);
+        assert!(ink[2] > 200 && ink[0] < 60);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn applies_filters() {
+        let mut layer: Layer = serde_json::from_value(red_layer(0.0, 0.0)).unwrap();
+        let mut image = image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 255]));
+        assert!(!has_filters(&layer));
+        layer.invert = Some(true);
+        assert!(has_filters(&layer));
+        apply_filters(&mut image, &layer, 1.0);
+        assert_eq!(*image.get_pixel(0, 0), image::Rgba([0, 255, 255, 255]));
+
+        layer.invert = None;
+        layer.grayscale = Some(true);
+        apply_filters(&mut image, &layer, 1.0);
+        let gray = image.get_pixel(1, 1);
+        assert!(gray[0] == gray[1] && gray[1] == gray[2]);
+    }
+
+    #[test]
+    fn refuses_empty_and_oversized_renders() {
+        let dir = images_dir();
+        let empty = board(serde_json::json!([]));
+        assert!(render_board(&empty, &dir, &RenderOptions::default()).is_err());
+
+        let board = board(serde_json::json!([red_layer(0.0, 0.0)]));
+        let huge = RenderOptions { scale: Some(2000.0), ..Default::default() };
+        assert!(render_board(&board, &dir, &huge).is_err());
+        let region = Rect { x: 0.0, y: 0.0, width: 0.0, height: 10.0 };
+        assert!(export_region(&board, Some(region), None).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn flattens_jpegs_onto_white() {
+        let image = image::RgbaImage::from_pixel(8, 8, image::Rgba([0, 0, 0, 0]));
+        let bytes = encode_image(&image, RasterFormat::Jpeg, 90).unwrap();
+        let decoded = image::load_from_memory(&bytes).unwrap().to_rgb8();
+        assert!(decoded.get_pixel(4, 4)[0] > 250);
+
+        assert_eq!(RasterFormat::from_path(Path::new("out.JPG")).unwrap(), RasterFormat::Jpeg);
+        assert!(RasterFormat::from_path(Path::new("out.gif")).is_err());
+    }
+}
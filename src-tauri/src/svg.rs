@@ -0,0 +1,620 @@
+use crate::database::{self, Board, Layer};
+use crate::drawing::{self, CanvasObject, LayerGroup, Rect, Stroke};
+use crate::fonts;
+use crate::render::{self, BoardItem};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::path::Path;
+use tiny_skia::PathSegment;
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SvgImages {
+    /// Leave image layers out and export only vector content.
+    #[default]
+    None,
+    /// Reference the files in the media store by `file://` URL.
+    Link,
+    /// Inline the image data as data URLs.
+    Embed,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SvgOptions {
+    /// Area of the board to export. Defaults to the bounds of all content.
+    #[serde(default)]
+    pub region: Option<Rect>,
+    /// Margin added around the content bounds when no region is given.
+    #[serde(default)]
+    pub padding: Option<f64>,
+    #[serde(default)]
+    pub images: SvgImages,
+    /// Leave the background transparent instead of filling `bgColor`.
+    #[serde(default)]
+    pub transparent: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedSvg {
+    pub path: String,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Renders a board to an SVG file at `path`.
+pub fn export_board_svg(
+    board: &Board,
+    images_dir: &Path,
+    options: &SvgOptions,
+    path: &Path,
+) -> Result<ExportedSvg, String> {
+    let (svg, region) = board_to_svg(board, images_dir, options)?;
+    std::fs::write(path, svg).map_err(|e| format!("Failed to write SVG: {}", e))?;
+    Ok(ExportedSvg {
+        path: path.to_string_lossy().to_string(),
+        width: region.width,
+        height: region.height,
+    })
+}
+
+/// Builds the SVG document in board coordinates, returning it together with
+/// the exported region. Layer panel groups become `<g>` elements; a group
+/// whose members aren't adjacent in z-order is split into several `<g>`s so
+/// stacking stays faithful.
+pub fn board_to_svg(board: &Board, images_dir: &Path, options: &SvgOptions) -> Result<(String, Rect), String> {
+    let region = match options.images {
+        SvgImages::None => vector_region(board, options)?,
+        _ => render::export_region(board, options.region, options.padding)?,
+    };
+
+    let mut svg = SvgWriter { defs: String::new(), next_id: 1, region };
+    let mut body = String::new();
+    if !options.transparent {
+        let _ = writeln!(
+            body,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+            num(region.x),
+            num(region.y),
+            num(region.width),
+            num(region.height),
+            escape(&board.bg_color)
+        );
+    }
+
+    let groups = drawing::parse_groups(&board.groups);
+    let mut open_group: Option<usize> = None;
+    let mut group_runs: Vec<usize> = vec![0; groups.len()];
+    for item in render::board_items(board) {
+        let (element, group) = match &item {
+            BoardItem::Layer(layer) => {
+                if options.images == SvgImages::None {
+                    continue;
+                }
+                let group = groups.iter().position(|g| g.layer_ids.contains(&layer.id));
+                (svg.layer(layer, images_dir, options.images), group)
+            }
+            BoardItem::Object(obj) => {
+                let group = groups.iter().position(|g| !obj.id.is_null() && g.object_ids.contains(&obj.id));
+                (svg.object(obj)?, group)
+            }
+        };
+        let Some(element) = element else {
+            continue;
+        };
+
+        if open_group != group {
+            if open_group.is_some() {
+                body.push_str("</g>\n");
+            }
+            if let Some(index) = group {
+                group_runs[index] += 1;
+                body.push_str(&group_open(&groups[index], group_runs[index]));
+            }
+            open_group = group;
+        }
+        body.push_str(&element);
+    }
+    if open_group.is_some() {
+        body.push_str("</g>\n");
+    }
+
+    let ink = svg.ink(&drawing::parse_strokes(&board.strokes));
+    if !ink.is_empty() {
+        body.push_str("<g id=\"strokes\">\n");
+        body.push_str(&ink);
+        body.push_str("</g>\n");
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="{} {} {} {}">"#,
+        num(region.width),
+        num(region.height),
+        num(region.x),
+        num(region.y),
+        num(region.width),
+        num(region.height)
+    );
+    let _ = writeln!(out, "<title>{}</title>", escape(&board.name));
+    if !svg.defs.is_empty() {
+        let _ = write!(out, "<defs>\n{}</defs>\n", svg.defs);
+    }
+    out.push_str(&body);
+    out.push_str("</svg>\n");
+    Ok((out, region))
+}
+
+/// Bounds of the exported content when image layers are left out.
+fn vector_region(board: &Board, options: &SvgOptions) -> Result<Rect, String> {
+    if options.region.is_some() {
+        return render::export_region(board, options.region, options.padding);
+    }
+    let vector_only = Board { layers: Vec::new(), ..board.clone() };
+    render::export_region(&vector_only, None, options.padding)
+        .map_err(|_| "Board has no strokes, shapes or text to export".to_string())
+}
+
+fn group_open(group: &LayerGroup, run: usize) -> String {
+    let id = match &group.id {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    let id: String = id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    let suffix = if run > 1 { format!("-{}", run) } else { String::new() };
+    format!(
+        "<g id=\"group-{}{}\" data-name=\"{}\">\n<title>{}</title>\n",
+        id,
+        suffix,
+        escape(&group.name),
+        escape(&group.name)
+    )
+}
+
+struct SvgWriter {
+    defs: String,
+    next_id: u32,
+    region: Rect,
+}
+
+impl SvgWriter {
+    fn unique_id(&mut self, prefix: &str) -> String {
+        let id = format!("{}-{}", prefix, self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    fn layer(&mut self, layer: &Layer, images_dir: &Path, mode: SvgImages) -> Option<String> {
+        if layer.width <= 0.0 || layer.height <= 0.0 || layer.media_type.as_deref() == Some("video") {
+            return None;
+        }
+        let href = if layer.src.starts_with("data:") || layer.src.contains("://") {
+            layer.src.clone()
+        } else {
+            let path = database::media_path(images_dir, &layer.src).ok()?;
+            match mode {
+                SvgImages::Embed => {
+                    let bytes = std::fs::read(&path).ok()?;
+                    let mime = image::guess_format(&bytes)
+                        .map(|f| f.to_mime_type())
+                        .unwrap_or("application/octet-stream");
+                    format!(
+                        "data:{};base64,{}",
+                        mime,
+                        base64::engine::general_purpose::STANDARD.encode(bytes)
+                    )
+                }
+                _ => file_url(&path),
+            }
+        };
+
+        let mut attrs = String::new();
+        if let Some(transform) = layer_transform(layer) {
+            let _ = write!(attrs, r#" transform="{}""#, transform);
+        }
+        let opacity = layer.opacity.map(|o| o / 100.0).unwrap_or(1.0).clamp(0.0, 1.0);
+        if opacity < 1.0 {
+            let _ = write!(attrs, r#" opacity="{}""#, num(opacity));
+        }
+        if let Some(filter) = css_filter(layer) {
+            let _ = write!(attrs, r#" style="filter: {}""#, filter);
+        }
+
+        Some(format!(
+            "<image x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" preserveAspectRatio=\"none\" href=\"{}\"{}><title>{}</title></image>\n",
+            num(layer.x),
+            num(layer.y),
+            num(layer.width),
+            num(layer.height),
+            escape(&href),
+            attrs,
+            escape(&layer.name)
+        ))
+    }
+
+    fn object(&mut self, obj: &CanvasObject) -> Result<Option<String>, String> {
+        let element = match obj.kind.as_str() {
+            "shape" => shape(obj),
+            "text" => self.text(obj)?,
+            "colorPalette" => palette(obj),
+            _ => None,
+        };
+        Ok(element.map(|element| match obj.rotation.filter(|r| *r != 0.0) {
+            Some(rotation) => {
+                let (cx, cy) = obj.rotation_center();
+                format!(
+                    "<g transform=\"rotate({} {} {})\">\n{}</g>\n",
+                    num(rotation),
+                    num(cx),
+                    num(cy),
+                    element
+                )
+            }
+            None => element,
+        }))
+    }
+
+    /// Text keeps the editor's line breaks and alignment by positioning each
+    /// run explicitly, measured with the bundled fonts.
+    fn text(&mut self, obj: &CanvasObject) -> Result<Option<String>, String> {
+        let runs = fonts::layout_text(obj)?;
+        if runs.is_empty() {
+            return Ok(None);
+        }
+
+        let clip = self.unique_id("clip");
+        let _ = writeln!(
+            self.defs,
+            r#"<clipPath id="{}"><rect x="{}" y="{}" width="{}" height="{}"/></clipPath>"#,
+            clip,
+            num(obj.x),
+            num(obj.y),
+            num(obj.width),
+            num(obj.height)
+        );
+
+        // Layout works word by word; join neighbouring words that share a
+        // line and style back into one span.
+        let mut merged: Vec<fonts::PlacedRun> = Vec::new();
+        for run in runs {
+            match merged.last_mut() {
+                Some(last)
+                    if last.baseline == run.baseline
+                        && std::ptr::eq(last.face, run.face)
+                        && last.style == run.style
+                        && (last.x + last.width - run.x).abs() < 0.01 =>
+                {
+                    last.text.push_str(&run.text);
+                    last.width += run.width;
+                }
+                _ => merged.push(run),
+            }
+        }
+
+        let mut out = format!("<text clip-path=\"url(#{})\" xml:space=\"preserve\">", clip);
+        for run in merged {
+            let mut attrs = format!(
+                r#"x="{}" y="{}" font-family="{}" font-size="{}" fill="{}""#,
+                num(run.x),
+                num(run.baseline),
+                escape(&font_family(run.face.family, run.style.family())),
+                num(run.style.size()),
+                escape(run.style.color())
+            );
+            if run.style.is_bold() {
+                attrs.push_str(r#" font-weight="bold""#);
+            }
+            if let Some(decoration) = run.style.text_decoration.as_deref().filter(|d| *d != "none") {
+                let _ = write!(attrs, r#" text-decoration="{}""#, escape(decoration));
+            }
+            let _ = write!(out, "<tspan {}>{}</tspan>", attrs, escape(&run.text));
+        }
+        out.push_str("</text>\n");
+        Ok(Some(out))
+    }
+
+    /// Strokes in drawing order. Each pixel eraser becomes a mask over
+    /// everything inked before it, so later strokes stay intact.
+    fn ink(&mut self, strokes: &[Stroke]) -> String {
+        let region = self.region;
+        let mut ink = String::new();
+        for pass in drawing::ink_passes(strokes) {
+            for stroke in &pass.strokes {
+                let color = stroke.color.as_deref().unwrap_or("#000000");
+                ink.push_str(&stroke_element(stroke, color, stroke.alpha()));
+            }
+            if pass.erasers.is_empty() {
+                continue;
+            }
+
+            let mask = self.unique_id("eraser");
+            let _ = writeln!(
+                self.defs,
+                r#"<mask id="{}" maskUnits="userSpaceOnUse" x="{}" y="{}" width="{}" height="{}">"#,
+                mask,
+                num(region.x),
+                num(region.y),
+                num(region.width),
+                num(region.height)
+            );
+            let _ = writeln!(
+                self.defs,
+                r##"<rect x="{}" y="{}" width="{}" height="{}" fill="#ffffff"/>"##,
+                num(region.x),
+                num(region.y),
+                num(region.width),
+                num(region.height)
+            );
+            for eraser in &pass.erasers {
+                self.defs
+                    .push_str(&stroke_element(eraser, "#000000", eraser.opacity.unwrap_or(1.0)));
+            }
+            self.defs.push_str("</mask>\n");
+            ink = format!("<g mask=\"url(#{})\">\n{}</g>\n", mask, ink);
+        }
+        ink
+    }
+}
+
+fn shape(obj: &CanvasObject) -> Option<String> {
+    let fill = escape(obj.fill_color.as_deref().unwrap_or("#3b82f6"));
+    let stroke_color = escape(obj.stroke_color.as_deref().unwrap_or("#000000"));
+    let stroke = if obj.draws_stroke() {
+        format!(r#" stroke="{}" stroke-width="{}""#, stroke_color, num(obj.stroke_width()))
+    } else {
+        String::new()
+    };
+
+    let element = match obj.shape_type.as_deref().unwrap_or("square") {
+        "circle" => format!(
+            r#"<circle cx="{}" cy="{}" r="{}" fill="{}"{}/>"#,
+            num(obj.x + obj.width / 2.0),
+            num(obj.y + obj.height / 2.0),
+            num(obj.width.min(obj.height) / 2.0),
+            fill,
+            stroke
+        ),
+        "line" => {
+            let (x2, y2) = obj.end_point();
+            format!(
+                r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="{}"/>"#,
+                num(obj.x),
+                num(obj.y),
+                num(x2),
+                num(y2),
+                stroke_color,
+                num(obj.stroke_width())
+            )
+        }
+        "arrow" => {
+            let (path, _) = render::shape_path(obj)?;
+            format!(
+                r#"<path d="{}" fill="none" stroke="{}" stroke-width="{}"/>"#,
+                path_data(&path),
+                stroke_color,
+                num(obj.stroke_width())
+            )
+        }
+        _ => {
+            let radius = obj.corner_radius.unwrap_or(0.0).min(obj.width / 2.0).min(obj.height / 2.0);
+            let rounded = if radius > 0.0 { format!(r#" rx="{}""#, num(radius)) } else { String::new() };
+            format!(
+                r#"<rect x="{}" y="{}" width="{}" height="{}"{} fill="{}"{}/>"#,
+                num(obj.x),
+                num(obj.y),
+                num(obj.width),
+                num(obj.height),
+                rounded,
+                fill,
+                stroke
+            )
+        }
+    };
+    Some(element + "\n")
+}
+
+fn palette(obj: &CanvasObject) -> Option<String> {
+    let cell = obj.cell_size.unwrap_or(60.0);
+    let cols = obj.grid_cols.unwrap_or(1).max(1) as usize;
+    let colors = obj.colors.clone().unwrap_or_default();
+    let wide = obj.has_wide_cell == Some(true) && !colors.is_empty();
+    let regular = if wide { colors.len() - 1 } else { colors.len() };
+    let rows = obj.grid_rows.unwrap_or(1) as usize;
+
+    let mut out = String::from("<g>\n");
+    for (i, color) in colors.iter().take(regular.min(rows * cols)).enumerate() {
+        let _ = writeln!(
+            out,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+            num(obj.x + (i % cols) as f64 * cell),
+            num(obj.y + (i / cols) as f64 * cell),
+            num(cell),
+            num(cell),
+            escape(&color.hex)
+        );
+    }
+    if wide {
+        let _ = writeln!(
+            out,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+            num(obj.x),
+            num(obj.y + rows as f64 * cell),
+            num(cols as f64 * cell),
+            num(cell),
+            escape(&colors[colors.len() - 1].hex)
+        );
+    }
+    out.push_str("</g>\n");
+    Some(out)
+}
+
+/// A smoothed stroke as one path, or, when pressure was recorded, as one
+/// curve piece per point so the width can follow the pressure.
+fn stroke_element(stroke: &Stroke, color: &str, alpha: f64) -> String {
+    let alpha = alpha.clamp(0.0, 1.0);
+    let opacity = if alpha < 1.0 { format!(r#" opacity="{}""#, num(alpha)) } else { String::new() };
+    let style = format!(
+        r#"fill="none" stroke="{}" stroke-linecap="round" stroke-linejoin="round""#,
+        escape(color)
+    );
+
+    if !stroke.has_pressure() {
+        let Some(path) = render::stroke_path(stroke) else {
+            return String::new();
+        };
+        return format!(
+            "<path d=\"{}\" {} stroke-width=\"{}\"{}/>\n",
+            path_data(&path),
+            style,
+            num(stroke.width()),
+            opacity
+        );
+    }
+
+    // Same midpoint smoothing as stroke_path, split at the midpoints. The
+    // group carries the opacity so overlapping pieces don't double up.
+    let points = &stroke.points;
+    let mid = |i: usize| ((points[i].x + points[i + 1].x) / 2.0, (points[i].y + points[i + 1].y) / 2.0);
+    let mut out = format!("<g {}{}>\n", style, opacity);
+    let mut start = (points[0].x, points[0].y);
+    for i in 1..points.len() {
+        let p = &points[i];
+        let d = if i + 1 < points.len() {
+            let end = mid(i);
+            let d = format!(
+                "M{} {}Q{} {} {} {}",
+                num(start.0),
+                num(start.1),
+                num(p.x),
+                num(p.y),
+                num(end.0),
+                num(end.1)
+            );
+            start = end;
+            d
+        } else {
+            format!("M{} {}L{} {}", num(start.0), num(start.1), num(p.x), num(p.y))
+        };
+        let _ = writeln!(out, r#"<path d="{}" stroke-width="{}"/>"#, d, num(stroke.width_at(p)));
+    }
+    out.push_str("</g>\n");
+    out
+}
+
+fn path_data(path: &tiny_skia::Path) -> String {
+    let mut d = String::new();
+    for segment in path.segments() {
+        let _ = match segment {
+            PathSegment::MoveTo(p) => write!(d, "M{} {}", numf(p.x), numf(p.y)),
+            PathSegment::LineTo(p) => write!(d, "L{} {}", numf(p.x), numf(p.y)),
+            PathSegment::QuadTo(c, p) => write!(d, "Q{} {} {} {}", numf(c.x), numf(c.y), numf(p.x), numf(p.y)),
+            PathSegment::CubicTo(c1, c2, p) => write!(
+                d,
+                "C{} {} {} {} {} {}",
+                numf(c1.x),
+                numf(c1.y),
+                numf(c2.x),
+                numf(c2.y),
+                numf(p.x),
+                numf(p.y)
+            ),
+            PathSegment::Close => write!(d, "Z"),
+        };
+    }
+    d
+}
+
+/// Mirror then rotate around the center, matching the canvas.
+fn layer_transform(layer: &Layer) -> Option<String> {
+    let mut parts = Vec::new();
+    if layer.mirror == Some(true) {
+        parts.push(format!("translate({} 0) scale(-1 1)", num(layer.x * 2.0 + layer.width)));
+    }
+    if let Some(rotation) = layer.rotation.filter(|r| *r != 0.0) {
+        parts.push(format!(
+            "rotate({} {} {})",
+            num(rotation),
+            num(layer.x + layer.width / 2.0),
+            num(layer.y + layer.height / 2.0)
+        ));
+    }
+    (!parts.is_empty()).then(|| parts.join(" "))
+}
+
+/// The CSS filter string the editor builds for a layer.
+fn css_filter(layer: &Layer) -> Option<String> {
+    let mut filters = Vec::new();
+    if let Some(v) = layer.brightness.filter(|v| *v != 100.0 && *v != 0.0) {
+        filters.push(format!("brightness({}%)", num(v)));
+    }
+    if let Some(v) = layer.contrast.filter(|v| *v != 100.0 && *v != 0.0) {
+        filters.push(format!("contrast({}%)", num(v)));
+    }
+    if let Some(v) = layer.saturation.filter(|v| *v != 100.0 && *v != 0.0) {
+        filters.push(format!("saturate({}%)", num(v)));
+    }
+    if let Some(v) = layer.hue.filter(|v| *v != 0.0) {
+        filters.push(format!("hue-rotate({}deg)", num(v)));
+    }
+    if let Some(v) = layer.blur.filter(|v| *v > 0.0) {
+        filters.push(format!("blur({}px)", num(v)));
+    }
+    if layer.grayscale == Some(true) {
+        filters.push("grayscale(100%)".to_string());
+    }
+    if layer.invert == Some(true) {
+        filters.push("invert(100%)".to_string());
+    }
+    (!filters.is_empty()).then(|| filters.join(" "))
+}
+
+/// Bundled family first so viewers with our fonts installed match the layout,
+/// followed by whatever the editor asked for.
+fn font_family(bundled: &str, requested: &str) -> String {
+    let requested = requested.replace('"', "'");
+    if requested.to_lowercase().contains(&bundled.to_lowercase()) {
+        requested
+    } else {
+        format!("'{}', {}", bundled, requested)
+    }
+}
+
+fn file_url(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let path = path.replace(' ', "%20").replace('#', "%23");
+    if path.starts_with('/') {
+        format!("file://{}", path)
+    } else {
+        format!("file:///{}", path)
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Formats a coordinate with at most two decimals.
+fn num(value: f64) -> String {
+    let s = format!("{:.2}", value);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" {
+        "0".to_string()
+    } else {
+        s.to_string()
+    }
+}
+
+fn numf(value: f32) -> String {
+    num(value as f64)
+}
@@ -0,0 +1,593 @@
+//! Syncing boards and media through a shared folder (a network share or a
+//! cloud-synced directory) between machines.
+//!
+//! The folder holds `boards/<id>.json`, a `SyncRecord` per board carrying a
+//! revision counter, and `media/` with every file those boards use. Each
+//! machine remembers the revision and content hash it last synced per board
+//! in `sync_state.json`, which tells local edits, remote edits and both
+//! apart. When both sides changed a board, the folder's version keeps the
+//! board and the local one is saved as a conflicted copy, so nothing is
+//! overwritten. Deletions sync as tombstones; an edit wins over a delete.
+//!
+//! Encrypted boards aren't synced.
+
+use crate::database::{self, Board, DataDir};
+use crate::events;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+/// How often the background sync checks whether a sync is due.
+const SCHEDULE_CHECK: Duration = Duration::from_secs(30);
+
+/// Keeps a scheduled sync and one started by hand from overlapping.
+static SYNC_LOCK: Mutex<()> = Mutex::new(());
+
+/// One board in the sync folder.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncRecord {
+    pub id: u64,
+    /// Bumped on every push.
+    pub revision: u64,
+    /// The device that pushed this revision.
+    pub device_id: String,
+    pub device_name: String,
+    pub modified_at: u64,
+    /// SHA-256 of the board JSON.
+    pub hash: String,
+    /// A tombstone left by deleting the board.
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub board: Option<Board>,
+}
+
+/// What this machine last synced.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SyncState {
+    device_id: String,
+    last_synced_at: Option<u64>,
+    /// Board id to the revision and hash it had after the last sync.
+    boards: HashMap<u64, Synced>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Synced {
+    revision: u64,
+    hash: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncConflict {
+    pub board_id: u64,
+    /// The conflicted copy holding this machine's edits, if any survived.
+    pub copy_id: Option<u64>,
+    pub name: String,
+    /// The device whose version kept the board.
+    pub other_device: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncReport {
+    pub pushed: Vec<u64>,
+    pub pulled: Vec<u64>,
+    pub deleted: Vec<u64>,
+    pub conflicts: Vec<SyncConflict>,
+    pub media_copied: usize,
+    /// Boards that couldn't be synced, with why.
+    pub errors: Vec<String>,
+}
+
+fn state_path(app: &impl DataDir) -> PathBuf {
+    app.data_dir().join("sync_state.json")
+}
+
+fn load_state(app: &impl DataDir) -> Result<SyncState, String> {
+    let mut state: SyncState = match fs::read_to_string(state_path(app)) {
+        Ok(content) => serde_json::from_str(&content).map_err(|e| e.to_string())?,
+        Err(_) => SyncState::default(),
+    };
+    if state.device_id.is_empty() {
+        state.device_id = database::random_token()?[..16].to_string();
+    }
+    Ok(state)
+}
+
+fn save_state(app: &impl DataDir, state: &SyncState) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(state).map_err(|e| e.to_string())?;
+    fs::write(state_path(app), content).map_err(|e| e.to_string())
+}
+
+fn device_name(app: &impl DataDir) -> String {
+    database::load_sync_settings(app)
+        .ok()
+        .and_then(|s| s.device_name)
+        .filter(|n| !n.is_empty())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .unwrap_or_else(|| "another device".to_string())
+}
+
+/// Hash of a board's content. The thumbnail, view and save time change
+/// without any edit, so they are left out.
+pub fn board_hash(board: &Board) -> Result<String, String> {
+    let content = Board { updated_at: 0, thumbnail: None, view_state: None, rejected_media: Vec::new(), ..board.clone() };
+    let json = serde_json::to_vec(&content).map_err(|e| e.to_string())?;
+    Ok(Sha256::digest(&json).iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn record_path(folder: &Path, id: u64) -> PathBuf {
+    folder.join("boards").join(format!("{}.json", id))
+}
+
+fn read_record(folder: &Path, id: u64) -> Result<Option<SyncRecord>, String> {
+    match fs::read_to_string(record_path(folder, id)) {
+        Ok(content) => {
+            let record: SyncRecord = serde_json::from_str(&content).map_err(|e| format!("Invalid sync record for board {}: {}", id, e))?;
+            if record.id != id {
+                return Err(format!("Sync record for board {} names board {}", id, record.id));
+            }
+            Ok(Some(record))
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+fn write_record(folder: &Path, record: &SyncRecord) -> Result<(), String> {
+    let path = record_path(folder, record.id);
+    let partial = path.with_extension(format!("{}.part", database::now_millis()));
+    let content = serde_json::to_string_pretty(record).map_err(|e| e.to_string())?;
+    fs::write(&partial, content).map_err(|e| e.to_string())?;
+    fs::rename(&partial, &path).map_err(|e| e.to_string())
+}
+
+/// Ids of the boards in the folder.
+fn remote_ids(folder: &Path) -> Vec<u64> {
+    fs::read_dir(folder.join("boards"))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|e| e.file_name().to_string_lossy().strip_suffix(".json")?.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Stored files a board uses.
+fn media_srcs(board: &Board) -> BTreeSet<&str> {
+    board
+        .layers
+        .iter()
+        .map(|l| l.src.as_str())
+        .chain(board.assets.iter().map(|a| a.src.as_str()))
+        .filter(|src| !src.contains(':'))
+        .collect()
+}
+
+/// Copies the board's media that `to` lacks. Names are checked against
+/// both stores, so a record can't reach outside them.
+fn copy_media(board: &Board, from: &Path, to: &Path) -> Result<usize, String> {
+    let mut copied = 0;
+    for src in media_srcs(board) {
+        let (source, target) = (database::media_path(from, src)?, database::media_path(to, src)?);
+        if target.exists() || !source.is_file() {
+            continue;
+        }
+        let partial = target.with_extension(format!("{}.part", database::now_millis()));
+        fs::copy(&source, &partial).map_err(|e| e.to_string())?;
+        fs::rename(&partial, &target).map_err(|e| e.to_string())?;
+        copied += 1;
+    }
+    Ok(copied)
+}
+
+/// Syncs once with `folder`: pushes local edits, pulls remote ones and
+/// keeps both versions of boards edited on both sides.
+pub fn sync_once(app: &impl DataDir, folder: &Path) -> Result<SyncReport, String> {
+    let _guard = SYNC_LOCK.lock().unwrap();
+    fs::create_dir_all(folder.join("boards")).map_err(|e| e.to_string())?;
+    fs::create_dir_all(folder.join("media")).map_err(|e| e.to_string())?;
+
+    let mut state = load_state(app)?;
+    let device = device_name(app);
+    let mut report = SyncReport::default();
+
+    let mut local = HashMap::new();
+    let mut encrypted = HashSet::new();
+    for metadata in database::load_all_boards(app)? {
+        if metadata.encrypted {
+            encrypted.insert(metadata.id);
+        } else {
+            local.insert(metadata.id, database::load_board(app, metadata.id)?);
+        }
+    }
+    let ids: BTreeSet<u64> = local
+        .keys()
+        .copied()
+        .chain(remote_ids(folder))
+        .chain(state.boards.keys().copied())
+        .filter(|id| !encrypted.contains(id))
+        .collect();
+
+    let mut sync = Pass {
+        app,
+        folder,
+        device_id: state.device_id.clone(),
+        device_name: device,
+        report: &mut report,
+        copies: Vec::new(),
+    };
+    for id in ids {
+        let known = state.boards.get(&id).cloned();
+        match sync.board(id, local.remove(&id), known) {
+            Ok(Some(synced)) => {
+                state.boards.insert(id, synced);
+            }
+            Ok(None) => {
+                state.boards.remove(&id);
+            }
+            Err(e) => sync.report.errors.push(format!("Board {}: {}", id, e)),
+        }
+        // Conflicted copies were pushed as new boards
+        state.boards.extend(sync.copies.drain(..));
+    }
+    state.last_synced_at = Some(database::now_millis());
+    save_state(app, &state)?;
+    Ok(report)
+}
+
+/// Syncs with the folder in the settings.
+pub fn sync_now(app: &impl DataDir) -> Result<SyncReport, String> {
+    let settings = database::load_sync_settings(app)?;
+    let directory = settings.directory.filter(|d| !d.is_empty()).ok_or("No sync folder is set")?;
+    sync_once(app, Path::new(&directory))
+}
+
+/// Syncs when enabled and the interval has passed since the last sync.
+pub fn run_scheduled(app: &impl DataDir, now: u64) -> Result<Option<SyncReport>, String> {
+    let settings = database::load_sync_settings(app)?;
+    if !settings.enabled || settings.directory.as_deref().is_none_or(str::is_empty) {
+        return Ok(None);
+    }
+    let interval = u64::from(settings.interval_minutes.max(1)) * 60 * 1000;
+    let last = load_state(app)?.last_synced_at;
+    if last.is_some_and(|last| now.saturating_sub(last) < interval) {
+        return Ok(None);
+    }
+    sync_now(app).map(Some)
+}
+
+/// Emits `sync-completed`, and the board events for what the sync
+/// changed here.
+pub fn notify(app: &AppHandle, report: &SyncReport) {
+    let copies = report.conflicts.iter().filter_map(|c| c.copy_id);
+    events::boards_updated(app, report.pulled.iter().copied().chain(copies), None);
+    for &id in &report.deleted {
+        events::board_deleted(app, id, None);
+    }
+    let _ = app.emit("sync-completed", report);
+}
+
+/// Syncs in the background for as long as the app runs, emitting
+/// `sync-completed` or `sync-failed`.
+pub fn start_scheduler(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        match run_scheduled(&app, database::now_millis()) {
+            Ok(Some(report)) => notify(&app, &report),
+            Ok(None) => {}
+            Err(e) => {
+                log::error!("Scheduled sync failed: {}", e);
+                let _ = app.emit("sync-failed", e);
+            }
+        }
+        std::thread::sleep(SCHEDULE_CHECK);
+    });
+}
+
+struct Pass<'a, D: DataDir> {
+    app: &'a D,
+    folder: &'a Path,
+    device_id: String,
+    device_name: String,
+    report: &'a mut SyncReport,
+    copies: Vec<(u64, Synced)>,
+}
+
+impl<D: DataDir> Pass<'_, D> {
+    /// Syncs one board and returns what to remember for it.
+    fn board(&mut self, id: u64, local: Option<Board>, known: Option<Synced>) -> Result<Option<Synced>, String> {
+        let remote = read_record(self.folder, id)?;
+        let local_hash = local.as_ref().map(board_hash).transpose()?;
+        let local_changed = match (&known, &local_hash) {
+            (Some(known), Some(hash)) => known.hash != *hash,
+            (Some(_), None) => true,
+            (None, _) => local.is_some(),
+        };
+        let remote_changed = match (&known, &remote) {
+            (Some(known), Some(remote)) => known.revision != remote.revision,
+            (None, Some(_)) => true,
+            (_, None) => false,
+        };
+        let remote_hash = remote.as_ref().filter(|r| !r.deleted).map(|r| r.hash.clone());
+
+        match (local, remote) {
+            (None, None) => Ok(None),
+            // Same content on both sides, whoever wrote it
+            (Some(_), Some(remote)) if remote_hash == local_hash => Ok(Some(Synced { revision: remote.revision, hash: remote.hash })),
+            (Some(board), None) => self.push(&board, 0).map(Some),
+            (Some(board), Some(remote)) if !remote_changed => self.push(&board, remote.revision).map(Some),
+            (Some(board), Some(remote)) if !local_changed => self.pull(remote, Some(&board)),
+            (Some(board), Some(remote)) if remote.deleted => {
+                // Edited here, deleted there: the edit wins
+                self.push(&board, remote.revision).map(Some)
+            }
+            (Some(board), Some(remote)) => self.conflict(board, remote).map(Some),
+            (None, Some(remote)) if remote.deleted => Ok(Some(Synced { revision: remote.revision, hash: remote.hash })),
+            (None, Some(remote)) if known.is_none() || remote_changed => self.pull(remote, None),
+            (None, Some(remote)) => {
+                // Deleted here since the last sync
+                self.write(id, remote.revision + 1, None).map(Some)
+            }
+        }
+    }
+
+    fn write(&mut self, id: u64, revision: u64, board: Option<&Board>) -> Result<Synced, String> {
+        let hash = match board {
+            Some(board) => board_hash(board)?,
+            None => String::new(),
+        };
+        let record = SyncRecord {
+            id,
+            revision,
+            device_id: self.device_id.clone(),
+            device_name: self.device_name.clone(),
+            modified_at: database::now_millis(),
+            hash: hash.clone(),
+            deleted: board.is_none(),
+            board: board.cloned(),
+        };
+        write_record(self.folder, &record)?;
+        match board {
+            Some(_) => self.report.pushed.push(id),
+            None => self.report.deleted.push(id),
+        }
+        Ok(Synced { revision, hash })
+    }
+
+    fn push(&mut self, board: &Board, remote_revision: u64) -> Result<Synced, String> {
+        let images_dir = database::get_images_dir(self.app);
+        self.report.media_copied += copy_media(board, &images_dir, &self.folder.join("media"))?;
+        self.write(board.id, remote_revision + 1, Some(board))
+    }
+
+    /// Applies the folder's version; a tombstone deletes the local board.
+    fn pull(&mut self, remote: SyncRecord, local: Option<&Board>) -> Result<Option<Synced>, String> {
+        let synced = Synced { revision: remote.revision, hash: remote.hash.clone() };
+        match remote.board {
+            Some(board) if !remote.deleted => {
+                if board.id != remote.id {
+                    return Err(format!("Sync record for board {} holds board {}", remote.id, board.id));
+                }
+                let images_dir = database::get_images_dir(self.app);
+                database::check_media_srcs(&images_dir, media_srcs(&board))?;
+                self.report.media_copied += copy_media(&board, &self.folder.join("media"), &images_dir)?;
+                database::save_board(self.app, &board)?;
+                self.report.pulled.push(board.id);
+                Ok(Some(synced))
+            }
+            _ => {
+                if local.is_some() {
+                    database::delete_board(self.app, remote.id)?;
+                    self.report.deleted.push(remote.id);
+                }
+                Ok(Some(synced))
+            }
+        }
+    }
+
+    /// Both sides changed: the folder's version keeps the board and this
+    /// machine's becomes a conflicted copy, pushed as a new board.
+    fn conflict(&mut self, local: Board, remote: SyncRecord) -> Result<Synced, String> {
+        let existing: HashSet<u64> = database::load_all_boards(self.app)?.into_iter().map(|b| b.id).collect();
+        let mut copy_id = database::now_millis();
+        while existing.contains(&copy_id) || record_path(self.folder, copy_id).exists() {
+            copy_id += 1;
+        }
+        let copy = Board {
+            id: copy_id,
+            name: format!("{} (conflicted copy from {})", local.name, self.device_name),
+            ..local
+        };
+        database::save_board(self.app, &copy)?;
+        let copy_synced = self.push(&copy, 0)?;
+
+        let other_device = remote.device_name.clone();
+        let id = remote.id;
+        let synced = self.pull(remote, Some(&copy))?.ok_or("Failed to pull the board")?;
+        self.report.conflicts.push(SyncConflict { board_id: id, copy_id: Some(copy_id), name: copy.name, other_device });
+        self.copies.push((copy_id, copy_synced));
+        Ok(synced)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(label: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("eyedea-sync-{}-{}", label, database::random_token().unwrap()))
+    }
+
+    fn machine(label: &str) -> PathBuf {
+        let dir = temp_dir(label);
+        database::init_storage(&dir).unwrap();
+        dir
+    }
+
+    fn board_with_image(dir: &PathBuf, id: u64, name: &str) -> Board {
+        let images_dir = database::get_images_dir(dir);
+        let src = database::write_media_file(&images_dir, b"not really a png", "photo", "png").unwrap();
+        let mut board = database::new_board(name.to_string(), "#ffffff".to_string());
+        board.id = id;
+        board.layers = serde_json::from_value(serde_json::json!([
+            { "id": 1.0, "name": "photo", "src": src, "x": 0.0, "y": 0.0, "width": 10.0, "height": 10.0 }
+        ]))
+        .unwrap();
+        database::save_board(dir, &board).unwrap();
+        board
+    }
+
+    fn rename(dir: &PathBuf, id: u64, name: &str) {
+        let mut board = database::load_board(dir, id).unwrap();
+        board.name = name.to_string();
+        database::save_board(dir, &board).unwrap();
+    }
+
+    #[test]
+    fn mirrors_boards_and_media_between_machines() {
+        let (desktop, laptop, folder) = (machine("desktop"), machine("laptop"), temp_dir("folder"));
+        let board = board_with_image(&desktop, 1, "Moodboard");
+
+        let report = sync_once(&desktop, &folder).unwrap();
+        assert_eq!(report.pushed, vec![1]);
+        assert_eq!(report.media_copied, 1);
+        let report = sync_once(&laptop, &folder).unwrap();
+        assert_eq!(report.pulled, vec![1]);
+        let pulled = database::load_board(&laptop, 1).unwrap();
+        assert_eq!(pulled.name, "Moodboard");
+        assert!(database::get_images_dir(&laptop).join(&board.layers[0].src).is_file());
+
+        // Nothing changed, nothing to do
+        let report = sync_once(&laptop, &folder).unwrap();
+        assert!(report.pushed.is_empty() && report.pulled.is_empty());
+
+        // An edit on one side flows to the other
+        rename(&laptop, 1, "Moodboard v2");
+        assert_eq!(sync_once(&laptop, &folder).unwrap().pushed, vec![1]);
+        assert_eq!(sync_once(&desktop, &folder).unwrap().pulled, vec![1]);
+        assert_eq!(database::load_board(&desktop, 1).unwrap().name, "Moodboard v2");
+        assert_eq!(read_record(&folder, 1).unwrap().unwrap().revision, 2);
+
+        // And so does a delete
+        database::delete_board(&desktop, 1).unwrap();
+        assert_eq!(sync_once(&desktop, &folder).unwrap().deleted, vec![1]);
+        assert_eq!(sync_once(&laptop, &folder).unwrap().deleted, vec![1]);
+        assert!(database::load_board(&laptop, 1).is_err());
+
+        for dir in [desktop, laptop, folder] {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+
+    #[test]
+    fn keeps_both_versions_of_conflicting_edits() {
+        let (desktop, laptop, folder) = (machine("desktop"), machine("laptop"), temp_dir("folder"));
+        board_with_image(&desktop, 1, "Shared");
+        sync_once(&desktop, &folder).unwrap();
+        sync_once(&laptop, &folder).unwrap();
+
+        rename(&desktop, 1, "Desktop edit");
+        rename(&laptop, 1, "Laptop edit");
+        sync_once(&desktop, &folder).unwrap();
+        let report = sync_once(&laptop, &folder).unwrap();
+
+        assert_eq!(report.conflicts.len(), 1);
+        let copy_id = report.conflicts[0].copy_id.unwrap();
+        assert_eq!(database::load_board(&laptop, 1).unwrap().name, "Desktop edit");
+        assert!(database::load_board(&laptop, copy_id).unwrap().name.starts_with("Laptop edit (conflicted copy"));
+
+        // The desktop receives the copy and loses nothing either
+        sync_once(&desktop, &folder).unwrap();
+        let mut names: Vec<String> = database::load_all_boards(&desktop).unwrap().into_iter().map(|b| b.name).collect();
+        names.sort();
+        assert_eq!(names.len(), 2);
+        assert_eq!(names[0], "Desktop edit");
+        assert!(names[1].starts_with("Laptop edit"));
+
+        // Settled: another round changes nothing
+        for dir in [&desktop, &laptop] {
+            let report = sync_once(dir, &folder).unwrap();
+            assert!(report.pushed.is_empty() && report.pulled.is_empty() && report.conflicts.is_empty());
+        }
+
+        for dir in [desktop, laptop, folder] {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+
+    #[test]
+    fn an_edit_wins_over_a_delete() {
+        let (desktop, laptop, folder) = (machine("desktop"), machine("laptop"), temp_dir("folder"));
+        board_with_image(&desktop, 1, "Keep me");
+        sync_once(&desktop, &folder).unwrap();
+        sync_once(&laptop, &folder).unwrap();
+
+        database::delete_board(&desktop, 1).unwrap();
+        sync_once(&desktop, &folder).unwrap();
+        rename(&laptop, 1, "Still needed");
+        assert_eq!(sync_once(&laptop, &folder).unwrap().pushed, vec![1]);
+        assert_eq!(sync_once(&desktop, &folder).unwrap().pulled, vec![1]);
+        assert_eq!(database::load_board(&desktop, 1).unwrap().name, "Still needed");
+
+        for dir in [desktop, laptop, folder] {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+
+    #[test]
+    fn panning_is_not_an_edit() {
+        let (desktop, laptop, folder) = (machine("desktop"), machine("laptop"), temp_dir("folder"));
+        board_with_image(&desktop, 1, "Shared");
+        sync_once(&desktop, &folder).unwrap();
+        sync_once(&laptop, &folder).unwrap();
+
+        let mut board = database::load_board(&laptop, 1).unwrap();
+        board.view_state = Some(serde_json::json!({ "x": 40, "y": -12, "zoom": 2 }));
+        board.thumbnail = Some("data:image/png;base64,AAAA".to_string());
+        board.updated_at += 1000;
+        database::save_board(&laptop, &board).unwrap();
+        rename(&desktop, 1, "Desktop edit");
+        sync_once(&desktop, &folder).unwrap();
+
+        let report = sync_once(&laptop, &folder).unwrap();
+        assert!(report.conflicts.is_empty() && report.pushed.is_empty());
+        assert_eq!(report.pulled, vec![1]);
+        assert_eq!(database::load_board(&laptop, 1).unwrap().name, "Desktop edit");
+
+        for dir in [desktop, laptop, folder] {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+
+    #[test]
+    fn refuses_records_for_another_board() {
+        let (desktop, laptop, folder) = (machine("desktop"), machine("laptop"), temp_dir("folder"));
+        board_with_image(&laptop, 1, "Mine");
+        board_with_image(&desktop, 1, "Theirs");
+        sync_once(&desktop, &folder).unwrap();
+        let mut record = read_record(&folder, 1).unwrap().unwrap();
+        fs::remove_file(record_path(&folder, 1)).unwrap();
+        record.id = 2;
+        write_record(&folder, &record).unwrap();
+
+        let report = sync_once(&laptop, &folder).unwrap();
+        assert_eq!(report.errors.len(), 1);
+        assert!(report.pulled.is_empty());
+        assert_eq!(database::load_board(&laptop, 1).unwrap().name, "Mine");
+
+        for dir in [desktop, laptop, folder] {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
@@ -0,0 +1,316 @@
+//! Importing many web URLs at once. Page URLs are resolved to the image
+//! they show; downloads run on a bounded pool of worker threads.
+
+use crate::attribution;
+use crate::database::{self, Asset, DataDir, Layer};
+use crate::fetch::{self, Expect, FetchError};
+use crate::import;
+use crate::page_images;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use url::Url;
+
+/// Upper bound on workers, whatever the settings say.
+const MAX_WORKERS: usize = 16;
+
+/// A stored, unplaced layer and the URL of the media it holds.
+type Downloaded = Result<(Layer, String), String>;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UrlImportOptions {
+    /// Parallel downloads; `maxConcurrentDownloads` from the fetch settings when absent.
+    pub concurrency: Option<usize>,
+    /// Tags for library assets.
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UrlStage {
+    Downloading,
+    /// A page URL was resolved to the image in `imageUrl`.
+    Resolved,
+    Imported,
+    Failed,
+}
+
+/// Payload of `url-import-progress`, and of `url-import-failed` when a URL
+/// could not be imported.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UrlImportProgress {
+    /// Position of the URL in the request.
+    pub index: usize,
+    pub url: String,
+    pub stage: UrlStage,
+    /// URLs finished so far, failed ones included.
+    pub done: usize,
+    pub total: usize,
+    /// The media downloaded, when it differs from `url`.
+    pub image_url: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedUrl {
+    pub url: String,
+    pub image_url: String,
+    pub layer: Layer,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedUrl {
+    pub url: String,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UrlImportSummary {
+    /// The board the media went to; the library when absent.
+    pub board_id: Option<u64>,
+    /// In request order, placed as they were added.
+    pub imported: Vec<ImportedUrl>,
+    pub failed: Vec<FailedUrl>,
+    /// Assets added to the library.
+    pub library: Vec<Asset>,
+}
+
+/// Downloads `url` into the media store and returns an unplaced layer with
+/// its attribution, plus the URL of the media itself. A page URL is
+/// resolved to its best image first.
+pub fn download(app: &impl DataDir, images_dir: &Path, url: &str, mut on_resolved: impl FnMut(&str)) -> Downloaded {
+    let fetched = fetch::fetch(app, url, Expect::MediaOrPage, Some(url))?;
+    let (image_url, fetched, source) = if fetched.is_media() {
+        (url.to_string(), fetched, attribution::describe(app, url, None))
+    } else {
+        // The page is read once, both for its images and their attribution
+        let page = Url::parse(url).map_err(|_| FetchError::InvalidUrl { url: url.to_string() })?;
+        let html = fetched.text();
+        let candidates = page_images::resolve_html(app, &page, &html);
+        let image_url = candidates.into_iter().next().map(|c| c.url).ok_or("No image found on the page")?;
+        on_resolved(&image_url);
+        let fetched = fetch::fetch(app, &image_url, Expect::Media, Some(url))?;
+        let source = attribution::from_page(&html, &page, &image_url, database::now_millis());
+        (image_url, fetched, source)
+    };
+
+    let name = import::url_file_name(&image_url).unwrap_or_else(|| "download".to_string());
+    let ext = import::media_extension(fetched.content_type.as_deref(), &name, &fetched.bytes)
+        .ok_or("Unsupported media type")?;
+    // Stored names are only unique per millisecond; keep workers from racing on them
+    static WRITE_LOCK: Mutex<()> = Mutex::new(());
+    let mut layer = {
+        let _guard = WRITE_LOCK.lock().unwrap();
+        import::import_bytes(images_dir, &fetched.bytes, &name, ext)?
+    };
+    layer.source = Some(source);
+    Ok((layer, image_url))
+}
+
+/// Downloads every URL with up to `concurrency` workers. Results keep the
+/// request order; nothing is saved to a board or the library here.
+pub fn download_all(
+    app: &(impl DataDir + Sync),
+    urls: &[String],
+    concurrency: usize,
+    progress: impl Fn(UrlImportProgress) + Sync,
+) -> Vec<Downloaded> {
+    let images_dir = database::get_images_dir(app);
+    let total = urls.len();
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Downloaded>>> = Mutex::new(vec![None; total]);
+
+    let workers = concurrency.clamp(1, MAX_WORKERS).min(total.max(1));
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(url) = urls.get(index) else { break };
+                let report = |stage, done, image_url: Option<&str>, error: Option<&String>| {
+                    progress(UrlImportProgress {
+                        index,
+                        url: url.clone(),
+                        stage,
+                        done,
+                        total,
+                        image_url: image_url.map(str::to_string),
+                        error: error.cloned(),
+                    })
+                };
+
+                report(UrlStage::Downloading, done.load(Ordering::Relaxed), None, None);
+                let result = download(app, &images_dir, url, |image_url| {
+                    report(UrlStage::Resolved, done.load(Ordering::Relaxed), Some(image_url), None)
+                });
+                let finished = done.fetch_add(1, Ordering::Relaxed) + 1;
+                match &result {
+                    Ok((_, image_url)) => report(UrlStage::Imported, finished, Some(image_url), None),
+                    Err(e) => report(UrlStage::Failed, finished, None, Some(e)),
+                }
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|r| r.unwrap_or_else(|| Err("Not downloaded".to_string())))
+        .collect()
+}
+
+/// Downloads the URLs and adds them to the board at its current view, or
+/// to the library when no board is given.
+pub fn import_urls(
+    app: &(impl DataDir + Sync),
+    urls: &[String],
+    board_id: Option<u64>,
+    options: &UrlImportOptions,
+    progress: impl Fn(UrlImportProgress) + Sync,
+) -> Result<UrlImportSummary, String> {
+    // Fail before downloading anything if the board is missing
+    if let Some(id) = board_id {
+        database::load_board(app, id)?;
+    }
+    let concurrency = options
+        .concurrency
+        .unwrap_or_else(|| database::load_fetch_settings(app).unwrap_or_default().max_concurrent_downloads);
+
+    let mut downloaded = Vec::new();
+    let mut failed = Vec::new();
+    for (url, result) in urls.iter().zip(download_all(app, urls, concurrency, progress)) {
+        match result {
+            Ok((layer, image_url)) => downloaded.push((url.clone(), image_url, layer)),
+            Err(error) => failed.push(FailedUrl { url: url.clone(), error }),
+        }
+    }
+
+    let layers: Vec<Layer> = downloaded.iter().map(|(_, _, layer)| layer.clone()).collect();
+    let (placed, library) = match board_id {
+        Some(id) => {
+            // Loaded again so edits made during the downloads are kept
+            let board = database::load_board(app, id)?;
+            let count = layers.len();
+            let mut board = import::add_layers_at_view(board, layers);
+            if count > 0 {
+                board.updated_at = database::now_millis();
+                database::save_board(app, &board)?;
+            }
+            (board.layers[board.layers.len() - count..].to_vec(), Vec::new())
+        }
+        None => {
+            let first_id = database::new_ids(layers.len());
+            let layers: Vec<Layer> = layers
+                .into_iter()
+                .enumerate()
+                .map(|(i, layer)| Layer { id: first_id + i as f64, ..layer })
+                .collect();
+            let assets = layers.iter().map(|l| import::asset_for(l, options.tags.clone())).collect();
+            let library = database::add_assets_to_library(app, assets)?;
+            (layers, library)
+        }
+    };
+
+    let imported = downloaded
+        .into_iter()
+        .zip(placed)
+        .map(|((url, image_url, _), layer)| ImportedUrl { url, image_url, layer })
+        .collect();
+    Ok(UrlImportSummary { board_id, imported, failed, library })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    fn png() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image::RgbaImage::new(4, 3)
+            .write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    /// Serves a page pointing at an image and counts the requests per path.
+    fn serve() -> (String, Arc<Mutex<HashMap<String, usize>>>) {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let hits = Arc::new(Mutex::new(HashMap::new()));
+        let page = format!(
+            r#"<html><head><title>Lighthouse</title><meta property="og:image" content="{}/full.png"></head></html>"#,
+            base
+        );
+        let counted = hits.clone();
+        std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                *counted.lock().unwrap().entry(request.url().to_string()).or_insert(0) += 1;
+                let (body, content_type) = match request.url() {
+                    "/full.png" => (png(), "image/png"),
+                    _ => (page.clone().into_bytes(), "text/html"),
+                };
+                let response = tiny_http::Response::from_data(body)
+                    .with_header(tiny_http::Header::from_bytes("Content-Type", content_type).unwrap())
+                    .with_header(tiny_http::Header::from_bytes("Cache-Control", "no-store").unwrap());
+                let _ = request.respond(response);
+            }
+        });
+        (base, hits)
+    }
+
+    fn data_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("eyedea-url-import-{}", database::random_token().unwrap()));
+        database::init_storage(&dir).unwrap();
+        let settings = database::FetchSettings { allow_private_addresses: true, ..Default::default() };
+        database::save_fetch_settings(&dir, &settings).unwrap();
+        dir
+    }
+
+    #[test]
+    fn fetches_a_page_once() {
+        let dir = data_dir();
+        let (base, hits) = serve();
+        let page = format!("{}/page", base);
+
+        let mut resolved = None;
+        let (layer, image_url) =
+            download(&dir, &database::get_images_dir(&dir), &page, |url| resolved = Some(url.to_string())).unwrap();
+        assert_eq!(image_url, format!("{}/full.png", base));
+        assert_eq!(resolved.as_deref(), Some(image_url.as_str()));
+        let source = layer.source.unwrap();
+        assert_eq!(source.page_url.as_deref(), Some(page.as_str()));
+        assert_eq!(source.source_url, image_url);
+        assert_eq!(source.page_title.as_deref(), Some("Lighthouse"));
+
+        let hits = hits.lock().unwrap();
+        assert_eq!((hits["/page"], hits["/full.png"]), (1, 1));
+        drop(hits);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn gives_library_assets_their_own_ids() {
+        let dir = data_dir();
+        let (base, _) = serve();
+        let urls = vec![format!("{}/full.png", base), format!("{}/page", base)];
+
+        let first = import_urls(&dir, &urls, None, &UrlImportOptions::default(), |_| {}).unwrap();
+        let second = import_urls(&dir, &urls[..1], None, &UrlImportOptions::default(), |_| {}).unwrap();
+        assert!(first.failed.is_empty());
+        let mut ids: Vec<f64> = first.imported.iter().chain(&second.imported).map(|i| i.layer.id).collect();
+        ids.sort_by(f64::total_cmp);
+        ids.dedup();
+        assert_eq!(ids.len(), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,455 @@
+//! Passphrase-encrypted boards. A locked board's JSON and the media only it
+//! uses are sealed with a random data key, which is itself sealed with a key
+//! derived from the passphrase (Argon2id), so changing the passphrase only
+//! rewraps the data key. Sealing is XChaCha20-Poly1305 throughout.
+//!
+//! The envelope lives at `boards/<id>.locked` and the media under
+//! `locked_media/<id>/`. Only the name, colour and timestamps stay readable,
+//! for the board list. Unlocking keeps the data key in memory until the
+//! board is locked again or the app quits; nothing decrypted is written out.
+
+use crate::database::{self, Board, BoardMetadata, DataDir};
+use crate::media_protocol;
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+
+const NONCE_LEN: usize = 24;
+
+const WRONG_PASSPHRASE: &str = "Wrong passphrase";
+
+type Key = [u8; 32];
+
+/// An unlocked board: its data key and where its media is sealed.
+struct Session {
+    key: Key,
+    media: HashMap<String, String>,
+}
+
+/// Unlocked boards by images directory and board id.
+static SESSIONS: LazyLock<Mutex<HashMap<(PathBuf, u64), Session>>> = LazyLock::new(Default::default);
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Envelope {
+    id: u64,
+    name: String,
+    bg_color: String,
+    created_at: u64,
+    updated_at: u64,
+    kdf: Kdf,
+    /// The data key, sealed with the passphrase key.
+    wrapped_key: String,
+    /// `Contents`, sealed with the data key.
+    contents: String,
+}
+
+/// Argon2id parameters, kept so they can be raised without breaking
+/// existing boards.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Kdf {
+    salt: String,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Contents {
+    board: Board,
+    /// Stored filename to the sealed copy under `locked_media/<id>/`.
+    media: HashMap<String, String>,
+}
+
+fn envelope_path(app: &impl DataDir, id: u64) -> PathBuf {
+    database::get_boards_dir(app).join(format!("{}.locked", id))
+}
+
+fn media_dir(app: &impl DataDir, id: u64) -> PathBuf {
+    app.data_dir().join("locked_media").join(id.to_string())
+}
+
+fn session_key(app: &impl DataDir, id: u64) -> (PathBuf, u64) {
+    (database::get_images_dir(app), id)
+}
+
+fn random_bytes<const N: usize>() -> Result<[u8; N], String> {
+    let mut bytes = [0u8; N];
+    getrandom::fill(&mut bytes).map_err(|e| format!("Failed to generate a key: {}", e))?;
+    Ok(bytes)
+}
+
+fn new_kdf() -> Result<Kdf, String> {
+    let params = Params::default();
+    Ok(Kdf {
+        salt: STANDARD.encode(random_bytes::<16>()?),
+        memory_kib: params.m_cost(),
+        iterations: params.t_cost(),
+        parallelism: params.p_cost(),
+    })
+}
+
+fn derive_key(passphrase: &str, kdf: &Kdf) -> Result<Key, String> {
+    let salt = STANDARD.decode(&kdf.salt).map_err(|e| e.to_string())?;
+    let params = Params::new(kdf.memory_kib, kdf.iterations, kdf.parallelism, Some(32)).map_err(|e| e.to_string())?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+        .map_err(|e| e.to_string())?;
+    Ok(key)
+}
+
+/// A random nonce followed by the ciphertext. `aad` ties the result to
+/// where it's stored, so sealed pieces can't be swapped between boards.
+fn seal(key: &Key, plaintext: &[u8], aad: &str) -> Result<Vec<u8>, String> {
+    let nonce = random_bytes::<NONCE_LEN>()?;
+    let ciphertext = XChaCha20Poly1305::new(key.into())
+        .encrypt(XNonce::from_slice(&nonce), Payload { msg: plaintext, aad: aad.as_bytes() })
+        .map_err(|_| "Encryption failed".to_string())?;
+    Ok([nonce.as_slice(), &ciphertext].concat())
+}
+
+fn open(key: &Key, sealed: &[u8], aad: &str) -> Option<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        return None;
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    XChaCha20Poly1305::new(key.into())
+        .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: aad.as_bytes() })
+        .ok()
+}
+
+fn key_aad(id: u64) -> String {
+    format!("eyedea-key:{}", id)
+}
+
+fn contents_aad(id: u64) -> String {
+    format!("eyedea-board:{}", id)
+}
+
+fn media_aad(id: u64, sealed_name: &str) -> String {
+    format!("eyedea-media:{}:{}", id, sealed_name)
+}
+
+fn read_envelope(app: &impl DataDir, id: u64) -> Result<Envelope, String> {
+    let content = fs::read_to_string(envelope_path(app, id)).map_err(|_| format!("Board {} is not encrypted", id))?;
+    serde_json::from_str(&content).map_err(|e| format!("Invalid encrypted board: {}", e))
+}
+
+fn write_envelope(app: &impl DataDir, envelope: &Envelope) -> Result<(), String> {
+    let path = envelope_path(app, envelope.id);
+    let partial = path.with_extension("locked.part");
+    let content = serde_json::to_string_pretty(envelope).map_err(|e| e.to_string())?;
+    fs::write(&partial, content).map_err(|e| e.to_string())?;
+    fs::rename(&partial, &path).map_err(|e| e.to_string())
+}
+
+fn unwrap_key(envelope: &Envelope, passphrase: &str) -> Result<Key, String> {
+    let passphrase_key = derive_key(passphrase, &envelope.kdf)?;
+    let wrapped = STANDARD.decode(&envelope.wrapped_key).map_err(|e| e.to_string())?;
+    open(&passphrase_key, &wrapped, &key_aad(envelope.id))
+        .and_then(|key| Key::try_from(key.as_slice()).ok())
+        .ok_or_else(|| WRONG_PASSPHRASE.to_string())
+}
+
+fn open_contents(envelope: &Envelope, key: &Key) -> Result<Contents, String> {
+    let sealed = STANDARD.decode(&envelope.contents).map_err(|e| e.to_string())?;
+    let json = open(key, &sealed, &contents_aad(envelope.id)).ok_or("Encrypted board is damaged")?;
+    serde_json::from_slice(&json).map_err(|e| format!("Invalid encrypted board: {}", e))
+}
+
+fn seal_contents(envelope: &mut Envelope, key: &Key, contents: &Contents) -> Result<(), String> {
+    let board = &contents.board;
+    envelope.name = board.name.clone();
+    envelope.bg_color = board.bg_color.clone();
+    envelope.updated_at = board.updated_at;
+    let json = serde_json::to_vec(contents).map_err(|e| e.to_string())?;
+    envelope.contents = STANDARD.encode(seal(key, &json, &contents_aad(envelope.id))?);
+    Ok(())
+}
+
+/// Stored files the board refers to.
+fn media_srcs<'a>(images_dir: &Path, board: &'a Board) -> Vec<&'a str> {
+    let srcs = board.layers.iter().map(|l| l.src.as_str()).chain(board.assets.iter().map(|a| a.src.as_str()));
+    let mut seen = HashSet::new();
+    srcs.filter(|src| seen.insert(*src))
+        .filter(|src| database::media_path(images_dir, src).is_ok_and(|p| p.is_file()))
+        .collect()
+}
+
+/// Seals stored files not sealed yet and returns the ones it sealed.
+fn seal_new_media(app: &impl DataDir, id: u64, key: &Key, board: &Board, media: &mut HashMap<String, String>) -> Result<Vec<String>, String> {
+    let images_dir = database::get_images_dir(app);
+    let dir = media_dir(app, id);
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let mut sealed = Vec::new();
+    for src in media_srcs(&images_dir, board) {
+        if media.contains_key(src) {
+            continue;
+        }
+        let bytes = fs::read(database::media_path(&images_dir, src)?).map_err(|e| e.to_string())?;
+        let name = format!("{}.bin", STANDARD.encode(random_bytes::<12>()?).replace(['/', '+'], "_"));
+        fs::write(dir.join(&name), seal(key, &bytes, &media_aad(id, &name))?).map_err(|e| e.to_string())?;
+        media.insert(src.to_string(), name);
+        sealed.push(src.to_string());
+    }
+    Ok(sealed)
+}
+
+/// Deletes the plain copies of sealed media, with their cached variants and
+/// pyramids, unless another plain board or the library still uses them.
+fn remove_plain_media(app: &impl DataDir, srcs: &[String]) {
+    if srcs.is_empty() {
+        return;
+    }
+    let images_dir = database::get_images_dir(app);
+    let mut in_use: HashSet<String> = database::load_all_assets(app).unwrap_or_default().into_iter().map(|a| a.src).collect();
+    if let Ok(entries) = fs::read_dir(database::get_boards_dir(app)) {
+        for entry in entries.flatten() {
+            if entry.path().extension().is_some_and(|e| e == "json") {
+                let board = fs::read_to_string(entry.path()).ok().and_then(|c| serde_json::from_str::<Board>(&c).ok());
+                if let Some(board) = board {
+                    in_use.extend(board.layers.into_iter().map(|l| l.src));
+                    in_use.extend(board.assets.into_iter().map(|a| a.src));
+                }
+            }
+        }
+    }
+    for src in srcs.iter().filter(|src| !in_use.contains(*src)) {
+        media_protocol::forget_media(app, src);
+        if let Ok(path) = database::media_path(&images_dir, src) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+pub fn is_encrypted(app: &impl DataDir, id: u64) -> bool {
+    envelope_path(app, id).is_file()
+}
+
+/// Whether the board is encrypted and unlocked this session.
+pub fn is_unlocked(app: &impl DataDir, id: u64) -> bool {
+    SESSIONS.lock().unwrap().contains_key(&session_key(app, id))
+}
+
+/// The readable part of an envelope, as a locked list entry.
+pub fn read_metadata(content: &str) -> Option<BoardMetadata> {
+    let envelope: Envelope = serde_json::from_str(content).ok()?;
+    Some(BoardMetadata {
+        id: envelope.id,
+        name: envelope.name,
+        bg_color: envelope.bg_color,
+        created_at: envelope.created_at,
+        updated_at: envelope.updated_at,
+        thumbnail: None,
+        locked: true,
+        encrypted: true,
+    })
+}
+
+/// List entries for encrypted boards, never with a thumbnail.
+pub fn list(app: &impl DataDir) -> Vec<BoardMetadata> {
+    let mut boards = Vec::new();
+    if let Ok(entries) = fs::read_dir(database::get_boards_dir(app)) {
+        for entry in entries.flatten() {
+            if entry.path().extension().is_some_and(|e| e == "locked") {
+                let metadata = fs::read_to_string(entry.path()).ok().and_then(|c| read_metadata(&c));
+                if let Some(mut metadata) = metadata {
+                    metadata.locked = !is_unlocked(app, metadata.id);
+                    boards.push(metadata);
+                }
+            }
+        }
+    }
+    boards
+}
+
+/// Locks every unlocked board, e.g. after their files were replaced.
+pub fn lock_all(app: &impl DataDir) {
+    let images_dir = database::get_images_dir(app);
+    SESSIONS.lock().unwrap().retain(|(dir, _), _| *dir != images_dir);
+}
+
+/// The board if it's encrypted and unlocked; `None` if it isn't encrypted.
+pub fn load(app: &impl DataDir, id: u64) -> Result<Option<Board>, String> {
+    if !is_encrypted(app, id) {
+        return Ok(None);
+    }
+    let sessions = SESSIONS.lock().unwrap();
+    let session = sessions.get(&session_key(app, id)).ok_or_else(|| format!("Board {} is locked", id))?;
+    Ok(Some(open_contents(&read_envelope(app, id)?, &session.key)?.board))
+}
+
+/// Re-seals an unlocked board, sealing any media added since.
+pub fn save(app: &impl DataDir, board: &Board) -> Result<(), String> {
+    let mut sessions = SESSIONS.lock().unwrap();
+    let session = sessions
+        .get_mut(&session_key(app, board.id))
+        .ok_or_else(|| format!("Board {} is locked", board.id))?;
+    let mut envelope = read_envelope(app, board.id)?;
+    let sealed = seal_new_media(app, board.id, &session.key, board, &mut session.media)?;
+    let contents = Contents { board: board.clone(), media: session.media.clone() };
+    seal_contents(&mut envelope, &session.key, &contents)?;
+    write_envelope(app, &envelope)?;
+    drop(sessions);
+    remove_plain_media(app, &sealed);
+    Ok(())
+}
+
+/// Encrypts a plain board with a passphrase, or locks an unlocked one again.
+pub fn lock(app: &impl DataDir, id: u64, passphrase: Option<&str>) -> Result<(), String> {
+    if is_encrypted(app, id) {
+        SESSIONS.lock().unwrap().remove(&session_key(app, id));
+        return Ok(());
+    }
+    let passphrase = passphrase.filter(|p| !p.is_empty()).ok_or("A passphrase is required to encrypt a board")?;
+    let board = database::load_board(app, id)?;
+
+    let key = random_bytes::<32>()?;
+    let kdf = new_kdf()?;
+    let wrapped = seal(&derive_key(passphrase, &kdf)?, &key, &key_aad(id))?;
+    let mut media = HashMap::new();
+    let sealed = seal_new_media(app, id, &key, &board, &mut media)?;
+    let mut envelope = Envelope {
+        id,
+        name: String::new(),
+        bg_color: String::new(),
+        created_at: board.created_at,
+        updated_at: 0,
+        kdf,
+        wrapped_key: STANDARD.encode(wrapped),
+        contents: String::new(),
+    };
+    seal_contents(&mut envelope, &key, &Contents { board, media })?;
+    write_envelope(app, &envelope)?;
+
+    database::delete_plain_board(app, id)?;
+    remove_plain_media(app, &sealed);
+    Ok(())
+}
+
+/// Unlocks an encrypted board for this session and returns it.
+pub fn unlock(app: &impl DataDir, id: u64, passphrase: &str) -> Result<Board, String> {
+    let envelope = read_envelope(app, id)?;
+    let key = unwrap_key(&envelope, passphrase)?;
+    let contents = open_contents(&envelope, &key)?;
+    SESSIONS
+        .lock()
+        .unwrap()
+        .insert(session_key(app, id), Session { key, media: contents.media });
+    Ok(contents.board)
+}
+
+/// Rewraps the data key under a new passphrase.
+pub fn change_passphrase(app: &impl DataDir, id: u64, old: &str, new: &str) -> Result<(), String> {
+    if new.is_empty() {
+        return Err("The new passphrase can't be empty".to_string());
+    }
+    let mut envelope = read_envelope(app, id)?;
+    let key = unwrap_key(&envelope, old)?;
+    envelope.kdf = new_kdf()?;
+    envelope.wrapped_key = STANDARD.encode(seal(&derive_key(new, &envelope.kdf)?, &key, &key_aad(id))?);
+    write_envelope(app, &envelope)
+}
+
+/// Deletes an encrypted board and its sealed media. Returns false if the
+/// board isn't encrypted.
+pub fn delete(app: &impl DataDir, id: u64) -> Result<bool, String> {
+    if !is_encrypted(app, id) {
+        return Ok(false);
+    }
+    SESSIONS.lock().unwrap().remove(&session_key(app, id));
+    fs::remove_file(envelope_path(app, id)).map_err(|e| e.to_string())?;
+    let _ = fs::remove_dir_all(media_dir(app, id));
+    Ok(true)
+}
+
+/// Decrypted media for a stored filename used by an unlocked board.
+pub fn media_bytes(images_dir: &Path, src: &str) -> Option<Vec<u8>> {
+    let sessions = SESSIONS.lock().unwrap();
+    sessions.iter().find_map(|((dir, id), session)| {
+        let name = session.media.get(src).filter(|_| dir == images_dir)?;
+        let data_dir = images_dir.parent()?.to_path_buf();
+        let sealed = fs::read(media_dir(&data_dir, *id).join(name)).ok()?;
+        open(&session.key, &sealed, &media_aad(*id, name))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("eyedea-vault-{}-{}", std::process::id(), database::random_token().unwrap()));
+        database::init_storage(&dir).unwrap();
+        dir
+    }
+
+    fn board_with_media(dir: &PathBuf, id: u64) -> Board {
+        let src = database::write_media_file(&database::get_images_dir(dir), b"secret pixels", "ref", "png").unwrap();
+        let mut board = database::new_board("Client".to_string(), "#101010".to_string());
+        board.id = id;
+        board.thumbnail = Some("data:image/png;base64,AAAA".to_string());
+        board.layers.push(serde_json::from_value(serde_json::json!({
+            "id": 1.0, "name": "ref", "src": src, "x": 0.0, "y": 0.0, "width": 10.0, "height": 10.0
+        })).unwrap());
+        database::save_board(dir, &board).unwrap();
+        board
+    }
+
+    #[test]
+    fn locks_and_unlocks_a_board() {
+        let dir = data_dir();
+        let board = board_with_media(&dir, 7);
+        let src = board.layers[0].src.clone();
+        let images_dir = database::get_images_dir(&dir);
+
+        lock(&dir, 7, Some("hunter2")).unwrap();
+        assert!(!database::media_path(&images_dir, &src).unwrap().exists());
+        assert!(database::load_board(&dir, 7).unwrap_err().contains("locked"));
+        let listed = database::load_all_boards(&dir).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert!(listed[0].locked && listed[0].thumbnail.is_none());
+        let on_disk = fs::read_to_string(envelope_path(&dir, 7)).unwrap();
+        assert!(!on_disk.contains("base64,AAAA") && !on_disk.contains(&src));
+
+        assert_eq!(unlock(&dir, 7, "wrong").unwrap_err(), WRONG_PASSPHRASE);
+        let unlocked = unlock(&dir, 7, "hunter2").unwrap();
+        assert_eq!(unlocked.layers[0].src, src);
+        assert_eq!(media_bytes(&images_dir, &src).unwrap(), b"secret pixels");
+
+        let mut renamed = database::load_board(&dir, 7).unwrap();
+        renamed.name = "Renamed".to_string();
+        database::save_board(&dir, &renamed).unwrap();
+        assert_eq!(database::load_all_boards(&dir).unwrap()[0].name, "Renamed");
+
+        lock(&dir, 7, None).unwrap();
+        assert!(media_bytes(&images_dir, &src).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn changes_the_passphrase() {
+        let dir = data_dir();
+        board_with_media(&dir, 8);
+        lock(&dir, 8, Some("old")).unwrap();
+
+        assert_eq!(change_passphrase(&dir, 8, "nope", "new").unwrap_err(), WRONG_PASSPHRASE);
+        change_passphrase(&dir, 8, "old", "new").unwrap();
+        assert_eq!(unlock(&dir, 8, "old").unwrap_err(), WRONG_PASSPHRASE);
+        let board = unlock(&dir, 8, "new").unwrap();
+        assert!(media_bytes(&database::get_images_dir(&dir), &board.layers[0].src).is_some());
+
+        database::delete_board(&dir, 8).unwrap();
+        assert!(database::load_all_boards(&dir).unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
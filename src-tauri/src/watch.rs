@@ -0,0 +1,218 @@
+//! Watch folders: new media files landing in a registered directory are
+//! imported into the folder's board next to where its view was left.
+
+use crate::database::{self, WatchFolder};
+use crate::events;
+use crate::import;
+use notify::event::ModifyKind;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+/// How long a file's size must stay unchanged before it is imported, so
+/// downloads still being written are left alone.
+const SETTLE_TIME: Duration = Duration::from_millis(1500);
+
+/// Running watchers, held in Tauri state.
+#[derive(Default)]
+pub struct WatchState {
+    inner: Mutex<Option<Running>>,
+}
+
+struct Running {
+    // Dropping the watchers closes the channel, which stops the worker
+    _watchers: Vec<RecommendedWatcher>,
+}
+
+struct Arrival {
+    board_id: u64,
+    path: PathBuf,
+}
+
+pub fn add_folder(app: &AppHandle, board_id: u64, path: String, recursive: bool) -> Result<WatchFolder, String> {
+    if !Path::new(&path).is_dir() {
+        return Err(format!("Not a folder: {}", path));
+    }
+    database::load_board(app, board_id)?;
+
+    let mut folders = database::load_watch_folders(app)?;
+    if let Some(existing) = folders.iter().find(|f| f.board_id == board_id && f.path == path) {
+        return Ok(existing.clone());
+    }
+    let folder = WatchFolder {
+        id: folders.iter().map(|f| f.id + 1).fold(database::now_millis(), u64::max),
+        board_id,
+        path,
+        recursive,
+    };
+    folders.push(folder.clone());
+    database::save_watch_folders(app, &folders)?;
+    reload(app)?;
+    Ok(folder)
+}
+
+pub fn remove_folders(app: &AppHandle, keep: impl Fn(&WatchFolder) -> bool) -> Result<(), String> {
+    let mut folders = database::load_watch_folders(app)?;
+    let before = folders.len();
+    folders.retain(keep);
+    if folders.len() != before {
+        database::save_watch_folders(app, &folders)?;
+        reload(app)?;
+    }
+    Ok(())
+}
+
+pub fn is_running(app: &AppHandle) -> bool {
+    let state = app.state::<WatchState>();
+    let running = state.inner.lock().unwrap().is_some();
+    running
+}
+
+/// Starts watching every registered folder. Files that are already there
+/// are left alone; only new arrivals are imported.
+pub fn resume(app: &AppHandle) -> Result<(), String> {
+    let state = app.state::<WatchState>();
+    let mut inner = state.inner.lock().unwrap();
+    if inner.is_some() {
+        return Ok(());
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watchers = Vec::new();
+    for folder in database::load_watch_folders(app)? {
+        match watch_folder(&folder, sender.clone()) {
+            Ok(watcher) => watchers.push(watcher),
+            Err(e) => log::warn!("Failed to watch {}: {}", folder.path, e),
+        }
+    }
+    drop(sender);
+
+    let handle = app.clone();
+    std::thread::spawn(move || run_worker(handle, receiver));
+    *inner = Some(Running { _watchers: watchers });
+    Ok(())
+}
+
+/// Stops all watchers. Files still settling are dropped.
+pub fn pause(app: &AppHandle) {
+    let state = app.state::<WatchState>();
+    state.inner.lock().unwrap().take();
+}
+
+/// Picks up changes to the registered folders if watching is active.
+fn reload(app: &AppHandle) -> Result<(), String> {
+    if is_running(app) {
+        pause(app);
+        resume(app)?;
+    }
+    Ok(())
+}
+
+fn watch_folder(folder: &WatchFolder, sender: Sender<Arrival>) -> Result<RecommendedWatcher, String> {
+    let board_id = folder.board_id;
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+            || matches!(event.kind, EventKind::Modify(ModifyKind::Metadata(_)))
+        {
+            return;
+        }
+        for path in event.paths {
+            let hidden = path
+                .file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with('.'));
+            if !hidden && import::media_kind(&path).is_some() {
+                let _ = sender.send(Arrival { board_id, path });
+            }
+        }
+    })
+    .map_err(|e| e.to_string())?;
+
+    let mode = if folder.recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+    watcher.watch(Path::new(&folder.path), mode).map_err(|e| e.to_string())?;
+    Ok(watcher)
+}
+
+struct Pending {
+    board_id: u64,
+    size: Option<u64>,
+    changed: Instant,
+}
+
+fn run_worker(app: AppHandle, receiver: Receiver<Arrival>) {
+    let mut pending: HashMap<PathBuf, Pending> = HashMap::new();
+    // Saving over an imported file shouldn't import it again
+    let mut imported: HashSet<PathBuf> = HashSet::new();
+    loop {
+        match receiver.recv_timeout(SETTLE_TIME / 3) {
+            Ok(arrival) if imported.contains(&arrival.path) => {}
+            Ok(arrival) => {
+                pending.entry(arrival.path).or_insert(Pending {
+                    board_id: arrival.board_id,
+                    size: None,
+                    changed: Instant::now(),
+                });
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+
+        let mut ready: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+        pending.retain(|path, entry| {
+            let Ok(meta) = std::fs::metadata(path) else {
+                // Renamed away or deleted before it settled
+                return false;
+            };
+            let size = Some(meta.len());
+            if entry.size != size {
+                entry.size = size;
+                entry.changed = Instant::now();
+                return true;
+            }
+            if entry.changed.elapsed() < SETTLE_TIME || meta.len() == 0 {
+                return true;
+            }
+            ready.entry(entry.board_id).or_default().push(path.clone());
+            imported.insert(path.clone());
+            false
+        });
+
+        for (board_id, mut paths) in ready {
+            paths.sort();
+            if let Err(e) = import_arrivals(&app, board_id, &paths) {
+                log::warn!("Watch folder import into board {} failed: {}", board_id, e);
+            }
+        }
+    }
+}
+
+fn import_arrivals(app: &AppHandle, board_id: u64, paths: &[PathBuf]) -> Result<(), String> {
+    let board = database::load_board(app, board_id)?;
+    let images_dir = database::get_images_dir(app);
+
+    let mut layers = Vec::new();
+    for path in paths {
+        match import::import_file(&images_dir, path) {
+            Ok(layer) => layers.push(layer),
+            Err(e) => log::warn!("Skipping {}: {}", path.display(), e),
+        }
+    }
+    if layers.is_empty() {
+        return Ok(());
+    }
+
+    let count = layers.len();
+    let mut board = import::add_layers_at_view(board, layers);
+    board.updated_at = database::now_millis();
+    database::save_board(app, &board)?;
+
+    let added = board.layers[board.layers.len() - count..].to_vec();
+    events::board_updated(app, &board, added, None);
+    Ok(())
+}
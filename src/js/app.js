@@ -201,36 +201,7 @@ class App {
         }
 
         try {
-            const { WebviewWindow } = window.__TAURI__.webviewWindow;
-            const windowLabel = 'floating_' + this.currentBoardId + '_' + Date.now();
-            // Construct URL using the same origin as current window
-            const url = new URL(window.location.href);
-            url.pathname = '/floating.html';
-            url.search = `?id=${this.currentBoardId}`;
-            const floatingUrl = url.toString();
-            console.log('Opening floating window with URL:', floatingUrl);
-
-            const floatingWindow = new WebviewWindow(windowLabel, {
-                url: floatingUrl,
-                title: boardManager.currentBoard?.name || 'Board',
-                width: 800,
-                height: 600,
-                alwaysOnTop: false,
-                decorations: false,
-                titleBarStyle: 'overlay',
-                resizable: true,
-                center: true,
-                transparent: false,
-                dragDropEnabled: false
-            });
-
-            floatingWindow.once('tauri://created', () => {
-                console.log('Floating window created');
-            });
-
-            floatingWindow.once('tauri://error', (e) => {
-                console.error('Error creating floating window:', e);
-            });
+            await boardManager.openFloatingWindow(this.currentBoardId);
         } catch (err) {
             console.error('Error opening floating window:', err);
         }
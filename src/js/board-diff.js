@@ -0,0 +1,62 @@
+/**
+ * Board Diff - Turns two states of a board into the ops that get from one
+ * to the other, for collab sessions and the board's change history
+ */
+
+const COLLECTIONS = ['layers', 'strokes', 'objects', 'groups'];
+const BOARD_FIELDS = ['name', 'bgColor'];
+
+/**
+ * Ops turning one list of elements into another, matched by id
+ */
+export function diffCollection(collection, before, after) {
+    const ops = [];
+    const previous = new Map(before.map(item => [String(item.id), item]));
+    const seen = new Set();
+
+    for (const item of after) {
+        const key = String(item.id);
+        seen.add(key);
+        const old = previous.get(key);
+        if (!old) {
+            ops.push({ collection, id: item.id, change: { type: 'put', value: item } });
+            continue;
+        }
+        const fields = {};
+        for (const name of new Set([...Object.keys(old), ...Object.keys(item)])) {
+            if (JSON.stringify(old[name] ?? null) !== JSON.stringify(item[name] ?? null)) {
+                fields[name] = item[name] ?? null;
+            }
+        }
+        if (Object.keys(fields).length > 0) {
+            ops.push({ collection, id: item.id, change: { type: 'patch', fields } });
+        }
+    }
+
+    for (const [key, item] of previous) {
+        if (!seen.has(key)) {
+            ops.push({ collection, id: item.id, change: { type: 'remove' } });
+        }
+    }
+    return ops;
+}
+
+/**
+ * Ops turning one board state into another. Collections or board fields
+ * missing from `after` are left alone
+ */
+export function diffBoardState(before, after) {
+    const ops = COLLECTIONS.filter(collection => after[collection])
+        .flatMap(collection => diffCollection(collection, before[collection] || [], after[collection]));
+
+    const fields = {};
+    for (const name of BOARD_FIELDS) {
+        if (after.board?.[name] !== undefined && after.board[name] !== before.board?.[name]) {
+            fields[name] = after.board[name];
+        }
+    }
+    if (Object.keys(fields).length > 0) {
+        ops.push({ collection: 'board', change: { type: 'patch', fields } });
+    }
+    return ops;
+}
@@ -1,3 +1,5 @@
+import { diffBoardState } from './board-diff.js';
+
 class BoardManager {
     constructor() {
         this.boards = [];
@@ -13,6 +15,17 @@ class BoardManager {
         return null;
     }
 
+    // Listens for a backend change event (board-updated, board-deleted,
+    // asset-library-changed, tag-presets-changed), skipping the ones this
+    // window caused. Resolves to the unlisten function
+    async onChange(event, handler) {
+        if (!window.__TAURI__) return () => {};
+        const label = window.__TAURI__.window.Window.getCurrent().label;
+        return await window.__TAURI__.event.listen(event, ({ payload }) => {
+            if (payload.origin !== label) handler(payload);
+        });
+    }
+
     async loadBoards() {
         if (window.__TAURI__) {
             this.boards = await this.invoke('get_all_boards');
@@ -93,6 +106,118 @@ class BoardManager {
         return board;
     }
 
+    // Saves a window's edits since `saved` (a state as the collab session
+    // builds it) as ops on the board's change history, so windows editing
+    // one board merge rather than overwrite each other. Encrypted boards
+    // keep no history and are saved whole. `updates` are fields outside
+    // the history, like viewState and thumbnail
+    async saveBoardEdits(boardId, saved, state, updates = {}) {
+        const entry = this.boards.find(b => b.id === boardId);
+        if (!window.__TAURI__ || !saved || entry?.encrypted) {
+            const { board, ...collections } = state;
+            return await this.updateBoard(boardId, { ...collections, ...(board && { bgColor: board.bgColor }), ...updates });
+        }
+        const ops = diffBoardState(saved, state);
+        if (ops.length > 0) await this.applyBoardOps(boardId, ops);
+        return await this.updateBoard(boardId, updates);
+    }
+
+    // Downloads many URLs at once into a board, or the library when boardId
+    // is null. Progress arrives as url-import-progress events.
+    async importUrls(urls, boardId = null) {
+        if (!window.__TAURI__) return null;
+        return await this.invoke('import_urls', { urls, boardId });
+    }
+
+    // Encrypts a board, or locks an unlocked encrypted board again when no
+    // passphrase is given
+    async lockBoard(boardId, passphrase = null) {
+        await this.invoke('lock_board', { id: boardId, passphrase });
+        await this.loadBoards();
+    }
+
+    async unlockBoard(boardId, passphrase) {
+        const board = await this.invoke('unlock_board', { id: boardId, passphrase });
+        await this.loadBoards();
+        return board;
+    }
+
+    async changeBoardPassphrase(boardId, oldPassphrase, newPassphrase) {
+        await this.invoke('change_board_passphrase', { id: boardId, oldPassphrase, newPassphrase });
+    }
+
+    // Hosts a LAN session for a board; the returned address and code are
+    // what others join with
+    async startCollab(boardId, name = null) {
+        return await this.invoke('start_collab', { boardId, name });
+    }
+
+    async joinCollab(address, code, name = null) {
+        const session = await this.invoke('join_collab', { address, code, name });
+        await this.loadBoards();
+        return session;
+    }
+
+    async getCollabSession() {
+        if (!window.__TAURI__) return null;
+        return await this.invoke('get_collab_session');
+    }
+
+    async sendCollabOps(ops) {
+        await this.invoke('send_collab_ops', { ops });
+    }
+
+    async sendCollabCursor(x, y) {
+        await this.invoke('send_collab_cursor', { x, y });
+    }
+
+    async leaveCollab() {
+        await this.invoke('leave_collab');
+    }
+
+    // Applies collab-style ops to the board's change history and returns
+    // the merged board
+    async applyBoardOps(boardId, ops) {
+        return await this.invoke('apply_board_ops', { boardId, ops });
+    }
+
+    // Changes since heads (or all of them) as { heads, data } for another
+    // copy of the board to merge
+    async getBoardChanges(boardId, heads = null) {
+        return await this.invoke('get_board_changes', { boardId, heads });
+    }
+
+    async mergeBoardChanges(boardId, data) {
+        return await this.invoke('merge_board_changes', { boardId, data });
+    }
+
+    // Opens a floating window for a board, or just one layer, where it was
+    // last left. Reopening one that's open brings it forward and turns
+    // click-through off
+    async openFloatingWindow(boardId, layerId = null) {
+        return await this.invoke('open_floating_window', { boardId, layerId });
+    }
+
+    // options: { opacity, alwaysOnTop, clickThrough }, any of them
+    async setFloatingWindowOptions(boardId, layerId, options) {
+        return await this.invoke('set_floating_window_options', { boardId, layerId, options });
+    }
+
+    async getFloatingWindows() {
+        if (!window.__TAURI__) return [];
+        return await this.invoke('get_floating_windows');
+    }
+
+    async buildPyramids(boardId) {
+        if (!window.__TAURI__) return 0;
+        return await this.invoke('build_pyramids', { boardId });
+    }
+
+    async getLayerResolution(boardId, layerId, scale) {
+        if (!window.__TAURI__) return null;
+        return await this.invoke('get_layer_resolution', { boardId, layerId, scale });
+    }
+
     async deleteBoard(boardId) {
         if (window.__TAURI__) {
             await this.invoke('delete_board', { id: boardId });
@@ -117,10 +242,10 @@ class BoardManager {
         return [];
     }
 
-    async addToAllAssets(name, src) {
+    async addToAllAssets(name, src, metadata = null) {
         if (window.__TAURI__) {
             try {
-                return await this.invoke('add_to_all_assets', { name, src });
+                return await this.invoke('add_to_all_assets', { name, src, metadata });
             } catch (e) {
                 console.error('Failed to add to all assets:', e);
                 return null;
@@ -138,6 +263,7 @@ class BoardManager {
             name,
             src
         };
+        if (metadata) asset.metadata = metadata;
         allAssets.push(asset);
         localStorage.setItem(this.ALL_ASSETS_KEY, JSON.stringify(allAssets));
         return asset;
@@ -218,18 +344,29 @@ class BoardManager {
         return null;
     }
 
-    async resolveImageSrc(src) {
+    // eyedea:// URL serving a stored file; with a width, a downscaled copy
+    // at least that wide
+    mediaUrl(src, width = null) {
+        const url = this.protocolUrl(`media/${src}`);
+        return width ? `${url}?w=${Math.ceil(width)}` : url;
+    }
+
+    // eyedea:// URL for a protocol path, e.g. `mip/2/<filename>`
+    protocolUrl(path) {
+        return window.__TAURI__.core.convertFileSrc(path, 'eyedea');
+    }
+
+    async resolveImageSrc(src, width = null) {
         // Old format (base64 dataURL) — use directly
         if (!src || src.startsWith('data:')) return src;
         // Already resolved blob URL
         if (src.startsWith('blob:')) return src;
-        // File reference — fetch via asset protocol and create same-origin blob URL
-        // (asset:// URLs are cross-origin which taints canvas, breaking pixel operations)
+        // File reference — fetch via the media protocol and create a same-origin blob URL
+        // (eyedea:// URLs are cross-origin which taints canvas, breaking pixel operations)
         if (window.__TAURI__) {
             try {
-                const fullPath = await this.invoke('get_image_file_path', { filename: src });
-                const assetUrl = window.__TAURI__.core.convertFileSrc(fullPath);
-                const response = await fetch(assetUrl);
+                const response = await fetch(this.mediaUrl(src, width));
+                if (!response.ok) throw new Error(`${response.status} ${await response.text()}`);
                 const blob = await response.blob();
                 return URL.createObjectURL(blob);
             } catch (e) {
@@ -239,6 +376,15 @@ class BoardManager {
         }
         return src;
     }
+
+    // Videos stream straight from the media protocol so seeking only reads
+    // the ranges it needs; set crossOrigin = 'anonymous' on the element to
+    // keep the canvas untainted.
+    resolveVideoSrc(src) {
+        if (!src || src.startsWith('data:') || src.startsWith('blob:') || !window.__TAURI__) return src;
+        return this.mediaUrl(src);
+    }
+
 }
 
 export const boardManager = new BoardManager();
\ No newline at end of file
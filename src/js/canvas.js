@@ -1141,89 +1141,24 @@ export class Canvas {
         return uniqueUrls;
     }
 
-    async extractImageFromPinterestPin(pinUrl) {
+    async resolvePageImage(pageUrl) {
         try {
-            console.log('Fetching Pinterest page:', pinUrl);
-            const html = await window.__TAURI__.core.invoke('fetch_page_html', { url: pinUrl });
-
-            // Extract pin ID from URL
-            const pinIdMatch = pinUrl.match(/\/pin\/(\d+)/);
-            const pinId = pinIdMatch ? pinIdMatch[1] : null;
-            console.log('Pin ID:', pinId);
-
-            // Pinterest embeds JSON data in script tags - look for __PWS_DATA__ or similar
-            // The JSON contains "orig" image URLs which are the highest quality
-
-            // Method 1: Look for the specific pin's image in JSON data
-            // Pinterest JSON often has structure like: "images":{"orig":{"url":"..."}}
-            const origUrlPattern = /"orig"\s*:\s*\{\s*[^}]*"url"\s*:\s*"(https:\/\/i\.pinimg\.com\/originals\/[^"]+)"/g;
-            const origMatches = [...html.matchAll(origUrlPattern)];
-            if (origMatches.length > 0) {
-                // Get unique URLs
-                const urls = [...new Set(origMatches.map(m => m[1]))];
-                console.log('Found orig URLs in JSON:', urls);
-                // Return the first one (should be the pin's image)
-                return urls[0];
-            }
-
-            // Method 2: Look for "url" fields with originals path in JSON context
-            const jsonOriginalsPattern = /"url"\s*:\s*"(https:\/\/i\.pinimg\.com\/originals\/[^"]+)"/g;
-            const jsonOrigMatches = [...html.matchAll(jsonOriginalsPattern)];
-            if (jsonOrigMatches.length > 0) {
-                const urls = [...new Set(jsonOrigMatches.map(m => m[1]))];
-                console.log('Found originals in JSON url fields:', urls);
-                return urls[0];
-            }
-
-            // Method 3: Look for high-res URLs (736x or higher) in JSON
-            const highResPattern = /"url"\s*:\s*"(https:\/\/i\.pinimg\.com\/\d+x\/[^"]+)"/g;
-            const highResMatches = [...html.matchAll(highResPattern)];
-            if (highResMatches.length > 0) {
-                const urls = [...new Set(highResMatches.map(m => m[1]))];
-                // Sort by resolution
-                urls.sort((a, b) => {
-                    const aRes = parseInt(a.match(/\/(\d+)x\//)?.[1] || '0');
-                    const bRes = parseInt(b.match(/\/(\d+)x\//)?.[1] || '0');
-                    return bRes - aRes;
-                });
-                console.log('Found high-res URLs in JSON:', urls[0]);
-                return urls[0];
-            }
-
-            // Method 4: Fallback - look for any pinimg URL that's not a small thumbnail
-            const allPinimgPattern = /https:\/\/i\.pinimg\.com\/(?:originals|\d+x)\/[a-f0-9\/]+\.[a-z]+/gi;
-            const allMatches = [...new Set(html.match(allPinimgPattern) || [])];
-            if (allMatches.length > 0) {
-                // Filter out small sizes (75x, 140x, 236x) and sort by size
-                const filtered = allMatches.filter(url => {
-                    const sizeMatch = url.match(/\/(\d+)x\//);
-                    if (!sizeMatch) return true; // originals
-                    return parseInt(sizeMatch[1]) >= 474;
-                });
-                filtered.sort((a, b) => {
-                    if (a.includes('/originals/')) return -1;
-                    if (b.includes('/originals/')) return 1;
-                    const aRes = parseInt(a.match(/\/(\d+)x\//)?.[1] || '0');
-                    const bRes = parseInt(b.match(/\/(\d+)x\//)?.[1] || '0');
-                    return bRes - aRes;
-                });
-                if (filtered.length > 0) {
-                    console.log('Found pinimg URL (fallback):', filtered[0]);
-                    return filtered[0];
-                }
-            }
-
-            console.log('No image URL found in Pinterest page');
-            return null;
+            const candidates = await window.__TAURI__.core.invoke('resolve_page_images', { url: pageUrl });
+            return candidates.length > 0 ? candidates[0].url : null;
         } catch (err) {
-            console.error('Failed to fetch Pinterest page:', err);
+            console.error('Failed to resolve images from page:', err);
             return null;
         }
     }
 
-    async fetchAndAddImageFromUrl(url, x, y) {
+    async fetchAndAddImageFromUrl(url, x, y, pageUrl = null) {
         try {
             const dataUrl = await window.__TAURI__.core.invoke('fetch_image_url', { url });
+            const source = await window.__TAURI__.core.invoke('describe_image_source', { url, pageUrl })
+                .catch(err => {
+                    console.warn('Failed to describe image source:', err);
+                    return null;
+                });
 
             // Generate a filename from URL
             const urlObj = new URL(url);
@@ -1248,7 +1183,8 @@ export class Canvas {
                 img.onload = () => {
                     const added = this.addImage(img, x, y, filename);
                     if (filePath) added.filePath = filePath;
-                    this.addToAssets(img, filePath || dataUrl, filename);
+                    if (source) added.source = source;
+                    this.addToAssets(img, filePath || dataUrl, filename, null, source);
                     this.canvas.dispatchEvent(new CustomEvent('imageDropped', {
                         detail: {
                             id: added.id,
@@ -1397,6 +1333,8 @@ export class Canvas {
 
         // Handle URL drops from websites (text/html or text/uri-list)
         let imageUrls = [];
+        // Page each resolved image was found on, for attribution
+        const pageUrls = new Map();
 
         // Debug: log all available data types
         console.log('Drop event - available types:', e.dataTransfer.types);
@@ -1426,16 +1364,12 @@ export class Canvas {
                         trimmed.match(/^https?:\/\/.*pinimg\.com/i)) {
                         imageUrls.push(trimmed);
                     }
-                    // Check for Pinterest pin page URLs - we'll need to fetch and parse these
-                    else if (trimmed.match(/^https?:\/\/(www\.)?pinterest\.[a-z]+\/pin\//i)) {
-                        console.log('Detected Pinterest pin URL, will fetch page to extract image');
-                        try {
-                            const extractedUrl = await this.extractImageFromPinterestPin(trimmed);
-                            if (extractedUrl) {
-                                imageUrls.push(extractedUrl);
-                            }
-                        } catch (err) {
-                            console.error('Failed to extract image from Pinterest pin:', err);
+                    // Any other page: fetch it and pick the image it's about
+                    else if (trimmed.match(/^https?:\/\//i)) {
+                        const extractedUrl = await this.resolvePageImage(trimmed);
+                        if (extractedUrl) {
+                            imageUrls.push(extractedUrl);
+                            pageUrls.set(extractedUrl, trimmed);
                         }
                     }
                 }
@@ -1449,7 +1383,7 @@ export class Canvas {
             // Use the first URL found (highest priority from extraction)
             console.log('Attempting to fetch:', imageUrls[0]);
             try {
-                await this.fetchAndAddImageFromUrl(imageUrls[0], x, y);
+                await this.fetchAndAddImageFromUrl(imageUrls[0], x, y, pageUrls.get(imageUrls[0]) || null);
             } catch (err) {
                 console.error('Failed to add image from URL drop:', err);
             }
@@ -1502,6 +1436,13 @@ export class Canvas {
         // Check for image URL in text (e.g., "Copy Image Address")
         const text = clipboardData.getData('text/plain');
         console.log('[Paste] Text data:', text ? text.substring(0, 100) : '(none)');
+        // A list of URLs is imported in one batch by the editor
+        const pastedUrls = (text || '').split(/\s+/).filter(u => /^https?:\/\/\S+$/i.test(u));
+        if (pastedUrls.length > 1) {
+            e.preventDefault();
+            this.canvas.dispatchEvent(new CustomEvent('urlsPasted', { detail: { urls: pastedUrls } }));
+            return;
+        }
         if (text && this.isImageUrl(text)) {
             console.log('[Paste] Detected image URL in text');
             e.preventDefault();
@@ -1601,7 +1542,7 @@ export class Canvas {
         img.src = imgSrc;
     }
 
-    addToAssets(img, src, name, mediaType = null) {
+    addToAssets(img, src, name, mediaType = null, source = null) {
         const bm = window.boardManagerInstance;
         const board = bm?.currentBoard;
         if (!board) return;
@@ -1616,8 +1557,10 @@ export class Canvas {
             src: src,
             name: name
         };
-        if (mediaType) {
-            newAsset.metadata = { mediaType, created: Date.now() };
+        if (mediaType || source) {
+            newAsset.metadata = { created: Date.now() };
+            if (mediaType) newAsset.metadata.mediaType = mediaType;
+            if (source) newAsset.metadata.source = source;
         }
 
         const updatedAssets = [...currentAssets, newAsset];
@@ -1626,12 +1569,7 @@ export class Canvas {
             bm.updateBoard(window.currentBoardId, { assets: updatedAssets });
 
             // Also add to All Assets
-            bm.addToAllAssets(name, src).then(globalAsset => {
-                if (globalAsset && mediaType) {
-                    // Persist metadata to the global asset too
-                    globalAsset.metadata = newAsset.metadata;
-                }
-            }).catch(err => console.warn('Failed to add to all assets:', err));
+            bm.addToAllAssets(name, src, newAsset.metadata || null).catch(err => console.warn('Failed to add to all assets:', err));
         }
 
         if (window.renderAssetsCallback) {
@@ -3496,8 +3434,9 @@ export class Canvas {
                         drawSw = img._filteredCanvas.width;
                         drawSh = img._filteredCanvas.height;
                     } else {
-                        // Draw directly from source image
-                        const sourceImg = img.originalImg || img.img;
+                        // Draw directly from source image, or the pyramid level
+                        // picked for the current zoom
+                        const sourceImg = img.originalImg || img.mipImg || img.img;
                         drawSource = sourceImg;
                         drawSx = 0;
                         drawSy = 0;
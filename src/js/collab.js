@@ -0,0 +1,164 @@
+/**
+ * Collab Session - Connects an open board to a LAN session in the backend
+ * Diffs editor state into ops, hands merged remote changes back to the
+ * editor and draws other peers' cursors
+ */
+
+import { boardManager } from './board-manager.js';
+import { diffBoardState } from './board-diff.js';
+
+const SEND_DELAY = 100;
+const CURSOR_INTERVAL = 50;
+
+function clone(value) {
+    return JSON.parse(JSON.stringify(value ?? null));
+}
+
+export class CollabSession {
+    /**
+     * @param {Object} info - Session info from the backend
+     * @param {Object} hooks - getState() returns the board's collections and
+     *   fields; applyRemote(collection, id, value) shows a merged change;
+     *   onMedia(src) runs when shared media arrives; onCodeChanged(code)
+     *   when too many wrong codes replaced the join code; onEnded(remote)
+     *   when the session is over
+     */
+    constructor(info, canvas, container, hooks) {
+        this.info = info;
+        this.boardId = info.boardId;
+        this.canvas = canvas;
+        this.hooks = hooks;
+        this.snapshot = clone(hooks.getState());
+        this.peers = new Map(info.peers.map(peer => [peer.id, peer]));
+        this.cursors = new Map();
+        this.unlisteners = [];
+        this.sendTimeout = null;
+        this.lastCursorSent = 0;
+
+        this.overlay = document.createElement('div');
+        this.overlay.className = 'collab-cursors';
+        container.appendChild(this.overlay);
+
+        this.onMouseMove = (e) => this.sendCursor(e);
+        this.onViewChanged = () => this.positionCursors();
+        canvas.canvas.addEventListener('mousemove', this.onMouseMove);
+        canvas.canvas.addEventListener('viewChanged', this.onViewChanged);
+    }
+
+    async listen() {
+        const { listen } = window.__TAURI__.event;
+        this.unlisteners = await Promise.all([
+            listen('collab-op', (event) => this.receive(event.payload)),
+            listen('collab-cursor', (event) => this.moveCursor(event.payload)),
+            listen('collab-presence', (event) => this.updatePeers(event.payload)),
+            listen('collab-media', (event) => {
+                if (event.payload.boardId === this.boardId) this.hooks.onMedia(event.payload.src);
+            }),
+            listen('collab-code-changed', (event) => {
+                if (event.payload.boardId === this.boardId) this.hooks.onCodeChanged(event.payload.code);
+            }),
+            listen('collab-ended', () => this.end(true)),
+        ]);
+    }
+
+    // Edits go out shortly after they happen, batched
+    scheduleSend() {
+        if (this.sendTimeout) clearTimeout(this.sendTimeout);
+        this.sendTimeout = setTimeout(() => this.send(), SEND_DELAY);
+    }
+
+    async send() {
+        this.sendTimeout = null;
+        const state = clone(this.hooks.getState());
+        const ops = diffBoardState(this.snapshot, state);
+        this.snapshot = state;
+        if (ops.length === 0) return;
+        try {
+            await boardManager.sendCollabOps(ops);
+        } catch (e) {
+            console.warn('Failed to share edits:', e);
+        }
+    }
+
+    async receive({ boardId, op, value }) {
+        if (boardId !== this.boardId) return;
+
+        // Track the merged value so it isn't sent back as a local edit
+        if (op.collection === 'board') {
+            Object.assign(this.snapshot.board, value);
+        } else {
+            const items = this.snapshot[op.collection] || (this.snapshot[op.collection] = []);
+            const index = items.findIndex(item => String(item.id) === String(op.id));
+            if (value === null) {
+                if (index >= 0) items.splice(index, 1);
+            } else if (index >= 0) {
+                items[index] = clone(value);
+            } else {
+                items.push(clone(value));
+            }
+        }
+        await this.hooks.applyRemote(op.collection, op.id, value);
+    }
+
+    sendCursor(e) {
+        const now = Date.now();
+        if (now - this.lastCursorSent < CURSOR_INTERVAL) return;
+        this.lastCursorSent = now;
+        const rect = this.canvas.canvas.getBoundingClientRect();
+        const { x, y } = this.canvas.screenToWorld(e.clientX - rect.left, e.clientY - rect.top);
+        boardManager.sendCollabCursor(x, y).catch(() => {});
+    }
+
+    moveCursor({ peerId, x, y }) {
+        const peer = this.peers.get(peerId);
+        if (!peer) return;
+        let cursor = this.cursors.get(peerId);
+        if (!cursor) {
+            const element = document.createElement('div');
+            element.className = 'collab-cursor';
+            element.style.setProperty('--peer-color', peer.color);
+            const label = document.createElement('span');
+            label.className = 'collab-cursor-label';
+            label.textContent = peer.name;
+            element.appendChild(label);
+            this.overlay.appendChild(element);
+            cursor = { element };
+            this.cursors.set(peerId, cursor);
+        }
+        cursor.x = x;
+        cursor.y = y;
+        this.positionCursor(cursor);
+    }
+
+    positionCursor(cursor) {
+        const { x, y } = this.canvas.worldToScreen(cursor.x, cursor.y);
+        cursor.element.style.transform = `translate(${x}px, ${y}px)`;
+    }
+
+    positionCursors() {
+        this.cursors.forEach(cursor => this.positionCursor(cursor));
+    }
+
+    updatePeers({ boardId, peers }) {
+        if (boardId !== this.boardId) return;
+        this.peers = new Map(peers.map(peer => [peer.id, peer]));
+        for (const [peerId, cursor] of this.cursors) {
+            if (!this.peers.has(peerId)) {
+                cursor.element.remove();
+                this.cursors.delete(peerId);
+            }
+        }
+    }
+
+    // Stops sharing this board; the backend session is left separately.
+    // remote is true when the host ended it or the connection dropped
+    end(remote = false) {
+        if (this.sendTimeout) clearTimeout(this.sendTimeout);
+        this.unlisteners.forEach(unlisten => unlisten());
+        this.unlisteners = [];
+        this.canvas.canvas.removeEventListener('mousemove', this.onMouseMove);
+        this.canvas.canvas.removeEventListener('viewChanged', this.onViewChanged);
+        this.overlay.remove();
+        this.hooks.onEnded(remote);
+    }
+}
@@ -7,6 +7,8 @@ import { updateTitlebarTitle } from './titlebar.js';
 import { FontDropdown } from './font-dropdown.js';
 import { MediaControls } from './media-controls.js';
 import { hijackColorInput } from './color-picker.js';
+import { CollabSession } from './collab.js';
+import { diffBoardState } from './board-diff.js';
 
 // Apply theme on page load
 const savedSettings = JSON.parse(localStorage.getItem('canvas_settings') || '{}');
@@ -15,11 +17,20 @@ document.documentElement.setAttribute('data-theme', theme);
 document.body.setAttribute('data-theme', theme);
 console.log('Applied theme on load:', theme);
 
+// Width requested for asset grid thumbnails; the backend serves a downscaled copy
+const ASSET_THUMB_WIDTH = 256;
+
 // Editor instance manager - stores separate state for each board container
 const editorInstances = new Map(); // Map<container, editorState>
+
 let activeContainer = null; // Currently active editor container
 let sidebarToggleListenerAttached = false; // Prevent duplicate listeners on titlebar button
 let undoRedoListenerAttached = false; // Prevent duplicate listeners on undo/redo buttons
+let pyramidListenerAttached = false; // Prevent duplicate pyramid-ready listeners
+let libraryListenerAttached = false; // Prevent duplicate asset-library-changed listeners
+let boardListenerAttached = false; // Prevent duplicate board-updated listeners
+let collabSession = null; // The LAN session this app takes part in, tied to one board
+const pendingCollabMedia = new Map(); // Shared layers waiting for their file: src → layer id
 
 // Helper to get element from active container
 function getElement(id) {
@@ -47,6 +58,7 @@ function getEditorInstance(container) {
             currentBoardId: null,
             saveTimeout: null,
             pendingSave: false,
+            savedState: null,
             dragSourceIndex: null,
             currentOrder: [],
             draggedImageId: null,
@@ -79,6 +91,7 @@ let historyManager;
 let currentBoardId;
 let saveTimeout = null;
 let pendingSave = false;
+let savedState = null; // The board as last saved, to diff edits against
 let dragSourceIndex = null;
 let currentOrder = [];
 let draggedImageId = null;
@@ -113,6 +126,7 @@ function saveInstanceState() {
         instance.currentBoardId = currentBoardId;
         instance.saveTimeout = saveTimeout;
         instance.pendingSave = pendingSave;
+        instance.savedState = savedState;
         instance.dragSourceIndex = dragSourceIndex;
         instance.currentOrder = currentOrder;
     }
@@ -131,6 +145,7 @@ function setActiveInstance(container) {
     currentBoardId = instance.currentBoardId;
     saveTimeout = instance.saveTimeout;
     pendingSave = instance.pendingSave;
+    savedState = instance.savedState;
     dragSourceIndex = instance.dragSourceIndex;
     currentOrder = instance.currentOrder;
     draggedImageId = instance.draggedImageId;
@@ -162,6 +177,7 @@ function saveActiveInstance() {
     instance.currentBoardId = currentBoardId;
     instance.saveTimeout = saveTimeout;
     instance.pendingSave = pendingSave;
+    instance.savedState = savedState;
     instance.dragSourceIndex = dragSourceIndex;
     instance.currentOrder = currentOrder;
     instance.draggedImageId = draggedImageId;
@@ -304,6 +320,11 @@ export async function initEditor(boardId, container) {
         objectsCount: board.objects?.length || 0
     });
 
+    const rejected = board.rejectedMedia?.length || 0;
+    if (rejected) {
+        showToast(`${rejected} media ${rejected === 1 ? 'file points' : 'files point'} outside the media folder and ${rejected === 1 ? 'was' : 'were'} left out`, 'warning', 8000);
+    }
+
     window.boardManagerInstance = boardManager;
     window.currentBoardId = currentBoardId;
     window.renderAssetsCallback = renderAssets;
@@ -355,6 +376,36 @@ export async function initEditor(boardId, container) {
     }
 
     await loadLayers(board.layers, board.viewState);
+    boardManager.buildPyramids(boardId).catch(e => console.warn('Failed to queue image pyramids:', e));
+    scheduleResolutionUpdate();
+    if (window.__TAURI__ && !pyramidListenerAttached) {
+        pyramidListenerAttached = true;
+        window.__TAURI__.event.listen('pyramid-ready', (event) => {
+            if (!canvas) return;
+            canvas.images.forEach(img => {
+                if (img.filePath === event.payload.src) img.mipBucket = null;
+            });
+            scheduleResolutionUpdate();
+        });
+    }
+    if (!libraryListenerAttached) {
+        libraryListenerAttached = true;
+        // Library edits from other windows show up without reopening it
+        boardManager.onChange('asset-library-changed', () => {
+            const searchBar = getElement('assets-search-bar');
+            if (searchBar) loadAssetsLibrary(searchBar.value);
+        });
+    }
+    if (!boardListenerAttached) {
+        boardListenerAttached = true;
+        // Saves from other windows, imports, sync and restores show up in
+        // the open board
+        boardManager.onChange('board-updated', ({ id }) => {
+            if (canvas && id === currentBoardId) {
+                mergeSavedBoard().catch(e => console.warn('Failed to merge board changes:', e));
+            }
+        });
+    }
 
     // Load groups if they exist
     if (board.groups && board.groups.length > 0) {
@@ -385,12 +436,21 @@ export async function initEditor(boardId, container) {
         console.log('No objects to load');
     }
 
+    savedState = captureState();
+
+    // Rejoin the LAN session when it's sharing this board
+    const collabInfo = await boardManager.getCollabSession().catch(() => null);
+    if (collabInfo?.active && collabInfo.boardId === currentBoardId) {
+        await attachCollab(collabInfo);
+    }
+
     // Listen for canvas changes to trigger save
     canvasElement.addEventListener('canvasChanged', () => {
         scheduleSave();
     });
     canvasElement.addEventListener('viewChanged', () => {
         scheduleSave();
+        scheduleResolutionUpdate();
     });
 
     // Text/shape object events
@@ -547,6 +607,58 @@ export async function initEditor(boardId, container) {
     console.log('[initEditor] Editor initialization complete!');
 }
 
+// Still layers draw the smallest pyramid level that covers them at the
+// current zoom; the full original stays in img.img for edits and exports
+const RESOLUTION_UPDATE_DELAY = 250;
+let resolutionTimeout = null;
+
+function scheduleResolutionUpdate() {
+    if (!window.__TAURI__) return;
+    const targetCanvas = canvas;
+    const boardId = currentBoardId;
+    clearTimeout(resolutionTimeout);
+    resolutionTimeout = setTimeout(() => updateLayerResolutions(targetCanvas, boardId), RESOLUTION_UPDATE_DELAY);
+}
+
+async function updateLayerResolutions(targetCanvas, boardId) {
+    const scale = targetCanvas.zoom * (window.devicePixelRatio || 1);
+    // Only ask again once the scale crosses a power of two
+    const bucket = Math.round(Math.log2(scale));
+    await Promise.all(targetCanvas.cullImages().map(async (img) => {
+        if (!img.filePath || img.mediaType || img.cropData || img._filteredCanvas || img.mipBucket === bucket) return;
+        img.mipBucket = bucket;
+        let resolution;
+        try {
+            resolution = await boardManager.getLayerResolution(boardId, img.id, scale);
+        } catch (e) {
+            // Not saved yet; try again on the next update
+            img.mipBucket = null;
+            return;
+        }
+        if (!resolution || !resolution.ready || img.mipBucket !== bucket) return;
+        if (resolution.level === 0) {
+            if (img.mipImg) {
+                img.mipImg = null;
+                img.mipPath = null;
+                targetCanvas.needsRender = true;
+                targetCanvas.render();
+            }
+            return;
+        }
+        if (img.mipPath === resolution.path) return;
+        const level = new Image();
+        level.crossOrigin = 'anonymous';
+        level.onload = () => {
+            if (img.mipBucket !== bucket) return;
+            img.mipImg = level;
+            img.mipPath = resolution.path;
+            targetCanvas.needsRender = true;
+            targetCanvas.render();
+        };
+        level.src = boardManager.protocolUrl(resolution.path);
+    }));
+}
+
 async function loadLayers(layers, viewState = null) {
     canvas.clear();
 
@@ -572,7 +684,10 @@ async function loadLayers(layers, viewState = null) {
     // Resolve all image sources (file refs → asset URLs) up front
     const resolvedLayers = await Promise.all(layers.map(async (layer) => {
         const rawSrc = layer.cropData && layer.originalSrc ? layer.originalSrc : layer.src;
-        const resolvedSrc = await boardManager.resolveImageSrc(rawSrc);
+        const isVideo = layer.mediaType === 'video' || /\.(mp4|mov|webm)$/i.test(layer.src || '');
+        const resolvedSrc = isVideo
+            ? boardManager.resolveVideoSrc(rawSrc)
+            : await boardManager.resolveImageSrc(rawSrc);
         // Track whether this layer uses file-based storage
         const filePath = (layer.src && !layer.src.startsWith('data:')) ? layer.src : null;
         return { layer, resolvedSrc, filePath };
@@ -605,6 +720,7 @@ async function loadLayers(layers, viewState = null) {
             if (mediaType === 'video') {
                 // Restore video layer
                 const video = document.createElement('video');
+                video.crossOrigin = 'anonymous';
                 video.preload = 'auto';
                 video.muted = layer.muted !== false;
                 video.onloadedmetadata = () => {
@@ -616,6 +732,7 @@ async function loadLayers(layers, viewState = null) {
                     added.volume = layer.volume != null ? layer.volume : 1;
                     added.muted = layer.muted !== false;
                     if (filePath) added.filePath = filePath;
+                    if (layer.source) added.source = layer.source;
                     if (layer.opacity != null) added.opacity = layer.opacity;
                     video.currentTime = added.currentTime;
                     finishIfDone();
@@ -637,6 +754,7 @@ async function loadLayers(layers, viewState = null) {
                             added.gifCurrentFrame = layer.gifCurrentFrame || 0;
                             added.gifPlaying = false;
                             if (filePath) added.filePath = filePath;
+                            if (layer.source) added.source = layer.source;
                             if (layer.opacity != null) added.opacity = layer.opacity;
                         }
                         finishIfDone();
@@ -652,6 +770,7 @@ async function loadLayers(layers, viewState = null) {
                     added.zIndex = layer.zIndex || 0;
                     added.rotation = layer.rotation || 0;
                     if (filePath) added.filePath = filePath;
+                    if (layer.source) added.source = layer.source;
                     if (layer.brightness != null) added.brightness = layer.brightness;
                     if (layer.contrast != null) added.contrast = layer.contrast;
                     if (layer.saturation != null) added.saturation = layer.saturation;
@@ -972,6 +1091,10 @@ function setupEventListeners(container) {
         }
     });
 
+    canvas.canvas.addEventListener('urlsPasted', (e) => {
+        importPastedUrls(e.detail.urls);
+    });
+
     canvas.canvas.addEventListener('imageSelected', (e) => {
         highlightLayer(e.detail ? e.detail.id : null);
     });
@@ -1353,6 +1476,9 @@ function setupDrawingToolbar() {
 }
 
 function scheduleSave() {
+    if (collabSession && collabSession.boardId === currentBoardId) {
+        collabSession.scheduleSend();
+    }
     pendingSave = true;
     if (saveTimeout) clearTimeout(saveTimeout);
     saveTimeout = setTimeout(() => {
@@ -1368,8 +1494,55 @@ async function saveNow() {
         saveTimeout = null;
     }
 
-    const images = canvas.getImages();
-    const layers = images.map(img => {
+    const viewState = {
+        pan: { x: canvas.pan.x, y: canvas.pan.y },
+        zoom: canvas.zoom
+    };
+    const thumbnail = canvas.generateThumbnail(200, 150);
+    // Only what changed since the last save goes out, so edits other
+    // windows saved in the meantime are kept
+    const saved = savedState;
+    savedState = captureState();
+    await boardManager.saveBoardEdits(currentBoardId, saved, savedState, { viewState, thumbnail });
+}
+
+// A copy of the board as it stands, to diff the next save against
+function captureState() {
+    return JSON.parse(JSON.stringify(getCollabState(canvas)));
+}
+
+// Brings in what was saved to the board elsewhere. Pending edits are saved
+// first, so what differs from the last save is someone else's change, and
+// is shown the way shared session changes are
+async function mergeSavedBoard() {
+    // A shared session keeps the board up to date itself
+    if (collabSession && collabSession.boardId === currentBoardId) return;
+    const boardId = currentBoardId;
+    const targetCanvas = canvas;
+    if (pendingSave) await saveNow();
+    const board = await boardManager.getBoard(boardId);
+    if (!board || boardId !== currentBoardId || targetCanvas !== canvas || !savedState) return;
+
+    const saved = JSON.parse(JSON.stringify({
+        layers: board.layers,
+        strokes: board.strokes || [],
+        objects: board.objects || [],
+        groups: board.groups || [],
+        board: { name: board.name, bgColor: board.bgColor }
+    }));
+    const ops = diffBoardState(savedState, saved);
+    savedState = saved;
+    for (const op of ops) {
+        const value = op.collection === 'board' ? op.change.fields
+            : op.change.type === 'remove' ? null
+            : saved[op.collection].find(item => String(item.id) === String(op.id));
+        await applyRemoteChange(targetCanvas, op.collection, op.id, value);
+    }
+}
+
+// Canvas images as saved board layers
+function serializeLayers(images) {
+    return images.map(img => {
         // Determine src: for video use videoSrc/filePath, for gif use gifSrc/filePath
         let src;
         if (img.mediaType === 'video') {
@@ -1397,6 +1570,9 @@ async function saveNow() {
             layer.mediaType = img.mediaType;
         }
 
+        // Where web imports came from
+        if (img.source) layer.source = img.source;
+
         // Video state
         if (img.mediaType === 'video') {
             layer.currentTime = img.videoElement?.currentTime || img.currentTime || 0;
@@ -1432,22 +1608,220 @@ async function saveNow() {
 
         return layer;
     });
-    const bgColor = canvas.bgColor;
-    const viewState = {
-        pan: { x: canvas.pan.x, y: canvas.pan.y },
-        zoom: canvas.zoom
-    };
-    const strokes = canvas.getStrokes();
-    const objects = canvas.objectsManager.getObjects();
-    const thumbnail = canvas.generateThumbnail(200, 150);
-    const groups = layerGroups.map(g => ({
+}
+
+function serializeGroups() {
+    return layerGroups.map(g => ({
         id: g.id,
         name: g.name,
         layerIds: g.layerIds,
         objectIds: g.objectIds || [],
         collapsed: g.collapsed || false
     }));
-    await boardManager.updateBoard(currentBoardId, { layers, bgColor, viewState, strokes, objects, groups, thumbnail });
+}
+
+// Board state as shared in a LAN session and diffed when saving
+function getCollabState(targetCanvas) {
+    return {
+        layers: serializeLayers(targetCanvas.getImages()),
+        strokes: targetCanvas.getStrokes() || [],
+        objects: targetCanvas.objectsManager.getObjects() || [],
+        groups: serializeGroups(),
+        board: { name: getElement('board-name')?.textContent, bgColor: targetCanvas.bgColor }
+    };
+}
+
+async function attachCollab(info) {
+    if (collabSession) collabSession.end();
+    const targetCanvas = canvas;
+    const session = new CollabSession(info, targetCanvas, targetCanvas.canvas.parentElement, {
+        getState: () => getCollabState(targetCanvas),
+        applyRemote: (collection, id, value) => applyRemoteChange(targetCanvas, collection, id, value),
+        onMedia: (src) => {
+            const layerId = pendingCollabMedia.get(src);
+            const layer = collabSession?.snapshot.layers.find(l => l.id === layerId);
+            pendingCollabMedia.delete(src);
+            if (layer) applyRemoteLayer(targetCanvas, layer.id, layer);
+        },
+        onCodeChanged: (code) => {
+            showToast(`Too many wrong codes were tried, so the join code is now ${code}`, 'warning', 8000);
+        },
+        onEnded: (remote) => {
+            if (collabSession === session) collabSession = null;
+            updateCollabMenuItem();
+            if (remote) showToast('The shared session has ended', 'info');
+        }
+    });
+    collabSession = session;
+    updateCollabMenuItem();
+    await session.listen();
+}
+
+// Shows a change merged from other peers
+async function applyRemoteChange(targetCanvas, collection, id, value) {
+    const isActive = targetCanvas === canvas;
+    const replaceById = (items, item) => {
+        const index = items.findIndex(i => String(i.id) === String(id));
+        if (item === null) {
+            if (index >= 0) items.splice(index, 1);
+        } else if (index >= 0) {
+            items[index] = item;
+        } else {
+            items.push(item);
+        }
+        return items;
+    };
+
+    if (collection === 'board') {
+        if (value.bgColor) {
+            targetCanvas.setBackgroundColor(value.bgColor, true);
+            const colorInput = isActive ? getElement('bg-color') : null;
+            if (colorInput) colorInput.value = value.bgColor;
+        }
+        if (value.name && isActive) {
+            getElement('board-name').textContent = value.name;
+            updateTitlebarTitle(`EyeDea - ${value.name}`);
+        }
+    } else if (collection === 'layers') {
+        await applyRemoteLayer(targetCanvas, id, value);
+    } else if (collection === 'strokes') {
+        targetCanvas.loadStrokes(replaceById([...targetCanvas.getStrokes()], value && structuredClone(value)));
+    } else if (collection === 'objects') {
+        targetCanvas.objectsManager.loadObjects(replaceById([...targetCanvas.objectsManager.getObjects()], value && structuredClone(value)));
+    } else if (collection === 'groups' && isActive) {
+        const group = value && {
+            id: value.id,
+            name: value.name,
+            layerIds: value.layerIds || [],
+            objectIds: value.objectIds || [],
+            collapsed: value.collapsed || false
+        };
+        layerGroups = replaceById(layerGroups, group);
+        nextGroupId = Math.max(nextGroupId, ...layerGroups.map(g => g.id + 1));
+    }
+
+    targetCanvas.needsRender = true;
+    if (isActive) renderLayers();
+}
+
+function applyLayerProperties(targetCanvas, img, layer) {
+    img.x = layer.x;
+    img.y = layer.y;
+    img.width = layer.width;
+    img.height = layer.height;
+    img.name = layer.name;
+    img.visible = layer.visible !== false;
+    img.zIndex = layer.zIndex || 0;
+    img.rotation = layer.rotation || 0;
+    img.opacity = layer.opacity ?? 100;
+    if (layer.source) img.source = layer.source;
+    if (!img.mediaType || img.mediaType === 'image') {
+        img.brightness = layer.brightness ?? 100;
+        img.contrast = layer.contrast ?? 100;
+        img.saturation = layer.saturation ?? 100;
+        img.hue = layer.hue ?? 0;
+        img.blur = layer.blur ?? 0;
+        img.grayscale = layer.grayscale === true;
+        img.invert = layer.invert === true;
+        img.mirror = layer.mirror === true;
+        targetCanvas.clearFilterCache(img);
+        if (targetCanvas.buildFilterString(img)) targetCanvas.applyFilters(img);
+    }
+    targetCanvas.invalidateCullCache();
+}
+
+async function applyRemoteLayer(targetCanvas, id, layer) {
+    const existing = targetCanvas.images.find(img => img.id === id);
+    if (!layer) {
+        if (existing) targetCanvas.deleteImage(id, true);
+        return;
+    }
+    if (existing && (existing.filePath || '') === layer.src && !layer.cropData && !existing.cropData) {
+        applyLayerProperties(targetCanvas, existing, layer);
+        return;
+    }
+
+    // Videos, GIFs and crops need the full loader
+    if ((layer.mediaType && layer.mediaType !== 'image') || layer.cropData) {
+        if (targetCanvas === canvas) {
+            const viewState = { pan: { ...canvas.pan }, zoom: canvas.zoom };
+            await loadLayers(collabSession ? collabSession.snapshot.layers : serializeLayers(canvas.getImages()), viewState);
+        }
+        return;
+    }
+
+    const img = new Image();
+    img.onload = () => {
+        if (existing) targetCanvas.deleteImage(id, true);
+        const added = targetCanvas.addImageSilent(img, layer.x, layer.y, layer.name, layer.width, layer.height, layer.visible !== false);
+        added.id = layer.id;
+        if (!layer.src.startsWith('data:')) added.filePath = layer.src;
+        applyLayerProperties(targetCanvas, added, layer);
+        targetCanvas.needsRender = true;
+        if (targetCanvas === canvas) renderLayers();
+    };
+    // The file may still be on its way from another peer
+    img.onerror = () => pendingCollabMedia.set(layer.src, layer.id);
+    img.src = await boardManager.resolveImageSrc(layer.src);
+}
+
+async function toggleCollab() {
+    if (collabSession) {
+        const confirmed = await showConfirmModal('Leave Session', 'Stop sharing this board with others on the network?', 'Leave');
+        if (!confirmed) return;
+        await boardManager.leaveCollab();
+        if (collabSession) collabSession.end();
+        showToast('Left the shared session', 'info');
+        return;
+    }
+    try {
+        const info = await boardManager.startCollab(currentBoardId);
+        await attachCollab(info);
+        await showChoiceModal(
+            'Board Shared',
+            `Others on your network can join at <strong>${info.address}</strong> with the code <strong>${info.code}</strong>.`,
+            [{ title: 'Done', value: 'done' }]
+        );
+    } catch (e) {
+        showToast(`Failed to share board: ${e}`, 'error');
+    }
+}
+
+function updateCollabMenuItem() {
+    const item = getElement('dropdown-collab');
+    if (item) item.textContent = collabSession ? 'Leave Shared Session' : 'Share on Network';
+}
+
+async function importPastedUrls(urls) {
+    const boardId = currentBoardId;
+    // The backend adds to the saved board, so save pending edits first
+    pendingSave = true;
+    await saveNow();
+
+    showToast(`Importing ${urls.length} URLs...`);
+    let summary;
+    try {
+        summary = await boardManager.importUrls(urls, boardId);
+    } catch (err) {
+        console.error('Failed to import URLs:', err);
+        showToast('Failed to import URLs', 'error');
+        return;
+    }
+    if (!summary || boardId !== currentBoardId) return;
+
+    if (summary.imported.length > 0) {
+        const board = await boardManager.getBoard(boardId);
+        await loadLayers(board.layers, { pan: { ...canvas.pan }, zoom: canvas.zoom });
+        scheduleResolutionUpdate();
+        renderLayers();
+        renderAssets();
+    }
+    if (summary.failed.length > 0) {
+        console.warn('URLs that failed to import:', summary.failed);
+        showToast(`Imported ${summary.imported.length} of ${urls.length} URLs`, 'warning', 4000);
+    } else {
+        showToast(`Imported ${summary.imported.length} URLs`, 'success');
+    }
 }
 
 function createLayerItem(img, images) {
@@ -3044,7 +3418,7 @@ async function renderAssets() {
     assetsGrid.innerHTML = '';
 
     // Resolve file references for display
-    const resolvedSrcs = await Promise.all(assets.map(a => boardManager.resolveImageSrc(a.src)));
+    const resolvedSrcs = await Promise.all(assets.map(a => boardManager.resolveImageSrc(a.src, ASSET_THUMB_WIDTH)));
 
     assets.forEach((asset, idx) => {
         const assetItem = document.createElement('div');
@@ -3446,6 +3820,19 @@ function setupBoardDropdown() {
         });
     }
 
+    let collabItem = getElement('dropdown-collab');
+    if (collabItem) {
+        const newCollabItem = collabItem.cloneNode(true);
+        collabItem.parentNode.replaceChild(newCollabItem, collabItem);
+        collabItem = newCollabItem;
+        updateCollabMenuItem();
+
+        collabItem.addEventListener('click', () => {
+            dropdownMenu.classList.remove('show');
+            toggleCollab();
+        });
+    }
+
     let exportLinesItem = getElement('dropdown-export-lines');
     console.log('[setupBoardDropdown] exportLinesItem:', exportLinesItem);
     if (exportLinesItem) {
@@ -3930,7 +4317,7 @@ async function appendAssetToLibrary(asset, container, isAllAssets) {
     const isVideo = asset.metadata?.mediaType === 'video' || /\.(mp4|mov|webm)$/i.test(nameLC);
     const isGif = asset.metadata?.mediaType === 'gif' || /\.gif$/i.test(nameLC);
 
-    const resolvedSrc = await boardManager.resolveImageSrc(asset.src);
+    const resolvedSrc = await boardManager.resolveImageSrc(asset.src, ASSET_THUMB_WIDTH);
 
     const img = document.createElement('img');
     if (isVideo && asset.metadata?.thumbnailSrc) {
@@ -5934,6 +6321,11 @@ function showLayerContextMenu(x, y, layer, type) {
         ungroupButton.style.display = isInGroup ? 'block' : 'none';
     }
 
+    const floatingButton = getElement('layer-context-floating');
+    if (floatingButton) {
+        floatingButton.style.display = type === 'image' && window.__TAURI__ ? 'block' : 'none';
+    }
+
     contextMenu.style.left = `${x}px`;
     contextMenu.style.top = `${y}px`;
     contextMenu.classList.add('show');
@@ -5950,6 +6342,7 @@ function setupLayerContextMenu() {
     const renameItem = getElement('layer-context-rename');
     const ungroupItem = getElement('layer-context-ungroup');
     const duplicateItem = getElement('layer-context-duplicate');
+    const floatingItem = getElement('layer-context-floating');
     const deleteItem = getElement('layer-context-delete');
 
     // Close menu when clicking outside
@@ -6033,6 +6426,18 @@ function setupLayerContextMenu() {
         }
     });
 
+    // Show just this layer in its own floating window
+    floatingItem?.addEventListener('click', async () => {
+        contextMenu.classList.remove('show');
+        if (!currentContextLayer) return;
+        await window.editorForceSave();
+        try {
+            await boardManager.openFloatingWindow(currentBoardId, currentContextLayer.id);
+        } catch (e) {
+            console.error('Failed to open floating window:', e);
+        }
+    });
+
     // Duplicate layer
     duplicateItem.addEventListener('click', () => {
         contextMenu.classList.remove('show');
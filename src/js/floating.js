@@ -85,97 +85,31 @@ function extractImageUrlsFromHtml(html) {
     return uniqueUrls;
 }
 
-async function extractImageFromPinterestPin(pinUrl) {
+async function resolvePageImage(pageUrl) {
     try {
-        console.log('Fetching Pinterest page:', pinUrl);
-        const html = await window.__TAURI__.core.invoke('fetch_page_html', { url: pinUrl });
-
-        // Extract pin ID from URL
-        const pinIdMatch = pinUrl.match(/\/pin\/(\d+)/);
-        const pinId = pinIdMatch ? pinIdMatch[1] : null;
-        console.log('Pin ID:', pinId);
-
-        // Pinterest embeds JSON data in script tags - look for __PWS_DATA__ or similar
-        // The JSON contains "orig" image URLs which are the highest quality
-
-        // Method 1: Look for the specific pin's image in JSON data
-        // Pinterest JSON often has structure like: "images":{"orig":{"url":"..."}}
-        const origUrlPattern = /"orig"\s*:\s*\{\s*[^}]*"url"\s*:\s*"(https:\/\/i\.pinimg\.com\/originals\/[^"]+)"/g;
-        const origMatches = [...html.matchAll(origUrlPattern)];
-        if (origMatches.length > 0) {
-            // Get unique URLs
-            const urls = [...new Set(origMatches.map(m => m[1]))];
-            console.log('Found orig URLs in JSON:', urls);
-            // Return the first one (should be the pin's image)
-            return urls[0];
-        }
-
-        // Method 2: Look for "url" fields with originals path in JSON context
-        const jsonOriginalsPattern = /"url"\s*:\s*"(https:\/\/i\.pinimg\.com\/originals\/[^"]+)"/g;
-        const jsonOrigMatches = [...html.matchAll(jsonOriginalsPattern)];
-        if (jsonOrigMatches.length > 0) {
-            const urls = [...new Set(jsonOrigMatches.map(m => m[1]))];
-            console.log('Found originals in JSON url fields:', urls);
-            return urls[0];
-        }
-
-        // Method 3: Look for high-res URLs (736x or higher) in JSON
-        const highResPattern = /"url"\s*:\s*"(https:\/\/i\.pinimg\.com\/\d+x\/[^"]+)"/g;
-        const highResMatches = [...html.matchAll(highResPattern)];
-        if (highResMatches.length > 0) {
-            const urls = [...new Set(highResMatches.map(m => m[1]))];
-            // Sort by resolution
-            urls.sort((a, b) => {
-                const aRes = parseInt(a.match(/\/(\d+)x\//)?.[1] || '0');
-                const bRes = parseInt(b.match(/\/(\d+)x\//)?.[1] || '0');
-                return bRes - aRes;
-            });
-            console.log('Found high-res URLs in JSON:', urls[0]);
-            return urls[0];
-        }
-
-        // Method 4: Fallback - look for any pinimg URL that's not a small thumbnail
-        const allPinimgPattern = /https:\/\/i\.pinimg\.com\/(?:originals|\d+x)\/[a-f0-9\/]+\.[a-z]+/gi;
-        const allMatches = [...new Set(html.match(allPinimgPattern) || [])];
-        if (allMatches.length > 0) {
-            // Filter out small sizes (75x, 140x, 236x) and sort by size
-            const filtered = allMatches.filter(url => {
-                const sizeMatch = url.match(/\/(\d+)x\//);
-                if (!sizeMatch) return true; // originals
-                return parseInt(sizeMatch[1]) >= 474;
-            });
-            filtered.sort((a, b) => {
-                if (a.includes('/originals/')) return -1;
-                if (b.includes('/originals/')) return 1;
-                const aRes = parseInt(a.match(/\/(\d+)x\//)?.[1] || '0');
-                const bRes = parseInt(b.match(/\/(\d+)x\//)?.[1] || '0');
-                return bRes - aRes;
-            });
-            if (filtered.length > 0) {
-                console.log('Found pinimg URL (fallback):', filtered[0]);
-                return filtered[0];
-            }
-        }
-
-        console.log('No image URL found in Pinterest page');
-        return null;
+        const candidates = await window.__TAURI__.core.invoke('resolve_page_images', { url: pageUrl });
+        return candidates.length > 0 ? candidates[0].url : null;
     } catch (err) {
-        console.error('Failed to fetch Pinterest page:', err);
+        console.error('Failed to resolve images from page:', err);
         return null;
     }
 }
 
 let canvas;
 let currentBoardId;
+// Set when the window shows a single layer; nothing is saved then
+let layerId = null;
 let isPinned = false;
 let saveTimeout = null;
 let pendingSave = false;
+let savedState = null; // The board as last saved, to diff edits against
 
 let syncChannel = null;
 
 document.addEventListener('DOMContentLoaded', async () => {
     const params = new URLSearchParams(window.location.search);
     currentBoardId = parseInt(params.get('id'));
+    if (params.has('layer')) layerId = parseFloat(params.get('layer'));
     
     if (!currentBoardId) return;
     
@@ -214,6 +148,7 @@ document.addEventListener('DOMContentLoaded', async () => {
             canvas.needsRender = true;
             canvas.render();
         } else if (event.data.type === 'image_added') {
+            if (layerId !== null) return;
             // Image added in editor — add it here too
             const data = event.data.image;
             const resolvedSrc = await boardManager.resolveImageSrc(data.src);
@@ -262,7 +197,9 @@ async function initFloatingWindow() {
     
     if (!board) return;
     
-    document.getElementById('window-title').textContent = board.name;
+    const layer = layerId !== null ? board.layers.find(l => l.id === layerId) : null;
+    if (layerId !== null && !layer) return;
+    document.getElementById('window-title').textContent = layer ? layer.name : board.name;
     
     const canvasElement = document.getElementById('floating-canvas');
     canvas = new Canvas(canvasElement);
@@ -277,6 +214,12 @@ async function initFloatingWindow() {
     document.body.style.backgroundColor = bgColor;
     updateTitlebarTheme(bgColor);
     
+    if (layer) {
+        await loadLayers([layer]);
+        canvas.fitToContent();
+        return;
+    }
+
     await loadLayers(board.layers, board.viewState);
 
     // Fit content to view if no viewState was saved
@@ -299,6 +242,7 @@ async function initFloatingWindow() {
         console.log('No objects to load in floating window', board.objects);
     }
 
+    savedState = captureState();
     canvas.canvas.addEventListener('canvasChanged', scheduleSave);
     canvas.canvas.addEventListener('objectsChanged', scheduleSave);
 
@@ -401,6 +345,8 @@ function setupDragAndDrop() {
 
         // Handle URL drops from websites (text/html or text/uri-list)
         let imageUrls = [];
+        // Page each resolved image was found on, for attribution
+        const pageUrls = new Map();
 
         // Try to extract from HTML first (most reliable for complex sites like Pinterest)
         const html = e.dataTransfer.getData('text/html');
@@ -420,16 +366,12 @@ function setupDragAndDrop() {
                         trimmed.match(/^https?:\/\/.*pinimg\.com/i)) {
                         imageUrls.push(trimmed);
                     }
-                    // Check for Pinterest pin page URLs - we'll need to fetch and parse these
-                    else if (trimmed.match(/^https?:\/\/(www\.)?pinterest\.[a-z]+\/pin\//i)) {
-                        console.log('Detected Pinterest pin URL, will fetch page to extract image');
-                        try {
-                            const extractedUrl = await extractImageFromPinterestPin(trimmed);
-                            if (extractedUrl) {
-                                imageUrls.push(extractedUrl);
-                            }
-                        } catch (err) {
-                            console.error('Failed to extract image from Pinterest pin:', err);
+                    // Any other page: fetch it and pick the image it's about
+                    else if (trimmed.match(/^https?:\/\//i)) {
+                        const extractedUrl = await resolvePageImage(trimmed);
+                        if (extractedUrl) {
+                            imageUrls.push(extractedUrl);
+                            pageUrls.set(extractedUrl, trimmed);
                         }
                     }
                 }
@@ -441,6 +383,11 @@ function setupDragAndDrop() {
             try {
                 const url = imageUrls[0];
                 const dataUrl = await window.__TAURI__.core.invoke('fetch_image_url', { url });
+                const source = await window.__TAURI__.core.invoke('describe_image_source', { url, pageUrl: pageUrls.get(url) || null })
+                    .catch(err => {
+                        console.warn('Failed to describe image source:', err);
+                        return null;
+                    });
 
                 // Generate a filename from URL
                 const urlObj = new URL(url);
@@ -459,6 +406,7 @@ function setupDragAndDrop() {
                 img.onload = async () => {
                     const added = canvas.addImage(img, x, y, filename);
                     if (filePath) added.filePath = filePath;
+                    if (source) added.source = source;
 
                     const srcForSync = filePath || dataUrl;
 
@@ -483,13 +431,15 @@ function setupDragAndDrop() {
 
                     const assetExists = currentAssets.some(a => a.name === filename);
                     if (!assetExists) {
-                        const updatedAssets = [...currentAssets, {
+                        const metadata = source ? { created: Date.now(), source } : null;
+                        const newAsset = {
                             id: Date.now() + Math.random(),
                             src: srcForSync,
                             name: filename
-                        }];
-                        await boardManager.updateBoard(currentBoardId, { assets: updatedAssets });
-                        await boardManager.addToAllAssets(filename, srcForSync);
+                        };
+                        if (metadata) newAsset.metadata = metadata;
+                        await boardManager.updateBoard(currentBoardId, { assets: [...currentAssets, newAsset] });
+                        await boardManager.addToAllAssets(filename, srcForSync, metadata);
                     }
                 };
                 img.src = imgSrc;
@@ -566,6 +516,7 @@ async function loadLayers(layers, viewState = null) {
                     added.volume = layer.volume != null ? layer.volume : 1;
                     added.muted = layer.muted !== false;
                     if (filePath) added.filePath = filePath;
+                    if (layer.source) added.source = layer.source;
                     if (layer.opacity != null) added.opacity = layer.opacity;
                     video.currentTime = added.currentTime;
                     finishIfDone();
@@ -586,6 +537,7 @@ async function loadLayers(layers, viewState = null) {
                             added.gifCurrentFrame = layer.gifCurrentFrame || 0;
                             added.gifPlaying = false;
                             if (filePath) added.filePath = filePath;
+                            if (layer.source) added.source = layer.source;
                             if (layer.opacity != null) added.opacity = layer.opacity;
                         }
                         finishIfDone();
@@ -600,6 +552,7 @@ async function loadLayers(layers, viewState = null) {
                     added.zIndex = layer.zIndex || 0;
                     added.rotation = layer.rotation || 0;
                     if (filePath) added.filePath = filePath;
+                    if (layer.source) added.source = layer.source;
                     if (layer.brightness != null) added.brightness = layer.brightness;
                     if (layer.contrast != null) added.contrast = layer.contrast;
                     if (layer.saturation != null) added.saturation = layer.saturation;
@@ -633,8 +586,19 @@ async function setupTitlebarControls() {
         const { Window } = window.__TAURI__.window;
         const currentWindow = Window.getCurrent();
 
-        await currentWindow.setAlwaysOnTop(false);
-        isPinned = false;
+        // Pin, opacity and click-through are kept by the backend per window
+        const saved = (await boardManager.getFloatingWindows())
+            .find(w => w.boardId === currentBoardId && (w.layerId ?? null) === layerId);
+        if (saved) applyWindowSettings(saved);
+        await currentWindow.listen('floating-window-changed', (event) => applyWindowSettings(event.payload));
+
+        const setOptions = async (options) => {
+            try {
+                applyWindowSettings(await boardManager.setFloatingWindowOptions(currentBoardId, layerId, options));
+            } catch (err) {
+                console.error('Failed to change floating window:', err);
+            }
+        };
 
         // Setup titlebar dragging (needed for macOS)
         const titlebar = document.getElementById('floating-titlebar');
@@ -652,10 +616,21 @@ async function setupTitlebarControls() {
             });
         }
 
-        document.getElementById('pin-btn').addEventListener('click', async () => {
-            isPinned = !isPinned;
-            await currentWindow.setAlwaysOnTop(isPinned);
-            document.getElementById('pin-btn').classList.toggle('pinned', isPinned);
+        document.getElementById('pin-btn').addEventListener('click', () => {
+            setOptions({ alwaysOnTop: !isPinned });
+        });
+
+        const opacitySlider = document.getElementById('opacity-slider');
+        // Previewed here while dragging; the backend applies the saved value
+        opacitySlider.addEventListener('input', () => {
+            document.documentElement.style.opacity = opacitySlider.value / 100;
+        });
+        opacitySlider.addEventListener('change', () => {
+            setOptions({ opacity: opacitySlider.value / 100 });
+        });
+
+        document.getElementById('click-through-btn').addEventListener('click', () => {
+            setOptions({ clickThrough: true });
         });
 
         document.getElementById('minimize-btn').addEventListener('click', async () => {
@@ -671,6 +646,13 @@ async function setupTitlebarControls() {
     }
 }
 
+function applyWindowSettings(settings) {
+    isPinned = settings.alwaysOnTop;
+    document.getElementById('pin-btn').classList.toggle('pinned', isPinned);
+    document.getElementById('click-through-btn').classList.toggle('active', settings.clickThrough);
+    document.getElementById('opacity-slider').value = Math.round(settings.opacity * 100);
+}
+
 function setupContextMenu() {
     const contextMenu = document.createElement('div');
     contextMenu.className = 'context-menu';
@@ -854,15 +836,22 @@ function scheduleSave() {
 }
 
 function saveNow() {
-    if (!pendingSave) return;
+    if (!pendingSave || layerId !== null) return;
     pendingSave = false;
     if (saveTimeout) {
         clearTimeout(saveTimeout);
         saveTimeout = null;
     }
 
-    const images = canvas.getImages();
-    const layers = images.map(img => {
+    const thumbnail = canvas.generateThumbnail(200, 150);
+    const saved = savedState;
+    savedState = captureState();
+    boardManager.saveBoardEdits(currentBoardId, saved, savedState, { thumbnail });
+}
+
+// The board's collections as they stand, to diff the next save against
+function captureState() {
+    const layers = canvas.getImages().map(img => {
         const layer = {
             id: img.id,
             name: img.name,
@@ -875,6 +864,8 @@ function saveNow() {
             zIndex: img.zIndex || 0
         };
 
+        if (img.source) layer.source = img.source;
+
         // Only include filter properties if they have non-default values
         if (img.rotation !== undefined && img.rotation !== 0) layer.rotation = img.rotation;
         if (img.brightness !== undefined && img.brightness !== 100) layer.brightness = img.brightness;
@@ -889,10 +880,9 @@ function saveNow() {
 
         return layer;
     });
-    const strokes = canvas.getStrokes();
-    const objects = canvas.objectsManager.getObjects();
-    const thumbnail = canvas.generateThumbnail(200, 150);
-    boardManager.updateBoard(currentBoardId, { layers, strokes, objects, thumbnail });
+    const strokes = canvas.getStrokes() || [];
+    const objects = canvas.objectsManager.getObjects() || [];
+    return JSON.parse(JSON.stringify({ layers, strokes, objects }));
 }
 
 function updateTitlebarTheme(bgColor) {
@@ -1,6 +1,6 @@
 import { boardManager } from './board-manager.js';
 import { showCreateBoardModal, showEditBoardModal, showDeleteConfirm } from './modal.js';
-import { showToast } from './modal-utils.js';
+import { showToast, showInputModal } from './modal-utils.js';
 import CollectionManager from './collection-manager.js';
 import { showSettingsModal } from './settingsModal.js';
 import { showLibraryModal } from './library.js';
@@ -208,6 +208,15 @@ function setupEventListeners() {
     if (eventListenersSetup) return;
     eventListenersSetup = true;
 
+    // Boards changed by other windows or in the background
+    const refreshBoards = async () => {
+        await boardManager.loadBoards();
+        renderBoards();
+        renderPinnedSidebar();
+    };
+    boardManager.onChange('board-updated', refreshBoards);
+    boardManager.onChange('board-deleted', refreshBoards);
+
     // Board search
     const searchInput = document.getElementById('board-search');
     if (searchInput) {
@@ -304,6 +313,12 @@ function setupEventListeners() {
         importBtnSearch.addEventListener('click', importBoardAsNew);
     }
 
+    // Join a board shared on the network
+    const joinBtnSearch = document.getElementById('join-board-btn-search');
+    if (joinBtnSearch) {
+        joinBtnSearch.addEventListener('click', joinSharedBoard);
+    }
+
     // Library button
     const libraryBtn = document.getElementById('library-btn');
     if (libraryBtn) {
@@ -480,6 +495,13 @@ function renderBoards() {
         `;
 
         const thumbnailDiv = card.querySelector('.board-card-thumbnail');
+        if (board.encrypted) {
+            const lockBadge = document.createElement('div');
+            lockBadge.className = 'board-lock-badge' + (board.locked ? ' locked' : '');
+            lockBadge.title = board.locked ? 'Encrypted (locked)' : 'Encrypted (unlocked)';
+            lockBadge.innerHTML = `<svg width="14" height="14" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"><rect x="5" y="11" width="14" height="10" rx="2"></rect><path d="${board.locked ? 'M8 11V7a4 4 0 0 1 8 0v4' : 'M8 11V7a4 4 0 0 1 7.5-2'}"></path></svg>`;
+            thumbnailDiv.appendChild(lockBadge);
+        }
         if (board.thumbnail) {
             const img = document.createElement('img');
             img.src = board.thumbnail;
@@ -576,9 +598,90 @@ async function deleteBoard(boardId) {
     renderPinnedSidebar();
 }
 
+// Asks for the passphrase of a locked board; false if the user gave up
+async function unlockIfLocked(boardId) {
+    const entry = boardManager.boards.find(b => b.id === boardId);
+    if (!entry || !entry.locked) return true;
+    let message = `"${entry.name}" is encrypted. Enter its passphrase to open it.`;
+    for (;;) {
+        const passphrase = await showInputModal('Unlock Board', message, '', 'Passphrase', 'password');
+        if (!passphrase) return false;
+        try {
+            await boardManager.unlockBoard(boardId, passphrase);
+            return true;
+        } catch (error) {
+            message = `${error}. Try again.`;
+        }
+    }
+}
+
+// Asks for a new passphrase twice; null if cancelled or they differ
+async function askNewPassphrase(title) {
+    const passphrase = await showInputModal(title, 'Choose a passphrase. It can\'t be recovered if you forget it.', '', 'New passphrase', 'password');
+    if (!passphrase) return null;
+    const confirmation = await showInputModal(title, 'Enter the passphrase again.', '', 'Confirm passphrase', 'password');
+    if (confirmation === null) return null;
+    if (confirmation !== passphrase) {
+        showToast('Passphrases don\'t match', 'error');
+        return null;
+    }
+    return passphrase;
+}
+
+async function encryptBoard(boardId) {
+    const passphrase = await askNewPassphrase('Encrypt Board');
+    if (!passphrase) return;
+    try {
+        await boardManager.lockBoard(boardId, passphrase);
+        showToast('Board encrypted', 'success');
+    } catch (error) {
+        showToast(`Failed to encrypt board: ${error}`, 'error', 3000);
+    }
+    renderBoards();
+    renderPinnedSidebar();
+}
+
+async function lockBoard(boardId) {
+    try {
+        await boardManager.lockBoard(boardId);
+        showToast('Board locked', 'success');
+    } catch (error) {
+        showToast(`Failed to lock board: ${error}`, 'error', 3000);
+    }
+    renderBoards();
+}
+
+async function changeBoardPassphrase(boardId) {
+    const current = await showInputModal('Change Passphrase', 'Enter the current passphrase.', '', 'Current passphrase', 'password');
+    if (!current) return;
+    const passphrase = await askNewPassphrase('Change Passphrase');
+    if (!passphrase) return;
+    try {
+        await boardManager.changeBoardPassphrase(boardId, current, passphrase);
+        showToast('Passphrase changed', 'success');
+    } catch (error) {
+        showToast(`Failed to change passphrase: ${error}`, 'error', 3000);
+    }
+}
+
+async function joinSharedBoard() {
+    const address = await showInputModal('Join Shared Board', 'Enter the address shown on the host:', '', '192.168.1.20:50000');
+    if (!address) return;
+    const code = await showInputModal('Join Shared Board', 'Enter the code shown on the host:', '', '7KQ2-M9XD-04TB');
+    if (!code) return;
+    try {
+        const session = await boardManager.joinCollab(address.trim(), code.trim());
+        renderBoards();
+        await openBoard(session.boardId);
+    } catch (error) {
+        showToast(`Failed to join: ${error}`, 'error');
+    }
+}
+
 async function openBoard(boardId) {
     try {
         console.log('[openBoard] Opening board:', boardId);
+        if (!(await unlockIfLocked(boardId))) return;
         // Get board name for breadcrumb
         const board = await boardManager.getBoard(boardId);
         console.log('[openBoard] Board data:', board);
@@ -818,11 +921,30 @@ function showBoardContextMenu(event, boardId) {
         });
     }
 
+    const board = boardManager.boards.find(b => b.id === boardId);
+    menuHTML += '<div class="context-menu-header">Encryption</div>';
+    if (!board || !board.encrypted) {
+        menuHTML += '<div class="context-menu-item" data-action="encrypt"><span class="context-menu-check"></span><span>Encrypt with Passphrase…</span></div>';
+    } else {
+        if (!board.locked) {
+            menuHTML += '<div class="context-menu-item" data-action="lock"><span class="context-menu-check"></span><span>Lock Now</span></div>';
+        }
+        menuHTML += '<div class="context-menu-item" data-action="change-passphrase"><span class="context-menu-check"></span><span>Change Passphrase…</span></div>';
+    }
+
     menu.innerHTML = menuHTML;
     document.body.appendChild(menu);
 
+    const actions = { 'encrypt': encryptBoard, 'lock': lockBoard, 'change-passphrase': changeBoardPassphrase };
+    menu.querySelectorAll('.context-menu-item[data-action]').forEach(item => {
+        item.addEventListener('click', () => {
+            menu.remove();
+            actions[item.dataset.action](boardId);
+        });
+    });
+
     // Add click handlers
-    menu.querySelectorAll('.context-menu-item:not(.disabled)').forEach(item => {
+    menu.querySelectorAll('.context-menu-item[data-collection-id]').forEach(item => {
         item.addEventListener('click', () => {
             const collectionId = item.dataset.collectionId;
             const isChecked = item.classList.contains('checked');
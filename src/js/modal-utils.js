@@ -55,7 +55,7 @@ function removeToast(toast) {
     }, 250);
 }
 
-export function showInputModal(title, message, defaultValue = '', placeholder = '') {
+export function showInputModal(title, message, defaultValue = '', placeholder = '', type = 'text') {
     return new Promise((resolve) => {
         const overlay = document.createElement('div');
         overlay.className = 'modal-overlay';
@@ -68,7 +68,7 @@ export function showInputModal(title, message, defaultValue = '', placeholder =
                 <div class="modal-body">
                     ${message ? `<p class="modal-text">${message}</p>` : ''}
                     <div class="form-group">
-                        <input type="text" id="modal-input" value="${defaultValue}" placeholder="${placeholder}" autocomplete="off">
+                        <input type="${type}" id="modal-input" value="${defaultValue}" placeholder="${placeholder}" autocomplete="off">
                     </div>
                 </div>
                 <div class="modal-footer">